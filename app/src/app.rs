@@ -3,7 +3,6 @@ use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{mpsc, Arc};
 use std::{mem, thread};
 use std::cmp::max;
-use std::slice::from_raw_parts;
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use log::{error, info, warn};
@@ -16,15 +15,16 @@ use winit::{dpi, keyboard};
 use winit::keyboard::NamedKey;
 use winit::monitor::Fullscreen;
 use winit::window::Window;
-use vulkan_lib::{vk, VulkanInstance};
+use vulkan_lib::{vk, SwapchainConfig, VulkanInstanceBuilder};
 use vulkan_lib::queue::shared::SharedState;
 use vulkan_lib::resources::buffer::BufferResource;
 use vulkan_lib::resources::VulkanAllocator;
+use vulkan_lib::shaders::updates::{CollectDrawStateUpdates, DirtyUpdates, StateUpdatesArray};
 use vulkan_lib::vk::{BufferCreateFlags, BufferUsageFlags};
 use crate::component::Component;
 use crate::layout::calculator::LayoutCalculator;
 use crate::render;
-use crate::render::{RenderData, RenderRequest, SolidAttributes, UpdateInstances};
+use crate::render::{RenderData, RenderRequest, SolidAttributes, SolidAttributesExt, UpdateInstances};
 use crate::resources::get_resource;
 use crate::util::{AtomicResizeRequest, DoubleBuffered, FrameCounter, TrippleAutoStaging};
 
@@ -45,8 +45,9 @@ pub struct App {
     // Rendering thread
     shared: SharedState,
     frame_counter: FrameCounter,
-    pub instances: Vec<SolidAttributes>,
-    instances_changed: bool,
+    pub instances: StateUpdatesArray<SolidAttributes>,
+    // instance bytes in buffer used by render thread
+    uploaded_len: usize,
     staging: TrippleAutoStaging,
     instance_buffers: DoubleBuffered<Arc<BufferResource>>,
 
@@ -71,8 +72,12 @@ impl App {
         // // try load resource
         // let font_data = get_resource(Path::join("fonts".as_ref(), "Ubuntu-Regular.ttf")).unwrap();
 
-        let api_version = vk::API_VERSION_1_1;
-        let vulkan_renderer = VulkanInstance::new_for_handle(raw_window_handle, raw_display_handle, (inner_size.width, inner_size.height), api_version).unwrap();
+        let vulkan_renderer = VulkanInstanceBuilder::new()
+            .with_app_name("Hello Vulkan", vk::make_api_version(0, 1, 0, 0))
+            .with_api_version(vk::API_VERSION_1_1)
+            .with_swapchain_config(SwapchainConfig::default())
+            .build(raw_window_handle, raw_display_handle, (inner_size.width, inner_size.height))
+            .unwrap();
         let shared = vulkan_renderer.shared();
         let mut allocator = vulkan_renderer.new_allocator();
         let pending_resize = AtomicResizeRequest::new();
@@ -91,7 +96,7 @@ impl App {
 
         let staging = TrippleAutoStaging::new(&frame_counter, &mut allocator, 4096);
         let instance_buffers = DoubleBuffered::new(&frame_counter, || {
-            allocator.new_buffer(BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::TRANSFER_DST, BufferCreateFlags::empty(), 100_000)
+            allocator.new_buffer(BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::TRANSFER_DST, BufferCreateFlags::empty(), 100_000, Some("instance buffer"))
         });
 
         Self {
@@ -107,8 +112,9 @@ impl App {
 
             prev_win_size: PhysicalSize::default(),
             frame_counter,
-            instances,
-            instances_changed: false,
+            // instance buffers are double buffered, each change is uploaded to both
+            instances: StateUpdatesArray::from_vec(instances).with_copies(2),
+            uploaded_len: 0,
             staging,
             instance_buffers,
             shared,
@@ -129,7 +135,6 @@ impl App {
             d: 0.5.into(),
             color: [1.0, 1.0, 1.0, 1.0].into(),
         });
-        self.instances_changed = true;
     }
     pub fn is_finished(&self) -> bool {
         self.app_finished
@@ -244,7 +249,7 @@ impl App {
 
         // Run layout and produce render rects
         let size = self.window.surface_size();
-        if size != self.prev_win_size {
+        if size != self.prev_win_size || self.layout_calculator.is_dirty() {
             self.prev_win_size = size;
             self.layout_calculator.calculate_layout(size.width, size.height);
 
@@ -252,30 +257,49 @@ impl App {
             self.window.set_min_surface_size(Some(PhysicalSize::new(w, h).into()));
 
             let render_rects = self.layout_calculator.get_render_rects();
-            self.instances.clear();
-            for rect in &render_rects {
-                self.instances.push(SolidAttributes {
-                    pos: [rect.x, rect.y].into(),
-                    size: [rect.w, rect.h].into(),
-                    d: rect.depth.into(),
-                    color: [rect.r, rect.g, rect.b, rect.a].into(),
-                });
+            // only fields which differ from previous layout are uploaded
+            for (i, rect) in render_rects.iter().enumerate() {
+                if i >= self.instances.len() {
+                    self.instances.push(SolidAttributes {
+                        pos: [rect.x, rect.y].into(),
+                        size: [rect.w, rect.h].into(),
+                        d: rect.depth.into(),
+                        color: [rect.r, rect.g, rect.b, rect.a].into(),
+                    });
+                    continue;
+                }
+                let prev = self.instances.items()[i];
+                let mut instance = self.instances.get_mut(i);
+                if <[i32; 2]>::from(prev.pos) != [rect.x, rect.y] {
+                    instance.set_pos([rect.x, rect.y]);
+                }
+                if <[i32; 2]>::from(prev.size) != [rect.w, rect.h] {
+                    instance.set_size([rect.w, rect.h]);
+                }
+                if f32::from(prev.d) != rect.depth {
+                    instance.set_d(rect.depth);
+                }
+                if <[f32; 4]>::from(prev.color) != [rect.r, rect.g, rect.b, rect.a] {
+                    instance.set_color([rect.r, rect.g, rect.b, rect.a]);
+                }
             }
-            self.instances_changed = true;
+            self.instances.truncate(render_rects.len());
         }
 
-        let bytes_len = self.instances.len() * size_of::<SolidAttributes>();
-        let new_instances = if self.instances_changed && bytes_len > 0 {
+        let bytes_len = self.instances.bytes_len();
+        let new_instances = if self.instances.has_updates() || bytes_len != self.uploaded_len {
             let g = range_event_start!("instance buffer prepare");
-            let mut range = self.staging.allocate(&mut self.allocator, bytes_len);
-            range.update(|r| {
-                let bytes = unsafe { from_raw_parts(self.instances.as_ptr() as *const u8, bytes_len) };
-                r[..bytes_len].copy_from_slice(bytes);
+            let updates = DirtyUpdates::new(self.instances.collect_updates());
+            let update = (!updates.is_empty()).then(|| {
+                let range = self.staging.allocate(&mut self.allocator, updates.size());
+                updates.write(range)
             });
-            self.instances_changed = false;
+            self.instances.clear_updates();
+            self.uploaded_len = bytes_len;
             Some(UpdateInstances {
-                staging: range,
-                buf: self.instance_buffers.current().clone()
+                update,
+                buf: self.instance_buffers.current().clone(),
+                len: bytes_len,
             })
         } else {
             None
@@ -0,0 +1,114 @@
+//! Re-executes device command capture recorded with `VULKAN_CAPTURE=<path>`
+//!
+//! Usage: `replay <capture file>`
+use std::path::PathBuf;
+use log::{error, info, LevelFilter};
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use simple_logger::SimpleLogger;
+use sparkles::config::SparklesConfig;
+use sparkles::FinalizeGuard;
+use winit::application::ApplicationHandler;
+use winit::dpi::PhysicalSize;
+use winit::event::WindowEvent;
+use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::event_loop::run_on_demand::EventLoopExtRunOnDemand;
+use winit::window::{Window, WindowAttributes, WindowId};
+use vulkan_lib::capture::{Replay, ReplayPlayer};
+use vulkan_lib::queue::GraphicsQueue;
+use vulkan_lib::resources::VulkanAllocator;
+use vulkan_lib::{vk, VulkanInstanceBuilder};
+
+struct ReplayState {
+    player: ReplayPlayer,
+    allocator: VulkanAllocator,
+    queue: GraphicsQueue,
+    window: Box<dyn Window>,
+    frame: usize,
+}
+
+struct ReplayApp {
+    replay: Option<Replay>,
+    state: Option<ReplayState>,
+    _g: FinalizeGuard,
+}
+
+impl ApplicationHandler for ReplayApp {
+    fn can_create_surfaces(&mut self, event_loop: &dyn ActiveEventLoop) {
+        let Some(replay) = self.replay.take() else {
+            return;
+        };
+        let (width, height) = replay.swapchain_extent();
+        let window = event_loop
+            .create_window(WindowAttributes::default()
+                .with_title("Capture replay")
+                .with_surface_size(PhysicalSize::new(width, height)))
+            .unwrap();
+
+        let raw_window_handle = window.window_handle().unwrap().as_raw();
+        let raw_display_handle = window.display_handle().unwrap().as_raw();
+        let queue = VulkanInstanceBuilder::new()
+            .with_app_name("Capture replay", vk::make_api_version(0, 1, 0, 0))
+            .build(raw_window_handle, raw_display_handle, (width, height))
+            .unwrap();
+        let allocator = queue.new_allocator();
+        info!("Replaying {} frames", replay.frame_count());
+
+        window.request_redraw();
+        self.state = Some(ReplayState {
+            player: replay.into_player(),
+            allocator,
+            queue,
+            window,
+            frame: 0,
+        });
+    }
+
+    fn window_event(&mut self, event_loop: &dyn ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::RedrawRequested => {
+                match state.player.play_frame(&mut state.queue, &mut state.allocator) {
+                    Ok(true) => {
+                        state.frame += 1;
+                        state.window.request_redraw();
+                    }
+                    Ok(false) => {
+                        info!("Replay finished after {} frames", state.frame);
+                        state.queue.wait_idle();
+                        event_loop.exit();
+                    }
+                    Err(e) => {
+                        error!("Replay failed at frame {}: {:?}", state.frame, e);
+                        event_loop.exit();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    SimpleLogger::new().with_utc_timestamps().with_colors(true)
+        .with_level(LevelFilter::Info)
+        .with_module_level("sparkles_parser", LevelFilter::Warn)
+        .init().unwrap();
+    let Some(path) = std::env::args_os().nth(1).map(PathBuf::from) else {
+        anyhow::bail!("Usage: replay <capture file>");
+    };
+    let replay = Replay::load(&path)?;
+
+    let g = sparkles::init(SparklesConfig::default()
+        .without_file_sender()
+        .with_udp_multicast_default());
+    let mut event_loop = EventLoop::new()?;
+    event_loop.run_app_on_demand(ReplayApp {
+        replay: Some(replay),
+        state: None,
+        _g: g,
+    })?;
+    Ok(())
+}
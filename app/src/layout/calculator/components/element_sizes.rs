@@ -3,6 +3,7 @@ use std::ops::{Deref, DerefMut};
 use crate::layout::calculator::{ParametricStage, SideParametricState, ZERO_LENGTH_GUARD};
 use crate::layout::Lu;
 
+#[derive(Clone)]
 pub struct Calculated(pub Vec<ElementSizes>);
 impl Deref for Calculated {
     type Target = Vec<ElementSizes>;
@@ -29,7 +30,7 @@ impl Calculated {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct ElementSizes {
     pub parametric: ParametricSolveState,
     pub post_parametric: ParametricSolveState,
@@ -129,6 +130,15 @@ impl ElementSizes {
         true
     }
     
+    /// Dependent width is already resolved from height, must be kept by parent
+    pub fn has_resolved_width(&self) -> bool {
+        self.cur_parametric().width.is_dependent() && self.dim_fix.width.is_some()
+    }
+    /// Dependent height is already resolved from width, must be kept by parent
+    pub fn has_resolved_height(&self) -> bool {
+        self.cur_parametric().height.is_dependent() && self.dim_fix.height.is_some()
+    }
+
     // min width for current parametric or dim fix width if fixed
     pub fn min_width(&self) -> Lu {
         self.dim_fix.width.unwrap_or(self.cur_parametric().width.min)
@@ -161,7 +171,7 @@ impl<'a> ElementSizesChildren<'a> {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct ParametricSolveState {
     pub width: SideParametricState,
     pub height: SideParametricState,
@@ -215,7 +225,7 @@ impl ParametricSolveState {
 }
 
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct DimFixState {
     height: Option<Lu>,
     width: Option<Lu>,
@@ -248,7 +258,7 @@ impl DimFixState {
         self.subtree_fixed_y = true;
     }
 }
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct PosFixState {
     pub pos_x: Lu,
     pub pos_y: Lu,
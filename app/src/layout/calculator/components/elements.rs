@@ -1,9 +1,19 @@
 use std::ops::{Deref, DerefMut};
 use crate::layout::ElementNode;
 
+#[derive(Clone)]
 pub struct Elements(pub Vec<ElementNode>);
 
 impl Elements {
+    /// End of preorder range containing element i and all its descendants
+    pub fn subtree_end(&self, i: usize) -> usize {
+        let mut end = i + 1;
+        while end < self.0.len() && self.0[end].parent_i as usize >= i {
+            end += 1;
+        }
+        end
+    }
+
     pub fn children(&mut self, i: usize) -> (&mut ElementNode, ElementsChildrenMut) {
         let (element, children) = self.0[i..].split_first_mut().unwrap();
 
@@ -67,7 +77,7 @@ impl<'a> Iterator for ElementsChildrenIter<'a> {
             return None;
         }
 
-        if let Some(i) = self.i {
+        while let Some(i) = self.i {
             let ptr = self.inner.elements.as_ptr();
 
             // SAFETY: We guarantee that `next_sibling_i` never creates a cycle,
@@ -79,10 +89,13 @@ impl<'a> Iterator for ElementsChildrenIter<'a> {
 
             self.i = el.next_sibling_i.map(|next_i| next_i - self.inner.parent_i - 1);
 
-            Some((i + self.inner.parent_i + 1, el))
-        } else {
-            None
+            // hidden children are skipped by container solvers
+            if el.hidden {
+                continue;
+            }
+            return Some((i + self.inner.parent_i + 1, el));
         }
+        None
     }
 }
 pub struct ElementsChildrenIterMut<'a, 'b> {
@@ -98,7 +111,7 @@ impl<'a, 'b> Iterator for ElementsChildrenIterMut<'a, 'b> {
             return None;
         }
 
-        if let Some(i) = self.i {
+        while let Some(i) = self.i {
             let ptr = self.inner.elements.as_mut_ptr();
 
             // SAFETY: We guarantee that `next_sibling_i` never creates a cycle,
@@ -110,10 +123,12 @@ impl<'a, 'b> Iterator for ElementsChildrenIterMut<'a, 'b> {
 
             self.i = el.next_sibling_i.map(|next_i| next_i - self.inner.parent_i - 1);
 
-            Some((i + self.inner.parent_i + 1, el))
-        }
-        else {
-            None
+            // hidden children are skipped by container solvers
+            if el.hidden {
+                continue;
+            }
+            return Some((i + self.inner.parent_i + 1, el));
         }
+        None
     }
 }
\ No newline at end of file
@@ -7,6 +7,7 @@ use swash::scale::image::Image;
 use crate::layout::FontFamily;
 use crate::resources::get_resource;
 
+#[derive(Clone)]
 pub struct Fonts(pub HashMap<FontFamily, FontInfo>);
 impl Fonts {
     pub fn load_font(&mut self, name: FontFamily) -> &mut FontInfo {
@@ -67,6 +68,7 @@ impl DerefMut for Fonts {
 }
 
 
+#[derive(Clone)]
 pub struct FontInfo {
     font_raw: Vec<u8>,
     default_line_height: f32,
@@ -84,6 +86,7 @@ impl FontInfo {
     }
 }
 
+#[derive(Clone)]
 pub struct FontSizeInfo {
     // map of rendered glyphs for fixed size
     rendered_glyphs: HashMap<GlyphId, Image>,
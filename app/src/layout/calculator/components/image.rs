@@ -5,6 +5,7 @@ use log::{error, warn};
 use crate::resources::get_resource;
 use crate::util::read_image_from_bytes;
 
+#[derive(Clone)]
 pub struct Images(pub HashMap<String, ImageInfo>);
 impl Images {
     pub fn load_image(&mut self, src: String) -> &ImageInfo {
@@ -53,11 +54,13 @@ impl DerefMut for Images {
     }
 }
 
+#[derive(Clone)]
 pub enum ImageSource {
     Bytes(Vec<u8>),
     OpenError,
 }
 
+#[derive(Clone)]
 pub struct ImageInfo {
     // calculated as height / width
     aspect: f32,
@@ -65,6 +68,14 @@ pub struct ImageInfo {
 }
 
 impl ImageInfo {
+    #[cfg(test)]
+    pub fn with_aspect(aspect: f32) -> Self {
+        Self {
+            aspect,
+            src: ImageSource::OpenError,
+        }
+    }
+
     pub fn aspect(&self) -> f32 {
         self.aspect
     }
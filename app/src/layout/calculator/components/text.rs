@@ -7,6 +7,7 @@ use swash::text::Script;
 use crate::layout::{FontFamily, Lu, PX_PER_LU};
 use crate::layout::calculator::components::font::FontInfo;
 
+#[derive(Clone)]
 pub struct Texts(pub HashMap<u32, TextInfo>);
 
 impl Deref for Texts {
@@ -58,6 +59,13 @@ impl Texts {
             let line_height = metrics.ascent + metrics.descent + metrics.leading;
             let mut x_pos = 0.0;
             shaper.shape_with(|cluster| {
+                let advance: f32 = cluster.glyphs.iter().map(|g| g.advance).sum();
+                // break line before cluster overflowing width, unless it starts the line
+                if let Some(max_w) = width_constraint && x_pos > 0.0 && (x_pos + advance) * PX_PER_LU as f32 > max_w as f32 {
+                    max_width = f32::max(max_width, x_pos);
+                    x_pos = 0.0;
+                    y_pos += line_height;
+                }
                 for g in cluster.glyphs {
                     glyphs.push(TextGlyph {
                         font: font_name.clone(),
@@ -69,9 +77,6 @@ impl Texts {
                     font_glyphs.insert(g.id);
 
                     x_pos += g.advance;
-                    if let Some(max_w) = width_constraint && x_pos * PX_PER_LU as f32 > max_w as f32 {
-                        y_pos += line_height;
-                    }
                 }
             });
 
@@ -109,12 +114,15 @@ impl Texts {
         text
     }
 
-    pub fn set_text(&mut self, text_id: u32, value: Arc<str>) {
+    /// Returns true -> text was changed
+    pub fn set_text(&mut self, text_id: u32, value: Arc<str>) -> bool {
         let mut entry = self.entry(text_id).or_default();
         if entry.value != value {
             entry.value = value;
             entry.calculated_cache = None;
+            return true;
         }
+        false
     }
 
     pub fn remove_text(&mut self, text_id: u32) {
@@ -57,15 +57,16 @@ impl ContainerParametricSolver for ColSolver<'_> {
         let height = (!grow_en && cur_parametric.can_fix_height()).then_some(None);
         let width = (!cross_stretch_en && cur_parametric.can_fix_width()).then_some(None);
 
-        if height.is_none() && cur_parametric.width.is_dependent() && !cur_parametric.height.is_fixed() {
+        // self-dep-both child is driven by width, same as dependent height
+        if cur_parametric.is_self_dep_both() && width.is_none() && height.is_none() {
+            state.is_any_selfdepboth = true
+        }
+        else if height.is_none() && cur_parametric.width.is_dependent() && !cur_parametric.height.is_fixed() {
             state.is_any_selfdepx = true
         }
         else if width.is_none() && cur_parametric.height.is_dependent() && !cur_parametric.width.is_fixed() {
             state.is_any_selfdepy = true
         }
-        else if cur_parametric.is_self_dep_both() && width.is_none() && height.is_none() {
-            state.is_any_selfdepboth = true
-        }
 
         (width, height)
     }
@@ -84,21 +85,23 @@ impl ContainerParametricSolver for ColSolver<'_> {
         }
 
 
+        // child height from width: col height depends on col width
         if is_selfdepy {
-            res.width = SideParametricState::new_dependent()
+            res.height = SideParametricState::new_dependent()
         }
         else {
-            res.width.min = state.children_max_width;
+            res.height.min = state.children_height_sum;
+            if let Some(gap) = self.attrs.main_gap_mode.fixed() {
+                res.height.min += gap * state.children_count.saturating_sub(1) as Lu;
+            }
         }
 
+        // child width from height: col width depends on col height
         if is_selfdepx {
-            res.height = SideParametricState::new_dependent();
+            res.width = SideParametricState::new_dependent();
         }
         else {
-            res.height.min = state.children_height_sum;
-            if let Some(gap) = self.attrs.main_gap_mode.fixed() {
-                res.height.min += gap * state.children_count.saturating_sub(1) as Lu;
-            }
+            res.width.min = state.children_max_width;
         }
 
         if !cross_stretch_en {
@@ -128,6 +131,9 @@ impl ContainerFixSolver for ColSolver<'_> {
     fn handle_child_x(&self, state: &mut Self::StateX, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
         let cross_stretch_en = self.attrs.cross_stretch;
         let child_parametric = child_sizes.cur_parametric();
+        if child_sizes.has_resolved_width() {
+            return None;
+        }
 
         child_parametric.can_fix_width().then_some(cross_stretch_en.then_some(el_sizes.dim_fix.width().unwrap()))
     }
@@ -143,7 +149,7 @@ impl ContainerFixSolver for ColSolver<'_> {
             let child_sizes = children_sizes.get(i);
 
             fixed_height_sum += child_sizes.dim_fix.height().unwrap_or(0);
-            if child_sizes.cur_parametric().can_fix_height() {
+            if child_sizes.cur_parametric().can_fix_height() && !child_sizes.has_resolved_height() {
                 breakpoints.entry(child_sizes.cur_parametric().height.min).and_modify(|v| *v += 1).or_insert(1);
             }
             ch_cnt += 1;
@@ -162,6 +168,9 @@ impl ContainerFixSolver for ColSolver<'_> {
     fn handle_child_y(&self, state: &mut Self::StateY, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
         let grow_en = matches!(self.attrs.main_size_mode, MainSizeMode::EqualWidth);
         let child_parametric = child_sizes.cur_parametric();
+        if child_sizes.has_resolved_height() {
+            return None;
+        }
 
         if grow_en {
             let height = if child_parametric.can_fix_height() {
@@ -1,6 +1,7 @@
 use crate::layout::calculator::{Images, SideParametricState};
 use crate::layout::{ImgAttributes, Lu};
-use crate::layout::calculator::components::element_sizes::ParametricSolveState;
+use crate::layout::calculator::components::element_sizes::{ElementSizes, ParametricSolveState};
+use crate::layout::calculator::elements::SelfDepResolve;
 
 pub fn parametric_solve(attrs: &ImgAttributes, images: &mut Images) -> ParametricSolveState {
     let mut res = ParametricSolveState::default();
@@ -29,4 +30,14 @@ pub fn parametric_solve(attrs: &ImgAttributes, images: &mut Images) -> Parametri
     }
     
     res
-}
\ No newline at end of file
+}
+
+/// Missing side from aspect of the image, once the other side is known
+pub fn resolve_selfdep(attrs: &ImgAttributes, sizes: &ElementSizes, images: &mut Images) -> Option<SelfDepResolve> {
+    let aspect = images.load_image(attrs.resource.clone()).aspect();
+    match (sizes.dim_fix.width(), sizes.dim_fix.height()) {
+        (Some(w), None) => Some(SelfDepResolve::Height((w as f32 * aspect) as Lu)),
+        (None, Some(h)) => Some(SelfDepResolve::Width((h as f32 / aspect) as Lu)),
+        _ => None,
+    }
+}
@@ -130,7 +130,7 @@ impl ContainerFixSolver for RowSolver<'_> {
             let child_sizes = children_sizes.get(i);
 
             fixed_width_sum += child_sizes.dim_fix.width().unwrap_or(0);
-            if child_sizes.cur_parametric().can_fix_width() {
+            if child_sizes.cur_parametric().can_fix_width() && !child_sizes.has_resolved_width() {
                 breakpoints.entry(child_sizes.cur_parametric().width.min).and_modify(|v| *v += 1).or_insert(1);
             }
             ch_cnt += 1;
@@ -149,6 +149,9 @@ impl ContainerFixSolver for RowSolver<'_> {
     fn handle_child_x(&self, state: &mut Self::StateX, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
         let grow_en = matches!(self.attrs.main_size_mode, MainSizeMode::EqualWidth);
         let child_parametric = child_sizes.cur_parametric();
+        if child_sizes.has_resolved_width() {
+            return None;
+        }
 
         if grow_en {
             let width = if child_parametric.can_fix_width() {
@@ -202,6 +205,9 @@ impl ContainerFixSolver for RowSolver<'_> {
     fn handle_child_y(&self, state: &mut Self::StateY, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
         let cross_stretch_en = self.attrs.cross_stretch;
         let child_parametric = child_sizes.cur_parametric();
+        if child_sizes.has_resolved_height() {
+            return None;
+        }
 
         child_parametric.can_fix_height().then_some(cross_stretch_en.then_some(el_sizes.dim_fix.height().unwrap()))
     }
@@ -1,7 +1,10 @@
+use std::cmp::max;
+use log::warn;
+use crate::layout::{ChildAttributes, StackAttributes, StackChildAttributes, Lu, SelfDepAxis};
 use crate::layout::calculator::components::element_sizes::{ElementSizes, ElementSizesChildren, ParametricSolveState};
-use crate::layout::{ChildAttributes, StackAttributes, StackChildAttributes, Lu, ColAttributes};
 use crate::layout::calculator::components::elements::{ElementsChildrenIter};
-use crate::layout::calculator::elements::{ContainerFixSolver, ContainerParametricSolver, HasChildAttributes, SelfDepResolve};
+use crate::layout::calculator::elements::{ContainerFixSolver, ContainerParametricSolver, HasChildAttributes};
+use crate::layout::calculator::SideParametricState;
 
 #[derive(Copy, Clone)]
 pub struct StackParametricSolver<'a> {
@@ -23,16 +26,72 @@ impl HasChildAttributes for StackParametricSolver<'_> {
     }
 }
 
+/// Self-dep-both child (e.g. image without size) is driven by width unless asked otherwise.
+/// Cover and Fit proportional modes are resolved as FixAxis by the driving side
+fn is_width_from_height(child_attrs: &StackChildAttributes) -> bool {
+    matches!(child_attrs.self_dep_axis, SelfDepAxis::WidthFromHeight)
+}
+
 
 // STAGE 1: PARAMETRIC SOLVE
+#[derive(Default)]
+pub struct StackSolverState {
+    children_max_width: Lu,
+    children_max_height: Lu,
+    is_any_selfdepx: bool,
+    is_any_selfdepy: bool,
+}
 impl ContainerParametricSolver for StackParametricSolver<'_> {
-    type State = ();
-    fn handle_child(&mut self, state: &mut (), child_sizes: &ElementSizes, child_attrs: &StackChildAttributes) -> (Option<Option<Lu>>, Option<Option<Lu>>) {
+    type State = StackSolverState;
+    fn handle_child(&mut self, state: &mut StackSolverState, child_sizes: &ElementSizes, child_attrs: &StackChildAttributes) -> (Option<Option<Lu>>, Option<Option<Lu>>) {
+        state.children_max_width = max(state.children_max_width, child_sizes.min_width());
+        state.children_max_height = max(state.children_max_height, child_sizes.min_height());
+
+        // Children are stretched to stack size on both axes, nothing to fix early.
+        // Stretch is disabled per child with nostretch_x/nostretch_y general attributes
+        let cur_parametric = child_sizes.cur_parametric();
+        if cur_parametric.is_self_dep_both() {
+            if is_width_from_height(child_attrs) {
+                state.is_any_selfdepy = true;
+            }
+            else {
+                state.is_any_selfdepx = true;
+            }
+        }
+        else if cur_parametric.height.is_dependent() && !cur_parametric.width.is_fixed() {
+            state.is_any_selfdepx = true;
+        }
+        else if cur_parametric.width.is_dependent() && !cur_parametric.height.is_fixed() {
+            state.is_any_selfdepy = true;
+        }
+
         (None, None)
     }
 
-    fn finalize(self, state: ()) -> ParametricSolveState {
-        ParametricSolveState::default()
+    fn finalize(self, state: StackSolverState) -> ParametricSolveState {
+        let mut res = ParametricSolveState::default();
+
+        let is_selfdepx = state.is_any_selfdepx;
+        let is_selfdepy = !state.is_any_selfdepx && state.is_any_selfdepy;
+        if state.is_any_selfdepx && state.is_any_selfdepy {
+            warn!("stack parametric: selfdepx and selfdepy conflict! Choosing selfdepx");
+        }
+
+        if is_selfdepx {
+            res.height = SideParametricState::new_dependent();
+        }
+        else {
+            res.height.min = state.children_max_height;
+        }
+
+        if is_selfdepy {
+            res.width = SideParametricState::new_dependent();
+        }
+        else {
+            res.width.min = state.children_max_width;
+        }
+
+        res
     }
 }
 // STAGE 2: DIM FIX
@@ -40,25 +99,33 @@ impl ContainerParametricSolver for StackParametricSolver<'_> {
 impl ContainerFixSolver for StackParametricSolver<'_> {
     type StateX = ();
 
-    fn init_x(&self, children_sizes: &ElementSizesChildren, children: ElementsChildrenIter) -> Self::StateX {
-        todo!()
+    fn init_x(&self, _children_sizes: &ElementSizesChildren, _children: ElementsChildrenIter) -> Self::StateX {
     }
 
-    fn handle_child_x(&self, state: &mut Self::StateX, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, sizes: &ElementSizes) -> Option<Option<Lu>> {
-        todo!()
+    fn handle_child_x(&self, _state: &mut Self::StateX, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
+        let child_parametric = child_sizes.cur_parametric();
+        if !child_parametric.can_fix_width() || child_sizes.has_resolved_width() {
+            return None;
+        }
+        // width of this child is resolved from height on Y pass
+        if child_parametric.is_self_dep_both() && is_width_from_height(child_attrs) {
+            return None;
+        }
+
+        Some(Some(el_sizes.dim_fix.width().unwrap()))
     }
 
     type StateY = ();
 
-    fn init_y(&self, children_sizes: &ElementSizesChildren, children: ElementsChildrenIter) -> Self::StateY {
-        todo!()
+    fn init_y(&self, _children_sizes: &ElementSizesChildren, _children: ElementsChildrenIter) -> Self::StateY {
     }
 
-    fn handle_child_y(&self, state: &mut Self::StateY, child_sizes: &ElementSizes, child_attrs: &Self::ChildAttributes, sizes: &ElementSizes) -> Option<Option<Lu>> {
-        todo!()
-    }
-}
+    fn handle_child_y(&self, _state: &mut Self::StateY, child_sizes: &ElementSizes, _child_attrs: &Self::ChildAttributes, el_sizes: &ElementSizes) -> Option<Option<Lu>> {
+        let child_parametric = child_sizes.cur_parametric();
+        if !child_parametric.can_fix_height() || child_sizes.has_resolved_height() {
+            return None;
+        }
 
-pub fn resolve_selfdep(attrs: &ColAttributes, sizes: &ElementSizes, children: ElementsChildrenIter, children_sizes: &ElementSizesChildren) -> SelfDepResolve {
-    SelfDepResolve::Width(100)
+        Some(Some(el_sizes.dim_fix.height().unwrap()))
+    }
 }
@@ -1,6 +1,7 @@
 use crate::layout::calculator::{Fonts, SideParametricState};
-use crate::layout::calculator::components::element_sizes::ParametricSolveState;
+use crate::layout::calculator::components::element_sizes::{ElementSizes, ParametricSolveState};
 use crate::layout::calculator::components::text::Texts;
+use crate::layout::calculator::elements::SelfDepResolve;
 use crate::layout::TextAttributes;
 
 pub fn parametric_solve(attrs: &TextAttributes, i: usize, fonts: &mut Fonts, texts: &mut Texts) -> ParametricSolveState {
@@ -32,3 +33,16 @@ pub fn parametric_solve(attrs: &TextAttributes, i: usize, fonts: &mut Fonts, tex
 
     res
 }
+
+/// Height of text shaped within the fixed width, preformatted text is solved in parametric stage
+pub fn resolve_selfdep(attrs: &TextAttributes, i: usize, sizes: &ElementSizes, fonts: &mut Fonts, texts: &mut Texts) -> Option<SelfDepResolve> {
+    if attrs.preformat || sizes.dim_fix.height().is_some() {
+        return None;
+    }
+    let w = sizes.dim_fix.width()?;
+
+    let font = fonts.load_font(attrs.font.clone());
+    let size = attrs.font_size.with_scale(1.0);
+    let text = texts.calculate_layout(i as u32, font, attrs.font.clone(), size, Some(w));
+    Some(SelfDepResolve::Height(text.height()))
+}
@@ -1,5 +1,6 @@
 use std::cmp::max;
 use std::collections::{HashMap};
+use std::ops::Range;
 use log::warn;
 use crate::layout::{AttributeValue, Element, ElementKind, ElementNode, ElementNodeRepr, Lu, MainGapMode, ParsedAttributes, XAlign, YAlign};
 use crate::layout::calculator::components::element_sizes::{Calculated, ElementSizes, ElementSizesChildren, ParametricSolveState};
@@ -13,6 +14,7 @@ mod elements;
 pub mod components;
 
 const ZERO_LENGTH_GUARD: Lu = 200;
+const MAX_SECOND_PASSES: usize = 4;
 
 pub enum FixAxis {
     FixWidth,
@@ -73,7 +75,7 @@ impl SideParametricState {
     }
 }
 
-#[derive(Default, Clone, Debug, Copy)]
+#[derive(Default, Clone, Debug, Copy, PartialEq)]
 pub enum ParametricStage {
     #[default]
     Parametric,
@@ -90,6 +92,7 @@ pub struct RenderRect {
     pub r: f32,
     pub g: f32,
     pub b: f32,
+    /// Inherited opacity, takes effect only if the pipeline drawing rects has blending enabled
     pub a: f32,
     pub depth: f32,
 }
@@ -108,12 +111,20 @@ enum ControlFlow {
     SkipChildren,
 }
 
+#[derive(Clone)]
 pub struct LayoutCalculator {
     elements: Elements,
     elements_sizes: Calculated,
     images: Images,
     fonts: Fonts,
-    texts: Texts
+    texts: Texts,
+    /// Elements changed since last layout
+    dirty: Vec<bool>,
+    /// Window size of last layout, None if full recalculation is required
+    window_size: Option<(u32, u32)>,
+    /// Sizes of subtrees shown since last layout, reused if parent provides the same dimensions
+    shown_sizes: HashMap<usize, Vec<ElementSizes>>,
+    check_incremental: bool,
 }
 
 impl LayoutCalculator {
@@ -123,10 +134,21 @@ impl LayoutCalculator {
             elements_sizes: Calculated(Vec::new()),
             images: Images(HashMap::new()),
             fonts: Fonts(HashMap::new()),
-            texts: Texts(HashMap::new())
+            texts: Texts(HashMap::new()),
+            dirty: Vec::new(),
+            window_size: None,
+            shown_sizes: HashMap::new(),
+            check_incremental: false,
         }
     }
 
+    /// Test mode: compare each incremental relayout with full recalculation on a copy of the calculator,
+    /// panic if any cached element sizes differ
+    pub fn with_incremental_check(mut self, enabled: bool) -> Self {
+        self.check_incremental = enabled;
+        self
+    }
+
     pub fn init(&mut self, elements: Vec<ElementNodeRepr>) {
         let mut element_nodes = Vec::with_capacity(elements.len());
         let mut last_sibling_i: HashMap<u32, u32> = HashMap::new();
@@ -139,6 +161,7 @@ impl LayoutCalculator {
                 element,
                 general_attributes: attributes.general.unwrap_or_default(),
                 self_child_attributes: attributes.self_child.unwrap_or_default(),
+                hidden: false,
             });
 
             if i > 0 && let Some(last_sibling_i) = last_sibling_i.get(&elem.parent_i) {
@@ -151,22 +174,119 @@ impl LayoutCalculator {
         let len = element_nodes.len();
         self.elements = Elements(element_nodes);
         self.elements_sizes = Calculated(vec![Default::default(); len]);
+        self.dirty = vec![false; len];
+        self.window_size = None;
+        self.shown_sizes.clear();
     }
     
     pub fn set_text(&mut self, i: u32, text: &str) {
-        self.texts.set_text(i, text.into())
+        if self.texts.set_text(i, text.into()) {
+            self.dirty[i as usize] = true;
+        }
     }
 
-    pub fn hide_element(&mut self, element_id: u32) {
+    /// Layout must be recalculated even if window size is unchanged
+    pub fn is_dirty(&self) -> bool {
+        self.window_size.is_none() || self.dirty.contains(&true)
+    }
 
+    /// Hidden element and its subtree take no space in parent container and produce no render rects.
+    /// Sizes of hidden subtree are kept and reused once shown, if parent provides the same dimensions
+    pub fn hide_element(&mut self, element_id: u32) {
+        self.set_hidden(element_id, true);
     }
 
     pub fn show_element(&mut self, element_id: u32) {
+        self.set_hidden(element_id, false);
+    }
+
+    fn set_hidden(&mut self, element_id: u32, hidden: bool) {
+        let el = &mut self.elements[element_id as usize];
+        if el.hidden != hidden {
+            el.hidden = hidden;
+            // siblings are moved by parent solver
+            self.dirty[el.parent_i as usize] = true;
+
+            let i = element_id as usize;
+            let end = self.elements.subtree_end(i);
+            let dim_fix = &self.elements_sizes[i].dim_fix;
+            if !hidden && end > i + 1 && dim_fix.width().is_some() && dim_fix.height().is_some() {
+                self.shown_sizes.insert(i, self.elements_sizes[i..end].to_vec());
+            }
+            else {
+                self.shown_sizes.remove(&i);
+            }
+        }
+    }
+
+    /// Nearest hidden element among i and its ancestors
+    fn hidden_ancestor(&self, mut i: usize) -> Option<usize> {
+        loop {
+            if self.elements[i].hidden {
+                return Some(i);
+            }
+            if i == 0 {
+                return None;
+            }
+            i = self.elements[i].parent_i as usize;
+        }
+    }
+
+    /// Restore cached sizes of shown subtree if parent provided the same dimensions and subtree is unchanged.
+    /// Position of element i is provided by parent later and is kept
+    fn reuse_shown_sizes(&mut self, i: usize) -> bool {
+        let Some(cached) = self.shown_sizes.get(&i) else {
+            return false;
+        };
+        let end = i + cached.len();
+        let dim_fix = &self.elements_sizes[i].dim_fix;
+        if cached[0].dim_fix.width() != dim_fix.width() || cached[0].dim_fix.height() != dim_fix.height()
+            || self.dirty[i..end].contains(&true) {
+            return false;
+        }
+
+        let pos_fix = self.elements_sizes[i].pos_fix.clone();
+        self.elements_sizes[i..end].clone_from_slice(cached);
+        self.elements_sizes[i].pos_fix = pos_fix;
+        true
+    }
 
+    /// Reset sizes of range, hidden subtrees keep sizes of their last layout
+    fn reset_sizes(&mut self, range: Range<usize>) {
+        let mut i = range.start;
+        while i < range.end {
+            if self.elements[i].hidden {
+                i = self.elements.subtree_end(i);
+                continue;
+            }
+            self.elements_sizes[i] = Default::default();
+            i += 1;
+        }
+    }
+
+    /// Element and all its ancestors are not hidden
+    fn is_visible(&self, mut i: usize) -> bool {
+        loop {
+            if self.elements[i].hidden {
+                return false;
+            }
+            if i == 0 {
+                return true;
+            }
+            i = self.elements[i].parent_i as usize;
+        }
     }
 
     pub fn update_attribute(&mut self, element_id: u32, attr: AttributeValue) {
+        // child attributes are used by parent solver
+        let dirty_i = match &attr {
+            AttributeValue::ColChild(_, false) | AttributeValue::RowChild(_, false) | AttributeValue::StackChild(_, false) => {
+                self.elements[element_id as usize].parent_i
+            }
+            _ => element_id,
+        };
         self.elements[element_id as usize].apply(attr);
+        self.dirty[dirty_i as usize] = true;
     }
 
     /// Phase 1.1: Parametric solve (dfs)
@@ -219,7 +339,7 @@ impl LayoutCalculator {
                 (child_fixes_x, child_fixes_y, solver.finalize(solver_state))
             }
 
-            let (child_fixes_x, child_fixes_y, parametric) = match &element.element {
+            let (child_fixes_x, child_fixes_y, mut parametric) = match &element.element {
                 Element::Row(attrs) => {
                     parametric_solve_container(elements::row::solver(attrs), el_sizes, &mut children, children_sizes)
                 }
@@ -232,6 +352,11 @@ impl LayoutCalculator {
                 _ => unreachable!(),
             };
             // fix children
+            let mut dependent_fixed = false;
+            for child_fix in child_fixes_x.iter().chain(&child_fixes_y) {
+                let child_parametric = self.elements_sizes[*child_fix as usize].cur_parametric();
+                dependent_fixed |= child_parametric.width.is_dependent() || child_parametric.height.is_dependent();
+            }
             for child_fix in child_fixes_x {
                 self.dfs(child_fix as usize, Phase::FixPassX);
             }
@@ -239,6 +364,11 @@ impl LayoutCalculator {
                 self.dfs(child_fix as usize, Phase::FixPassY);
             }
 
+            // Second pass: dependent sides of fixed children are resolved now and may change container size
+            if dependent_fixed {
+                parametric = self.resolve_container_parametric(i);
+            }
+
             parametric
         }
         else {
@@ -270,6 +400,28 @@ impl LayoutCalculator {
     }
 
 
+    /// Solve container parametric again with current children sizes, children fix requests are ignored
+    fn resolve_container_parametric(&mut self, i: usize) -> ParametricSolveState {
+        fn solve_container<'a, T: ContainerParametricSolver>(mut solver: T,
+                                                             children: &mut ElementsChildrenMut<'a>,
+                                                             mut children_sizes: ElementSizesChildren) -> ParametricSolveState {
+            let mut solver_state = T::State::default();
+            for (i, child) in children.iter_mut() {
+                let _ = solver.handle_child(&mut solver_state, children_sizes.get_mut(i), T::unwrap(&mut child.self_child_attributes));
+            }
+            solver.finalize(solver_state)
+        }
+
+        let (element, mut children) = self.elements.children(i);
+        let (_, children_sizes) = self.elements_sizes.children(i);
+        match &element.element {
+            Element::Row(attrs) => solve_container(elements::row::solver(attrs), &mut children, children_sizes),
+            Element::Col(attrs) => solve_container(elements::col::solver(attrs), &mut children, children_sizes),
+            Element::Stack(attrs) => solve_container(elements::stack::solver(attrs), &mut children, children_sizes),
+            _ => unreachable!(),
+        }
+    }
+
     /// Phase 1.2: Apply general attributes
     /// fill in *.post_parametric, probably make subtree fix
     /// Call guarantee: parametric solved, not fixed
@@ -416,33 +568,20 @@ impl LayoutCalculator {
             else {
                 assert!(el_sizes.try_fix_height());
             }
-            match &element.element {
+            let res = match &element.element {
                 Element::Img(attrs) => {
-                    let img_info = self.images.load_image(attrs.resource.clone());
-                    let aspect = img_info.aspect();
-                    if let Some(w) = el_sizes.dim_fix.width() && el_sizes.dim_fix.height().is_none() {
-                        el_sizes.dim_fix.set_height((w as f32 * aspect) as Lu);
-                    }
-                    else if let Some(h) = el_sizes.dim_fix.height() && el_sizes.dim_fix.height().is_none() {
-                        el_sizes.dim_fix.set_width((h as f32 / aspect) as Lu);
-                    }
+                    elements::img::resolve_selfdep(attrs, el_sizes, &mut self.images)
                 }
                 Element::Box(_) => {
                     // No dependent dimensions to resolve
+                    None
                 }
                 Element::Text(attrs) => {
-                    if let Some(w) = el_sizes.dim_fix.width() {
-                        let font = self.fonts.load_font(attrs.font.clone());
-                        let size = attrs.font_size.with_scale(1.0);
-                        let text = self.texts.calculate_layout(i as u32, font, attrs.font.clone(), size, Some(w));
-
-                        if el_sizes.dim_fix.height().is_none() {
-                            el_sizes.dim_fix.set_height(text.height());
-                        }
-                    }
+                    elements::text::resolve_selfdep(attrs, i, el_sizes, &mut self.fonts, &mut self.texts)
                 }
                 _ => unreachable!()
-            }
+            };
+            Self::handle_self_dep_resolve(el_sizes, res);
         }
     }
 
@@ -540,9 +679,8 @@ impl LayoutCalculator {
                 // Calculate total children height for gap/alignment
                 let mut children_height_sum: Lu = 0;
                 let mut child_count: u32 = 0;
-                let mut ci = i + 1;
                 for (i, _) in self.elements.children(i).1.iter_mut() {
-                    children_height_sum += el_sizes.dim_fix.height().unwrap_or(0);
+                    children_height_sum += self.elements_sizes[i as usize].dim_fix.height().unwrap_or(0);
                     child_count += 1;
                 }
 
@@ -564,8 +702,20 @@ impl LayoutCalculator {
                     y += child_h + gap;
                 }
             }
+            Element::Stack(_) => {
+                // Children overlay each other, each aligned within the stack by own attributes
+                for (i, element) in self.elements.children(i).1.iter_mut() {
+                    let el_sizes = &mut self.elements_sizes[i as usize];
+                    let child_w = el_sizes.dim_fix.width().unwrap_or(0);
+                    let child_h = el_sizes.dim_fix.height().unwrap_or(0);
+
+                    let stack_attrs = &element.self_child_attributes.stack;
+                    el_sizes.pos_fix.pos_x = Self::align_x(stack_attrs.align_x, parent_w, child_w);
+                    el_sizes.pos_fix.pos_y = Self::align_y(stack_attrs.align_y, parent_h, child_h);
+                }
+            }
             _ => {
-                // Leaf elements and Stack: no positioning needed (Stack is not yet implemented)
+                // Leaf elements: no positioning needed
             }
         }
     }
@@ -641,6 +791,9 @@ impl LayoutCalculator {
             }
             Phase::FixPassY => {
                 if self.elements_sizes[i].dim_fix.height().is_some() && !self.elements_sizes[i].cur_parametric().height.is_fixed() {
+                    if self.reuse_shown_sizes(i) {
+                        return ControlFlow::SkipChildren;
+                    }
                     self.dim_fix_children(i, false);
                     ControlFlow::Continue
                 }
@@ -687,12 +840,111 @@ impl LayoutCalculator {
     }
 
 
+    /// Recalculate layout. If window size is unchanged, only subtrees of changed elements are recalculated
     pub fn calculate_layout(&mut self, width: u32, height: u32) {
-        // full reset on each recalculation for now
-        for el in self.elements_sizes.iter_mut() {
-            *el = Default::default();
+        // changes inside hidden subtree invalidate its kept sizes
+        for i in 0..self.dirty.len() {
+            if self.dirty[i] && let Some(hidden_i) = self.hidden_ancestor(i) {
+                self.elements_sizes[hidden_i] = Default::default();
+            }
         }
 
+        let incremental = self.window_size == Some((width, height)) && self.relayout_dirty(width, height);
+        if !incremental {
+            self.calculate_full(width, height);
+        }
+        else if self.check_incremental {
+            let mut full = self.clone();
+            full.shown_sizes.clear();
+            full.calculate_full(width, height);
+            for (i, (inc, full)) in self.elements_sizes.iter().zip(full.elements_sizes.iter()).enumerate() {
+                if inc != full {
+                    panic!("Incremental relayout mismatch on element {i}: {inc:?}, full recalculation: {full:?}");
+                }
+            }
+        }
+
+        self.window_size = Some((width, height));
+        self.dirty.fill(false);
+        self.shown_sizes.clear();
+    }
+
+    /// Recalculate subtrees of dirty elements.
+    /// Dirty element propagates up to the nearest ancestor with unchanged size, which is recalculated with cached parent input.
+    /// Returns false if root is affected and full recalculation is required
+    fn relayout_dirty(&mut self, width: u32, height: u32) -> bool {
+        for i in 0..self.dirty.len() {
+            // hidden subtree is recalculated by parent once shown
+            if !self.dirty[i] || !self.is_visible(i) {
+                continue;
+            }
+
+            let mut stable_i = i;
+            loop {
+                if stable_i == 0 {
+                    return false;
+                }
+                if self.relayout_subtree(stable_i, width, height) {
+                    break;
+                }
+                stable_i = self.elements[stable_i].parent_i as usize;
+            }
+
+            let end = self.elements.subtree_end(stable_i);
+            self.dirty[stable_i..end].fill(false);
+        }
+        true
+    }
+
+    /// Recalculate subtree of element i with the same dimensions provided by parent on previous layout.
+    /// Returns true if element sizes are unchanged, so parent and siblings stay valid
+    fn relayout_subtree(&mut self, i: usize, width: u32, height: u32) -> bool {
+        let prev = self.elements_sizes[i].clone();
+        let end = self.elements.subtree_end(i);
+        self.reset_sizes(i..end);
+
+        self.dfs(i, Phase::ParametricSolve);
+        let sizes = &mut self.elements_sizes[i];
+        if sizes.parametric != prev.parametric || sizes.post_parametric != prev.post_parametric {
+            return false;
+        }
+        // same as parent parametric solve
+        if !sizes.cur_parametric().is_fixed() {
+            sizes.parent_parametric = sizes.post_parametric;
+            sizes.set_parametric_stage(ParametricStage::ParentParametric);
+        }
+
+        // dependent side is provided after the other one
+        for _ in 0..2 {
+            let sizes = &mut self.elements_sizes[i];
+            if let Some(w) = prev.dim_fix.width() && sizes.dim_fix.width().is_none() && w >= sizes.cur_parametric().width.min
+                && sizes.try_provide_width(Some(w)) {
+                self.dfs(i, Phase::FixPassX);
+            }
+            let sizes = &mut self.elements_sizes[i];
+            if let Some(h) = prev.dim_fix.height() && sizes.dim_fix.height().is_none() && h >= sizes.cur_parametric().height.min
+                && sizes.try_provide_height(Some(h)) {
+                self.dfs(i, Phase::FixPassY);
+            }
+        }
+        for _ in 0..MAX_SECOND_PASSES {
+            if !self.fix_pending(i + 1..end, width, height) {
+                break;
+            }
+        }
+
+        let sizes = &mut self.elements_sizes[i];
+        sizes.pos_fix = prev.pos_fix.clone();
+        if *sizes != prev {
+            return false;
+        }
+        self.dfs(i, Phase::PosFixPass);
+        true
+    }
+
+    fn calculate_full(&mut self, width: u32, height: u32) {
+        self.reset_sizes(0..self.elements.len());
+
         self.dfs(0, Phase::ParametricSolve);
 
         let min_width = self.elements_sizes[0].cur_parametric().width.min;
@@ -703,6 +955,13 @@ impl LayoutCalculator {
         if self.elements_sizes[0].try_provide_height(Some(max(min_height, height))) {
             self.dfs(0, Phase::FixPassY);
         }
+
+        // Second pass: sides depending on the other axis could be fixed only after it was resolved
+        for _ in 0..MAX_SECOND_PASSES {
+            if !self.fix_pending(0..self.elements.len(), width, height) {
+                break;
+            }
+        }
         self.dfs(0, Phase::PosFixPass);
 
         // // diagnostics print
@@ -718,8 +977,56 @@ impl LayoutCalculator {
     }
 
 
+    /// Provide dependent sides left unresolved after fix passes in range of elements, resolved size is parametric min.
+    /// Returns true if any side was fixed
+    fn fix_pending(&mut self, range: Range<usize>, width: u32, height: u32) -> bool {
+        let mut fixed = false;
+        for (fix_x, phase) in [(true, Phase::FixPassX), (false, Phase::FixPassY)] {
+            let mut i = range.start;
+            while i < range.end {
+                if self.elements[i].hidden {
+                    i = self.elements.subtree_end(i);
+                    continue;
+                }
+                let parent = self.elements[i].parent_i as usize;
+                let sizes = &self.elements_sizes[i];
+                let (dim, parent_dim, can_fix, min) = if fix_x {
+                    (sizes.dim_fix.width(), self.elements_sizes[parent].dim_fix.width(), sizes.cur_parametric().can_fix_width(), sizes.cur_parametric().width.min)
+                }
+                else {
+                    (sizes.dim_fix.height(), self.elements_sizes[parent].dim_fix.height(), sizes.cur_parametric().can_fix_height(), sizes.cur_parametric().height.min)
+                };
+
+                if dim.is_none() && can_fix && (i == 0 || parent_dim.is_some()) {
+                    // root takes the window size, others keep resolved size
+                    let len = if i == 0 {
+                        Some(max(min, if fix_x { width } else { height }))
+                    }
+                    else {
+                        (min > 0).then_some(min)
+                    };
+                    let provided = if fix_x {
+                        self.elements_sizes[i].try_provide_width(len)
+                    }
+                    else {
+                        self.elements_sizes[i].try_provide_height(len)
+                    };
+                    if provided {
+                        self.dfs(i, phase);
+                        fixed = true;
+                    }
+                }
+                i += 1;
+            }
+        }
+        fixed
+    }
+
     /// Result for FixPass dfs is Fixed parametric kind and exact width and height for element and all other subtree elements
     pub fn dfs(&mut self, first_element: usize, phase: Phase) {
+        if self.elements[first_element].hidden {
+            return;
+        }
         let mut parents = vec![first_element];
         if self.handle_node(first_element, &[], phase) == ControlFlow::SkipChildren {
             self.finalize_node(first_element, phase);
@@ -738,6 +1045,11 @@ impl LayoutCalculator {
                 last_parent = *parents.last().unwrap();
             }
 
+            // hidden subtree takes no part in layout
+            if self.elements[i].hidden {
+                i = self.elements.subtree_end(i);
+                continue;
+            }
 
             if self.handle_node(i, &parents, phase) == ControlFlow::SkipChildren {
                 self.finalize_node(i, phase);
@@ -765,16 +1077,20 @@ impl LayoutCalculator {
 
     /// Produce render rects for visualization.
     /// Containers get a 3px border outline, leaf elements get a solid fill.
+    /// Hidden and fully transparent (opacity 0) subtrees produce no rects, but transparent ones still occupy space.
+    /// Partial opacity is passed in alpha, which is blended only if the rendering pipeline enables blending
     pub fn get_render_rects(&self) -> Vec<RenderRect> {
         let mut rects = Vec::new();
         let mut abs_x = vec![0i32; self.elements.len()];
         let mut abs_y = vec![0i32; self.elements.len()];
+        let mut opacity = vec![0f32; self.elements.len()];
 
         // Compute absolute positions
         for i in 0..self.elements.len() {
             let rel_x = self.elements_sizes[i].pos_fix.pos_x as i32;
             let rel_y = self.elements_sizes[i].pos_fix.pos_y as i32;
             let parent = self.elements[i].parent_i as usize;
+            let parent_opacity = if i == 0 { 1.0 } else { opacity[parent] };
             if i == 0 {
                 abs_x[i] = rel_x;
                 abs_y[i] = rel_y;
@@ -783,6 +1099,15 @@ impl LayoutCalculator {
                 abs_y[i] = abs_y[parent] + rel_y;
             }
 
+            // opacity is inherited by subtree
+            if !self.elements[i].hidden {
+                opacity[i] = parent_opacity * self.elements[i].general_attributes.opacity;
+            }
+            let a = opacity[i];
+            if a <= 0.0 {
+                continue;
+            }
+
             let w = self.elements_sizes[i].dim_fix.width().unwrap_or(0) as i32;
             let h = self.elements_sizes[i].dim_fix.height().unwrap_or(0) as i32;
             let x = abs_x[i];
@@ -799,13 +1124,13 @@ impl LayoutCalculator {
                     _ => (0.5, 0.5, 0.5),
                 };
                 // Top
-                rects.push(RenderRect { x, y, w, h: bw, r, g, b, a: 1.0, depth });
+                rects.push(RenderRect { x, y, w, h: bw, r, g, b, a, depth });
                 // Bottom
-                rects.push(RenderRect { x, y: y + h - bw, w, h: bw, r, g, b, a: 1.0, depth });
+                rects.push(RenderRect { x, y: y + h - bw, w, h: bw, r, g, b, a, depth });
                 // Left
-                rects.push(RenderRect { x, y, w: bw, h, r, g, b, a: 1.0, depth });
+                rects.push(RenderRect { x, y, w: bw, h, r, g, b, a, depth });
                 // Right
-                rects.push(RenderRect { x: x + w - bw, y, w: bw, h, r, g, b, a: 1.0, depth });
+                rects.push(RenderRect { x: x + w - bw, y, w: bw, h, r, g, b, a, depth });
             } else {
                 let (r, g, b) = match &self.elements[i].element {
                     Element::Box(attrs) => {
@@ -818,10 +1143,394 @@ impl LayoutCalculator {
                     Element::Text(_) => (0.9, 0.9, 0.9),
                     _ => (0.5, 0.5, 0.5),
                 };
-                rects.push(RenderRect { x, y, w, h, r, g, b, a: 1.0, depth });
+                rects.push(RenderRect { x, y, w, h, r, g, b, a, depth });
             }
         }
 
         rects
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use smallvec::smallvec;
+    use super::*;
+    use crate::layout::{AttributeValues, ColChildValue, ColValue, FontFamily, GeneralValue, ImgValue, MainSizeMode, RowChildValue, RowValue, SelfDepAxis, StackChildValue};
+    use crate::layout::calculator::components::image::ImageInfo;
+
+    fn node(parent_i: u32, element: ElementKind, attributes: AttributeValues) -> ElementNodeRepr {
+        ElementNodeRepr {
+            parent_i,
+            element,
+            attributes,
+        }
+    }
+
+    fn fixed_box(w: Lu, h: Lu) -> AttributeValues {
+        smallvec![
+            AttributeValue::General(GeneralValue::MinWidth(w)),
+            AttributeValue::General(GeneralValue::MinHeight(h)),
+            AttributeValue::General(GeneralValue::NostretchX(true)),
+            AttributeValue::General(GeneralValue::NostretchY(true)),
+        ]
+    }
+
+    fn layout(nodes: Vec<ElementNodeRepr>, texts: &[(u32, &str)], width: u32, height: u32) -> LayoutCalculator {
+        let mut calc = LayoutCalculator::new();
+        calc.images.insert("half.png".to_string(), ImageInfo::with_aspect(0.5));
+        calc.init(nodes);
+        for (i, text) in texts {
+            calc.set_text(*i, text);
+        }
+        calc.calculate_layout(width, height);
+        calc
+    }
+
+    /// Height of default text shaped within `width`
+    fn text_height(text: &str, width: Option<Lu>) -> Lu {
+        let mut fonts = Fonts(HashMap::new());
+        let mut texts = Texts(HashMap::new());
+        texts.set_text(0, text.into());
+        let font = fonts.load_font(FontFamily::Default);
+        texts.calculate_layout(0, font, FontFamily::Default, 1.0, width).height()
+    }
+
+    const LONG_TEXT: &str = "Wrapped text takes as many lines as needed to fit into the width of its parent container";
+
+    /// Position relative to parent and size
+    fn rect(calc: &LayoutCalculator, i: usize) -> (Lu, Lu, Lu, Lu) {
+        let sizes = &calc.elements_sizes[i];
+        (sizes.pos_fix.pos_x, sizes.pos_fix.pos_y, sizes.dim_fix.width().unwrap(), sizes.dim_fix.height().unwrap())
+    }
+
+    #[test]
+    fn row_equal_width() {
+        let calc = layout(vec![
+            node(0, ElementKind::Row, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+        ], &[], 300, 100);
+
+        assert_eq!(rect(&calc, 1), (0, 0, 100, 100));
+        assert_eq!(rect(&calc, 2), (100, 0, 100, 100));
+        assert_eq!(rect(&calc, 3), (200, 0, 100, 100));
+    }
+
+    #[test]
+    fn col_fixed_gap() {
+        let calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![
+                AttributeValue::Col(ColValue::MainSizeMode(MainSizeMode::Min)),
+                AttributeValue::Col(ColValue::MainGapMode(MainGapMode::Fixed(10))),
+            ]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+        ], &[], 100, 300);
+
+        // min size col without free gap is fixed to children and gaps
+        assert_eq!(rect(&calc, 1), (0, 0, 100, 50));
+        assert_eq!(rect(&calc, 2), (0, 60, 100, 50));
+        assert_eq!(rect(&calc, 3), (0, 120, 100, 50));
+    }
+
+    #[test]
+    fn stack_overlay_align() {
+        let mut bottom_left = fixed_box(100, 50);
+        bottom_left.push(AttributeValue::StackChild(StackChildValue::AlignX(XAlign::Left), false));
+        bottom_left.push(AttributeValue::StackChild(StackChildValue::AlignY(YAlign::Bottom), false));
+        let calc = layout(vec![
+            node(0, ElementKind::Stack, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, bottom_left),
+            node(0, ElementKind::Box, fixed_box(60, 40)),
+        ], &[], 300, 200);
+
+        // stretched to stack, aligned by child attributes, centered by default
+        assert_eq!(rect(&calc, 1), (0, 0, 300, 200));
+        assert_eq!(rect(&calc, 2), (0, 150, 100, 50));
+        assert_eq!(rect(&calc, 3), (120, 80, 60, 40));
+    }
+
+    #[test]
+    fn stack_size_is_max_of_children() {
+        let calc = layout(vec![
+            node(0, ElementKind::Row, smallvec![
+                AttributeValue::Row(RowValue::MainSizeMode(MainSizeMode::Min)),
+                AttributeValue::Row(RowValue::MainGapMode(MainGapMode::None)),
+                AttributeValue::Row(RowValue::CrossStretch(false)),
+            ]),
+            node(0, ElementKind::Stack, smallvec![]),
+            node(1, ElementKind::Box, fixed_box(100, 50)),
+            node(1, ElementKind::Box, fixed_box(60, 80)),
+        ], &[], 300, 200);
+
+        assert_eq!(rect(&calc, 1), (0, 0, 100, 80));
+        assert_eq!(rect(&calc, 2), (0, 15, 100, 50));
+        assert_eq!(rect(&calc, 3), (20, 0, 60, 80));
+    }
+
+    #[test]
+    fn stack_mixed_self_dependent() {
+        let mut top_right = fixed_box(50, 40);
+        top_right.push(AttributeValue::StackChild(StackChildValue::AlignX(XAlign::Right), false));
+        top_right.push(AttributeValue::StackChild(StackChildValue::AlignY(YAlign::Top), false));
+        let text = "Stack children overlay each other, wrapped text takes the stack width";
+        let calc = layout(vec![
+            node(0, ElementKind::Stack, smallvec![]),
+            node(0, ElementKind::Text, smallvec![]),
+            // missing image resolves with square aspect
+            node(0, ElementKind::Img, smallvec![AttributeValue::Img(ImgValue::Resource("missing.png".to_string()))]),
+            node(0, ElementKind::Box, top_right),
+        ], &[(1, text)], 200, 10);
+
+        let (_, _, text_w, text_h) = rect(&calc, 1);
+        assert_eq!(text_w, 200);
+        assert!(text_h > 0);
+
+        // stack height depends on children heights resolved from stack width
+        let (_, _, stack_w, stack_h) = rect(&calc, 0);
+        assert_eq!(stack_w, 200);
+        assert_eq!(stack_h, max(text_h, 200));
+        assert_eq!(rect(&calc, 2), (0, (stack_h - 200) / 2, 200, 200));
+        assert_eq!(rect(&calc, 3), (150, 0, 50, 40));
+    }
+
+    #[test]
+    fn wrapped_text_in_row() {
+        let calc = layout(vec![
+            node(0, ElementKind::Row, smallvec![]),
+            node(0, ElementKind::Text, smallvec![]),
+            node(0, ElementKind::Text, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+        ], &[(1, LONG_TEXT), (2, "Short")], 300, 10);
+
+        let long_h = text_height(LONG_TEXT, Some(100));
+        let short_h = text_height("Short", Some(100));
+        assert!(long_h > text_height(LONG_TEXT, None));
+        assert_eq!(rect(&calc, 1), (0, 0, 100, long_h));
+        assert_eq!(rect(&calc, 2).3, short_h);
+        // row height is resolved from wrapped text, other children are stretched to it
+        assert_eq!(rect(&calc, 0).3, long_h);
+        assert_eq!(rect(&calc, 3), (200, 0, 100, long_h));
+    }
+
+    #[test]
+    fn wrapped_text_in_col() {
+        let calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![]),
+            node(0, ElementKind::Text, smallvec![]),
+            node(0, ElementKind::Text, smallvec![]),
+        ], &[(1, LONG_TEXT), (2, LONG_TEXT)], 120, 10);
+
+        let h = text_height(LONG_TEXT, Some(120));
+        assert_eq!(rect(&calc, 0).3, 2 * h);
+        assert_eq!(rect(&calc, 1), (0, 0, 120, h));
+        assert_eq!(rect(&calc, 2), (0, h, 120, h));
+    }
+
+    #[test]
+    fn wrapped_text_in_row_in_col() {
+        let calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![]),
+            node(0, ElementKind::Row, smallvec![]),
+            node(1, ElementKind::Text, smallvec![]),
+            node(1, ElementKind::Text, smallvec![]),
+            node(0, ElementKind::Box, smallvec![
+                AttributeValue::General(GeneralValue::MinHeight(30)),
+                AttributeValue::General(GeneralValue::NostretchY(true)),
+            ]),
+        ], &[(2, LONG_TEXT), (3, "Short")], 200, 10);
+
+        // row height from its text changes col height
+        let h = text_height(LONG_TEXT, Some(100));
+        assert_eq!(rect(&calc, 1), (0, 0, 200, h));
+        assert_eq!(rect(&calc, 4), (0, h, 200, 30));
+        assert_eq!(rect(&calc, 0).3, h + 30);
+    }
+
+    #[test]
+    fn image_height_from_width() {
+        let calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![]),
+            node(0, ElementKind::Img, smallvec![AttributeValue::Img(ImgValue::Resource("half.png".to_string()))]),
+            node(0, ElementKind::Box, smallvec![
+                AttributeValue::General(GeneralValue::MinHeight(20)),
+                AttributeValue::General(GeneralValue::NostretchY(true)),
+            ]),
+        ], &[], 300, 10);
+
+        assert_eq!(rect(&calc, 1), (0, 0, 300, 150));
+        assert_eq!(rect(&calc, 2), (0, 150, 300, 20));
+    }
+
+    #[test]
+    fn image_width_from_height() {
+        let calc = layout(vec![
+            node(0, ElementKind::Stack, smallvec![]),
+            node(0, ElementKind::Img, smallvec![
+                AttributeValue::Img(ImgValue::Resource("half.png".to_string())),
+                AttributeValue::StackChild(StackChildValue::SelfDepAxis(SelfDepAxis::WidthFromHeight), false),
+            ]),
+        ], &[], 300, 200);
+
+        // stack width is known only after height pass
+        assert_eq!(rect(&calc, 0).2, 400);
+        assert_eq!(rect(&calc, 1), (0, 0, 400, 200));
+    }
+
+    #[test]
+    fn incremental_matches_full() {
+        let nodes = vec![
+            node(0, ElementKind::Col, smallvec![]),
+            node(0, ElementKind::Row, smallvec![]),
+            node(1, ElementKind::Text, smallvec![]),
+            node(1, ElementKind::Text, smallvec![]),
+            node(1, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Stack, smallvec![]),
+            node(5, ElementKind::Box, fixed_box(40, 30)),
+            node(5, ElementKind::Text, smallvec![]),
+            node(0, ElementKind::Col, smallvec![]),
+            node(8, ElementKind::Img, smallvec![AttributeValue::Img(ImgValue::Resource("half.png".to_string()))]),
+            node(8, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+        ];
+        let texts = ["", "Short", "Wrapped text takes as many lines", LONG_TEXT];
+
+        let mut calc = LayoutCalculator::new().with_incremental_check(true);
+        calc.images.insert("half.png".to_string(), ImageInfo::with_aspect(0.5));
+        let len = nodes.len() as u64;
+        calc.init(nodes);
+        calc.set_text(2, LONG_TEXT);
+        calc.set_text(3, "Short");
+        calc.set_text(7, "Short");
+        calc.calculate_layout(300, 200);
+
+        // xorshift, fixed seed for reproducible sequences
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+
+        for _ in 0..500 {
+            for _ in 0..=rand(2) {
+                let i = rand(len) as u32;
+                match rand(8) {
+                    0 => {
+                        let text_i = [2, 3, 7][rand(3) as usize];
+                        calc.set_text(text_i, texts[rand(texts.len() as u64) as usize]);
+                    }
+                    1 => calc.update_attribute(i, AttributeValue::General(GeneralValue::MinWidth(rand(120) as Lu))),
+                    2 => calc.update_attribute(i, AttributeValue::General(GeneralValue::MinHeight(rand(120) as Lu))),
+                    3 => calc.update_attribute(i, AttributeValue::ColChild(ColChildValue::CrossAlign(
+                        [XAlign::Left, XAlign::Center, XAlign::Right][rand(3) as usize]), false)),
+                    4 => calc.update_attribute(i, AttributeValue::RowChild(RowChildValue::CrossAlign(
+                        [YAlign::Top, YAlign::Center, YAlign::Bottom][rand(3) as usize]), false)),
+                    5 => calc.update_attribute(i, AttributeValue::StackChild(StackChildValue::AlignX(
+                        [XAlign::Left, XAlign::Center, XAlign::Right][rand(3) as usize]), false)),
+                    6 => calc.hide_element(1 + rand(len - 1) as u32),
+                    _ => calc.show_element(1 + rand(len - 1) as u32),
+                }
+            }
+            // panics on mismatch with full recalculation
+            calc.calculate_layout(300, 200);
+        }
+    }
+
+    #[test]
+    fn hidden_child_takes_no_space() {
+        let mut calc = layout(vec![
+            node(0, ElementKind::Row, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+        ], &[], 300, 100);
+        assert_eq!(calc.get_render_rects().len(), 4 + 3);
+
+        calc.hide_element(2);
+        calc.calculate_layout(300, 100);
+        assert_eq!(rect(&calc, 1), (0, 0, 150, 100));
+        assert_eq!(rect(&calc, 3), (150, 0, 150, 100));
+        assert_eq!(calc.get_render_rects().len(), 4 + 2);
+
+        calc.show_element(2);
+        calc.calculate_layout(300, 100);
+        assert_eq!(rect(&calc, 2), (100, 0, 100, 100));
+        assert_eq!(rect(&calc, 3), (200, 0, 100, 100));
+        assert_eq!(calc.get_render_rects().len(), 4 + 3);
+    }
+
+    #[test]
+    fn hidden_child_gap() {
+        let mut calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![
+                AttributeValue::Col(ColValue::MainSizeMode(MainSizeMode::Min)),
+                AttributeValue::Col(ColValue::MainGapMode(MainGapMode::Fixed(10))),
+            ]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::MinHeight(50))]),
+        ], &[], 100, 300);
+
+        calc.hide_element(1);
+        calc.calculate_layout(100, 300);
+        assert_eq!(rect(&calc, 2), (0, 0, 100, 50));
+        assert_eq!(rect(&calc, 3), (0, 60, 100, 50));
+        assert_eq!(calc.get_min_root_size().1, 110);
+    }
+
+    #[test]
+    fn shown_subtree_reuses_kept_sizes() {
+        let mut calc = layout(vec![
+            node(0, ElementKind::Col, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Row, smallvec![]),
+            node(2, ElementKind::Box, smallvec![]),
+            node(2, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+        ], &[], 300, 300);
+        let row = rect(&calc, 2);
+        let row_child = calc.elements_sizes[4].clone();
+
+        calc.hide_element(2);
+        calc.calculate_layout(300, 300);
+        assert_eq!(rect(&calc, 5), (0, 150, 300, 150));
+        assert_eq!(calc.elements_sizes[4], row_child);
+
+        // marks kept sizes to tell reuse from recalculation
+        calc.elements_sizes[4].has_problems = true;
+        calc.show_element(2);
+        calc.calculate_layout(300, 300);
+        assert_eq!(rect(&calc, 2), row);
+        assert!(calc.elements_sizes[4].has_problems);
+
+        // parent provides different dimensions after resize, subtree is recalculated
+        calc.hide_element(2);
+        calc.calculate_layout(300, 300);
+        calc.show_element(2);
+        calc.calculate_layout(600, 300);
+        assert_eq!(rect(&calc, 2), (0, 100, 600, 100));
+        assert!(!calc.elements_sizes[4].has_problems);
+    }
+
+    #[test]
+    fn transparent_child_occupies_space() {
+        let calc = layout(vec![
+            node(0, ElementKind::Row, smallvec![]),
+            node(0, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Col, smallvec![AttributeValue::General(GeneralValue::Opacity(0.0))]),
+            node(2, ElementKind::Box, smallvec![]),
+            node(0, ElementKind::Box, smallvec![AttributeValue::General(GeneralValue::Opacity(0.5))]),
+        ], &[], 300, 100);
+
+        assert_eq!(rect(&calc, 2), (100, 0, 100, 100));
+        assert_eq!(rect(&calc, 4), (200, 0, 100, 100));
+        // transparent col and its child are not rendered
+        let rects = calc.get_render_rects();
+        assert_eq!(rects.len(), 4 + 2);
+        assert_eq!(rects.last().unwrap().a, 0.5);
+    }
+}
@@ -88,7 +88,9 @@ pub struct ElementNode {
     next_sibling_i: Option<u32>,
     element: Element,
     general_attributes: GeneralAttributes,
-    self_child_attributes: ChildAttributes
+    self_child_attributes: ChildAttributes,
+    /// Hidden element and its subtree take no space and are not rendered
+    hidden: bool,
 }
 
 impl ElementNode {
@@ -288,6 +290,7 @@ pub struct GeneralAttributes {
     pub margin_x: Lu,
     pub margin_y: Lu,
     pub self_dep_axis: SelfDepAxis,
+    /// Multiplied into alpha of subtree render rects. Values between 0 and 1 require blending in the rendering pipeline
     pub opacity: f32,
 }
 
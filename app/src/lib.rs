@@ -80,7 +80,7 @@ impl ApplicationHandler for WinitApp {
             .unwrap();
 
         // preserve instances between surface recreation on android (aka state ser/deser)
-        let instances = self.app.take().map(|a| a.instances.clone());
+        let instances = self.app.take().map(|a| a.instances.items().to_vec());
         let wakeup = event_loop.create_proxy();
         let app_state = App::new_winit(window, instances.unwrap_or_default(), wakeup);
         self.app = Some(app_state);
@@ -21,23 +21,27 @@ use swash::scale::{Render, ScaleContext, Source, StrikeWith};
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoopProxy;
 use render_macro::define_layout;
-use vulkan_lib::{descriptor_set, use_shader, ReflexMode};
-use vulkan_lib::vk::{BufferCreateFlags, ImageCreateFlags};
+use vulkan_lib::{descriptor_set, shader_sources, use_shader, LatencyMarker, ReflexMode};
+use vulkan_lib::vk::{BufferCreateFlags, ImageCreateFlags, VertexInputRate};
 use vulkan_lib::queue::GraphicsQueue;
 use vulkan_lib::queue::recording::BufferRange;
 use vulkan_lib::resources::buffer::BufferResource;
 use vulkan_lib::resources::image::ImageResource;
-use vulkan_lib::resources::pipeline::GraphicsPipelineDesc;
+use vulkan_lib::resources::pipeline::{GraphicsPipelineDesc, VertexInputDesc};
 use vulkan_lib::resources::render_pass::AttachmentsDescription;
 use vulkan_lib::resources::staging_buffer::StagingBufferRange;
 use vulkan_lib::resources::VulkanAllocator;
 use vulkan_lib::shaders::layout::types::{vec2, vec3, vec4, ivec2};
+use vulkan_lib::shaders::updates::{BufferUpdate, ModifyLayout};
 use crate::resources::get_resource;
 use crate::util::{AtomicResizeRequest, DoubleBuffered, FrameCounter};
 
 pub struct UpdateInstances {
-    pub staging: StagingBufferRange,
+    /// Changed bytes of instances, none when only instance count decreased
+    pub update: Option<BufferUpdate>,
     pub buf: Arc<BufferResource>,
+    /// Size of all instances in bytes
+    pub len: usize,
 }
 
 pub struct RenderTask {
@@ -51,6 +55,23 @@ pub struct RenderTask {
 }
 
 define_layout! {
+    #[layout(vertex)]
+    pub struct QuadVertex {
+        pub pos: vec2<0>,
+        pub uv: vec2<0>,
+    }
+}
+
+/// Unit quad for triangle strip: top-left, top-right, bottom-left, bottom-right
+const QUAD: [([f32; 2], [f32; 2]); 4] = [
+    ([0.0, 0.0], [0.0, 0.0]),
+    ([1.0, 0.0], [1.0, 0.0]),
+    ([0.0, 1.0], [0.0, 1.0]),
+    ([1.0, 1.0], [1.0, 1.0]),
+];
+
+define_layout! {
+    #[layout(vertex)]
     pub struct SolidAttributes {
         pub pos: ivec2<0>,
         pub size: ivec2<0>,
@@ -61,6 +82,7 @@ define_layout! {
 
 // uniforms
 define_layout! {
+    #[layout(std140)]
     pub struct Global {
         pub aspect: ivec2<0>,
     }
@@ -129,11 +151,13 @@ fn load_font_texture(allocator: &mut VulkanAllocator) -> (StagingBufferRange, Ar
         img.placement.height,
         Format::R8_UNORM,
         SampleCountFlags::TYPE_1,
+        Some("font texture"),
     );
 
     // Upload texture using staging buffer
     let staging_texture = allocator.new_staging_buffer(
         img.data.len() as u64,
+        Some("font texture staging"),
     );
 
     let mut tex_range = staging_texture.try_freeze(img.data.len()).expect("Should be empty");
@@ -188,9 +212,11 @@ impl RenderTask {
             // Create double-buffered staging buffers for instance data
             let staging_a = allocator.new_staging_buffer(
                 bytes_per_instance,
+                Some("vertex staging A"),
             );
             let staging_b = allocator.new_staging_buffer(
                 bytes_per_instance,
+                Some("vertex staging B"),
             );
 
             // Create render pass
@@ -227,7 +253,7 @@ impl RenderTask {
                 i
                     .min_filter(Filter::NEAREST)
                     .mag_filter(Filter::NEAREST)
-            });
+            }, Some("pixel perfect sampler"));
 
             if need_resolve {
                 // Add color attachment for MSAA
@@ -242,12 +268,16 @@ impl RenderTask {
             }
 
             let swapchain_format = self.vulkan_renderer.swapchain_format();
-            let render_pass = allocator.new_render_pass(
+            let mut render_pass = allocator.new_render_pass(
                 attachments_desc.clone(),
                 swapchain_format,
+                Some("main render pass"),
             );
 
-            let attributes = SolidAttributes::get_attributes_configuration();
+            // quad mesh per vertex, rect attributes per instance
+            let attributes = VertexInputDesc::default()
+                .with_binding::<QuadVertex>(VertexInputRate::VERTEX)
+                .with_binding::<SolidAttributes>(VertexInputRate::INSTANCE);
 
             // Create double-buffered vertex buffers
             let mut vertex_buffer = DoubleBuffered::new(&frame_counter, || {
@@ -255,14 +285,23 @@ impl RenderTask {
                     BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::TRANSFER_DST,
                     BufferCreateFlags::empty(),
                     bytes_per_instance,
+                    Some("vertex buffer"),
                 )
             });
 
             let pipeline_desc = GraphicsPipelineDesc::new(use_shader!("solid"), attributes, smallvec![GlobalDescriptorSet::bindings()]);
-            let pipeline = allocator.new_pipeline(render_pass.clone(), pipeline_desc, false);
+            let pipeline = allocator.new_hot_pipeline(render_pass.clone(), pipeline_desc, false, shader_sources!("solid"), Some("solid pipeline"));
 
             let (font_staging, font_texture, font_size) = load_font_texture(&mut allocator);
 
+            // UI overdraw: samples passed by UI draws, one pool per frame in flight
+            let ui_overdraw_queries = [
+                allocator.new_occlusion_query_pool(1, true, Some("UI overdraw queries A")),
+                allocator.new_occlusion_query_pool(1, true, Some("UI overdraw queries B")),
+            ].into_iter().collect::<anyhow::Result<Vec<_>>>()
+                .inspect_err(|e| error!("UI overdraw queries disabled: {:#}", e))
+                .ok();
+
             self.vulkan_renderer.record_device_commands(None, |ctx| {
                 ctx.copy_buffer_to_image_full(
                     font_staging,
@@ -272,14 +311,15 @@ impl RenderTask {
             });
 
             // Create descriptor sets
-            let descriptor_set = allocator.allocate_descriptor_set(GlobalDescriptorSet::bindings());
-            let ds_b = allocator.allocate_descriptor_set(GlobalDescriptorSet::bindings());
+            let descriptor_set = allocator.allocate_descriptor_set(GlobalDescriptorSet::bindings(), Some("global set A"));
+            let ds_b = allocator.allocate_descriptor_set(GlobalDescriptorSet::bindings(), Some("global set B"));
 
             // Create uniform buffers
             let global_ds_buffer = allocator.new_buffer(
                 BufferUsageFlags::UNIFORM_BUFFER | BufferUsageFlags::TRANSFER_DST,
                 BufferCreateFlags::empty(),
                 16,
+                Some("global uniform buffer"),
             );
 
             // Bind resources
@@ -289,6 +329,7 @@ impl RenderTask {
             // Upload initial uniform data
             let global_staging = allocator.new_staging_buffer(
                 16,
+                Some("global uniform staging"),
             );
 
             let mut global = Global {
@@ -298,13 +339,40 @@ impl RenderTask {
             let mut staging_global_range = global_staging.try_freeze(16).unwrap();
             staging_global_range.update(|data| data.copy_from_slice(global.as_bytes()));
 
+            // Upload quad mesh
+            let quad_bytes = QUAD.len() * QuadVertex::SIZE;
+            let quad_buffer = allocator.new_buffer(
+                BufferUsageFlags::VERTEX_BUFFER | BufferUsageFlags::TRANSFER_DST,
+                BufferCreateFlags::empty(),
+                quad_bytes as u64,
+                Some("quad mesh"),
+            );
+            let quad_staging = allocator.new_staging_buffer(
+                quad_bytes as u64,
+                Some("quad mesh staging"),
+            );
+            let mut quad_staging_range = quad_staging.try_freeze(quad_bytes).unwrap();
+            quad_staging_range.update(|data| {
+                for (chunk, (pos, uv)) in data.chunks_exact_mut(QuadVertex::SIZE).zip(QUAD) {
+                    let vertex = QuadVertex {
+                        pos: pos.into(),
+                        uv: uv.into(),
+                    };
+                    chunk.copy_from_slice(vertex.as_bytes());
+                }
+            });
+
             let initial_submission_number = self.vulkan_renderer.record_device_commands(None, |ctx| {
                 ctx.copy_buffer(staging_global_range, global_ds_buffer.full());
+                ctx.copy_buffer(quad_staging_range, quad_buffer.full());
             });
 
             // 1 frame in-flight
             let mut last_frame_submission_num = initial_submission_number;
             let mut pre_last_frame_submission_num = initial_submission_number;
+            // UI overdraw query pool written in each of the last two submissions
+            let mut last_frame_ui_query = None;
+            let mut pre_last_frame_ui_query = None;
             let mut instance_buffer: Option<BufferRange> = None;
 
             let mut waited_submission = self.vulkan_renderer.shared().last_host_waited_submission();
@@ -314,13 +382,22 @@ impl RenderTask {
                 if let Some((width,height)) = self.pending_resize.try_take() {
                     info!("[WINDOW RESIZE] Recreate swapchain...");
                     let g = range_event_start!("Recreate Resize");
-                    self.vulkan_renderer.recreate_resize((width, height));
+                    if let Some(format_change) = self.vulkan_renderer.recreate_resize((width, height)) {
+                        info!("Swapchain format changed to {:?}, recreating render pass and pipeline", format_change.new);
+                        render_pass = allocator.new_render_pass(attachments_desc.clone(), format_change.new.format, Some("main render pass"));
+                        // keeps shaders reloaded since startup
+                        allocator.rebuild_hot_pipeline(&pipeline, render_pass.clone());
+                    }
                     self.swapchain_recreated = true;
                     self.extent = [width as i32, height as i32];
                 }
 
                 // handle Redraw
                 let bg_color = [0.15, 0.12, 0.11];
+                let g = range_event_start!("Latency sleep");
+                self.vulkan_renderer.latency_sleep();
+                drop(g);
+                self.vulkan_renderer.set_latency_marker(LatencyMarker::SimulationStart);
                 let g = range_event_start!("Request rendering");
                 let (render_tx, render_rx) = oneshot::channel();
                 if self.render_request_tx.send(RenderRequest {
@@ -340,14 +417,16 @@ impl RenderTask {
                     }
                 };
                 drop(g);
+                self.vulkan_renderer.set_latency_marker(LatencyMarker::SimulationEnd);
+                self.vulkan_renderer.set_latency_marker(LatencyMarker::RenderSubmitStart);
                 if let Some(new_instances) = render_data.new_instances {
-                    let staging = new_instances.staging;
-                    let buf = new_instances.buf;
-                    self.vulkan_renderer.record_device_commands(None, |ctx| {
-                        let len = staging.len();
-                        ctx.copy_buffer(staging, buf.range(0..len));
-                        instance_buffer = Some(buf.range(0..len));
-                    });
+                    let UpdateInstances { update, buf, len } = new_instances;
+                    if let Some(update) = update {
+                        self.vulkan_renderer.record_device_commands(None, |ctx| {
+                            ctx.copy_buffer_update(update, buf.clone());
+                        });
+                    }
+                    instance_buffer = (len > 0).then(|| buf.range(0..len));
                 }
                 'render: {
                     let g = range_event_start!("Render");
@@ -432,28 +511,49 @@ impl RenderTask {
                         });
                     }
 
+                    let ui_query_pool = ui_overdraw_queries.as_ref().map(|pools| &pools[frame_counter.current_frame() % 2]);
                     let (present_wait_ref, new_sub_num) = self.vulkan_renderer.record_device_commands_signal(Some(acquire_wait_ref.with_stages(PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)), |ctx| {
-                        if let Some(range) = global_range {
-                            ctx.copy_buffer(range, global_ds_buffer.full());
-                        }
+                        ctx.timestamp_scope("Uploads", |ctx| {
+                            if let Some(range) = global_range {
+                                ctx.copy_buffer(range, global_ds_buffer.full());
+                            }
 
-                        ctx.copy_buffer(vertex_staging_range, vertex_buffer.current().full());
-                        ctx.render_pass(render_pass.clone(), image_index, clear_values, |ctx| {
-                            ctx.bind_pipeline(pipeline.clone());
-                            ctx.bind_descriptor_set(0, descriptor_set.clone());
-                            ctx.bind_vertex_buffer(vertex_buffer.current().full());
-                            ctx.draw(4, 1, 0, 0);
-
-                            if let Some(instance_buf) = &instance_buffer {
-                                let instance_count = instance_buf.len() as u32 / bytes_per_instance as u32;
-                                ctx.bind_vertex_buffer(instance_buf.clone());
-                                ctx.draw(4, instance_count, 0, 0);
+                            ctx.copy_buffer(vertex_staging_range, vertex_buffer.current().full());
+                        });
+                        ctx.begin_label("Main pass", [0.2, 0.6, 1.0, 1.0]);
+                        ctx.timestamp_scope("Main pass", |ctx| {
+                            let res = ctx.render_pass(render_pass.clone(), image_index, clear_values, |ctx| {
+                                ctx.bind_pipeline(pipeline.get());
+                                ctx.bind_descriptor_set(0, descriptor_set.clone());
+                                ctx.bind_vertex_buffers(0, &[quad_buffer.full(), vertex_buffer.current().full()]);
+                                ctx.draw(4, 1, 0, 0);
+
+                                if let Some(instance_buf) = &instance_buffer {
+                                    ctx.timestamp_scope("UI", |ctx| {
+                                        let instance_count = instance_buf.len() as u32 / bytes_per_instance as u32;
+                                        ctx.bind_vertex_buffers(0, &[quad_buffer.full(), instance_buf.clone()]);
+                                        if let Some(pool) = ui_query_pool {
+                                            ctx.begin_query(pool, 0);
+                                        }
+                                        ctx.draw(4, instance_count, 0, 0);
+                                        if let Some(pool) = ui_query_pool {
+                                            ctx.end_query(pool, 0);
+                                        }
+                                    });
+                                }
+                            });
+                            if let Err(e) = res {
+                                error!("Main pass skipped: {:?}", e);
                             }
-                        })
+                        });
+                        ctx.end_label();
                     });
+                    self.vulkan_renderer.set_latency_marker(LatencyMarker::RenderSubmitEnd);
                     self.swapchain_recreated = false;
                     pre_last_frame_submission_num = last_frame_submission_num;
                     last_frame_submission_num = new_sub_num;
+                    pre_last_frame_ui_query = last_frame_ui_query.take();
+                    last_frame_ui_query = ui_query_pool.filter(|_| instance_buffer.is_some()).cloned();
 
                     let g = range_event_start!("Present");
                     match self.vulkan_renderer.queue_present(image_index, present_wait_ref) {
@@ -474,12 +574,25 @@ impl RenderTask {
 
 
                 let g = range_event_start!("destroy old resources");
+                allocator.reload_shaders();
                 allocator.destroy_old_resources();
                 frame_counter.increment_frame();
                 drop(g);
 
+                let gpu_frame = self.vulkan_renderer.drain_gpu_scopes().last();
                 if self.last_print.elapsed().as_secs() >= 3 {
-                    allocator.dump_resource_usage();
+                    info!("Resource usage: {}", allocator.resource_usage().to_json());
+                    if let Some(frame) = gpu_frame {
+                        info!("GPU time: uploads {:?}, main pass {:?}, UI {:?}",
+                            frame.total("Uploads"), frame.total("Main pass"), frame.total("UI"));
+                    }
+                    // results of the pool written in the waited submission
+                    let ui_samples = pre_last_frame_ui_query.as_ref()
+                        .and_then(|pool| pool.try_get_results(0..1, waited_submission));
+                    if let Some(ui_samples) = ui_samples {
+                        let pixels = (self.extent[0] * self.extent[1]).max(1) as f64;
+                        info!("UI overdraw: {} samples, {:.2} per pixel", ui_samples[0], ui_samples[0] as f64 / pixels);
+                    }
                     self.last_print = Instant::now();
                 }
             }
@@ -495,3 +608,46 @@ pub struct RenderRequest {
 pub struct RenderData {
     pub new_instances: Option<UpdateInstances>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render_macro::CollectDrawStateUpdates;
+    use vulkan_lib::shaders::updates::{CollectDrawStateUpdates, GraphicsUpdateCmd, StateUpdatesArray, StateUpdatesBytes};
+
+    #[derive(CollectDrawStateUpdates)]
+    struct FrameState {
+        global: StateUpdatesBytes<Global>,
+        instances: StateUpdatesArray<SolidAttributes>,
+    }
+
+    fn updates(state: &FrameState) -> Vec<(usize, usize)> {
+        state.collect_updates().map(|c| (c.offset, c.data.len())).collect()
+    }
+
+    #[test]
+    fn derived_updates_follow_setters() {
+        let instance = SolidAttributes {
+            pos: [0, 0].into(),
+            size: [1, 1].into(),
+            d: 0.0.into(),
+            color: [1.0; 4].into(),
+        };
+        let mut state = FrameState {
+            global: StateUpdatesBytes::new(Global { aspect: [1, 1].into() }),
+            instances: StateUpdatesArray::from_vec(vec![instance; 3]).with_offset(256),
+        };
+        // new sources are fully dirty, in field order
+        assert_eq!(updates(&state), [(0, 16), (256, 3 * 48)]);
+        state.clear_updates();
+        assert!(updates(&state).is_empty());
+
+        state.instances.get_mut(2).set_d(0.5);
+        state.global.set_aspect([16, 9]);
+        state.instances.get_mut(0).set_color([0.0; 4]);
+        // ranges of each field in marking order
+        assert_eq!(updates(&state), [(0, 8), (256 + 2 * 48 + 16, 4), (256 + 32, 16)]);
+        state.clear_updates();
+        assert!(updates(&state).is_empty());
+    }
+}
@@ -74,9 +74,9 @@ pub struct TrippleAutoStaging {
 
 impl TrippleAutoStaging {
     pub fn new(frame_counter: &FrameCounter, allocator: &mut VulkanAllocator, initial_size: u64) -> Self {
-        let s1 = vec![allocator.new_staging_buffer(initial_size)];
-        let s2 = vec![allocator.new_staging_buffer(initial_size)];
-        let s3 = vec![allocator.new_staging_buffer(initial_size)];
+        let s1 = vec![allocator.new_staging_buffer(initial_size, Some("auto staging"))];
+        let s2 = vec![allocator.new_staging_buffer(initial_size, Some("auto staging"))];
+        let s3 = vec![allocator.new_staging_buffer(initial_size, Some("auto staging"))];
 
         Self {
             s1,
@@ -137,7 +137,7 @@ impl TrippleAutoStaging {
             new_size *= 2;
 
             let g = range_event_start!("Allocate new staging buffer, twice the size");
-            let buffer = allocator.new_staging_buffer(new_size as u64);
+            let buffer = allocator.new_staging_buffer(new_size as u64, Some("auto staging"));
             buffers.push(buffer);
 
             if let Some(range) = buffers.last_mut().unwrap().try_freeze(size) {
@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, ItemStruct};
+use crate::layout_rules::{parse_layout_attr, parse_glsl_type, place_fields, LayoutRules};
 
 pub fn define_layout(input: TokenStream) -> TokenStream {
     // Parse the input TokenStream into a syntax tree
@@ -16,6 +17,72 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
         panic!("Only named fields are supported in define_layout!");
     };
 
+    let layout_rules = match parse_layout_attr(&input.attrs) {
+        Ok(rules) => rules,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let mut field_types: Vec<_> = fields.iter().map(|f| f.ty.clone()).collect();
+    let mut glsl_fields = Vec::new();
+    let mut errors: Option<syn::Error> = None;
+    let mut push_error = |e: syn::Error| match &mut errors {
+        Some(errors) => errors.combine(e),
+        None => errors = Some(e),
+    };
+    for ty in &field_types {
+        match parse_glsl_type(ty) {
+            Ok(field) => glsl_fields.push(field),
+            Err(e) => push_error(e),
+        }
+    }
+
+    // offsets required by layout rules, missing padding is added to previous member
+    let mut layout_checks = Vec::new();
+    if let Some(rules) = layout_rules && glsl_fields.len() == fields.len() {
+        let placed = place_fields(rules, glsl_fields);
+        let names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+        for (i, placement) in placed.iter().enumerate() {
+            let field_name = names[i];
+            let position = |offset: usize| match placement.after {
+                Some(j) => format!("{} bytes after `{}`", offset, names[j]),
+                None => format!("offset {}", offset),
+            };
+            if let Some(error) = &placement.error {
+                push_error(syn::Error::new_spanned(&fields[i].ty, format!("`{}`: {}", field_name, error)));
+            }
+            else if placement.offset != placement.expected {
+                push_error(syn::Error::new_spanned(&fields[i].ty, format!(
+                    "`{}` is placed at {}, but {} expects {}",
+                    field_name, position(placement.offset), rules.name(), position(placement.expected),
+                )));
+            }
+            placement.field.apply(&mut field_types[i]);
+
+            let base = match placement.after {
+                Some(j) => {
+                    let (base_name, base_type) = (names[j], &field_types[j]);
+                    quote! { offset_of!(#struct_name, #base_name) + size_of::<#base_type>() }
+                }
+                None => quote! { 0 },
+            };
+            let message = format!("{}.{}: {} expects {}", struct_name, field_name, rules.name(), position(placement.expected));
+            let expected = placement.expected;
+            layout_checks.push(quote! {
+                const _: () = assert!(offset_of!(#struct_name, #field_name) == #base + #expected, #message);
+            });
+            if rules == LayoutRules::Std430 && placement.field.contains_struct() {
+                let field_type = &field_types[i];
+                let message = format!("{}.{}: nested struct has to be 16 byte aligned in std430", struct_name, field_name);
+                layout_checks.push(quote! {
+                    const _: () = assert!(<#field_type as GlslType>::T.std430_align() == 16, #message);
+                });
+            }
+        }
+    }
+    if let Some(errors) = errors {
+        return errors.to_compile_error().into();
+    }
+
     // Generate MEMBER_META entries
     let mut member_meta_entries = Vec::new();
     let mut trait_methods = Vec::new();
@@ -23,22 +90,12 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
 
     for (i, field) in fields.iter().enumerate() {
         let field_name = field.ident.as_ref().unwrap();
-        let field_type = &field.ty;
-        let glsl_type = match quote!(#field_type).to_string().as_str() {
-            "vec4 < 0 >" => quote! { GlslTypeVariant::Vec4 },
-            "vec3 < 0 >" => quote! { GlslTypeVariant::Vec3 },
-            "vec2 < 0 >" => quote! { GlslTypeVariant::Vec2 },
-            "uint < 0 >" => quote! { GlslTypeVariant::Uint },
-            "float < 0 >" => quote! { GlslTypeVariant::Float },
-            "int < 0 >" => quote! { GlslTypeVariant::Int },
-            "ivec2 < 0 >" => quote! { GlslTypeVariant::Ivec2 },
-            t => panic!("Unsupported type in define_layout: {}", t),
-        };
+        let field_type = &field_types[i];
         member_meta_entries.push(quote! {
             MemberMeta {
                 name: stringify!(#field_name),
                 range: offset_of!(#struct_name, #field_name)..offset_of!(#struct_name, #field_name) + size_of::<#field_type>(),
-                ty: #glsl_type,
+                ty: <#field_type as GlslType>::T,
             }
         });
 
@@ -61,7 +118,7 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
             fn #set_method_name(&mut self, value: #inner_type) {
                 unsafe {
                     self.modify_field(|s| {
-                        s.#field_name = value.into();
+                        s.#field_name = <#field_type as GlslType>::from_inner(value);
                         #struct_name::MEMBERS_META[#i].range.clone()
                     });
                 }
@@ -73,7 +130,7 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
             {
                 unsafe {
                     self.modify_field(|s| {
-                        s.#field_name = f(s.#field_name.into()).into();
+                        s.#field_name = <#field_type as GlslType>::from_inner(f(<#field_type as GlslType>::into_inner(s.#field_name)));
                         #struct_name::MEMBERS_META[#i].range.clone()
                     });
                 }
@@ -81,14 +138,15 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
         });
     }
 
-    let pub_fields = fields.iter().map(|f| {
+    let field_names: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+    let pub_fields = fields.iter().zip(&field_types).map(|(f, field_type)| {
         let field_name = &f.ident;
-        let field_type = &f.ty;
         quote! {
             pub #field_name: #field_type
         }
     });
 
+    let field_count = fields.len();
     let trait_name = format_ident!("{}Ext", struct_name);
     // Generate the final struct implementation
     let expanded = quote! {
@@ -104,13 +162,34 @@ pub fn define_layout(input: TokenStream) -> TokenStream {
             ];
         }
 
+        impl GlslType for #struct_name {
+            const T: GlslTypeVariant = GlslTypeVariant::Struct { members: Self::MEMBERS_META };
+            type Inner = Self;
+
+            fn from_inner(inner: Self) -> Self {
+                inner
+            }
+            fn into_inner(self) -> Self {
+                self
+            }
+        }
+
+        impl #struct_name {
+            /// Member offsets as laid out by compiler, in declaration order
+            pub const OFFSETS: [usize; #field_count] = [
+                #(offset_of!(#struct_name, #field_names)),*
+            ];
+        }
+
+        #(#layout_checks)*
+
         pub trait #trait_name {
             #(#trait_methods_defs)*
         }
 
-        // impl #trait_name for StateUpdatesBytes<#struct_name> {
-        //     #(#trait_methods)*
-        // }
+        impl<U: ModifyLayout<#struct_name>> #trait_name for U {
+            #(#trait_methods)*
+        }
     };
 
     TokenStream::from(expanded)
@@ -0,0 +1,414 @@
+use syn::{parse_quote, Attribute, Expr, GenericArgument, Ident, Lit, LitInt, PathArguments, Type};
+
+/// Offset rules selected with `#[layout(...)]` on a `define_layout!` struct
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayoutRules {
+    Std140,
+    Std430,
+    /// Vertex attributes: members keep natural alignment of their Rust types
+    Vertex,
+}
+
+impl LayoutRules {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayoutRules::Std140 => "std140",
+            LayoutRules::Std430 => "std430",
+            LayoutRules::Vertex => "vertex",
+        }
+    }
+}
+
+pub fn parse_layout_attr(attrs: &[Attribute]) -> syn::Result<Option<LayoutRules>> {
+    let mut rules = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("layout")) {
+        let ident: Ident = attr.parse_args()?;
+        rules = Some(match ident.to_string().as_str() {
+            "std140" => LayoutRules::Std140,
+            "std430" => LayoutRules::Std430,
+            "vertex" => LayoutRules::Vertex,
+            _ => return Err(syn::Error::new_spanned(ident, "expected `std140`, `std430` or `vertex`")),
+        });
+    }
+    Ok(rules)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Scalar or vector
+    Basic {
+        /// Size of GLSL type without padding
+        size: usize,
+        /// Base alignment of GLSL type, same in std140 and std430
+        align: usize,
+        /// Alignment of Rust type
+        rust_align: usize,
+        /// Can be read as vertex attribute
+        vertex: bool,
+        /// Can be member of uniform or storage buffer
+        buffer: bool,
+    },
+    /// Square float matrix with `column_padding` u32 words after each column
+    Matrix { n: usize, column_padding: usize },
+    Array { elem: Box<GlslField>, len: usize },
+    /// Nested `define_layout!` struct, its size is known only to compiler
+    Struct,
+}
+
+/// Member type from `shaders::layout::types` with its padding const generics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlslField {
+    pub name: String,
+    pub kind: FieldKind,
+    pub padding: usize,
+}
+
+impl GlslField {
+    fn basic(name: &str, padding: usize, size: usize, align: usize, rust_align: usize) -> Self {
+        let kind = FieldKind::Basic { size, align, rust_align, vertex: name != "bool", buffer: true };
+        Self { name: name.to_string(), kind, padding }
+    }
+
+    fn vertex_only(name: &str, padding: usize) -> Self {
+        let kind = FieldKind::Basic { size: 4, align: 4, rust_align: 4, vertex: true, buffer: false };
+        Self { name: name.to_string(), kind, padding }
+    }
+
+    fn rust_align(&self) -> usize {
+        match &self.kind {
+            FieldKind::Basic { rust_align, .. } => *rust_align,
+            FieldKind::Matrix { n: 2, .. } => 8,
+            FieldKind::Matrix { .. } | FieldKind::Struct => 16,
+            FieldKind::Array { elem, .. } => elem.rust_align(),
+        }
+    }
+
+    /// Size of Rust type, padding and trailing alignment included
+    fn rust_size(&self) -> Option<usize> {
+        match &self.kind {
+            FieldKind::Basic { size, .. } => Some((size + self.padding * 4).next_multiple_of(self.rust_align())),
+            FieldKind::Matrix { n, column_padding } => {
+                Some((n * (n + column_padding) * 4 + self.padding * 4).next_multiple_of(self.rust_align()))
+            }
+            FieldKind::Array { elem, len } => elem.rust_size().map(|size| size * len),
+            FieldKind::Struct => None,
+        }
+    }
+
+    fn column_stride(n: usize, rules: LayoutRules) -> usize {
+        match rules {
+            LayoutRules::Std140 => 16,
+            LayoutRules::Std430 | LayoutRules::Vertex => (n * 4).next_multiple_of(if n == 2 { 8 } else { 16 }),
+        }
+    }
+
+    /// Base alignment of GLSL type
+    fn align(&self, rules: LayoutRules) -> usize {
+        match &self.kind {
+            FieldKind::Basic { align, .. } => *align,
+            FieldKind::Matrix { n, .. } => Self::column_stride(*n, rules),
+            FieldKind::Array { elem, .. } if rules == LayoutRules::Std140 => elem.align(rules).next_multiple_of(16),
+            FieldKind::Array { elem, .. } => elem.align(rules),
+            // std430 alignment of nested struct is checked by compiler
+            FieldKind::Struct => 16,
+        }
+    }
+
+    /// Size of GLSL type
+    fn size(&self, rules: LayoutRules) -> Option<usize> {
+        match &self.kind {
+            FieldKind::Basic { size, .. } => Some(*size),
+            FieldKind::Matrix { n, .. } => Some(n * Self::column_stride(*n, rules)),
+            FieldKind::Array { elem, len } => Some(self.element_stride(elem, rules)? * len),
+            FieldKind::Struct => None,
+        }
+    }
+
+    fn element_stride(&self, elem: &GlslField, rules: LayoutRules) -> Option<usize> {
+        Some(elem.size(rules)?.next_multiple_of(self.align(rules)))
+    }
+
+    fn grow_padding(&mut self) -> bool {
+        match self.kind {
+            FieldKind::Basic { .. } | FieldKind::Matrix { .. } => {
+                self.padding += 1;
+                true
+            }
+            FieldKind::Array { .. } | FieldKind::Struct => false,
+        }
+    }
+
+    pub fn contains_struct(&self) -> bool {
+        match &self.kind {
+            FieldKind::Struct => true,
+            FieldKind::Array { elem, .. } => elem.contains_struct(),
+            FieldKind::Basic { .. } | FieldKind::Matrix { .. } => false,
+        }
+    }
+
+    /// Set column and element padding required by rules
+    fn fit(&mut self, rules: LayoutRules) -> Result<(), String> {
+        let stride = match &self.kind {
+            FieldKind::Array { elem, .. } => self.element_stride(elem, rules),
+            _ => None,
+        };
+        match &mut self.kind {
+            FieldKind::Basic { vertex: false, .. } if rules == LayoutRules::Vertex => {
+                Err(format!("`{}` can't be a vertex attribute", self.name))
+            }
+            FieldKind::Basic { buffer: false, .. } if rules != LayoutRules::Vertex => {
+                Err(format!("`{}` can only be used as vertex attribute", self.name))
+            }
+            FieldKind::Basic { .. } => Ok(()),
+            FieldKind::Struct if rules == LayoutRules::Vertex => Err("nested struct can't be a vertex attribute".to_string()),
+            FieldKind::Struct => Ok(()),
+            FieldKind::Matrix { .. } if rules == LayoutRules::Vertex => Ok(()),
+            FieldKind::Matrix { n, column_padding } => {
+                let stride = Self::column_stride(*n, rules);
+                let required = stride / 4 - *n;
+                if *column_padding > required {
+                    return Err(format!("matrix column stride is {}, but {} expects {}", (*n + *column_padding) * 4, rules.name(), stride));
+                }
+                *column_padding = required;
+                Ok(())
+            }
+            FieldKind::Array { elem, .. } => {
+                elem.fit(rules)?;
+                if rules == LayoutRules::Vertex {
+                    return Ok(());
+                }
+                let (Some(stride), Some(mut size)) = (stride, elem.rust_size()) else {
+                    return Ok(());
+                };
+                while size < stride && elem.grow_padding() {
+                    size = elem.rust_size().unwrap();
+                }
+                if size != stride {
+                    return Err(format!("array stride is {}, but {} expects {}", size, rules.name(), stride));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Write padding const generics back to type accepted by `parse_glsl_type`
+    pub fn apply(&self, ty: &mut Type) {
+        let literal = |value: usize| LitInt::new(&value.to_string(), proc_macro2::Span::call_site());
+        match (&self.kind, ty) {
+            (FieldKind::Array { elem, .. }, Type::Array(array)) => elem.apply(&mut array.elem),
+            (FieldKind::Basic { .. }, Type::Path(path)) => {
+                let padding = literal(self.padding);
+                if let Some(segment) = path.path.segments.last_mut() {
+                    segment.arguments = PathArguments::AngleBracketed(parse_quote!(<#padding>));
+                }
+            }
+            (FieldKind::Matrix { column_padding, .. }, Type::Path(path)) => {
+                let (padding, column_padding) = (literal(self.padding), literal(*column_padding));
+                if let Some(segment) = path.path.segments.last_mut() {
+                    segment.arguments = PathArguments::AngleBracketed(parse_quote!(<#padding, #column_padding>));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn int_literal(expr: &Expr) -> Option<syn::Result<usize>> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(int) => Some(int.base10_parse()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn parse_glsl_type(ty: &Type) -> syn::Result<GlslField> {
+    let unsupported = || syn::Error::new_spanned(ty, "Unsupported type in define_layout, expected GLSL type like `vec4<0>`, array or nested layout struct");
+    let path = match ty {
+        Type::Path(path) => path,
+        Type::Array(array) => {
+            let elem = parse_glsl_type(&array.elem)?;
+            let len = int_literal(&array.len)
+                .ok_or_else(|| syn::Error::new_spanned(&array.len, "array length has to be integer literal"))??;
+            return Ok(GlslField { name: format!("[{}; {}]", elem.name, len), kind: FieldKind::Array { elem: Box::new(elem), len }, padding: 0 });
+        }
+        _ => return Err(unsupported()),
+    };
+    let segment = path.path.segments.last().ok_or_else(unsupported)?;
+    let name = segment.ident.to_string();
+    let args = match &segment.arguments {
+        PathArguments::None => Vec::new(),
+        PathArguments::AngleBracketed(args) => args.args.iter()
+            .map(|arg| match arg {
+                GenericArgument::Const(expr) => int_literal(expr).unwrap_or_else(|| Err(unsupported())),
+                _ => Err(unsupported()),
+            })
+            .collect::<syn::Result<_>>()?,
+        PathArguments::Parenthesized(_) => return Err(unsupported()),
+    };
+    let field = match (name.as_str(), args.as_slice()) {
+        ("float" | "int" | "uint" | "bool", &[p]) => GlslField::basic(&name, p, 4, 4, 4),
+        ("vec2" | "ivec2" | "uvec2", &[p]) => GlslField::basic(&name, p, 8, 8, 8),
+        ("vec3" | "ivec3" | "uvec3", &[p]) => GlslField::basic(&name, p, 12, 16, 16),
+        ("vec4" | "ivec4" | "uvec4" | "dvec2", &[p]) => GlslField::basic(&name, p, 16, 16, 16),
+        ("dvec3", &[p]) => GlslField::basic(&name, p, 24, 32, 32),
+        ("dvec4", &[p]) => GlslField::basic(&name, p, 32, 32, 32),
+        ("unorm8x4" | "snorm8x4" | "unorm16x2" | "snorm16x2" | "unorm10_10_10_2", &[p]) => GlslField::vertex_only(&name, p),
+        ("mat2" | "mat3" | "mat4", &[p]) | ("mat2" | "mat3" | "mat4", &[p, _]) => GlslField {
+            kind: FieldKind::Matrix {
+                n: (name.as_bytes()[3] - b'0') as usize,
+                column_padding: args.get(1).copied().unwrap_or(0),
+            },
+            name,
+            padding: p,
+        },
+        // layout structs are CamelCase, GLSL types are not
+        (_, []) if name.starts_with(char::is_uppercase) => GlslField { name, kind: FieldKind::Struct, padding: 0 },
+        _ => return Err(unsupported()),
+    };
+    Ok(field)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub field: GlslField,
+    /// Offset in Rust struct
+    pub offset: usize,
+    /// Offset required by layout rules
+    pub expected: usize,
+    /// Index of nested struct member which end offsets are relative to, its size is unknown to macro
+    pub after: Option<usize>,
+    /// Column or element stride which can't be fixed by padding
+    pub error: Option<String>,
+}
+
+/// Place members according to `rules`. When member has to start later than Rust layout puts it,
+/// padding of previous member is grown. Members placed further than expected are left for caller to report
+pub fn place_fields(rules: LayoutRules, fields: Vec<GlslField>) -> Vec<Placement> {
+    let mut placed: Vec<Placement> = Vec::with_capacity(fields.len());
+    let mut glsl_end: usize = 0;
+    let mut rust_end: usize = 0;
+    let mut after = None;
+    for (i, mut field) in fields.into_iter().enumerate() {
+        let error = field.fit(rules).err();
+        let mut offset = rust_end.next_multiple_of(field.rust_align());
+        let expected = match rules {
+            LayoutRules::Std140 | LayoutRules::Std430 => glsl_end.next_multiple_of(field.align(rules)),
+            LayoutRules::Vertex => offset,
+        };
+        if let Some(prev) = placed.last_mut() && prev.after == after {
+            while offset < expected && prev.field.grow_padding() {
+                rust_end = prev.offset + prev.field.rust_size().unwrap();
+                offset = rust_end.next_multiple_of(field.rust_align());
+            }
+        }
+        let next_after = match (field.rust_size(), field.size(rules)) {
+            (Some(rust_size), Some(size)) => {
+                rust_end = offset + rust_size;
+                glsl_end = expected + size;
+                after
+            }
+            _ => {
+                rust_end = 0;
+                glsl_end = 0;
+                Some(i)
+            }
+        };
+        placed.push(Placement { field, offset, expected, after, error });
+        after = next_after;
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(s: &str) -> GlslField {
+        parse_glsl_type(&syn::parse_str(s).unwrap()).unwrap()
+    }
+
+    fn place(rules: LayoutRules, types: &[&str]) -> Vec<Placement> {
+        place_fields(rules, types.iter().map(|t| ty(t)).collect())
+    }
+
+    fn offsets(rules: LayoutRules, types: &[&str]) -> Vec<(usize, usize)> {
+        place(rules, types).iter().map(|p| (p.offset, p.expected)).collect()
+    }
+
+    #[test]
+    fn std140_offsets() {
+        assert_eq!(offsets(LayoutRules::Std140, &["ivec2<0>"]), [(0, 0)]);
+        assert_eq!(offsets(LayoutRules::Std140, &["float<0>", "vec3<0>", "vec2<0>", "vec4<0>"]),
+            [(0, 0), (16, 16), (32, 32), (48, 48)]);
+        // vec3 followed by scalar is packed into its last 4 bytes, not representable with vec3<P>
+        assert_eq!(offsets(LayoutRules::Std430, &["vec3<0>", "float<0>"]), [(0, 0), (16, 12)]);
+        // manual padding which breaks std140
+        assert_eq!(offsets(LayoutRules::Std140, &["vec2<2>", "float<0>"]), [(0, 0), (16, 8)]);
+        assert_eq!(offsets(LayoutRules::Std430, &["float<0>", "dvec3<0>", "uint<0>"]), [(0, 0), (32, 32), (64, 56)]);
+    }
+
+    #[test]
+    fn vertex_offsets() {
+        assert_eq!(offsets(LayoutRules::Vertex, &["ivec2<0>", "ivec2<0>", "float<0>", "vec4<0>"]),
+            [(0, 0), (8, 8), (16, 16), (32, 32)]);
+        assert!(place(LayoutRules::Vertex, &["bool<0>"])[0].error.is_some());
+        assert!(place(LayoutRules::Std140, &["unorm8x4<0>"])[0].error.is_some());
+    }
+
+    #[test]
+    fn matrices() {
+        // std140 columns are vec4 aligned, std430 mat2 columns are tightly packed
+        let placed = place(LayoutRules::Std140, &["mat2<0>", "float<0>", "mat3<0>"]);
+        assert_eq!(placed[0].field.kind, FieldKind::Matrix { n: 2, column_padding: 2 });
+        assert_eq!(placed.iter().map(|p| (p.offset, p.expected)).collect::<Vec<_>>(), [(0, 0), (32, 32), (48, 48)]);
+        assert_eq!(placed[2].field.kind, FieldKind::Matrix { n: 3, column_padding: 1 });
+
+        let placed = place(LayoutRules::Std430, &["mat2<0>", "float<0>"]);
+        assert_eq!(placed[0].field.kind, FieldKind::Matrix { n: 2, column_padding: 0 });
+        assert_eq!((placed[1].offset, placed[1].expected), (16, 16));
+        assert!(place(LayoutRules::Std430, &["mat2<0, 2>"])[0].error.is_some());
+    }
+
+    #[test]
+    fn arrays() {
+        // std140 rounds element stride up to 16, padding of element is inserted
+        let placed = place(LayoutRules::Std140, &["[float<0>; 4]", "vec2<0>"]);
+        let FieldKind::Array { elem, .. } = &placed[0].field.kind else { panic!() };
+        assert_eq!(elem.padding, 3);
+        assert_eq!((placed[1].offset, placed[1].expected), (64, 64));
+
+        let placed = place(LayoutRules::Std430, &["[float<0>; 4]", "vec2<0>"]);
+        let FieldKind::Array { elem, .. } = &placed[0].field.kind else { panic!() };
+        assert_eq!(elem.padding, 0);
+        assert_eq!((placed[1].offset, placed[1].expected), (16, 16));
+
+        assert!(place(LayoutRules::Std140, &["[vec2<3>; 2]"])[0].error.is_some());
+    }
+
+    #[test]
+    fn nested_structs() {
+        let placed = place(LayoutRules::Std140, &["float<0>", "Light", "float<0>", "vec4<0>"]);
+        assert_eq!(placed.iter().map(|p| (p.offset, p.expected, p.after)).collect::<Vec<_>>(),
+            [(0, 0, None), (16, 16, None), (0, 0, Some(1)), (16, 16, Some(1))]);
+        assert!(place(LayoutRules::Vertex, &["Light"])[0].error.is_some());
+    }
+
+    #[test]
+    fn padding_inserted() {
+        let mut wide = ty("float<0>");
+        wide.kind = FieldKind::Basic { size: 4, align: 16, rust_align: 4, vertex: true, buffer: true };
+        let placed = place_fields(LayoutRules::Std140, vec![ty("float<0>"), wide]);
+        assert_eq!(placed[0].field.padding, 3);
+        assert_eq!((placed[1].offset, placed[1].expected), (16, 16));
+    }
+
+    #[test]
+    fn padding_rewritten() {
+        let mut t: Type = syn::parse_str("[mat3<0>; 2]").unwrap();
+        let placed = place_fields(LayoutRules::Std140, vec![parse_glsl_type(&t).unwrap()]);
+        placed[0].field.apply(&mut t);
+        assert_eq!(quote::quote!(#t).to_string(), "[mat3 < 0 , 1 > ; 2]");
+    }
+}
@@ -1,4 +1,5 @@
 mod define_layout;
+mod layout_rules;
 
 use proc_macro::TokenStream;
 use quote::quote;
@@ -19,7 +20,7 @@ pub fn derive_collect_draw_state_updates(input: TokenStream) -> TokenStream {
                             self.#field_name.collect_updates()
                         }
                     }).collect();
-                    let first_update_call = update_calls.first();
+                    let first_update_call = update_calls.first().cloned().unwrap_or(quote! { std::iter::empty() });
                     let rest_update_calls = update_calls.iter().skip(1);
 
                     let clear_calls: Vec<_> = fields.named.iter().map(|f| {
@@ -46,7 +47,7 @@ pub fn derive_collect_draw_state_updates(input: TokenStream) -> TokenStream {
                             self.#index.collect_updates()
                         }
                     });
-                    let first_update_call = update_calls.next();
+                    let first_update_call = update_calls.next().unwrap_or(quote! { std::iter::empty() });
 
                     let clear_calls = fields.unnamed.iter().enumerate().map(|(i, _)| {
                         let index = syn::Index::from(i);
@@ -66,27 +67,27 @@ pub fn derive_collect_draw_state_updates(input: TokenStream) -> TokenStream {
                     )
                 }
                 Fields::Unit => (
-                    quote! { iter::empty() },
+                    quote! { std::iter::empty() },
                     quote! {},
                 ),
             }
         } else {
             (
-                quote! { iter::empty() },
+                quote! { std::iter::empty() },
                 quote! {},
             )
         };
 
     let expanded = quote! {
-        // impl CollectDrawStateUpdates for #name {
-        //     fn collect_updates(&self) -> impl Iterator<Item=GraphicsUpdateCmd> + '_ {
-        //         #updates
-        //     }
-        // 
-        //     fn clear_updates(&mut self) { 
-        //         #clear_updates
-        //     }
-        // }
+        impl CollectDrawStateUpdates for #name {
+            fn collect_updates(&self) -> impl Iterator<Item=GraphicsUpdateCmd<'_>> + '_ {
+                #updates
+            }
+
+            fn clear_updates(&mut self) {
+                #clear_updates
+            }
+        }
     };
 
     TokenStream::from(expanded)
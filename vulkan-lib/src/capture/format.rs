@@ -0,0 +1,502 @@
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use crate::resources::query_pool::QueryKind;
+use crate::resources::registry::ResourceKind;
+
+/// Bumped on incompatible changes of [`CaptureEvent`]
+pub const CAPTURE_VERSION: u32 = 3;
+
+/// Single line of capture file. Resources are referenced by raw vulkan handles,
+/// which are unique while the resource is alive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum CaptureEvent {
+    Header {
+        version: u32,
+        swapchain_format: i32,
+        swapchain_extent: (u32, u32),
+    },
+    CreateBuffer {
+        handle: u64,
+        usage: u32,
+        flags: u32,
+        size: u64,
+        name: Option<String>,
+    },
+    CreateStagingBuffer {
+        handle: u64,
+        size: u64,
+        name: Option<String>,
+    },
+    CreateImage {
+        handle: u64,
+        usage: u32,
+        flags: u32,
+        width: u32,
+        height: u32,
+        format: i32,
+        samples: u32,
+        name: Option<String>,
+    },
+    CreateSampler {
+        handle: u64,
+        info: CapturedSampler,
+        name: Option<String>,
+    },
+    CreateRenderPass {
+        handle: u64,
+        swapchain: CapturedAttachment,
+        swapchain_layout: i32,
+        depth: Option<(CapturedAttachment, i32)>,
+        color: Option<(CapturedAttachment, i32)>,
+        swapchain_format: i32,
+        name: Option<String>,
+    },
+    CreatePipeline {
+        handle: u64,
+        render_pass: u64,
+        triangle_list: bool,
+        /// location, binding, format, offset
+        attributes: Vec<(u32, u32, i32, u32)>,
+        /// binding, stride, input rate
+        vertex_bindings: Vec<(u32, u32, i32)>,
+        set_layouts: Vec<Vec<CapturedBindingDesc>>,
+        #[serde(with = "hex_bytes")]
+        vert_shader: Vec<u8>,
+        #[serde(with = "hex_bytes")]
+        frag_shader: Vec<u8>,
+        with_depth_test: bool,
+        name: Option<String>,
+    },
+    CreateQueryPool {
+        handle: u64,
+        kind: CapturedQueryKind,
+        count: u32,
+        name: Option<String>,
+    },
+    AllocateDescriptorSet {
+        handle: u64,
+        bindings: Vec<CapturedBindingDesc>,
+        name: Option<String>,
+    },
+    /// Host-written contents of staging source range of the command with index `command` in the next submission
+    StagingUpload {
+        command: usize,
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+    },
+    /// Resource was destroyed, its handle may be reused by later resources
+    Destroy {
+        kind: ResourceKind,
+        handle: u64,
+    },
+    Submit {
+        submission_num: usize,
+        commands: Vec<CapturedCommand>,
+    },
+    Present {
+        image_index: u32,
+    },
+    Resize {
+        width: u32,
+        height: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CapturedSampler {
+    pub mag_filter: i32,
+    pub min_filter: i32,
+    pub mipmap_mode: i32,
+    pub address_modes: [i32; 3],
+    pub mip_lod_bias: f32,
+    pub anisotropy_enable: bool,
+    pub max_anisotropy: f32,
+    pub compare_enable: bool,
+    pub compare_op: i32,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: i32,
+    pub unnormalized_coordinates: bool,
+}
+
+impl CapturedSampler {
+    pub(crate) fn new(info: &vk::SamplerCreateInfo) -> Self {
+        Self {
+            mag_filter: info.mag_filter.as_raw(),
+            min_filter: info.min_filter.as_raw(),
+            mipmap_mode: info.mipmap_mode.as_raw(),
+            address_modes: [info.address_mode_u.as_raw(), info.address_mode_v.as_raw(), info.address_mode_w.as_raw()],
+            mip_lod_bias: info.mip_lod_bias,
+            anisotropy_enable: info.anisotropy_enable != vk::FALSE,
+            max_anisotropy: info.max_anisotropy,
+            compare_enable: info.compare_enable != vk::FALSE,
+            compare_op: info.compare_op.as_raw(),
+            min_lod: info.min_lod,
+            max_lod: info.max_lod,
+            border_color: info.border_color.as_raw(),
+            unnormalized_coordinates: info.unnormalized_coordinates != vk::FALSE,
+        }
+    }
+
+    pub fn apply<'a>(&self, info: vk::SamplerCreateInfo<'a>) -> vk::SamplerCreateInfo<'a> {
+        info.mag_filter(vk::Filter::from_raw(self.mag_filter))
+            .min_filter(vk::Filter::from_raw(self.min_filter))
+            .mipmap_mode(vk::SamplerMipmapMode::from_raw(self.mipmap_mode))
+            .address_mode_u(vk::SamplerAddressMode::from_raw(self.address_modes[0]))
+            .address_mode_v(vk::SamplerAddressMode::from_raw(self.address_modes[1]))
+            .address_mode_w(vk::SamplerAddressMode::from_raw(self.address_modes[2]))
+            .mip_lod_bias(self.mip_lod_bias)
+            .anisotropy_enable(self.anisotropy_enable)
+            .max_anisotropy(self.max_anisotropy)
+            .compare_enable(self.compare_enable)
+            .compare_op(vk::CompareOp::from_raw(self.compare_op))
+            .min_lod(self.min_lod)
+            .max_lod(self.max_lod)
+            .border_color(vk::BorderColor::from_raw(self.border_color))
+            .unnormalized_coordinates(self.unnormalized_coordinates)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapturedQueryKind {
+    Occlusion { precise: bool },
+    /// Raw `VkQueryPipelineStatisticFlags`
+    PipelineStatistics(u32),
+}
+
+impl From<QueryKind> for CapturedQueryKind {
+    fn from(kind: QueryKind) -> Self {
+        match kind {
+            QueryKind::Occlusion { precise } => CapturedQueryKind::Occlusion { precise },
+            QueryKind::PipelineStatistics(statistics) => CapturedQueryKind::PipelineStatistics(statistics.as_raw()),
+        }
+    }
+}
+
+impl From<CapturedQueryKind> for QueryKind {
+    fn from(kind: CapturedQueryKind) -> Self {
+        match kind {
+            CapturedQueryKind::Occlusion { precise } => QueryKind::Occlusion { precise },
+            CapturedQueryKind::PipelineStatistics(statistics) => QueryKind::PipelineStatistics(vk::QueryPipelineStatisticFlags::from_raw(statistics)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CapturedAttachment {
+    pub format: i32,
+    pub samples: u32,
+    pub load_op: i32,
+    pub store_op: i32,
+    pub stencil_load_op: i32,
+    pub stencil_store_op: i32,
+    pub initial_layout: i32,
+    pub final_layout: i32,
+}
+
+impl From<&vk::AttachmentDescription> for CapturedAttachment {
+    fn from(d: &vk::AttachmentDescription) -> Self {
+        Self {
+            format: d.format.as_raw(),
+            samples: d.samples.as_raw(),
+            load_op: d.load_op.as_raw(),
+            store_op: d.store_op.as_raw(),
+            stencil_load_op: d.stencil_load_op.as_raw(),
+            stencil_store_op: d.stencil_store_op.as_raw(),
+            initial_layout: d.initial_layout.as_raw(),
+            final_layout: d.final_layout.as_raw(),
+        }
+    }
+}
+
+impl From<&CapturedAttachment> for vk::AttachmentDescription {
+    fn from(d: &CapturedAttachment) -> Self {
+        vk::AttachmentDescription::default()
+            .format(vk::Format::from_raw(d.format))
+            .samples(vk::SampleCountFlags::from_raw(d.samples))
+            .load_op(vk::AttachmentLoadOp::from_raw(d.load_op))
+            .store_op(vk::AttachmentStoreOp::from_raw(d.store_op))
+            .stencil_load_op(vk::AttachmentLoadOp::from_raw(d.stencil_load_op))
+            .stencil_store_op(vk::AttachmentStoreOp::from_raw(d.stencil_store_op))
+            .initial_layout(vk::ImageLayout::from_raw(d.initial_layout))
+            .final_layout(vk::ImageLayout::from_raw(d.final_layout))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedBindingDesc {
+    pub binding: u32,
+    pub descriptor_type: i32,
+    pub descriptor_count: u32,
+    pub stage_flags: u32,
+    pub binding_flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedBufferRange {
+    pub buffer: u64,
+    /// Source is a staging buffer, its contents come from preceding [`CaptureEvent::StagingUpload`]
+    pub staging: bool,
+    pub offset: u64,
+    pub size: u64,
+    /// Range covers the whole buffer
+    pub full: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedBufferImageCopy {
+    pub buffer_offset: u64,
+    pub buffer_row_length: u32,
+    pub buffer_image_height: u32,
+    pub aspect_mask: u32,
+    pub mip_level: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+    pub offset: (i32, i32, i32),
+    pub extent: (u32, u32, u32),
+}
+
+impl From<&vk::BufferImageCopy> for CapturedBufferImageCopy {
+    fn from(r: &vk::BufferImageCopy) -> Self {
+        Self {
+            buffer_offset: r.buffer_offset,
+            buffer_row_length: r.buffer_row_length,
+            buffer_image_height: r.buffer_image_height,
+            aspect_mask: r.image_subresource.aspect_mask.as_raw(),
+            mip_level: r.image_subresource.mip_level,
+            base_array_layer: r.image_subresource.base_array_layer,
+            layer_count: r.image_subresource.layer_count,
+            offset: (r.image_offset.x, r.image_offset.y, r.image_offset.z),
+            extent: (r.image_extent.width, r.image_extent.height, r.image_extent.depth),
+        }
+    }
+}
+
+impl From<&CapturedBufferImageCopy> for vk::BufferImageCopy {
+    fn from(r: &CapturedBufferImageCopy) -> Self {
+        vk::BufferImageCopy::default()
+            .buffer_offset(r.buffer_offset)
+            .buffer_row_length(r.buffer_row_length)
+            .buffer_image_height(r.buffer_image_height)
+            .image_subresource(vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::from_raw(r.aspect_mask))
+                .mip_level(r.mip_level)
+                .base_array_layer(r.base_array_layer)
+                .layer_count(r.layer_count))
+            .image_offset(vk::Offset3D { x: r.offset.0, y: r.offset.1, z: r.offset.2 })
+            .image_extent(vk::Extent3D { width: r.extent.0, height: r.extent.1, depth: r.extent.2 })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapturedDescriptor {
+    Buffer(u64),
+    DynamicBuffer {
+        buffer: u64,
+        range: u64,
+    },
+    Image(u64),
+    CombinedImageSampler {
+        image: u64,
+        sampler: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedSetBinding {
+    pub set: u32,
+    pub descriptor_set: u64,
+    pub dynamic_offsets: Vec<u32>,
+    /// binding, array element and bound resource at submission time
+    pub contents: Vec<(u32, u32, CapturedDescriptor)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CapturedCommand {
+    CopyBuffer {
+        src: CapturedBufferRange,
+        dst: u64,
+        /// src offset, dst offset, size
+        regions: Vec<(u64, u64, u64)>,
+    },
+    CopyBufferToImage {
+        src: CapturedBufferRange,
+        dst: u64,
+        regions: Vec<CapturedBufferImageCopy>,
+    },
+    FillBuffer {
+        buffer: u64,
+        offset: u64,
+        size: u64,
+        data: u32,
+    },
+    Barrier,
+    ImageLayoutTransition {
+        image: u64,
+        new_layout: i32,
+        image_aspect: u32,
+    },
+    /// Raw bits of `VkClearColorValue`
+    ClearColorImage {
+        image: u64,
+        clear_color: [u32; 4],
+        image_aspect: u32,
+    },
+    ClearDepthStencilImage {
+        image: u64,
+        depth_value: Option<f32>,
+        stencil_value: Option<u32>,
+    },
+    /// Raw bits of `VkClearValue`
+    RenderPassBegin {
+        render_pass: u64,
+        framebuffer_index: u32,
+        clear_values: Vec<[u32; 4]>,
+    },
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+        pipeline: u64,
+        pipeline_changed: bool,
+        /// Binding index and buffer
+        vertex_buffers: Vec<(u32, CapturedBufferRange)>,
+        descriptor_sets: Vec<CapturedSetBinding>,
+    },
+    RenderPassEnd {
+        render_pass: u64,
+        framebuffer_index: u32,
+    },
+    BeginLabel {
+        name: String,
+        color: [f32; 4],
+    },
+    EndLabel,
+    InsertLabel {
+        name: String,
+        color: [f32; 4],
+    },
+    TimestampBegin {
+        name: String,
+    },
+    TimestampEnd,
+    BeginQuery {
+        pool: u64,
+        query: u32,
+    },
+    EndQuery {
+        pool: u64,
+        query: u32,
+    },
+    CopyQueryResults {
+        pool: u64,
+        first_query: u32,
+        query_count: u32,
+        dst: u64,
+        dst_offset: u64,
+        stride: u64,
+    },
+}
+
+/// Binary blobs as lowercase hex strings, keeps capture a plain JSON lines file
+mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = String::with_capacity(bytes.len() * 2);
+        for b in bytes {
+            s.push(char::from_digit((b >> 4) as u32, 16).unwrap());
+            s.push(char::from_digit((b & 0xf) as u32, 16).unwrap());
+        }
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = <&str>::deserialize(deserializer)?;
+        if s.len() % 2 != 0 {
+            return Err(de::Error::custom("odd length of hex string"));
+        }
+        s.as_bytes().chunks(2).map(|pair| {
+            let hi = (pair[0] as char).to_digit(16);
+            let lo = (pair[1] as char).to_digit(16);
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => Ok((hi << 4 | lo) as u8),
+                _ => Err(de::Error::custom("invalid hex digit")),
+            }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_roundtrip() {
+        let events = vec![
+            CaptureEvent::StagingUpload { command: 0, data: vec![0x00, 0xab, 0xff, 0x10] },
+            CaptureEvent::Submit {
+                submission_num: 3,
+                commands: vec![
+                    CapturedCommand::CopyBuffer {
+                        src: CapturedBufferRange { buffer: 7, staging: true, offset: 16, size: 4, full: false },
+                        dst: 9,
+                        regions: vec![(16, 0, 4)],
+                    },
+                    CapturedCommand::Barrier,
+                    CapturedCommand::CopyBufferToImage {
+                        src: CapturedBufferRange { buffer: 8, staging: false, offset: 0, size: 256, full: true },
+                        dst: 10,
+                        regions: vec![(&vk::BufferImageCopy::default()
+                            .buffer_offset(64)
+                            .image_subresource(vk::ImageSubresourceLayers::default()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1))
+                            .image_offset(vk::Offset3D { x: 4, y: 2, z: 0 })
+                            .image_extent(vk::Extent3D { width: 8, height: 6, depth: 1 })).into()],
+                    },
+                    CapturedCommand::BeginLabel { name: "Main pass".to_string(), color: [0.2, 0.6, 1.0, 1.0] },
+                ],
+            },
+            CaptureEvent::Destroy { kind: ResourceKind::Image, handle: 10 },
+        ];
+
+        for event in events {
+            let line = serde_json::to_string(&event).unwrap();
+            assert!(!line.contains('\n'));
+            let parsed: CaptureEvent = serde_json::from_str(&line).unwrap();
+            assert_eq!(parsed, event);
+        }
+    }
+
+    #[test]
+    fn test_hex_bytes() {
+        let line = serde_json::to_string(&CaptureEvent::StagingUpload { command: 1, data: vec![0x01, 0xfe] }).unwrap();
+        assert!(line.contains("\"01fe\""));
+
+        let invalid = r#"{"event":"StagingUpload","command":1,"data":"0g"}"#;
+        assert!(serde_json::from_str::<CaptureEvent>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_buffer_image_copy_roundtrip() {
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(128)
+            .buffer_row_length(32)
+            .buffer_image_height(16)
+            .image_subresource(vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                .mip_level(2)
+                .base_array_layer(1)
+                .layer_count(3))
+            .image_offset(vk::Offset3D { x: 1, y: 2, z: 3 })
+            .image_extent(vk::Extent3D { width: 4, height: 5, depth: 6 });
+        let captured = CapturedBufferImageCopy::from(&region);
+        let restored = vk::BufferImageCopy::from(&captured);
+        assert_eq!(CapturedBufferImageCopy::from(&restored), captured);
+        assert_eq!(captured.offset, (1, 2, 3));
+        assert_eq!(captured.extent, (4, 5, 6));
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use ash::vk::{self, Handle};
+use log::{info, warn};
+use crate::queue::recording::{AnyBufferRange, BufferRange, DeviceCommand, DrawCommand};
+use crate::resources::descriptor_set::{BoundResource, DescriptorSetResource};
+use crate::resources::pipeline::{GraphicsPipelineDesc, GraphicsPipelineResource, VertexAssembly};
+use crate::resources::render_pass::RenderPassResource;
+use crate::shaders::DescriptorSetLayoutBindingDesc;
+
+mod format;
+mod replay;
+
+pub use format::*;
+pub use replay::{Replay, ReplayPlayer};
+
+/// Path of capture file, enables capture when set
+pub const CAPTURE_ENV: &str = "VULKAN_CAPTURE";
+
+/// Writes capture events as JSON lines
+pub(crate) struct CaptureWriter {
+    file: Option<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        info!("Capturing device commands to {}", path.display());
+        Ok(Self {
+            file: Some(BufWriter::new(file)),
+        })
+    }
+
+    /// Capture is stopped on the first IO error
+    pub fn write(&mut self, event: &CaptureEvent) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let res = serde_json::to_writer(&mut *file, event)
+            .map_err(std::io::Error::from)
+            .and_then(|_| file.write_all(b"\n"));
+        if let Err(e) = res {
+            warn!("Failed to write capture, capture stopped: {:?}", e);
+            self.file = None;
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(file) = &mut self.file && let Err(e) = file.flush() {
+            warn!("Failed to flush capture: {:?}", e);
+        }
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+pub(crate) fn capture_bindings(bindings: &[DescriptorSetLayoutBindingDesc]) -> Vec<CapturedBindingDesc> {
+    bindings.iter().map(|b| CapturedBindingDesc {
+        binding: b.binding,
+        descriptor_type: b.descriptor_type.as_raw(),
+        descriptor_count: b.descriptor_count,
+        stage_flags: b.stage_flags.as_raw(),
+        binding_flags: b.binding_flags.as_raw(),
+    }).collect()
+}
+
+pub(crate) fn restore_bindings(bindings: &[CapturedBindingDesc]) -> Vec<DescriptorSetLayoutBindingDesc> {
+    bindings.iter().map(|b| DescriptorSetLayoutBindingDesc {
+        binding: b.binding,
+        descriptor_type: vk::DescriptorType::from_raw(b.descriptor_type),
+        descriptor_count: b.descriptor_count,
+        stage_flags: vk::ShaderStageFlags::from_raw(b.stage_flags),
+        binding_flags: vk::DescriptorBindingFlags::from_raw(b.binding_flags),
+    }).collect()
+}
+
+pub(crate) fn capture_render_pass(render_pass: &RenderPassResource, swapchain_format: vk::Format, name: Option<&str>) -> CaptureEvent {
+    let desc = render_pass.attachments_desc();
+    CaptureEvent::CreateRenderPass {
+        handle: render_pass.render_pass.as_raw(),
+        swapchain: (&desc.get_swapchain_desc()).into(),
+        swapchain_layout: desc.swapchain_layout().as_raw(),
+        depth: desc.get_depth_attachment_desc().map(|(d, l)| ((&d).into(), l.as_raw())),
+        color: desc.get_color_attachment_desc().map(|(d, l)| ((&d).into(), l.as_raw())),
+        swapchain_format: swapchain_format.as_raw(),
+        name: name.map(str::to_string),
+    }
+}
+
+pub(crate) fn capture_pipeline(pipeline: &GraphicsPipelineResource, render_pass: vk::RenderPass, desc: &GraphicsPipelineDesc, with_depth_test: bool, name: Option<&str>) -> CaptureEvent {
+    CaptureEvent::CreatePipeline {
+        handle: pipeline.pipeline.as_raw(),
+        render_pass: render_pass.as_raw(),
+        triangle_list: matches!(desc.vertex_assembly, VertexAssembly::TriangleList),
+        attributes: desc.attributes.attribute_descriptions().iter()
+            .map(|a| (a.location, a.binding, a.format.as_raw(), a.offset))
+            .collect(),
+        vertex_bindings: desc.attributes.binding_descriptions().iter()
+            .map(|b| (b.binding, b.stride, b.input_rate.as_raw()))
+            .collect(),
+        set_layouts: desc.bindings.iter().map(|b| capture_bindings(b)).collect(),
+        vert_shader: desc.vert_shader.clone(),
+        frag_shader: desc.frag_shader.clone(),
+        with_depth_test,
+        name: name.map(str::to_string),
+    }
+}
+
+fn capture_any_range(range: &AnyBufferRange) -> CapturedBufferRange {
+    CapturedBufferRange {
+        buffer: range.buffer().as_raw(),
+        staging: range.has_host_writes(),
+        offset: range.offset(),
+        size: range.size(),
+        full: range.size() == range.buffer_size(),
+    }
+}
+
+fn capture_range(range: &BufferRange) -> CapturedBufferRange {
+    let (offset, size) = range.custom_range.as_ref()
+        .map(|r| (r.start as u64, (r.end - r.start) as u64))
+        .unwrap_or((0, range.buffer.size() as u64));
+    CapturedBufferRange {
+        buffer: range.buffer.buffer.as_raw(),
+        staging: false,
+        offset,
+        size,
+        full: range.custom_range.is_none(),
+    }
+}
+
+fn capture_set_contents(descriptor_set: &DescriptorSetResource) -> Vec<(u32, u32, CapturedDescriptor)> {
+    let bindings = descriptor_set.bindings().lock().unwrap();
+    let mut contents = Vec::new();
+    for binding in bindings.iter() {
+        for (element, resource) in binding.resources.iter().enumerate() {
+            let Some(resource) = resource else {
+                continue;
+            };
+            let descriptor = match resource {
+                BoundResource::Buffer(b) => CapturedDescriptor::Buffer(b.buffer.as_raw()),
+                BoundResource::DynamicBuffer { buffer, range } => CapturedDescriptor::DynamicBuffer {
+                    buffer: buffer.buffer.as_raw(),
+                    range: *range,
+                },
+                BoundResource::Image(i) => CapturedDescriptor::Image(i.image.as_raw()),
+                BoundResource::CombinedImageSampler { image, sampler } => CapturedDescriptor::CombinedImageSampler {
+                    image: image.image.as_raw(),
+                    sampler: sampler.sampler.as_raw(),
+                },
+            };
+            contents.push((binding.binding_index, element as u32, descriptor));
+        }
+    }
+    contents
+}
+
+/// Staging contents read by commands, followed by the submission itself
+pub(crate) fn capture_submission(commands: &[DeviceCommand], submission_num: usize) -> Vec<CaptureEvent> {
+    let mut events = Vec::new();
+    let mut captured = Vec::with_capacity(commands.len());
+
+    for (i, cmd) in commands.iter().enumerate() {
+        if let DeviceCommand::CopyBuffer { src: AnyBufferRange::Staging(s), .. }
+            | DeviceCommand::CopyBufferToImage { src: AnyBufferRange::Staging(s), .. } = cmd {
+            events.push(CaptureEvent::StagingUpload {
+                command: i,
+                data: s.buffer.read(s.range.clone()),
+            });
+        }
+
+        captured.push(match cmd {
+            DeviceCommand::CopyBuffer { src, dst, regions } => CapturedCommand::CopyBuffer {
+                src: capture_any_range(src),
+                dst: dst.buffer.as_raw(),
+                regions: regions.iter().map(|r| (r.src_offset, r.dst_offset, r.size)).collect(),
+            },
+            DeviceCommand::CopyBufferToImage { src, dst, regions } => CapturedCommand::CopyBufferToImage {
+                src: capture_any_range(src),
+                dst: dst.image.as_raw(),
+                regions: regions.iter().map(CapturedBufferImageCopy::from).collect(),
+            },
+            DeviceCommand::FillBuffer { buffer, offset, size, data } => CapturedCommand::FillBuffer {
+                buffer: buffer.buffer.as_raw(),
+                offset: *offset,
+                size: *size,
+                data: *data,
+            },
+            DeviceCommand::Barrier => CapturedCommand::Barrier,
+            DeviceCommand::ImageLayoutTransition { image, new_layout, image_aspect } => CapturedCommand::ImageLayoutTransition {
+                image: image.image.as_raw(),
+                new_layout: new_layout.as_raw(),
+                image_aspect: image_aspect.as_raw(),
+            },
+            DeviceCommand::ClearColorImage { image, clear_color, image_aspect } => CapturedCommand::ClearColorImage {
+                image: image.image.as_raw(),
+                clear_color: unsafe { clear_color.uint32 },
+                image_aspect: image_aspect.as_raw(),
+            },
+            DeviceCommand::ClearDepthStencilImage { image, depth_value, stencil_value } => CapturedCommand::ClearDepthStencilImage {
+                image: image.image.as_raw(),
+                depth_value: *depth_value,
+                stencil_value: *stencil_value,
+            },
+            DeviceCommand::RenderPassBegin { render_pass, framebuffer_index, clear_values } => CapturedCommand::RenderPassBegin {
+                render_pass: render_pass.render_pass.as_raw(),
+                framebuffer_index: *framebuffer_index,
+                clear_values: clear_values.iter().map(|v| unsafe { v.color.uint32 }).collect(),
+            },
+            DeviceCommand::DrawCommand(DrawCommand::Draw {
+                vertex_count, instance_count, first_vertex, first_instance,
+                new_vertex_buffers, pipeline, pipeline_changed, new_descriptor_set_bindings,
+            }) => CapturedCommand::Draw {
+                vertex_count: *vertex_count,
+                instance_count: *instance_count,
+                first_vertex: *first_vertex,
+                first_instance: *first_instance,
+                pipeline: pipeline.pipeline.as_raw(),
+                pipeline_changed: *pipeline_changed,
+                vertex_buffers: new_vertex_buffers.iter().map(|(binding, b)| (*binding, capture_range(b))).collect(),
+                descriptor_sets: new_descriptor_set_bindings.iter().map(|(set, ds, offsets)| CapturedSetBinding {
+                    set: *set,
+                    descriptor_set: ds.descriptor_set.as_raw(),
+                    dynamic_offsets: offsets.to_vec(),
+                    contents: capture_set_contents(ds),
+                }).collect(),
+            },
+            DeviceCommand::RenderPassEnd { render_pass, framebuffer_index } => CapturedCommand::RenderPassEnd {
+                render_pass: render_pass.render_pass.as_raw(),
+                framebuffer_index: *framebuffer_index,
+            },
+            DeviceCommand::BeginLabel { name, color } => CapturedCommand::BeginLabel {
+                name: name.to_string_lossy().into_owned(),
+                color: *color,
+            },
+            DeviceCommand::EndLabel => CapturedCommand::EndLabel,
+            DeviceCommand::InsertLabel { name, color } => CapturedCommand::InsertLabel {
+                name: name.to_string_lossy().into_owned(),
+                color: *color,
+            },
+            DeviceCommand::TimestampBegin { name } => CapturedCommand::TimestampBegin {
+                name: name.to_string(),
+            },
+            DeviceCommand::TimestampEnd => CapturedCommand::TimestampEnd,
+            DeviceCommand::BeginQuery { pool, query } => CapturedCommand::BeginQuery {
+                pool: pool.query_pool.as_raw(),
+                query: *query,
+            },
+            DeviceCommand::EndQuery { pool, query } => CapturedCommand::EndQuery {
+                pool: pool.query_pool.as_raw(),
+                query: *query,
+            },
+            DeviceCommand::CopyQueryResults { pool, first_query, query_count, dst, dst_offset, stride } => CapturedCommand::CopyQueryResults {
+                pool: pool.query_pool.as_raw(),
+                first_query: *first_query,
+                query_count: *query_count,
+                dst: dst.buffer.as_raw(),
+                dst_offset: *dst_offset,
+                stride: *stride,
+            },
+        });
+    }
+
+    events.push(CaptureEvent::Submit {
+        submission_num,
+        commands: captured,
+    });
+    events
+}
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::{bail, Context};
+use ash::vk::{self, BufferCopy, BufferCreateFlags, BufferImageCopy, BufferUsageFlags, ClearColorValue, ClearValue, Format, ImageAspectFlags, ImageCreateFlags, ImageLayout, ImageUsageFlags, PipelineStageFlags, SampleCountFlags};
+use log::{error, info, warn};
+use smallvec::SmallVec;
+use crate::capture::{restore_bindings, CaptureEvent, CapturedBufferRange, CapturedCommand, CapturedDescriptor, CapturedSetBinding, CAPTURE_VERSION};
+use crate::queue::GraphicsQueue;
+use crate::queue::recording::{AnyBufferRange, BufferRange, RecordContext, RenderPassContext};
+use crate::queue::semaphores::WaitSemaphoreRef;
+use crate::resources::buffer::BufferResource;
+use crate::resources::descriptor_set::DescriptorSetResource;
+use crate::resources::image::ImageResource;
+use crate::resources::pipeline::{GraphicsPipelineDesc, GraphicsPipelineResource, VertexAssembly, VertexInputDesc};
+use crate::resources::query_pool::{QueryKind, QueryPoolResource};
+use crate::resources::registry::ResourceKind;
+use crate::resources::render_pass::{AttachmentsDescription, RenderPassResource};
+use crate::resources::sampler::SamplerResource;
+use crate::resources::staging_buffer::StagingBufferRange;
+use crate::resources::VulkanAllocator;
+use crate::shaders::DescriptorSetLayoutBindingDesc;
+
+/// Parsed capture file
+pub struct Replay {
+    events: Vec<CaptureEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open capture {}", path.display()))?;
+        let mut events = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = serde_json::from_str(&line).with_context(|| format!("Invalid capture event at line {}", i + 1))?;
+            events.push(event);
+        }
+        Self::from_events(events)
+    }
+
+    pub fn from_events(events: Vec<CaptureEvent>) -> anyhow::Result<Self> {
+        match events.first() {
+            Some(CaptureEvent::Header { version, .. }) if *version == CAPTURE_VERSION => Ok(Self { events }),
+            Some(CaptureEvent::Header { version, .. }) => bail!("Unsupported capture version {}, expected {}", version, CAPTURE_VERSION),
+            _ => bail!("Capture must start with header"),
+        }
+    }
+
+    pub fn events(&self) -> &[CaptureEvent] {
+        &self.events
+    }
+
+    /// Swapchain extent at the start of capture
+    pub fn swapchain_extent(&self) -> (u32, u32) {
+        match &self.events[0] {
+            CaptureEvent::Header { swapchain_extent, .. } => *swapchain_extent,
+            _ => unreachable!("checked in from_events"),
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, CaptureEvent::Present { .. })).count()
+    }
+
+    pub fn into_player(self) -> ReplayPlayer {
+        ReplayPlayer {
+            events: self.events.into(),
+            cursor: 0,
+            buffers: HashMap::new(),
+            images: HashMap::new(),
+            samplers: HashMap::new(),
+            render_passes: HashMap::new(),
+            pipelines: HashMap::new(),
+            descriptor_sets: HashMap::new(),
+            query_pools: HashMap::new(),
+            uploads: HashMap::new(),
+            pending_present: None,
+            scope_names: HashMap::new(),
+        }
+    }
+}
+
+enum Op {
+    CopyBuffer(AnyBufferRange, Arc<BufferResource>, SmallVec<[BufferCopy; 1]>),
+    CopyBufferToImage(AnyBufferRange, Arc<ImageResource>, SmallVec<[BufferImageCopy; 1]>),
+    FillBuffer(BufferRange, u32),
+    Barrier,
+    Transition(Arc<ImageResource>, ImageLayout, ImageAspectFlags),
+    ClearColor(Arc<ImageResource>, ClearColorValue, ImageAspectFlags),
+    ClearDepthStencil(Arc<ImageResource>, Option<f32>, Option<u32>),
+    RenderPass(Arc<RenderPassResource>, SmallVec<[ClearValue; 3]>, Vec<Op>),
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+        pipeline: Option<Arc<GraphicsPipelineResource>>,
+        vertex_buffers: Vec<(u32, BufferRange)>,
+        descriptor_sets: Vec<(u32, Arc<DescriptorSetResource>, Vec<u32>)>,
+    },
+    BeginLabel(String, [f32; 4]),
+    EndLabel,
+    InsertLabel(String, [f32; 4]),
+    TimestampScope(&'static str, Vec<Op>),
+    BeginQuery(Arc<QueryPoolResource>, u32),
+    EndQuery(Arc<QueryPoolResource>, u32),
+    CopyQueryResults(Arc<QueryPoolResource>, Range<u32>, BufferRange),
+}
+
+/// Re-creates captured resources and re-executes submissions through the public API.
+/// Requires a window: `GraphicsQueue` can only be created for a surface, headless replay is not implemented.
+pub struct ReplayPlayer {
+    events: Arc<[CaptureEvent]>,
+    cursor: usize,
+    buffers: HashMap<u64, Arc<BufferResource>>,
+    images: HashMap<u64, Arc<ImageResource>>,
+    samplers: HashMap<u64, Arc<SamplerResource>>,
+    render_passes: HashMap<u64, Arc<RenderPassResource>>,
+    pipelines: HashMap<u64, Arc<GraphicsPipelineResource>>,
+    descriptor_sets: HashMap<u64, Arc<DescriptorSetResource>>,
+    query_pools: HashMap<u64, Arc<QueryPoolResource>>,
+    /// Staging contents by command index, consumed by the next submission
+    uploads: HashMap<usize, StagingBufferRange>,
+    /// Acquired image and semaphore signaled by its render submission
+    pending_present: Option<(u32, WaitSemaphoreRef)>,
+    /// Timestamp scope names are leaked once per distinct name
+    scope_names: HashMap<String, &'static str>,
+}
+
+impl ReplayPlayer {
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.events.len()
+    }
+
+    /// Replay events up to and including the next present. Returns false if capture is over
+    pub fn play_frame(&mut self, queue: &mut GraphicsQueue, allocator: &mut VulkanAllocator) -> anyhow::Result<bool> {
+        let events = self.events.clone();
+        while let Some(event) = events.get(self.cursor) {
+            self.cursor += 1;
+            match event {
+                CaptureEvent::Header { .. } => {}
+                CaptureEvent::Resize { width, height } => {
+                    info!("Capture resized swapchain to {}x{}", width, height);
+                    let _ = queue.recreate_resize((*width, *height));
+                }
+                CaptureEvent::StagingUpload { command, data } => {
+                    if data.is_empty() {
+                        continue;
+                    }
+                    let staging = allocator.new_staging_buffer(data.len() as u64, Some("replay upload"));
+                    let mut range = staging.try_freeze(data.len()).context("Fresh staging buffer must fit upload")?;
+                    range.update(|dst| dst.copy_from_slice(data));
+                    self.uploads.insert(*command, range);
+                }
+                CaptureEvent::Destroy { kind, handle } => self.destroy(*kind, *handle),
+                CaptureEvent::Submit { commands, .. } => self.submit(commands, queue)?,
+                CaptureEvent::Present { .. } => {
+                    let Some((image_index, wait_ref)) = self.pending_present.take() else {
+                        warn!("Present without rendering to swapchain image, skipped");
+                        continue;
+                    };
+                    queue.queue_present(image_index, wait_ref)?;
+                    queue.wait_prev_submission(0);
+                    allocator.destroy_old_resources();
+                    return Ok(true);
+                }
+                event => self.create(event, allocator),
+            }
+        }
+        Ok(false)
+    }
+
+    fn create(&mut self, event: &CaptureEvent, allocator: &mut VulkanAllocator) {
+        match event {
+            CaptureEvent::CreateBuffer { handle, usage, flags, size, name } => {
+                let buffer = allocator.new_buffer(BufferUsageFlags::from_raw(*usage), BufferCreateFlags::from_raw(*flags), *size, name.as_deref());
+                self.buffers.insert(*handle, buffer);
+            }
+            CaptureEvent::CreateImage { handle, usage, flags, width, height, format, samples, name } => {
+                let image = allocator.new_image(ImageUsageFlags::from_raw(*usage), ImageCreateFlags::from_raw(*flags),
+                    *width, *height, Format::from_raw(*format), SampleCountFlags::from_raw(*samples), name.as_deref());
+                self.images.insert(*handle, image);
+            }
+            CaptureEvent::CreateSampler { handle, info, name } => {
+                let sampler = allocator.new_sampler(|i| info.apply(i), name.as_deref());
+                self.samplers.insert(*handle, sampler);
+            }
+            CaptureEvent::CreateRenderPass { handle, swapchain, swapchain_layout, depth, color, swapchain_format, name } => {
+                let mut desc = AttachmentsDescription::new(swapchain.into(), ImageLayout::from_raw(*swapchain_layout));
+                if let Some((depth, layout)) = depth {
+                    desc = desc.with_depth_attachment(depth.into(), ImageLayout::from_raw(*layout));
+                }
+                if let Some((color, layout)) = color {
+                    desc = desc.with_color_attachment(color.into(), ImageLayout::from_raw(*layout));
+                }
+                let render_pass = allocator.new_render_pass(desc, Format::from_raw(*swapchain_format), name.as_deref());
+                self.render_passes.insert(*handle, render_pass);
+            }
+            CaptureEvent::CreatePipeline {
+                handle, render_pass, triangle_list, attributes, vertex_bindings,
+                set_layouts, vert_shader, frag_shader, with_depth_test, name
+            } => {
+                let Some(render_pass) = self.render_passes.get(render_pass).cloned() else {
+                    warn!("Pipeline {:?} references unknown render pass, skipped", name);
+                    return;
+                };
+                let attributes = attributes.iter().map(|&(location, binding, format, offset)| vk::VertexInputAttributeDescription::default()
+                    .location(location)
+                    .binding(binding)
+                    .format(Format::from_raw(format))
+                    .offset(offset)).collect();
+                let vertex_bindings = vertex_bindings.iter().map(|&(binding, stride, rate)| vk::VertexInputBindingDescription::default()
+                    .binding(binding)
+                    .stride(stride)
+                    .input_rate(vk::VertexInputRate::from_raw(rate))).collect();
+                let desc = GraphicsPipelineDesc {
+                    vertex_assembly: if *triangle_list { VertexAssembly::TriangleList } else { VertexAssembly::TriangleStrip },
+                    attributes: VertexInputDesc::from_descriptions(attributes, vertex_bindings),
+                    bindings: set_layouts.iter().map(|b| leak_bindings(restore_bindings(b))).collect(),
+                    vert_shader: vert_shader.clone(),
+                    frag_shader: frag_shader.clone(),
+                };
+                let pipeline = allocator.new_pipeline(render_pass, desc, *with_depth_test, name.as_deref());
+                self.pipelines.insert(*handle, pipeline);
+            }
+            CaptureEvent::AllocateDescriptorSet { handle, bindings, name } => {
+                let set = allocator.allocate_descriptor_set(leak_bindings(restore_bindings(bindings)), name.as_deref());
+                self.descriptor_sets.insert(*handle, set);
+            }
+            CaptureEvent::CreateQueryPool { handle, kind, count, name } => {
+                let pool = match QueryKind::from(*kind) {
+                    QueryKind::Occlusion { precise } => allocator.new_occlusion_query_pool(*count, precise, name.as_deref()),
+                    QueryKind::PipelineStatistics(statistics) => allocator.new_pipeline_statistics_query_pool(*count, statistics, name.as_deref()),
+                };
+                match pool {
+                    Ok(pool) => { self.query_pools.insert(*handle, pool); }
+                    Err(e) => warn!("Captured query pool {:?} skipped: {:#}", name, e),
+                }
+            }
+            // uploads of the next submission are captured separately
+            CaptureEvent::CreateStagingBuffer { .. } => {}
+            _ => unreachable!("handled in play_frame"),
+        }
+    }
+
+    /// Replay copy is freed once it's no longer used by submitted commands
+    fn destroy(&mut self, kind: ResourceKind, handle: u64) {
+        match kind {
+            ResourceKind::Buffer => { self.buffers.remove(&handle); }
+            ResourceKind::Image => { self.images.remove(&handle); }
+            ResourceKind::Sampler => { self.samplers.remove(&handle); }
+            ResourceKind::Pipeline => { self.pipelines.remove(&handle); }
+            ResourceKind::RenderPass => { self.render_passes.remove(&handle); }
+            ResourceKind::DescriptorSet => { self.descriptor_sets.remove(&handle); }
+            ResourceKind::QueryPool => { self.query_pools.remove(&handle); }
+            // uploads are replayed from fresh staging buffers
+            ResourceKind::StagingBuffer => {}
+        }
+    }
+
+    fn submit(&mut self, commands: &[CapturedCommand], queue: &mut GraphicsQueue) -> anyhow::Result<()> {
+        for command in commands {
+            if let CapturedCommand::Draw { descriptor_sets, .. } = command {
+                for set_binding in descriptor_sets {
+                    self.apply_set_contents(set_binding);
+                }
+            }
+        }
+
+        let uses_swapchain = commands.iter().any(|c| matches!(c, CapturedCommand::RenderPassBegin { .. }));
+        let mut acquired = None;
+        if uses_swapchain && self.pending_present.is_none() {
+            let (image_index, wait_ref, _) = queue.acquire_next_image()?;
+            acquired = Some((image_index, wait_ref));
+        }
+        let framebuffer_index = acquired.as_ref().map(|(i, _)| *i)
+            .or(self.pending_present.as_ref().map(|(i, _)| *i))
+            .unwrap_or(0);
+
+        let ops = self.resolve(&mut commands.iter().enumerate(), false);
+        if !self.uploads.is_empty() {
+            warn!("{} staging uploads were not consumed by submission", self.uploads.len());
+            self.uploads.clear();
+        }
+        match acquired {
+            Some((image_index, wait_ref)) => {
+                let wait_ref = wait_ref.with_stages(PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+                let (signal_ref, _) = queue.record_device_commands_signal(Some(wait_ref), |ctx| record(ctx, ops, framebuffer_index));
+                self.pending_present = Some((image_index, signal_ref));
+            }
+            None => {
+                queue.record_device_commands(None, |ctx| record(ctx, ops, framebuffer_index));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_set_contents(&self, set_binding: &CapturedSetBinding) {
+        let Some(set) = self.descriptor_sets.get(&set_binding.descriptor_set) else {
+            return;
+        };
+        for &(binding, element, descriptor) in &set_binding.contents {
+            let res = match descriptor {
+                CapturedDescriptor::Buffer(buffer) => self.buffers.get(&buffer)
+                    .and_then(|b| set.try_bind_buffer_at(binding, element, b.clone())),
+                CapturedDescriptor::DynamicBuffer { buffer, range } => self.buffers.get(&buffer)
+                    .and_then(|b| set.try_bind_dynamic_buffer_at(binding, element, b.clone(), range)),
+                CapturedDescriptor::Image(image) => self.images.get(&image)
+                    .and_then(|i| set.try_bind_image_at(binding, element, i.clone())),
+                CapturedDescriptor::CombinedImageSampler { image, sampler } => self.images.get(&image)
+                    .zip(self.samplers.get(&sampler))
+                    .and_then(|(i, s)| set.try_bind_image_sampler_at(binding, element, i.clone(), s.clone())),
+            };
+            if res.is_none() {
+                warn!("Failed to restore binding {}[{}] of descriptor set {:x}", binding, element, set_binding.descriptor_set);
+            }
+        }
+    }
+
+    /// Source range of the command with index `command`
+    fn buffer_range(&mut self, range: &CapturedBufferRange, command: usize) -> Option<AnyBufferRange> {
+        if range.staging {
+            let upload = self.uploads.remove(&command);
+            if upload.is_none() {
+                warn!("Missing staging upload for command {}", command);
+            }
+            return upload.map(AnyBufferRange::from);
+        }
+        self.device_range(range).map(AnyBufferRange::from)
+    }
+
+    fn device_range(&self, range: &CapturedBufferRange) -> Option<BufferRange> {
+        let buffer = self.buffers.get(&range.buffer)?;
+        Some(if range.full {
+            buffer.full()
+        }
+        else {
+            buffer.range(range.offset as usize..(range.offset + range.size) as usize)
+        })
+    }
+
+    /// Commands referencing resources missing from capture (e.g. swapchain images) are skipped.
+    /// Nested call returns on the end of render pass or timestamp scope
+    fn resolve<'c>(&mut self, commands: &mut impl Iterator<Item = (usize, &'c CapturedCommand)>, nested: bool) -> Vec<Op> {
+        let mut ops = Vec::new();
+        while let Some((index, command)) = commands.next() {
+            let op = match command {
+                CapturedCommand::CopyBuffer { src, dst, regions } => {
+                    let dst = self.buffers.get(dst).cloned();
+                    self.buffer_range(src, index).zip(dst).map(|(replay_src, dst)| {
+                        let regions = regions.iter().map(|&(src_offset, dst_offset, size)| BufferCopy {
+                            src_offset: rebase_offset(src_offset, src, &replay_src),
+                            dst_offset,
+                            size,
+                        }).collect();
+                        Op::CopyBuffer(replay_src, dst, regions)
+                    })
+                }
+                CapturedCommand::CopyBufferToImage { src, dst, regions } => {
+                    let dst = self.images.get(dst).cloned();
+                    self.buffer_range(src, index).zip(dst).map(|(replay_src, dst)| {
+                        let regions = regions.iter().map(|r| {
+                            let mut region = BufferImageCopy::from(r);
+                            region.buffer_offset = rebase_offset(r.buffer_offset, src, &replay_src);
+                            region
+                        }).collect();
+                        Op::CopyBufferToImage(replay_src, dst, regions)
+                    })
+                }
+                CapturedCommand::FillBuffer { buffer, offset, size, data } => {
+                    let range = CapturedBufferRange { buffer: *buffer, staging: false, offset: *offset, size: *size, full: false };
+                    self.device_range(&range).map(|r| Op::FillBuffer(r, *data))
+                }
+                CapturedCommand::Barrier => Some(Op::Barrier),
+                CapturedCommand::ImageLayoutTransition { image, new_layout, image_aspect } => self.images.get(image)
+                    .map(|i| Op::Transition(i.clone(), ImageLayout::from_raw(*new_layout), ImageAspectFlags::from_raw(*image_aspect))),
+                CapturedCommand::ClearColorImage { image, clear_color, image_aspect } => self.images.get(image)
+                    .map(|i| Op::ClearColor(i.clone(), ClearColorValue { uint32: *clear_color }, ImageAspectFlags::from_raw(*image_aspect))),
+                CapturedCommand::ClearDepthStencilImage { image, depth_value, stencil_value } => self.images.get(image)
+                    .map(|i| Op::ClearDepthStencil(i.clone(), *depth_value, *stencil_value)),
+                CapturedCommand::RenderPassBegin { render_pass, clear_values, .. } => {
+                    let inner = self.resolve(commands, true);
+                    let clear_values = clear_values.iter().map(|v| ClearValue { color: ClearColorValue { uint32: *v } }).collect();
+                    self.render_passes.get(render_pass).map(|rp| Op::RenderPass(rp.clone(), clear_values, inner))
+                }
+                CapturedCommand::RenderPassEnd { .. } | CapturedCommand::TimestampEnd => {
+                    if nested {
+                        return ops;
+                    }
+                    None
+                }
+                CapturedCommand::TimestampBegin { name } => {
+                    let inner = self.resolve(commands, true);
+                    let name = *self.scope_names.entry(name.clone())
+                        .or_insert_with(|| Box::leak(name.clone().into_boxed_str()));
+                    Some(Op::TimestampScope(name, inner))
+                }
+                CapturedCommand::Draw {
+                    vertex_count, instance_count, first_vertex, first_instance,
+                    pipeline, pipeline_changed, vertex_buffers, descriptor_sets
+                } => Some(Op::Draw {
+                    vertex_count: *vertex_count,
+                    instance_count: *instance_count,
+                    first_vertex: *first_vertex,
+                    first_instance: *first_instance,
+                    pipeline: pipeline_changed.then(|| self.pipelines.get(pipeline).cloned()).flatten(),
+                    vertex_buffers: vertex_buffers.iter()
+                        .filter_map(|(binding, r)| self.device_range(r).map(|b| (*binding, b)))
+                        .collect(),
+                    descriptor_sets: descriptor_sets.iter()
+                        .filter_map(|s| self.descriptor_sets.get(&s.descriptor_set).map(|ds| (s.set, ds.clone(), s.dynamic_offsets.clone())))
+                        .collect(),
+                }),
+                CapturedCommand::BeginLabel { name, color } => Some(Op::BeginLabel(name.clone(), *color)),
+                CapturedCommand::EndLabel => Some(Op::EndLabel),
+                CapturedCommand::InsertLabel { name, color } => Some(Op::InsertLabel(name.clone(), *color)),
+                CapturedCommand::BeginQuery { pool, query } => self.query_pools.get(pool)
+                    .map(|p| Op::BeginQuery(p.clone(), *query)),
+                CapturedCommand::EndQuery { pool, query } => self.query_pools.get(pool)
+                    .map(|p| Op::EndQuery(p.clone(), *query)),
+                CapturedCommand::CopyQueryResults { pool, first_query, query_count, dst, dst_offset, stride } => {
+                    let size = *stride * *query_count as u64;
+                    let dst = self.device_range(&CapturedBufferRange { buffer: *dst, staging: false, offset: *dst_offset, size, full: false });
+                    self.query_pools.get(pool).cloned().zip(dst)
+                        .map(|(p, dst)| Op::CopyQueryResults(p, *first_query..*first_query + *query_count, dst))
+                }
+            };
+            match op {
+                Some(op) => ops.push(op),
+                None => warn!("Skipping command with resources missing from capture: {:?}", command),
+            }
+        }
+        ops
+    }
+}
+
+/// Captured offsets are absolute in the captured source buffer, replay source range may start elsewhere
+fn rebase_offset(offset: u64, captured: &CapturedBufferRange, replay: &AnyBufferRange) -> u64 {
+    offset - captured.offset + replay.offset()
+}
+
+/// Descriptor set layouts are expected to be static, replay leaks them once per created resource
+fn leak_bindings(bindings: Vec<DescriptorSetLayoutBindingDesc>) -> &'static [DescriptorSetLayoutBindingDesc] {
+    Box::leak(bindings.into_boxed_slice())
+}
+
+fn record(ctx: &mut RecordContext, ops: Vec<Op>, framebuffer_index: u32) {
+    for op in ops {
+        match op {
+            Op::CopyBuffer(src, dst, regions) => ctx.copy_buffer_regions(src, dst, regions),
+            Op::CopyBufferToImage(src, dst, regions) => ctx.copy_buffer_to_image_regions(src, dst, regions),
+            Op::FillBuffer(buffer, data) => ctx.fill_buffer(buffer, data),
+            Op::Barrier => ctx.barrier(),
+            Op::Transition(image, layout, aspect) => ctx.transition_image_layout(image, layout, aspect),
+            Op::ClearColor(image, color, aspect) => ctx.clear_color_image(image, color, aspect),
+            Op::ClearDepthStencil(image, depth, stencil) => ctx.clear_depth_stencil_image(image, depth, stencil),
+            Op::RenderPass(render_pass, clear_values, inner) => {
+                if let Err(e) = ctx.render_pass(render_pass, framebuffer_index, clear_values, |rp_ctx| record_in_pass(rp_ctx, inner)) {
+                    error!("Render pass in capture skipped: {:?}", e);
+                }
+            }
+            Op::BeginLabel(name, color) => ctx.begin_label(&name, color),
+            Op::EndLabel => ctx.end_label(),
+            Op::InsertLabel(name, color) => ctx.insert_label(&name, color),
+            Op::TimestampScope(name, inner) => ctx.timestamp_scope(name, |ctx| record(ctx, inner, framebuffer_index)),
+            Op::CopyQueryResults(pool, queries, dst) => ctx.copy_query_results(pool, queries, dst),
+            Op::Draw { .. } => warn!("Draw outside of render pass in capture, skipped"),
+            Op::BeginQuery(..) | Op::EndQuery(..) => warn!("Query outside of render pass in capture, skipped"),
+        }
+    }
+}
+
+fn record_in_pass(ctx: &mut RenderPassContext<'_>, ops: Vec<Op>) {
+    for op in ops {
+        match op {
+            Op::Draw { vertex_count, instance_count, first_vertex, first_instance, pipeline, vertex_buffers, descriptor_sets } => {
+                if let Some(pipeline) = pipeline {
+                    ctx.bind_pipeline(pipeline);
+                }
+                for (binding, vertex_buffer) in vertex_buffers {
+                    ctx.bind_vertex_buffers(binding, &[vertex_buffer]);
+                }
+                for (set, descriptor_set, dynamic_offsets) in descriptor_sets {
+                    if ctx.try_bind_descriptor_set_dynamic(set, descriptor_set, &dynamic_offsets).is_none() {
+                        warn!("Failed to bind descriptor set {} during replay", set);
+                    }
+                }
+                ctx.draw(vertex_count, instance_count, first_vertex, first_instance);
+            }
+            Op::BeginLabel(name, color) => ctx.begin_label(&name, color),
+            Op::EndLabel => ctx.end_label(),
+            Op::InsertLabel(name, color) => ctx.insert_label(&name, color),
+            Op::TimestampScope(name, inner) => ctx.timestamp_scope(name, |ctx| record_in_pass(ctx, inner)),
+            Op::BeginQuery(pool, query) => ctx.begin_query(&pool, query),
+            Op::EndQuery(pool, query) => ctx.end_query(&pool, query),
+            _ => warn!("Only draws, labels and queries are allowed inside render pass, skipped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_events_requires_header() {
+        assert!(Replay::from_events(vec![CaptureEvent::Present { image_index: 0 }]).is_err());
+        let header = |version| CaptureEvent::Header { version, swapchain_format: 0, swapchain_extent: (640, 480) };
+        assert!(Replay::from_events(vec![header(CAPTURE_VERSION + 1)]).is_err());
+
+        let replay = Replay::from_events(vec![
+            header(CAPTURE_VERSION),
+            CaptureEvent::Present { image_index: 0 },
+            CaptureEvent::Resize { width: 800, height: 600 },
+            CaptureEvent::Present { image_index: 1 },
+        ]).unwrap();
+        assert_eq!(replay.swapchain_extent(), (640, 480));
+        assert_eq!(replay.frame_count(), 2);
+    }
+}
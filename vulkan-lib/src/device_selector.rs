@@ -0,0 +1,514 @@
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::fmt;
+use ash::vk;
+use ash::vk::PhysicalDeviceType;
+use smallvec::{smallvec, SmallVec};
+use crate::wrappers::surface::VkSurface;
+
+/// Overrides device choice: device index, UUID (32 hex digits) or case-insensitive name substring
+pub const DEVICE_OVERRIDE_ENV: &str = "VULKAN_DEVICE";
+
+/// Device features, which can be required from physical device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DeviceFeature {
+    SamplerAnisotropy,
+    FillModeNonSolid,
+    WideLines,
+    LargePoints,
+    DepthClamp,
+    IndependentBlend,
+    MultiDrawIndirect,
+    GeometryShader,
+    TessellationShader,
+    ShaderFloat64,
+    ShaderInt64,
+    ShaderInt16,
+    PipelineStatisticsQuery,
+    OcclusionQueryPrecise,
+    TextureCompressionBc,
+    /// VK_KHR_timeline_semaphore
+    TimelineSemaphore,
+}
+
+impl DeviceFeature {
+    fn core_feature_mut(self, features: &mut vk::PhysicalDeviceFeatures) -> Option<&mut vk::Bool32> {
+        Some(match self {
+            DeviceFeature::SamplerAnisotropy => &mut features.sampler_anisotropy,
+            DeviceFeature::FillModeNonSolid => &mut features.fill_mode_non_solid,
+            DeviceFeature::WideLines => &mut features.wide_lines,
+            DeviceFeature::LargePoints => &mut features.large_points,
+            DeviceFeature::DepthClamp => &mut features.depth_clamp,
+            DeviceFeature::IndependentBlend => &mut features.independent_blend,
+            DeviceFeature::MultiDrawIndirect => &mut features.multi_draw_indirect,
+            DeviceFeature::GeometryShader => &mut features.geometry_shader,
+            DeviceFeature::TessellationShader => &mut features.tessellation_shader,
+            DeviceFeature::ShaderFloat64 => &mut features.shader_float64,
+            DeviceFeature::ShaderInt64 => &mut features.shader_int64,
+            DeviceFeature::ShaderInt16 => &mut features.shader_int16,
+            DeviceFeature::PipelineStatisticsQuery => &mut features.pipeline_statistics_query,
+            DeviceFeature::OcclusionQueryPrecise => &mut features.occlusion_query_precise,
+            DeviceFeature::TextureCompressionBc => &mut features.texture_compression_bc,
+            DeviceFeature::TimelineSemaphore => return None,
+        })
+    }
+
+    /// Set feature in create info. Features outside of `VkPhysicalDeviceFeatures` are chained separately on device creation
+    pub(crate) fn enable(self, features: &mut vk::PhysicalDeviceFeatures) {
+        if let Some(f) = self.core_feature_mut(features) {
+            *f = vk::TRUE;
+        }
+    }
+
+    const ALL: [DeviceFeature; 16] = [
+        DeviceFeature::SamplerAnisotropy,
+        DeviceFeature::FillModeNonSolid,
+        DeviceFeature::WideLines,
+        DeviceFeature::LargePoints,
+        DeviceFeature::DepthClamp,
+        DeviceFeature::IndependentBlend,
+        DeviceFeature::MultiDrawIndirect,
+        DeviceFeature::GeometryShader,
+        DeviceFeature::TessellationShader,
+        DeviceFeature::ShaderFloat64,
+        DeviceFeature::ShaderInt64,
+        DeviceFeature::ShaderInt16,
+        DeviceFeature::PipelineStatisticsQuery,
+        DeviceFeature::OcclusionQueryPrecise,
+        DeviceFeature::TextureCompressionBc,
+        DeviceFeature::TimelineSemaphore,
+    ];
+}
+
+/// Explicit physical device choice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceId {
+    /// Index in `vkEnumeratePhysicalDevices` order
+    Index(usize),
+    Uuid([u8; vk::UUID_SIZE]),
+    /// Case-insensitive substring of the device name
+    Name(String),
+}
+
+impl DeviceId {
+    /// Parse value of [`DEVICE_OVERRIDE_ENV`]
+    pub fn parse(s: &str) -> Option<DeviceId> {
+        let s = s.trim();
+        if s.is_empty() {
+            return None;
+        }
+        if let Ok(index) = s.parse::<usize>() {
+            return Some(DeviceId::Index(index));
+        }
+
+        let hex: String = s.chars().filter(|c| *c != '-').collect();
+        if hex.len() == vk::UUID_SIZE * 2 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut uuid = [0; vk::UUID_SIZE];
+            for (i, byte) in uuid.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+            }
+            return Some(DeviceId::Uuid(uuid));
+        }
+
+        Some(DeviceId::Name(s.to_string()))
+    }
+
+    fn matches(&self, candidate: &DeviceCandidate) -> bool {
+        match self {
+            DeviceId::Index(index) => candidate.index == *index,
+            DeviceId::Uuid(uuid) => candidate.uuid == *uuid,
+            DeviceId::Name(name) => candidate.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceId::Index(index) => write!(f, "index {}", index),
+            DeviceId::Uuid(uuid) => {
+                write!(f, "uuid ")?;
+                uuid.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            DeviceId::Name(name) => write!(f, "name \"{}\"", name),
+        }
+    }
+}
+
+/// Reason why physical device was not selected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    NotRequested(DeviceId),
+    DeviceType(PhysicalDeviceType),
+    ApiVersion {
+        required: u32,
+        supported: u32,
+    },
+    MissingExtension(String),
+    MissingFeature(DeviceFeature),
+    NoGraphicsPresentQueue,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |v: u32| format!("{}.{}.{}", vk::api_version_major(v), vk::api_version_minor(v), vk::api_version_patch(v));
+        match self {
+            RejectReason::NotRequested(id) => write!(f, "does not match requested device ({})", id),
+            RejectReason::DeviceType(ty) => write!(f, "device type {:?} is not in preference list", ty),
+            RejectReason::ApiVersion { required, supported } => write!(f, "api version {} is lower than required {}", version(*supported), version(*required)),
+            RejectReason::MissingExtension(name) => write!(f, "missing extension {}", name),
+            RejectReason::MissingFeature(feature) => write!(f, "missing feature {:?}", feature),
+            RejectReason::NoGraphicsPresentQueue => write!(f, "no queue family with graphics and present support"),
+        }
+    }
+}
+
+/// Returned when no physical device satisfies the selector
+#[derive(Debug, Clone)]
+pub struct DeviceSelectionError {
+    /// Device name and reasons for each enumerated device
+    pub rejected: Vec<(String, Vec<RejectReason>)>,
+}
+
+impl fmt::Display for DeviceSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rejected.is_empty() {
+            return write!(f, "No Vulkan physical devices found");
+        }
+        write!(f, "No suitable Vulkan physical device found:")?;
+        for (name, reasons) in &self.rejected {
+            write!(f, "\n  {}:", name)?;
+            for reason in reasons {
+                write!(f, "\n    - {}", reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeviceSelectionError {}
+
+/// Properties of a physical device relevant for selection
+#[derive(Debug, Clone)]
+pub(crate) struct DeviceCandidate {
+    pub index: usize,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub api_version: u32,
+    pub uuid: [u8; vk::UUID_SIZE],
+    pub extensions: BTreeSet<String>,
+    pub features: BTreeSet<DeviceFeature>,
+    /// Queue family supporting both graphics and present
+    pub queue_family_index: Option<u32>,
+}
+
+impl DeviceCandidate {
+    /// Properties2 queries require instance api version 1.1
+    fn query(instance: &ash::Instance, instance_api_version: u32, surface: &VkSurface, index: usize, physical_device: vk::PhysicalDevice) -> anyhow::Result<DeviceCandidate> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy().into_owned();
+
+        let extensions: BTreeSet<String> = unsafe { instance.enumerate_device_extension_properties(physical_device)? }
+            .iter()
+            .map(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) }.to_string_lossy().into_owned())
+            .collect();
+
+        let mut core_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let mut features: BTreeSet<DeviceFeature> = DeviceFeature::ALL.iter()
+            .copied()
+            .filter(|f| f.core_feature_mut(&mut core_features).is_some_and(|v| *v == vk::TRUE))
+            .collect();
+        let has_properties2 = instance_api_version >= vk::API_VERSION_1_1;
+        if has_properties2 && extensions.contains(ash::khr::timeline_semaphore::NAME.to_str().unwrap()) {
+            let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+            let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut timeline_features);
+            unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+            if timeline_features.timeline_semaphore == vk::TRUE {
+                features.insert(DeviceFeature::TimelineSemaphore);
+            }
+        }
+
+        let mut uuid = [0; vk::UUID_SIZE];
+        if has_properties2 {
+            let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+            unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+            uuid = id_properties.device_uuid;
+        }
+
+        let queue_family_index = unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.queue_flags.contains(vk::QueueFlags::GRAPHICS) && surface.query_presentation_support(physical_device))
+            .map(|(i, _)| i as u32);
+
+        Ok(DeviceCandidate {
+            index,
+            name,
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            uuid,
+            extensions,
+            features,
+            queue_family_index,
+        })
+    }
+}
+
+/// Physical device and its graphics/present queue family
+#[derive(Debug, Clone)]
+pub(crate) struct SelectedDevice {
+    pub physical_device: vk::PhysicalDevice,
+    pub queue_family_index: u32,
+    pub supported_features: BTreeSet<DeviceFeature>,
+}
+
+/// Policy for choosing physical device.
+///
+/// By default prefers discrete, then integrated, virtual and CPU devices,
+/// and respects [`DEVICE_OVERRIDE_ENV`].
+#[derive(Debug, Clone)]
+pub struct DeviceSelector {
+    preference: SmallVec<[PhysicalDeviceType; 4]>,
+    required_extensions: Vec<String>,
+    required_features: Vec<DeviceFeature>,
+    min_api_version: u32,
+    device: Option<DeviceId>,
+    env_override: bool,
+}
+
+impl Default for DeviceSelector {
+    fn default() -> Self {
+        Self {
+            preference: smallvec![
+                PhysicalDeviceType::DISCRETE_GPU,
+                PhysicalDeviceType::INTEGRATED_GPU,
+                PhysicalDeviceType::VIRTUAL_GPU,
+                PhysicalDeviceType::CPU,
+            ],
+            required_extensions: vec![ash::khr::swapchain::NAME.to_str().unwrap().to_string()],
+            required_features: Vec::new(),
+            min_api_version: vk::API_VERSION_1_0,
+            device: None,
+            env_override: true,
+        }
+    }
+}
+
+impl DeviceSelector {
+    /// Device types in order of preference, other types are rejected
+    pub fn with_preference(mut self, preference: &[PhysicalDeviceType]) -> Self {
+        self.preference = SmallVec::from_slice(preference);
+        self
+    }
+
+    pub fn with_required_extension(mut self, name: &CStr) -> Self {
+        self.required_extensions.push(name.to_string_lossy().into_owned());
+        self
+    }
+
+    pub fn with_required_feature(mut self, feature: DeviceFeature) -> Self {
+        self.required_features.push(feature);
+        self
+    }
+
+    pub fn with_min_api_version(mut self, version: u32) -> Self {
+        self.min_api_version = version;
+        self
+    }
+
+    /// Select exact device, still checked against requirements
+    pub fn with_device(mut self, device: DeviceId) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Ignore [`DEVICE_OVERRIDE_ENV`]
+    pub fn without_env_override(mut self) -> Self {
+        self.env_override = false;
+        self
+    }
+
+    pub fn required_extensions(&self) -> &[String] {
+        &self.required_extensions
+    }
+
+    pub fn required_features(&self) -> &[DeviceFeature] {
+        &self.required_features
+    }
+
+    pub(crate) fn select(&self, instance: &ash::Instance, instance_api_version: u32, surface: &VkSurface) -> anyhow::Result<SelectedDevice> {
+        let physical_devices = unsafe { instance.enumerate_physical_devices()? };
+        let candidates = physical_devices.iter()
+            .enumerate()
+            .map(|(i, &d)| DeviceCandidate::query(instance, instance_api_version, surface, i, d))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let requested = self.requested_device();
+        let index = self.choose(&candidates, requested.as_ref())?;
+        let candidate = &candidates[index];
+        Ok(SelectedDevice {
+            physical_device: physical_devices[candidate.index],
+            queue_family_index: candidate.queue_family_index.unwrap(),
+            supported_features: candidate.features.clone(),
+        })
+    }
+
+    fn requested_device(&self) -> Option<DeviceId> {
+        if self.env_override && let Ok(value) = std::env::var(DEVICE_OVERRIDE_ENV) {
+            match DeviceId::parse(&value) {
+                Some(id) => return Some(id),
+                None => log::warn!("Ignoring empty {} override", DEVICE_OVERRIDE_ENV),
+            }
+        }
+        self.device.clone()
+    }
+
+    /// Index of best candidate in `candidates`
+    fn choose(&self, candidates: &[DeviceCandidate], requested: Option<&DeviceId>) -> Result<usize, DeviceSelectionError> {
+        let mut rejected = Vec::new();
+        let mut best: Option<(usize, usize)> = None;
+        for (i, candidate) in candidates.iter().enumerate() {
+            let reasons = self.check(candidate, requested);
+            if !reasons.is_empty() {
+                rejected.push((candidate.name.clone(), reasons));
+                continue;
+            }
+
+            // explicitly requested device ignores type preference order
+            let rank = self.preference.iter().position(|t| *t == candidate.device_type).unwrap_or(self.preference.len());
+            if best.is_none_or(|(_, best_rank)| rank < best_rank) {
+                best = Some((i, rank));
+            }
+        }
+
+        best.map(|(i, _)| i).ok_or(DeviceSelectionError { rejected })
+    }
+
+    fn check(&self, candidate: &DeviceCandidate, requested: Option<&DeviceId>) -> Vec<RejectReason> {
+        let mut reasons = Vec::new();
+        if let Some(requested) = requested {
+            if !requested.matches(candidate) {
+                reasons.push(RejectReason::NotRequested(requested.clone()));
+            }
+        }
+        else if !self.preference.contains(&candidate.device_type) {
+            reasons.push(RejectReason::DeviceType(candidate.device_type));
+        }
+
+        if candidate.api_version < self.min_api_version {
+            reasons.push(RejectReason::ApiVersion {
+                required: self.min_api_version,
+                supported: candidate.api_version,
+            });
+        }
+        for extension in &self.required_extensions {
+            if !candidate.extensions.contains(extension) {
+                reasons.push(RejectReason::MissingExtension(extension.clone()));
+            }
+        }
+        for feature in &self.required_features {
+            if !candidate.features.contains(feature) {
+                reasons.push(RejectReason::MissingFeature(*feature));
+            }
+        }
+        if candidate.queue_family_index.is_none() {
+            reasons.push(RejectReason::NoGraphicsPresentQueue);
+        }
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(index: usize, name: &str, device_type: PhysicalDeviceType) -> DeviceCandidate {
+        DeviceCandidate {
+            index,
+            name: name.to_string(),
+            device_type,
+            api_version: vk::API_VERSION_1_3,
+            uuid: [index as u8; vk::UUID_SIZE],
+            extensions: ["VK_KHR_swapchain".to_string()].into_iter().collect(),
+            features: BTreeSet::new(),
+            queue_family_index: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_preference_order() {
+        let candidates = [
+            candidate(0, "llvmpipe", PhysicalDeviceType::CPU),
+            candidate(1, "Intel UHD", PhysicalDeviceType::INTEGRATED_GPU),
+            candidate(2, "NVIDIA RTX", PhysicalDeviceType::DISCRETE_GPU),
+        ];
+        let selector = DeviceSelector::default();
+        assert_eq!(selector.choose(&candidates, None).unwrap(), 2);
+
+        let selector = DeviceSelector::default()
+            .with_preference(&[PhysicalDeviceType::INTEGRATED_GPU, PhysicalDeviceType::DISCRETE_GPU]);
+        assert_eq!(selector.choose(&candidates, None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_requested_device() {
+        let candidates = [
+            candidate(0, "NVIDIA RTX", PhysicalDeviceType::DISCRETE_GPU),
+            candidate(1, "llvmpipe (LLVM 17)", PhysicalDeviceType::CPU),
+        ];
+        let selector = DeviceSelector::default();
+        let requested = DeviceId::parse("LLVMpipe").unwrap();
+        assert_eq!(selector.choose(&candidates, Some(&requested)).unwrap(), 1);
+        assert_eq!(selector.choose(&candidates, Some(&DeviceId::Index(1))).unwrap(), 1);
+        assert_eq!(selector.choose(&candidates, Some(&DeviceId::Uuid([0; vk::UUID_SIZE]))).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_requested_name_prefers_listed_types() {
+        let candidates = [
+            candidate(0, "Mesa llvmpipe", PhysicalDeviceType::CPU),
+            candidate(1, "Mesa Intel UHD", PhysicalDeviceType::INTEGRATED_GPU),
+        ];
+        let selector = DeviceSelector::default();
+        let requested = DeviceId::parse("mesa").unwrap();
+        assert_eq!(selector.choose(&candidates, Some(&requested)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_rejection_reasons() {
+        let mut cpu = candidate(0, "llvmpipe", PhysicalDeviceType::CPU);
+        cpu.api_version = vk::API_VERSION_1_1;
+        let mut gpu = candidate(1, "NVIDIA RTX", PhysicalDeviceType::DISCRETE_GPU);
+        gpu.queue_family_index = None;
+
+        let selector = DeviceSelector::default()
+            .with_preference(&[PhysicalDeviceType::DISCRETE_GPU])
+            .with_required_extension(ash::khr::present_id::NAME)
+            .with_required_feature(DeviceFeature::SamplerAnisotropy)
+            .with_min_api_version(vk::API_VERSION_1_2);
+        let err = selector.choose(&[cpu, gpu], None).unwrap_err();
+
+        assert_eq!(err.rejected.len(), 2);
+        let (name, reasons) = &err.rejected[0];
+        assert_eq!(name, "llvmpipe");
+        assert_eq!(reasons, &vec![
+            RejectReason::DeviceType(PhysicalDeviceType::CPU),
+            RejectReason::ApiVersion { required: vk::API_VERSION_1_2, supported: vk::API_VERSION_1_1 },
+            RejectReason::MissingExtension("VK_KHR_present_id".to_string()),
+            RejectReason::MissingFeature(DeviceFeature::SamplerAnisotropy),
+        ]);
+        assert!(err.rejected[1].1.contains(&RejectReason::NoGraphicsPresentQueue));
+        assert!(err.to_string().contains("missing extension VK_KHR_present_id"));
+    }
+
+    #[test]
+    fn test_parse_device_id() {
+        assert_eq!(DeviceId::parse("1"), Some(DeviceId::Index(1)));
+        assert_eq!(DeviceId::parse(" "), None);
+        assert_eq!(DeviceId::parse("00112233-4455-6677-8899-aabbccddeeff"), Some(DeviceId::Uuid([
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+        ])));
+        assert_eq!(DeviceId::parse("llvmpipe"), Some(DeviceId::Name("llvmpipe".to_string())));
+    }
+}
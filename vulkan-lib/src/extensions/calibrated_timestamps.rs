@@ -1,6 +1,43 @@
+use std::time::{Duration, Instant};
 use ash::{Entry, Instance};
 use ash::vk::{CalibratedTimestampInfoEXT, PhysicalDevice, TimeDomainEXT};
 use log::info;
+use sparkles::monotonic::get_perf_frequency;
+
+/// Maps host timestamps of a calibrateable time domain to `Instant`.
+/// `Instant` is read from the same clock, so the offset is measured once.
+#[derive(Clone, Copy, Debug)]
+pub struct HostClockMapping {
+    domain: TimeDomainEXT,
+    host_ns: u64,
+    instant: Instant,
+}
+
+impl HostClockMapping {
+    /// Host time domain to request along with other calibrated timestamps
+    pub fn domain(self) -> TimeDomainEXT {
+        self.domain
+    }
+
+    pub fn to_instant(self, host_tm: u64) -> Option<Instant> {
+        let host_ns = host_ticks_to_ns(self.domain, host_tm);
+        if host_ns >= self.host_ns {
+            self.instant.checked_add(Duration::from_nanos(host_ns - self.host_ns))
+        }
+        else {
+            self.instant.checked_sub(Duration::from_nanos(self.host_ns - host_ns))
+        }
+    }
+}
+
+fn host_ticks_to_ns(domain: TimeDomainEXT, tm: u64) -> u64 {
+    if domain == TimeDomainEXT::QUERY_PERFORMANCE_COUNTER {
+        tm * (1_000_000_000 / get_perf_frequency())
+    }
+    else {
+        tm
+    }
+}
 
 pub struct CalibratedTimestamps {
     instance: ash::ext::calibrated_timestamps::Instance,
@@ -74,4 +111,40 @@ impl CalibratedTimestamps {
             None
         }
     }
+
+    /// Reads host timestamp between two `Instant` readings, returns None if no host time domain is calibrateable
+    pub fn host_clock_mapping(&self) -> Option<HostClockMapping> {
+        let domain = [TimeDomainEXT::CLOCK_MONOTONIC, TimeDomainEXT::QUERY_PERFORMANCE_COUNTER].into_iter()
+            .find(|d| self.time_domains.contains(d))?;
+        let info = CalibratedTimestampInfoEXT {
+            time_domain: domain,
+            ..Default::default()
+        };
+        let before = Instant::now();
+        let (timestamps, _) = unsafe { self.device.get_calibrated_timestamps(&[info]) }.ok()?;
+        let after = Instant::now();
+        Some(HostClockMapping {
+            domain,
+            host_ns: host_ticks_to_ns(domain, timestamps[0]),
+            instant: before + (after - before) / 2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_clock_mapping() {
+        let instant = Instant::now();
+        let mapping = HostClockMapping {
+            domain: TimeDomainEXT::CLOCK_MONOTONIC,
+            host_ns: 10_000,
+            instant,
+        };
+        assert_eq!(mapping.to_instant(10_000), Some(instant));
+        assert_eq!(mapping.to_instant(12_500), Some(instant + Duration::from_nanos(2_500)));
+        assert_eq!(mapping.to_instant(9_000), instant.checked_sub(Duration::from_nanos(1_000)));
+    }
 }
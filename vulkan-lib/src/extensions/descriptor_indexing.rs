@@ -0,0 +1,63 @@
+use ash::Instance;
+use ash::vk::{self, DescriptorBindingFlags, DescriptorType, PhysicalDevice};
+
+/// Features of VK_EXT_descriptor_indexing that were enabled on the device.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DescriptorIndexing {
+    partially_bound: bool,
+    update_unused_while_pending: bool,
+    uniform_buffer_update_after_bind: bool,
+    storage_buffer_update_after_bind: bool,
+    sampled_image_update_after_bind: bool,
+    storage_image_update_after_bind: bool,
+}
+
+impl DescriptorIndexing {
+    /// Query supported descriptor indexing features. Returned struct can be chained into
+    /// `DeviceCreateInfo` as is, enabling everything the device supports.
+    pub fn query_features(instance: &Instance, physical_device: PhysicalDevice) -> vk::PhysicalDeviceDescriptorIndexingFeatures<'static> {
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default()
+            .push_next(&mut indexing_features);
+        unsafe {
+            instance.get_physical_device_features2(physical_device, &mut features);
+        }
+        indexing_features.p_next = std::ptr::null_mut();
+        indexing_features
+    }
+
+    pub fn from_features(features: &vk::PhysicalDeviceDescriptorIndexingFeatures) -> Self {
+        Self {
+            partially_bound: features.descriptor_binding_partially_bound != 0,
+            update_unused_while_pending: features.descriptor_binding_update_unused_while_pending != 0,
+            uniform_buffer_update_after_bind: features.descriptor_binding_uniform_buffer_update_after_bind != 0,
+            storage_buffer_update_after_bind: features.descriptor_binding_storage_buffer_update_after_bind != 0,
+            sampled_image_update_after_bind: features.descriptor_binding_sampled_image_update_after_bind != 0,
+            storage_image_update_after_bind: features.descriptor_binding_storage_image_update_after_bind != 0,
+        }
+    }
+
+    /// Binding flags that can be used for descriptor of type `ty`
+    pub fn supported_binding_flags(&self, ty: DescriptorType) -> DescriptorBindingFlags {
+        let mut res = DescriptorBindingFlags::empty();
+        if self.partially_bound {
+            res |= DescriptorBindingFlags::PARTIALLY_BOUND;
+        }
+        if self.update_unused_while_pending {
+            res |= DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+        }
+
+        let update_after_bind = match ty {
+            DescriptorType::UNIFORM_BUFFER => self.uniform_buffer_update_after_bind,
+            DescriptorType::STORAGE_BUFFER => self.storage_buffer_update_after_bind,
+            DescriptorType::SAMPLED_IMAGE | DescriptorType::COMBINED_IMAGE_SAMPLER | DescriptorType::SAMPLER => self.sampled_image_update_after_bind,
+            DescriptorType::STORAGE_IMAGE => self.storage_image_update_after_bind,
+            _ => false,
+        };
+        if update_after_bind {
+            res |= DescriptorBindingFlags::UPDATE_AFTER_BIND;
+        }
+
+        res
+    }
+}
@@ -0,0 +1,70 @@
+use ash::{Instance, vk};
+
+/// Mastering display and content light level metadata, as in SMPTE ST 2086 / CTA-861.3.
+/// Chromaticities are CIE 1931 xy, luminance is in nits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrMasteringMetadata {
+    pub display_primary_red: [f32; 2],
+    pub display_primary_green: [f32; 2],
+    pub display_primary_blue: [f32; 2],
+    pub white_point: [f32; 2],
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    /// Maximum content light level
+    pub max_content_light_level: f32,
+    /// Maximum frame-average light level
+    pub max_frame_average_light_level: f32,
+}
+
+impl Default for HdrMasteringMetadata {
+    /// BT.2020 primaries, D65 white point, 1000 nits mastering display
+    fn default() -> Self {
+        Self {
+            display_primary_red: [0.708, 0.292],
+            display_primary_green: [0.170, 0.797],
+            display_primary_blue: [0.131, 0.046],
+            white_point: [0.3127, 0.3290],
+            max_luminance: 1000.0,
+            min_luminance: 0.001,
+            max_content_light_level: 1000.0,
+            max_frame_average_light_level: 400.0,
+        }
+    }
+}
+
+impl HdrMasteringMetadata {
+    fn xy(v: [f32; 2]) -> vk::XYColorEXT {
+        vk::XYColorEXT { x: v[0], y: v[1] }
+    }
+
+    fn to_vk(self) -> vk::HdrMetadataEXT<'static> {
+        vk::HdrMetadataEXT::default()
+            .display_primary_red(Self::xy(self.display_primary_red))
+            .display_primary_green(Self::xy(self.display_primary_green))
+            .display_primary_blue(Self::xy(self.display_primary_blue))
+            .white_point(Self::xy(self.white_point))
+            .max_luminance(self.max_luminance)
+            .min_luminance(self.min_luminance)
+            .max_content_light_level(self.max_content_light_level)
+            .max_frame_average_light_level(self.max_frame_average_light_level)
+    }
+}
+
+pub struct HdrMetadata {
+    device: ash::ext::hdr_metadata::Device,
+}
+
+impl HdrMetadata {
+    pub fn new(instance: &Instance, device: &ash::Device) -> Self {
+        let device = ash::ext::hdr_metadata::Device::new(instance, device);
+        Self { device }
+    }
+
+    /// Metadata is attached to the swapchain and must be set again after swapchain recreation.
+    pub fn set_metadata(&self, swapchain: vk::SwapchainKHR, metadata: &HdrMasteringMetadata) {
+        let metadata = [metadata.to_vk()];
+        unsafe {
+            self.device.set_hdr_metadata(&[swapchain], &metadata);
+        }
+    }
+}
@@ -1,4 +1,9 @@
+use std::time::{Duration, Instant};
 use ash::{Instance, vk};
+use log::warn;
+
+/// Latency sleep should never block for longer than a frame at very low refresh rate
+const LATENCY_SLEEP_TIMEOUT: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReflexMode {
@@ -7,14 +12,109 @@ pub enum ReflexMode {
     Boost,
 }
 
+/// Frame phase boundaries reported to the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyMarker {
+    SimulationStart,
+    SimulationEnd,
+    RenderSubmitStart,
+    RenderSubmitEnd,
+    PresentStart,
+    PresentEnd,
+    InputSample,
+    TriggerFlash,
+}
+
+impl LatencyMarker {
+    fn to_vk(self) -> vk::LatencyMarkerNV {
+        match self {
+            LatencyMarker::SimulationStart => vk::LatencyMarkerNV::SIMULATION_START,
+            LatencyMarker::SimulationEnd => vk::LatencyMarkerNV::SIMULATION_END,
+            LatencyMarker::RenderSubmitStart => vk::LatencyMarkerNV::RENDERSUBMIT_START,
+            LatencyMarker::RenderSubmitEnd => vk::LatencyMarkerNV::RENDERSUBMIT_END,
+            LatencyMarker::PresentStart => vk::LatencyMarkerNV::PRESENT_START,
+            LatencyMarker::PresentEnd => vk::LatencyMarkerNV::PRESENT_END,
+            LatencyMarker::InputSample => vk::LatencyMarkerNV::INPUT_SAMPLE,
+            LatencyMarker::TriggerFlash => vk::LatencyMarkerNV::TRIGGER_FLASH,
+        }
+    }
+}
+
+/// Driver-measured timings of a single frame, in microseconds of the driver clock.
+/// Zero means the phase was not reported for this frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyTimings {
+    pub present_id: u64,
+    pub input_sample_us: u64,
+    pub sim_start_us: u64,
+    pub sim_end_us: u64,
+    pub render_submit_start_us: u64,
+    pub render_submit_end_us: u64,
+    pub present_start_us: u64,
+    pub present_end_us: u64,
+    pub driver_start_us: u64,
+    pub driver_end_us: u64,
+    pub os_render_queue_start_us: u64,
+    pub os_render_queue_end_us: u64,
+    pub gpu_render_start_us: u64,
+    pub gpu_render_end_us: u64,
+}
+
+impl LatencyTimings {
+    /// Simulation start (or input sample, if reported) to GPU render end
+    pub fn pc_latency(&self) -> Option<Duration> {
+        let start = if self.input_sample_us != 0 { self.input_sample_us } else { self.sim_start_us };
+        if start == 0 || self.gpu_render_end_us < start {
+            return None;
+        }
+        Some(Duration::from_micros(self.gpu_render_end_us - start))
+    }
+}
+
+impl From<&vk::LatencyTimingsFrameReportNV<'_>> for LatencyTimings {
+    fn from(r: &vk::LatencyTimingsFrameReportNV<'_>) -> Self {
+        Self {
+            present_id: r.present_id,
+            input_sample_us: r.input_sample_time_us,
+            sim_start_us: r.sim_start_time_us,
+            sim_end_us: r.sim_end_time_us,
+            render_submit_start_us: r.render_submit_start_time_us,
+            render_submit_end_us: r.render_submit_end_time_us,
+            present_start_us: r.present_start_time_us,
+            present_end_us: r.present_end_time_us,
+            driver_start_us: r.driver_start_time_us,
+            driver_end_us: r.driver_end_time_us,
+            os_render_queue_start_us: r.os_render_queue_start_time_us,
+            os_render_queue_end_us: r.os_render_queue_end_time_us,
+            gpu_render_start_us: r.gpu_render_start_time_us,
+            gpu_render_end_us: r.gpu_render_end_time_us,
+        }
+    }
+}
+
 pub struct LowLatency2 {
     device: ash::nv::low_latency2::Device,
+    raw_device: ash::Device,
+    /// Timeline semaphore signaled by the driver when latency sleep is over
+    sleep_semaphore: vk::Semaphore,
+    sleep_value: u64,
 }
 
 impl LowLatency2 {
-    pub fn new(instance: &Instance, device: &ash::Device) -> Self {
-        let device = ash::nv::low_latency2::Device::new(instance, device);
-        Self { device }
+    /// Requires timeline semaphore feature to be enabled on the device
+    pub fn new(instance: &Instance, device: &ash::Device) -> anyhow::Result<Self> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        let sleep_semaphore = unsafe { device.create_semaphore(&create_info, None)? };
+
+        Ok(Self {
+            device: ash::nv::low_latency2::Device::new(instance, device),
+            raw_device: device.clone(),
+            sleep_semaphore,
+            sleep_value: 0,
+        })
     }
 
     /// Toggle NVIDIA Reflex (low-latency mode) and clock boost on the given swapchain.
@@ -29,4 +129,68 @@ impl LowLatency2 {
             let _ = self.device.set_latency_sleep_mode(swapchain, Some(&info));
         }
     }
+
+    /// `present_id` must match the id passed with `VkPresentIdKHR` when the frame is presented
+    pub fn set_marker(&self, swapchain: vk::SwapchainKHR, present_id: u64, marker: LatencyMarker) {
+        let info = vk::SetLatencyMarkerInfoNV::default()
+            .present_id(present_id)
+            .marker(marker.to_vk());
+        unsafe {
+            self.device.set_latency_marker(swapchain, &info);
+        }
+    }
+
+    /// Block until the driver decides the next frame should start. Returns time spent sleeping.
+    /// Does nothing while Reflex mode is off, the semaphore is signaled immediately.
+    pub fn sleep(&mut self, swapchain: vk::SwapchainKHR) -> Duration {
+        self.sleep_value += 1;
+        let info = vk::LatencySleepInfoNV::default()
+            .signal_semaphore(self.sleep_semaphore)
+            .value(self.sleep_value);
+        if let Err(e) = unsafe { self.device.latency_sleep(swapchain, &info) } {
+            warn!("vkLatencySleepNV failed: {:?}", e);
+            return Duration::ZERO;
+        }
+
+        let start = Instant::now();
+        let semaphores = [self.sleep_semaphore];
+        let values = [self.sleep_value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        if let Err(e) = unsafe { self.raw_device.wait_semaphores(&wait_info, LATENCY_SLEEP_TIMEOUT.as_nanos() as u64) } {
+            warn!("Latency sleep semaphore wait failed: {:?}", e);
+        }
+        start.elapsed()
+    }
+
+    /// Timings of recently presented frames, oldest first
+    pub fn get_timings(&self, swapchain: vk::SwapchainKHR) -> Vec<LatencyTimings> {
+        let mut info = vk::GetLatencyMarkerInfoNV::default();
+        unsafe {
+            self.device.get_latency_timings(swapchain, &mut info);
+        }
+        if info.timing_count == 0 {
+            return Vec::new();
+        }
+
+        let mut reports = vec![vk::LatencyTimingsFrameReportNV::default(); info.timing_count as usize];
+        let mut info = vk::GetLatencyMarkerInfoNV::default().timings(&mut reports);
+        unsafe {
+            self.device.get_latency_timings(swapchain, &mut info);
+        }
+        let count = info.timing_count as usize;
+        reports[..count].iter()
+            .filter(|r| r.present_id != 0)
+            .map(LatencyTimings::from)
+            .collect()
+    }
+}
+
+impl Drop for LowLatency2 {
+    fn drop(&mut self) {
+        unsafe {
+            self.raw_device.destroy_semaphore(self.sleep_semaphore, None);
+        }
+    }
 }
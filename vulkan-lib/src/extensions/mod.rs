@@ -1,3 +1,5 @@
 pub mod calibrated_timestamps;
+pub mod descriptor_indexing;
+pub mod hdr_metadata;
 pub mod low_latency2;
 pub mod present_timing;
\ No newline at end of file
@@ -20,6 +20,7 @@ use std::time::{Duration, Instant};
 use ash::vk;
 use ash::{Device, Instance};
 use log::info;
+use crate::extensions::calibrated_timestamps::HostClockMapping;
 
 // === Registry constants (extension #209, base 1000208000) =========================
 pub const STRUCTURE_TYPE_PHYSICAL_DEVICE_PRESENT_TIMING_FEATURES_EXT: i32 = 1000208000;
@@ -238,6 +239,10 @@ pub struct PresentTiming {
     /// a real wall-time delta.
     calib_q_t0: Option<u64>,
     calib_o_t0: Option<u64>,
+    /// Host time of calibration, maps IMAGE_FIRST_PIXEL_OUT clock to `Instant`
+    calib_instant: Option<Instant>,
+    /// Host clock calibrated along with the stage clocks. Without it host time is taken right after calibration
+    host_clock: Option<HostClockMapping>,
 
     /// q->o accumulator; logged once per second.
     queue_to_pixel_out: LatencyAccum,
@@ -288,6 +293,8 @@ impl PresentTiming {
                 time_domain_id: None,
                 calib_q_t0: None,
                 calib_o_t0: None,
+                calib_instant: None,
+                host_clock: None,
                 queue_to_pixel_out: LatencyAccum::new(),
                 last_logged: Instant::now(),
                 frames_since_request: 0,
@@ -311,6 +318,10 @@ impl PresentTiming {
         }
     }
 
+    pub fn set_host_clock(&mut self, host_clock: Option<HostClockMapping>) {
+        self.host_clock = host_clock;
+    }
+
     /// Initialise the implementation's internal results queue and resolve a stable
     /// time domain. Must be called after every swapchain (re)creation.
     pub fn on_swapchain_created(&mut self, swapchain: vk::SwapchainKHR, image_count: u32) {
@@ -325,6 +336,7 @@ impl PresentTiming {
         self.time_domain_id = None;
         self.calib_q_t0 = None;
         self.calib_o_t0 = None;
+        self.calib_instant = None;
         self.queue_to_pixel_out = LatencyAccum::new();
         self.last_logged = Instant::now();
         self.frames_since_request = 0;
@@ -351,7 +363,9 @@ impl PresentTiming {
     }
 
     /// Drain pending timing results, accumulate q->o, log once per second.
-    pub fn drain_and_log(&mut self, swapchain: vk::SwapchainKHR) {
+    /// Returns present id and first pixel out time of each delivered result.
+    pub fn drain_and_log(&mut self, swapchain: vk::SwapchainKHR) -> Vec<(u64, Option<Instant>)> {
+        let mut displayed = Vec::new();
         const MAX_RESULTS: usize = 16;
         const STAGES_PER_RESULT: usize = 4;
 
@@ -389,7 +403,7 @@ impl PresentTiming {
 
         let r = unsafe { (self.get_past_timing)(self.device, &info, &mut props) };
         if r != vk::Result::SUCCESS && r != vk::Result::INCOMPLETE {
-            return;
+            return displayed;
         }
 
         let n = props.presentation_timing_count as usize;
@@ -412,6 +426,17 @@ impl PresentTiming {
                     self.queue_to_pixel_out.add((delta_ns as u64) / 1000);
                 }
             }
+
+            let displayed_instant = o.zip(self.calib_o_t0).zip(self.calib_instant)
+                .and_then(|((o, o0), calib_instant)| {
+                    if o >= o0 {
+                        calib_instant.checked_add(Duration::from_nanos(o - o0))
+                    }
+                    else {
+                        calib_instant.checked_sub(Duration::from_nanos(o0 - o))
+                    }
+                });
+            displayed.push((t.present_id, displayed_instant));
         }
 
         if self.last_logged.elapsed() >= Duration::from_secs(1) {
@@ -419,6 +444,7 @@ impl PresentTiming {
             self.queue_to_pixel_out = LatencyAccum::new();
             self.last_logged = Instant::now();
         }
+        displayed
     }
 
     fn calibrate_stage_clocks(&mut self, swapchain: vk::SwapchainKHR) {
@@ -449,13 +475,20 @@ impl PresentTiming {
                 p_next: (&o_chain as *const SwapchainCalibratedTimestampInfoEXT).cast(),
                 time_domain: vk::TimeDomainEXT::from_raw(TIME_DOMAIN_PRESENT_STAGE_LOCAL_EXT),
             },
+            // host clock, read at the same moment as stage clocks
+            CalibratedTimestampInfoEXT {
+                s_type: vk::StructureType::from_raw(STRUCTURE_TYPE_CALIBRATED_TIMESTAMP_INFO_EXT),
+                p_next: ptr::null(),
+                time_domain: self.host_clock.map_or(vk::TimeDomainEXT::default(), |c| c.domain()),
+            },
         ];
-        let mut timestamps: [u64; 2] = [0, 0];
+        let info_count = if self.host_clock.is_some() { 3 } else { 2 };
+        let mut timestamps: [u64; 3] = [0, 0, 0];
         let mut max_dev: u64 = 0;
         let r = unsafe {
             (self.get_calibrated_timestamps)(
                 self.device,
-                infos.len() as u32,
+                info_count,
                 infos.as_ptr(),
                 timestamps.as_mut_ptr(),
                 &mut max_dev,
@@ -464,6 +497,10 @@ impl PresentTiming {
         if r == vk::Result::SUCCESS {
             self.calib_q_t0 = Some(timestamps[0]);
             self.calib_o_t0 = Some(timestamps[1]);
+            self.calib_instant = match self.host_clock {
+                Some(host_clock) => host_clock.to_instant(timestamps[2]),
+                None => Some(Instant::now()),
+            };
         }
     }
 
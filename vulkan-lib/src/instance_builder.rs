@@ -0,0 +1,279 @@
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+use ash::vk;
+use log::{info, warn};
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+use crate::device_selector::{DeviceFeature, DeviceSelector};
+use crate::queue::GraphicsQueue;
+use crate::swapchain_wrapper::SwapchainConfig;
+use crate::VulkanInstance;
+use crate::capture::CAPTURE_ENV;
+
+/// Overrides validation level: `0`/`off`, `1`/`on` or `verbose`
+pub const VALIDATION_ENV: &str = "VULKAN_VALIDATION";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationLevel {
+    Off,
+    /// Khronos validation layer with best practices and synchronization validation
+    On,
+    /// Also report info and verbose messages
+    Verbose,
+}
+
+impl ValidationLevel {
+    /// Level selected by cargo features
+    pub fn from_features() -> Self {
+        if cfg!(feature = "validation-verbose") {
+            ValidationLevel::Verbose
+        }
+        else if cfg!(feature = "validation") {
+            ValidationLevel::On
+        }
+        else {
+            ValidationLevel::Off
+        }
+    }
+
+    /// Parse value of [`VALIDATION_ENV`]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "0" | "off" | "false" => Some(ValidationLevel::Off),
+            "1" | "on" | "true" => Some(ValidationLevel::On),
+            "verbose" => Some(ValidationLevel::Verbose),
+            _ => None,
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        self != ValidationLevel::Off
+    }
+}
+
+/// What was actually enabled during instance and device creation
+#[derive(Debug, Clone)]
+pub struct EnabledCapabilities {
+    pub validation: ValidationLevel,
+    pub layers: Vec<String>,
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+    /// Features enabled on the device: required, supported optional and ones enabled for extensions
+    pub features: Vec<DeviceFeature>,
+    /// Optional features not enabled on selected device
+    pub missing_optional_features: Vec<DeviceFeature>,
+}
+
+impl EnabledCapabilities {
+    pub fn has_instance_extension(&self, name: &CStr) -> bool {
+        self.instance_extensions.iter().any(|e| e.as_bytes() == name.to_bytes())
+    }
+
+    pub fn has_device_extension(&self, name: &CStr) -> bool {
+        self.device_extensions.iter().any(|e| e.as_bytes() == name.to_bytes())
+    }
+
+    pub fn has_feature(&self, feature: DeviceFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Features outside of `VkPhysicalDeviceFeatures` are enabled only if their feature struct was chained on device creation
+    pub(crate) fn set_chained_feature(&mut self, feature: DeviceFeature, chained: bool) {
+        let position = self.features.iter().position(|f| *f == feature);
+        match (chained, position) {
+            (true, None) => self.features.push(feature),
+            (false, Some(i)) => {
+                self.features.remove(i);
+                self.missing_optional_features.push(feature);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn log(&self) {
+        info!("Validation: {:?}", self.validation);
+        info!("Enabled device features: {:?}", self.features);
+        for feature in &self.missing_optional_features {
+            warn!("Optional feature {:?} is not supported, disabled", feature);
+        }
+    }
+}
+
+/// Configuration of instance and device creation
+#[derive(Debug, Clone)]
+pub struct VulkanInstanceBuilder {
+    pub(crate) app_name: CString,
+    pub(crate) app_version: u32,
+    pub(crate) engine_name: CString,
+    pub(crate) engine_version: u32,
+    pub(crate) api_version: u32,
+    pub(crate) validation: ValidationLevel,
+    pub(crate) validation_env_override: bool,
+    pub(crate) extra_instance_extensions: Vec<CString>,
+    pub(crate) extra_device_extensions: Vec<CString>,
+    pub(crate) optional_features: Vec<DeviceFeature>,
+    pub(crate) device_selector: DeviceSelector,
+    pub(crate) swapchain_config: SwapchainConfig,
+    pub(crate) capture_path: Option<PathBuf>,
+}
+
+impl Default for VulkanInstanceBuilder {
+    fn default() -> Self {
+        Self {
+            app_name: c"Hello Vulkan".to_owned(),
+            app_version: vk::make_api_version(0, 1, 0, 0),
+            engine_name: c"vulkan-lib".to_owned(),
+            engine_version: vk::make_api_version(0, 1, 0, 0),
+            api_version: vk::API_VERSION_1_1,
+            validation: ValidationLevel::from_features(),
+            validation_env_override: true,
+            extra_instance_extensions: Vec::new(),
+            extra_device_extensions: Vec::new(),
+            optional_features: Vec::new(),
+            device_selector: DeviceSelector::default(),
+            swapchain_config: SwapchainConfig::default(),
+            capture_path: None,
+        }
+    }
+}
+
+impl VulkanInstanceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interior nul bytes are dropped
+    pub fn with_app_name(mut self, name: &str, version: u32) -> Self {
+        self.app_name = crate::wrappers::debug_utils::to_cstring(name);
+        self.app_version = version;
+        self
+    }
+
+    pub fn with_engine_name(mut self, name: &str, version: u32) -> Self {
+        self.engine_name = crate::wrappers::debug_utils::to_cstring(name);
+        self.engine_version = version;
+        self
+    }
+
+    pub fn with_api_version(mut self, api_version: u32) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Defaults to level selected by cargo features. [`VALIDATION_ENV`] takes precedence
+    pub fn with_validation(mut self, validation: ValidationLevel) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Ignore [`VALIDATION_ENV`]
+    pub fn without_validation_env_override(mut self) -> Self {
+        self.validation_env_override = false;
+        self
+    }
+
+    /// Enabled if supported by instance
+    pub fn with_instance_extension(mut self, name: &CStr) -> Self {
+        self.extra_instance_extensions.push(name.to_owned());
+        self
+    }
+
+    /// Enabled if supported by device. Use [`DeviceSelector::with_required_extension`] to reject devices without it
+    pub fn with_device_extension(mut self, name: &CStr) -> Self {
+        self.extra_device_extensions.push(name.to_owned());
+        self
+    }
+
+    /// Enabled if supported by selected device, check [`EnabledCapabilities::has_feature`]
+    pub fn with_optional_feature(mut self, feature: DeviceFeature) -> Self {
+        self.optional_features.push(feature);
+        self
+    }
+
+    pub fn with_device_selector(mut self, device_selector: DeviceSelector) -> Self {
+        self.device_selector = device_selector;
+        self
+    }
+
+    pub fn with_swapchain_config(mut self, swapchain_config: SwapchainConfig) -> Self {
+        self.swapchain_config = swapchain_config;
+        self
+    }
+
+    /// Write resource creation and device commands to file, see [`crate::capture::Replay`].
+    /// [`CAPTURE_ENV`] takes precedence
+    pub fn with_capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.capture_path = Some(path.into());
+        self
+    }
+
+    pub(crate) fn effective_capture_path(&self) -> Option<PathBuf> {
+        std::env::var_os(CAPTURE_ENV).map(PathBuf::from)
+            .or_else(|| self.capture_path.clone())
+    }
+
+    pub(crate) fn effective_validation(&self) -> ValidationLevel {
+        if self.validation_env_override && let Ok(value) = std::env::var(VALIDATION_ENV) {
+            match ValidationLevel::parse(&value) {
+                Some(level) => return level,
+                None => warn!("Ignoring invalid {}={}", VALIDATION_ENV, value),
+            }
+        }
+        self.validation
+    }
+
+    pub fn build(self, window_handle: RawWindowHandle, display_handle: RawDisplayHandle, initial_size: (u32, u32)) -> anyhow::Result<GraphicsQueue> {
+        VulkanInstance::create(self, window_handle, display_handle, initial_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_validation_level() {
+        assert_eq!(ValidationLevel::parse("0"), Some(ValidationLevel::Off));
+        assert_eq!(ValidationLevel::parse("ON"), Some(ValidationLevel::On));
+        assert_eq!(ValidationLevel::parse(" verbose\n"), Some(ValidationLevel::Verbose));
+        assert_eq!(ValidationLevel::parse("maybe"), None);
+    }
+
+    #[test]
+    fn test_validation_without_env_override() {
+        let builder = VulkanInstanceBuilder::new()
+            .with_validation(ValidationLevel::Verbose)
+            .without_validation_env_override();
+        assert_eq!(builder.effective_validation(), ValidationLevel::Verbose);
+    }
+
+    fn capabilities(features: &[DeviceFeature]) -> EnabledCapabilities {
+        EnabledCapabilities {
+            validation: ValidationLevel::Off,
+            layers: vec![],
+            instance_extensions: vec![],
+            device_extensions: vec![],
+            features: features.to_vec(),
+            missing_optional_features: vec![],
+        }
+    }
+
+    #[test]
+    fn test_chained_feature_reported_only_if_enabled() {
+        // optional feature supported by device, but feature struct was not chained
+        let mut caps = capabilities(&[DeviceFeature::SamplerAnisotropy, DeviceFeature::TimelineSemaphore]);
+        caps.set_chained_feature(DeviceFeature::TimelineSemaphore, false);
+        assert!(!caps.has_feature(DeviceFeature::TimelineSemaphore));
+        assert_eq!(caps.missing_optional_features, [DeviceFeature::TimelineSemaphore]);
+
+        // not requested, but enabled for an extension
+        let mut caps = capabilities(&[DeviceFeature::SamplerAnisotropy]);
+        caps.set_chained_feature(DeviceFeature::TimelineSemaphore, true);
+        assert!(caps.has_feature(DeviceFeature::TimelineSemaphore));
+        assert!(caps.missing_optional_features.is_empty());
+
+        let mut caps = capabilities(&[]);
+        caps.set_chained_feature(DeviceFeature::TimelineSemaphore, false);
+        assert!(caps.features.is_empty());
+        assert!(caps.missing_optional_features.is_empty());
+    }
+}
@@ -2,18 +2,22 @@ use std::ffi::{c_char, CString};
 use std::sync::{Arc, Mutex, OnceLock, Weak};
 use anyhow::bail;
 use ash::Entry;
-use ash::vk::{make_api_version, ApplicationInfo, BufferCreateInfo, Extent2D, PhysicalDevice};
-use log::{info, warn};
+use ash::vk::{ApplicationInfo, BufferCreateInfo, Extent2D, PhysicalDevice};
+use log::{error, info, warn};
 use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use sparkles::range_event_start;
 use crate::swapchain_wrapper::SwapchainWrapper;
+pub use crate::swapchain_wrapper::{OutputColorSpace, SwapchainColorEncoding, SwapchainConfig, SwapchainFormatChange, SwapchainInfo};
 use crate::wrappers::capabilities_checker::CapabilitiesChecker;
-use crate::wrappers::debug_report::VkDebugReport;
+use crate::wrappers::debug_utils::VkDebugUtils;
 use crate::wrappers::device::VkDeviceRef;
 use crate::wrappers::surface::{VkSurface, VkSurfaceRef};
 use crate::extensions::calibrated_timestamps::CalibratedTimestamps;
+use crate::extensions::descriptor_indexing::DescriptorIndexing;
+use crate::extensions::hdr_metadata::HdrMetadata;
+pub use crate::extensions::hdr_metadata::HdrMasteringMetadata;
 use crate::extensions::low_latency2::LowLatency2;
-pub use crate::extensions::low_latency2::ReflexMode;
+pub use crate::extensions::low_latency2::{LatencyMarker, LatencyTimings, ReflexMode};
 use crate::extensions::present_timing::{
     PhysicalDevicePresentTimingFeaturesEXT, PresentTiming,
 };
@@ -23,9 +27,16 @@ use crate::queue::GraphicsQueue;
 pub use ash::vk;
 pub use vk::{DescriptorType, ShaderStageFlags};
 use crate::queue::shared::SharedState;
+use crate::resources::registry::{ResourceKind, ResourceRegistry};
+use crate::capture::{CaptureEvent, CaptureWriter};
+pub use crate::capture::CAPTURE_ENV;
+pub use crate::instance_builder::{EnabledCapabilities, ValidationLevel, VulkanInstanceBuilder, VALIDATION_ENV};
+pub use crate::device_selector::{DeviceFeature, DeviceId, DeviceSelectionError, DeviceSelector, RejectReason, DEVICE_OVERRIDE_ENV};
 
 mod wrappers;
 mod swapchain_wrapper;
+mod device_selector;
+mod instance_builder;
 mod util;
 pub mod shaders;
 mod extensions;
@@ -34,6 +45,7 @@ pub mod queue;
 #[cfg(target_os = "android")]
 pub mod android;
 pub mod resources;
+pub mod capture;
 
 static INSTANCE_SLOT: Mutex<Weak<VulkanInstance>> = Mutex::new(Weak::new());
 
@@ -42,55 +54,84 @@ pub(crate) fn try_get_instance() -> Option<Arc<VulkanInstance>> {
 }
 
 pub struct VulkanInstance {
-    debug_report: VkDebugReport,
+    debug_utils: Option<VkDebugUtils>,
     physical_device: PhysicalDevice,
     shared_state: SharedState,
     device: VkDeviceRef,
+    descriptor_indexing: DescriptorIndexing,
+    device_limits: vk::PhysicalDeviceLimits,
+    capabilities: EnabledCapabilities,
+    registry: Arc<ResourceRegistry>,
+    capture: Option<parking_lot::Mutex<CaptureWriter>>,
 
     entry: Entry,
 }
 
+impl Drop for VulkanInstance {
+    fn drop(&mut self) {
+        if let Some(report) = self.registry.leak_report() {
+            error!("VulkanInstance dropped with live resources! {}", report);
+        }
+    }
+}
+
 impl VulkanInstance {
+    /// Shortcut for [`VulkanInstanceBuilder`] with default application info
     #[track_caller]
-    pub fn new_for_handle(window_handle: RawWindowHandle, display_handle: RawDisplayHandle, initial_size: (u32, u32), api_version: u32) -> anyhow::Result<GraphicsQueue> {
+    pub fn new_for_handle(window_handle: RawWindowHandle, display_handle: RawDisplayHandle, initial_size: (u32, u32), api_version: u32, swapchain_config: SwapchainConfig, device_selector: &DeviceSelector) -> anyhow::Result<GraphicsQueue> {
+        VulkanInstanceBuilder::new()
+            .with_api_version(api_version)
+            .with_swapchain_config(swapchain_config)
+            .with_device_selector(device_selector.clone())
+            .build(window_handle, display_handle, initial_size)
+    }
+
+    pub(crate) fn create(builder: VulkanInstanceBuilder, window_handle: RawWindowHandle, display_handle: RawDisplayHandle, initial_size: (u32, u32)) -> anyhow::Result<GraphicsQueue> {
+        let api_version = builder.api_version;
+        let device_selector = &builder.device_selector;
+        let validation = builder.effective_validation();
         let Ok(entry) = (unsafe { Entry::load() }) else {
             bail!("Failed to load Vulkan entry");
         };
 
         let g = range_event_start!("[Vulkan] INIT");
-        let app_name = CString::new("Hello Vulkan")?;
-
         let app_info = ApplicationInfo::default()
-            .application_name(&app_name)
-            .application_version(make_api_version(0, 1, 0, 0))
-            .engine_name(&app_name)
-            .engine_version(make_api_version(0, 1, 0, 0))
+            .application_name(&builder.app_name)
+            .application_version(builder.app_version)
+            .engine_name(&builder.engine_name)
+            .engine_version(builder.engine_version)
             .api_version(api_version);
 
         //define desired layers
         // 1. Khronos validation layers (optional)
         let mut instance_layers = vec![];
-        if cfg!(feature = "validation") {
+        if validation.is_enabled() {
             instance_layers.push(CString::new("VK_LAYER_KHRONOS_validation")?);
         }
         let mut instance_layers_refs: Vec<*const c_char> =
             instance_layers.iter().map(|l| l.as_ptr()).collect();
 
         //define desired extensions
-        // 1 Debug report
+        // 1 Debug utils
         // 2,3 Required extensions for surface support (platform_specific surface + general surface)
         // 4 Portability enumeration (for moltenvk)
         let surface_required_extensions =
             ash_window::enumerate_required_extensions(display_handle)?;
         let mut instance_extensions: Vec<*const c_char> = surface_required_extensions.to_vec();
-        instance_extensions.push(ash::ext::debug_report::NAME.as_ptr());
+        instance_extensions.push(ash::ext::debug_utils::NAME.as_ptr());
         // Instance-level dependency of VK_EXT_present_timing.
         instance_extensions.push(ash::khr::get_surface_capabilities2::NAME.as_ptr());
-        if cfg!(feature = "validation") {
+        // Extended surface color spaces for HDR and wide gamut output
+        instance_extensions.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+        if validation.is_enabled() {
             instance_extensions.push(ash::ext::validation_features::NAME.as_ptr());
         }
+        for extension in &builder.extra_instance_extensions {
+            instance_extensions.push(extension.as_ptr());
+        }
 
-        let mut debug_report_callback_info = VkDebugReport::get_messenger_create_info();
+        let verbose = validation == ValidationLevel::Verbose;
+        let mut debug_messenger_info = VkDebugUtils::get_messenger_create_info(verbose);
 
         let enabled_validation_features = [
             vk::ValidationFeatureEnableEXT::BEST_PRACTICES,
@@ -98,8 +139,8 @@ impl VulkanInstance {
         ];
         let validation_features = vk::ValidationFeaturesEXT::default()
             .enabled_validation_features(&enabled_validation_features);
-        if cfg!(feature = "validation") {
-            debug_report_callback_info.p_next =
+        if validation.is_enabled() {
+            debug_messenger_info.p_next =
                 (&validation_features as *const vk::ValidationFeaturesEXT).cast();
         }
 
@@ -108,38 +149,21 @@ impl VulkanInstance {
         // caps_checker will check requested layers and extensions and enable only the
         // supported ones, which can be requested later
         let instance = caps_checker.create_instance(&entry, &app_info, &mut instance_layers_refs,
-                                                    &mut instance_extensions, &mut debug_report_callback_info)?;
+                                                    &mut instance_extensions, &mut debug_messenger_info)?;
 
         let surface = VkSurface::new(&entry, instance.clone(), display_handle, window_handle)?;
 
-        let debug_report = VkDebugReport::new(&entry, instance.clone())?;
-        // instance is created. debug report ready
-
-        let physical_devices = unsafe { instance.enumerate_physical_devices()? };
-
-        let physical_device = *physical_devices
-            .iter()
-            .find(|&d| {
-                let properties = unsafe { instance.get_physical_device_properties(*d) };
-                properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
-            })
-            .or_else(|| {
-                warn!("Discrete GPU was not found!");
-                physical_devices.iter().find(|&d| {
-                    let properties = unsafe { instance.get_physical_device_properties(*d) };
-                    properties.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU
-                })
-            })
-            .or_else(|| {
-                warn!("Integrated GPU was not found!");
-                physical_devices.iter().find(|&d| {
-                    let properties = unsafe { instance.get_physical_device_properties(*d) };
-                    properties.device_type == vk::PhysicalDeviceType::CPU
-                })
-            })
-            .unwrap_or_else(|| {
-                panic!("No available physical device found");
-            });
+        let mut debug_utils = if caps_checker.is_instance_extension_enabled(ash::ext::debug_utils::NAME) {
+            Some(VkDebugUtils::new(&entry, instance.clone(), verbose)?)
+        } else {
+            warn!("VK_EXT_debug_utils is not available, validation messages and debug names are disabled");
+            None
+        };
+        // instance is created. debug messenger ready
+
+        let selected = device_selector.select(&instance, api_version, &surface)?;
+        let physical_device = selected.physical_device;
+        let queue_family_index = selected.queue_family_index;
 
         //select chosen physical device
         let dev_name_array = unsafe {
@@ -152,19 +176,6 @@ impl VulkanInstance {
 
         let queue_family_properties =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-        let queue_family_index = queue_family_properties
-            .iter()
-            .enumerate()
-            .find(|(_, p)| {
-                let support_graphics = p.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-                let support_presentation = surface.query_presentation_support(physical_device);
-
-                support_graphics && support_presentation
-            })
-            .map(|(i, _)| i as u32)
-            .unwrap_or_else(|| {
-                panic!("No available queue family found");
-            });
 
         let present_timing_name = CString::new("VK_EXT_present_timing")?;
         let calibrated_timestamps_khr_name = CString::new("VK_KHR_calibrated_timestamps")?;
@@ -173,8 +184,19 @@ impl VulkanInstance {
             ash::khr::swapchain::NAME.as_ptr(),
             ash::ext::calibrated_timestamps::NAME.as_ptr(),
             ash::nv::low_latency2::NAME.as_ptr(),
+            ash::khr::present_id::NAME.as_ptr(),
+            ash::ext::descriptor_indexing::NAME.as_ptr(),
+            ash::ext::hdr_metadata::NAME.as_ptr(),
             timeline_sem_name.as_ptr(),
         ];
+        let required_extensions: Vec<CString> = device_selector.required_extensions().iter()
+            .map(|e| CString::new(e.as_str()))
+            .collect::<Result<_, _>>()?;
+        for extension in required_extensions.iter().chain(&builder.extra_device_extensions) {
+            if !device_extensions.iter().any(|&e| unsafe { std::ffi::CStr::from_ptr(e) } == extension.as_c_str()) {
+                device_extensions.push(extension.as_ptr());
+            }
+        }
         if cfg!(feature = "present-timing") {
             device_extensions.push(calibrated_timestamps_khr_name.as_ptr());
             device_extensions.push(present_timing_name.as_ptr());
@@ -183,9 +205,27 @@ impl VulkanInstance {
         let queue_create_infos = [vk::DeviceQueueCreateInfo::default()
             .queue_family_index(queue_family_index)
             .queue_priorities(&[1.0])];
+        let mut features: Vec<DeviceFeature> = device_selector.required_features().to_vec();
+        let mut missing_optional_features = Vec::new();
+        for feature in &builder.optional_features {
+            if features.contains(feature) {
+                continue;
+            }
+            if selected.supported_features.contains(feature) {
+                features.push(*feature);
+            }
+            else {
+                missing_optional_features.push(*feature);
+            }
+        }
+        let mut enabled_features = vk::PhysicalDeviceFeatures::default();
+        for feature in &features {
+            feature.enable(&mut enabled_features);
+        }
         let mut device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
-            .enabled_extension_names(&device_extensions);
+            .enabled_extension_names(&device_extensions)
+            .enabled_features(&enabled_features);
 
         let mut pt_features = PhysicalDevicePresentTimingFeaturesEXT::enabled();
         if cfg!(feature = "present-timing") {
@@ -198,12 +238,54 @@ impl VulkanInstance {
             )?;
         }
 
+        let mut descriptor_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        if caps_checker.is_device_extension_supported(&instance, physical_device, ash::ext::descriptor_indexing::NAME)? {
+            descriptor_indexing_features = DescriptorIndexing::query_features(&instance, physical_device);
+        }
+        device_create_info = caps_checker.try_chain_device_feature(
+            &instance,
+            physical_device,
+            device_create_info,
+            ash::ext::descriptor_indexing::NAME,
+            &mut descriptor_indexing_features,
+        )?;
+
+        // enabled if requested, or if supported for VK_NV_low_latency2
+        let timeline_semaphore_enabled = (features.contains(&DeviceFeature::TimelineSemaphore)
+            || (selected.supported_features.contains(&DeviceFeature::TimelineSemaphore)
+                && caps_checker.is_device_extension_supported(&instance, physical_device, ash::nv::low_latency2::NAME)?))
+            && caps_checker.is_device_extension_supported(&instance, physical_device, &timeline_sem_name)?;
+        let mut timeline_semaphore_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default()
+            .timeline_semaphore(true);
+        if timeline_semaphore_enabled {
+            device_create_info = caps_checker.try_chain_device_feature(
+                &instance,
+                physical_device,
+                device_create_info,
+                &timeline_sem_name,
+                &mut timeline_semaphore_features,
+            )?;
+        }
+        let mut present_id_features = vk::PhysicalDevicePresentIdFeaturesKHR::default()
+            .present_id(true);
+        device_create_info = caps_checker.try_chain_device_feature(
+            &instance,
+            physical_device,
+            device_create_info,
+            ash::khr::present_id::NAME,
+            &mut present_id_features,
+        )?;
+
         let device = caps_checker.create_device(
             instance.clone(),
             physical_device,
             &mut device_create_info,
         )?;
 
+        if let Some(debug_utils) = debug_utils.as_mut() {
+            debug_utils.attach_device(device.as_ref());
+        }
+
         let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
         let device_limits = device_properties.limits;
 
@@ -226,12 +308,27 @@ impl VulkanInstance {
             warn!("Calibrated timestamps extension is supported");
             None
         };
-        let low_latency2 = if caps_checker.is_device_extension_enabled(ash::nv::low_latency2::NAME) {
-            Some(LowLatency2::new(instance.as_ref(), device.as_ref()))
+        let low_latency2 = if caps_checker.is_device_extension_enabled(ash::nv::low_latency2::NAME)
+            && caps_checker.is_device_extension_enabled(ash::khr::present_id::NAME)
+            && caps_checker.is_device_extension_enabled(&timeline_sem_name)
+            && timeline_semaphore_enabled {
+            match LowLatency2::new(instance.as_ref(), device.as_ref()) {
+                Ok(ll2) => Some(ll2),
+                Err(e) => {
+                    warn!("VK_NV_low_latency2 initialization failed, latency markers are disabled: {:?}", e);
+                    None
+                }
+            }
         } else {
             warn!("VK_NV_low_latency2 not available on this device");
             None
         };
+        let hdr_metadata = if caps_checker.is_device_extension_enabled(ash::ext::hdr_metadata::NAME) {
+            Some(HdrMetadata::new(instance.as_ref(), device.as_ref()))
+        } else {
+            warn!("VK_EXT_hdr_metadata not available on this device");
+            None
+        };
         let present_timing = if caps_checker.is_device_extension_enabled(present_timing_name.as_c_str()) {
             match PresentTiming::new(instance.as_ref(), device.as_ref()) {
                 Some(pt) => {
@@ -246,6 +343,13 @@ impl VulkanInstance {
             None
         };
 
+        let descriptor_indexing = if caps_checker.is_device_extension_enabled(ash::ext::descriptor_indexing::NAME) {
+            DescriptorIndexing::from_features(&descriptor_indexing_features)
+        } else {
+            warn!("VK_EXT_descriptor_indexing not available on this device");
+            DescriptorIndexing::default()
+        };
+
         let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
 
 
@@ -271,15 +375,50 @@ impl VulkanInstance {
             None,
             low_latency2.is_some(),
             present_timing.is_some(),
+            builder.swapchain_config.clone(),
         )?;
 
+        let mut capabilities = EnabledCapabilities {
+            validation,
+            layers: caps_checker.activated_layers(),
+            instance_extensions: caps_checker.activated_instance_extensions(),
+            device_extensions: caps_checker.activated_device_extensions(),
+            features,
+            missing_optional_features,
+        };
+        capabilities.set_chained_feature(DeviceFeature::TimelineSemaphore, timeline_semaphore_enabled);
+        capabilities.log();
+
+        let capture = match builder.effective_capture_path() {
+            Some(path) => match CaptureWriter::create(&path) {
+                Ok(mut writer) => {
+                    writer.write(&CaptureEvent::Header {
+                        version: capture::CAPTURE_VERSION,
+                        swapchain_format: swapchain_wrapper.get_surface_format().as_raw(),
+                        swapchain_extent: (extent.width, extent.height),
+                    });
+                    Some(parking_lot::Mutex::new(writer))
+                }
+                Err(e) => {
+                    warn!("Failed to create capture file {}: {:?}", path.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+
         let shared_state = SharedState::new(device.clone());
         let res = Arc::new(Self {
             entry,
             physical_device,
             device: device.clone(),
-            debug_report,
+            debug_utils,
             shared_state,
+            descriptor_indexing,
+            device_limits,
+            capabilities,
+            registry: Arc::new(ResourceRegistry::default()),
+            capture,
         });
         {
             let mut slot = INSTANCE_SLOT.lock().unwrap();
@@ -297,8 +436,45 @@ impl VulkanInstance {
             timestamp_pool,
             low_latency2,
             present_timing,
+            hdr_metadata,
             memory_types,
             memory_heaps,
         ))
     }
+    /// Shown in validation messages and graphics debuggers. No-op without VK_EXT_debug_utils
+    pub(crate) fn set_debug_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        if let Some(debug_utils) = &self.debug_utils {
+            debug_utils.set_object_name(handle, name);
+        }
+    }
+
+    /// Called when resource is destroyed, replay frees its copy on this event
+    pub(crate) fn unregister_resource<H: vk::Handle + Copy>(&self, kind: ResourceKind, handle: H) {
+        self.registry.unregister(kind, handle);
+        self.capture(|| [CaptureEvent::Destroy { kind, handle: handle.as_raw() }]);
+    }
+
+    pub(crate) fn capture_enabled(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Events are built only when capture is enabled
+    pub(crate) fn capture<I: IntoIterator<Item = CaptureEvent>>(&self, f: impl FnOnce() -> I) {
+        if let Some(capture) = &self.capture {
+            let mut writer = capture.lock();
+            for event in f() {
+                writer.write(&event);
+            }
+        }
+    }
+
+    pub(crate) fn flush_capture(&self) {
+        if let Some(capture) = &self.capture {
+            capture.lock().flush();
+        }
+    }
+
+    pub(crate) fn debug_utils(&self) -> Option<&VkDebugUtils> {
+        self.debug_utils.as_ref()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Frames are reported incomplete if GPU or display timings did not arrive after this many presents
+const MAX_PENDING_FRAMES: usize = 32;
+const MAX_TRACKED_SUBMISSIONS: usize = 64;
+/// Oldest reports are dropped if not drained
+const MAX_READY_FRAMES: usize = 64;
+
+/// Timings of a single presented frame. All timestamps are in `Instant` clock domain.
+#[derive(Debug, Clone)]
+pub struct FrameTimingReport {
+    /// Sequential number of the present
+    pub frame_index: u64,
+    /// Submission, present operation waited on
+    pub submission_num: usize,
+    /// Command buffer submitted to the queue
+    pub cpu_submit: Option<Instant>,
+    /// GPU execution time of the submission, if timestamp queries are supported
+    pub gpu_duration: Option<Duration>,
+    /// Requires calibrated timestamps
+    pub gpu_start: Option<Instant>,
+    /// Requires calibrated timestamps
+    pub gpu_end: Option<Instant>,
+    /// `vkQueuePresentKHR` returned
+    pub present_queued: Instant,
+    /// First pixel left the presentation engine. Requires present timing, available on sampled frames only
+    pub displayed: Option<Instant>,
+}
+
+impl FrameTimingReport {
+    /// CPU submit to first pixel out
+    pub fn latency(&self) -> Option<Duration> {
+        self.displayed?.checked_duration_since(self.cpu_submit?)
+    }
+}
+
+struct PendingFrame {
+    report: FrameTimingReport,
+    present_id: Option<u64>,
+    wait_gpu: bool,
+    wait_display: bool,
+}
+
+/// Matches submission, GPU and presentation timings into per-frame reports
+pub(crate) struct FrameTimingCollector {
+    frame_index: u64,
+    submit_times: VecDeque<(usize, Instant)>,
+    gpu_times: HashMap<usize, (u64, u64)>,
+    /// GPU timestamp and `Instant` taken at the same moment
+    gpu_anchor: Option<(u64, Instant)>,
+    /// Nanoseconds per GPU timestamp tick
+    timestamp_period: Option<f32>,
+    pending: VecDeque<PendingFrame>,
+    ready: VecDeque<FrameTimingReport>,
+}
+
+impl FrameTimingCollector {
+    pub fn new(timestamp_period: Option<f32>) -> Self {
+        Self {
+            frame_index: 0,
+            submit_times: VecDeque::new(),
+            gpu_times: HashMap::new(),
+            gpu_anchor: None,
+            timestamp_period,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// `FrameTimingReport::frame_index` of the next present
+    pub fn next_frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    pub fn on_gpu_sync_point(&mut self, gpu_tm: u64, instant: Instant) {
+        self.gpu_anchor = Some((gpu_tm, instant));
+    }
+
+    pub fn on_submit(&mut self, submission_num: usize, tm: Instant) {
+        if self.submit_times.len() >= MAX_TRACKED_SUBMISSIONS {
+            self.submit_times.pop_front();
+        }
+        self.submit_times.push_back((submission_num, tm));
+    }
+
+    pub fn on_gpu_timestamps(&mut self, submission_num: usize, start: u64, end: u64) {
+        if let Some(frame) = self.pending.iter_mut().find(|f| f.wait_gpu && f.report.submission_num == submission_num) {
+            Self::fill_gpu_times(&mut frame.report, self.timestamp_period, self.gpu_anchor, start, end);
+            frame.wait_gpu = false;
+        }
+        else {
+            if self.gpu_times.len() >= MAX_TRACKED_SUBMISSIONS {
+                let oldest = *self.gpu_times.keys().min().unwrap();
+                self.gpu_times.remove(&oldest);
+            }
+            self.gpu_times.insert(submission_num, (start, end));
+        }
+        self.collect_ready();
+    }
+
+    /// `present_id`: id passed with `VkPresentIdKHR`, display times can't be matched without it
+    /// `display_requested`: present timing was requested for this present
+    pub fn on_present(&mut self, submission_num: usize, present_id: Option<u64>, present_queued: Instant, display_requested: bool) {
+        let cpu_submit = self.submit_times.iter()
+            .find(|(n, _)| *n == submission_num)
+            .map(|(_, tm)| *tm);
+        let mut report = FrameTimingReport {
+            frame_index: self.frame_index,
+            submission_num,
+            cpu_submit,
+            gpu_duration: None,
+            gpu_start: None,
+            gpu_end: None,
+            present_queued,
+            displayed: None,
+        };
+        self.frame_index += 1;
+
+        let mut wait_gpu = self.timestamp_period.is_some();
+        if let Some((start, end)) = self.gpu_times.remove(&submission_num) {
+            Self::fill_gpu_times(&mut report, self.timestamp_period, self.gpu_anchor, start, end);
+            wait_gpu = false;
+        }
+
+        self.pending.push_back(PendingFrame {
+            report,
+            present_id,
+            wait_gpu,
+            wait_display: display_requested && present_id.is_some(),
+        });
+        while self.pending.len() > MAX_PENDING_FRAMES {
+            let frame = self.pending.pop_front().unwrap();
+            self.push_ready(frame.report);
+        }
+        self.collect_ready();
+    }
+
+    /// Display time of the present with given id. Results may arrive out of order or be skipped
+    pub fn on_displayed(&mut self, present_id: u64, displayed: Option<Instant>) {
+        if let Some(frame) = self.pending.iter_mut().find(|f| f.wait_display && f.present_id == Some(present_id)) {
+            frame.report.displayed = displayed;
+            frame.wait_display = false;
+        }
+        self.collect_ready();
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = FrameTimingReport> + '_ {
+        self.ready.drain(..)
+    }
+
+    fn fill_gpu_times(report: &mut FrameTimingReport, period: Option<f32>, anchor: Option<(u64, Instant)>, start: u64, end: u64) {
+        let Some(period) = period else {
+            return;
+        };
+        let ticks_to_duration = |ticks: u64| Duration::from_nanos((ticks as f64 * period as f64) as u64);
+        report.gpu_duration = Some(ticks_to_duration(end.saturating_sub(start)));
+
+        if let Some((anchor_tm, anchor_instant)) = anchor {
+            let to_instant = |tm: u64| {
+                if tm >= anchor_tm {
+                    anchor_instant.checked_add(ticks_to_duration(tm - anchor_tm))
+                }
+                else {
+                    anchor_instant.checked_sub(ticks_to_duration(anchor_tm - tm))
+                }
+            };
+            report.gpu_start = to_instant(start);
+            report.gpu_end = to_instant(end);
+        }
+    }
+
+    fn collect_ready(&mut self) {
+        while let Some(frame) = self.pending.front() && !frame.wait_gpu && !frame.wait_display {
+            let frame = self.pending.pop_front().unwrap();
+            self.push_ready(frame.report);
+        }
+    }
+
+    fn push_ready(&mut self, report: FrameTimingReport) {
+        if self.ready.len() >= MAX_READY_FRAMES {
+            self.ready.pop_front();
+        }
+        self.ready.push_back(report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_without_timestamps() {
+        let mut collector = FrameTimingCollector::new(None);
+        let submit = Instant::now();
+        collector.on_submit(1, submit);
+        collector.on_present(1, None, submit + Duration::from_millis(1), false);
+
+        let reports: Vec<_> = collector.drain().collect();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].cpu_submit, Some(submit));
+        assert!(reports[0].gpu_duration.is_none());
+        assert!(reports[0].displayed.is_none());
+    }
+
+    #[test]
+    fn test_gpu_and_display_times_matched() {
+        let mut collector = FrameTimingCollector::new(Some(1.0));
+        let anchor = Instant::now();
+        collector.on_gpu_sync_point(1_000, anchor);
+
+        collector.on_submit(5, anchor);
+        collector.on_present(5, Some(1), anchor + Duration::from_millis(2), true);
+        assert_eq!(collector.drain().count(), 0);
+
+        collector.on_gpu_timestamps(5, 2_000, 502_000);
+        assert_eq!(collector.drain().count(), 0);
+
+        let displayed = anchor + Duration::from_millis(10);
+        collector.on_displayed(1, Some(displayed));
+        let reports: Vec<_> = collector.drain().collect();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.gpu_duration, Some(Duration::from_micros(500)));
+        assert_eq!(report.gpu_start, Some(anchor + Duration::from_micros(1)));
+        assert_eq!(report.gpu_end, Some(anchor + Duration::from_micros(501)));
+        assert_eq!(report.latency(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_display_times_matched_by_present_id() {
+        let mut collector = FrameTimingCollector::new(None);
+        let submit = Instant::now();
+        for (submission_num, present_id) in [(1, 10), (2, 11), (3, 12)] {
+            collector.on_submit(submission_num, submit);
+            collector.on_present(submission_num, Some(present_id), submit, true);
+        }
+
+        // result for the first present was skipped by the driver, others arrive out of order
+        let displayed = submit + Duration::from_millis(20);
+        collector.on_displayed(12, Some(displayed));
+        collector.on_displayed(11, Some(displayed - Duration::from_millis(5)));
+        collector.on_displayed(99, Some(displayed));
+        assert_eq!(collector.drain().count(), 0);
+
+        collector.on_displayed(10, None);
+        let reports: Vec<_> = collector.drain().collect();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].displayed, None);
+        assert_eq!(reports[1].displayed, Some(displayed - Duration::from_millis(5)));
+        assert_eq!(reports[2].displayed, Some(displayed));
+    }
+
+    #[test]
+    fn test_display_not_awaited_without_present_id() {
+        let mut collector = FrameTimingCollector::new(None);
+        let submit = Instant::now();
+        collector.on_submit(1, submit);
+        collector.on_present(1, None, submit, true);
+
+        assert_eq!(collector.drain().count(), 1);
+    }
+
+    #[test]
+    fn test_undrained_reports_bounded() {
+        let mut collector = FrameTimingCollector::new(None);
+        let submit = Instant::now();
+        for i in 0..MAX_READY_FRAMES * 3 {
+            collector.on_submit(i, submit);
+            collector.on_present(i, None, submit, false);
+        }
+        assert_eq!(collector.ready.len(), MAX_READY_FRAMES);
+
+        // oldest reports are dropped
+        let reports: Vec<_> = collector.drain().collect();
+        assert_eq!(reports[0].frame_index, MAX_READY_FRAMES as u64 * 2);
+        assert_eq!(reports.last().unwrap().frame_index, MAX_READY_FRAMES as u64 * 3 - 1);
+    }
+}
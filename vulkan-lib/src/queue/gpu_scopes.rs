@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Frames are reported incomplete if their submissions are not waited after this many presents
+const MAX_PENDING_FRAMES: usize = 32;
+/// Oldest breakdowns are dropped if not drained
+const MAX_READY_FRAMES: usize = 64;
+
+/// GPU time of commands recorded in `RecordContext::timestamp_scope`
+#[derive(Debug, Clone)]
+pub struct GpuScopeTiming {
+    pub name: &'static str,
+    /// Number of enclosing scopes
+    pub depth: u32,
+    pub submission_num: usize,
+    pub duration: Duration,
+}
+
+/// Timestamp scopes of all submissions recorded before a present
+#[derive(Debug, Clone, Default)]
+pub struct GpuFrameBreakdown {
+    /// Matches `FrameTimingReport::frame_index` of the present
+    pub frame_index: u64,
+    /// In submission and recording order
+    pub scopes: Vec<GpuScopeTiming>,
+}
+
+impl GpuFrameBreakdown {
+    /// Sum of all scopes with this name
+    pub fn total(&self, name: &str) -> Duration {
+        self.scopes.iter().filter(|s| s.name == name).map(|s| s.duration).sum()
+    }
+
+    /// Sum of top-level scopes
+    pub fn total_gpu_time(&self) -> Duration {
+        self.scopes.iter().filter(|s| s.depth == 0).map(|s| s.duration).sum()
+    }
+}
+
+/// Scope read from query pool: name, depth, start and end timestamps
+pub(crate) type ResolvedScope = (&'static str, u32, u64, u64);
+
+struct PendingBreakdown {
+    breakdown: GpuFrameBreakdown,
+    pending_submissions: usize,
+    presented: bool,
+}
+
+/// Groups resolved timestamp scopes into per-frame breakdowns.
+/// Frame indices come from `FrameTimingCollector`, so breakdowns match frame timing reports
+pub(crate) struct GpuScopeCollector {
+    /// Nanoseconds per GPU timestamp tick
+    timestamp_period: f32,
+    pending: VecDeque<PendingBreakdown>,
+    ready: VecDeque<GpuFrameBreakdown>,
+}
+
+impl GpuScopeCollector {
+    pub fn new(timestamp_period: f32) -> Self {
+        Self {
+            timestamp_period,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Submission with timestamp scopes, recorded before present of `frame_index`
+    pub fn on_submit(&mut self, frame_index: u64) {
+        match self.pending.back_mut() {
+            Some(frame) if frame.breakdown.frame_index == frame_index => frame.pending_submissions += 1,
+            _ => self.pending.push_back(PendingBreakdown {
+                breakdown: GpuFrameBreakdown {
+                    frame_index,
+                    scopes: Vec::new(),
+                },
+                pending_submissions: 1,
+                presented: false,
+            }),
+        }
+    }
+
+    /// Empty `scopes` if results could not be read
+    pub fn on_resolved(&mut self, frame_index: u64, submission_num: usize, scopes: &[ResolvedScope]) {
+        let Some(frame) = self.pending.iter_mut().find(|f| f.breakdown.frame_index == frame_index) else {
+            return;
+        };
+        let period = self.timestamp_period as f64;
+        frame.breakdown.scopes.extend(scopes.iter().map(|&(name, depth, start, end)| GpuScopeTiming {
+            name,
+            depth,
+            submission_num,
+            duration: Duration::from_nanos((end.saturating_sub(start) as f64 * period) as u64),
+        }));
+        frame.pending_submissions = frame.pending_submissions.saturating_sub(1);
+        self.collect_ready();
+    }
+
+    /// Frames without submitted scopes are ignored
+    pub fn on_present(&mut self, frame_index: u64) {
+        if let Some(frame) = self.pending.back_mut() && frame.breakdown.frame_index == frame_index {
+            frame.presented = true;
+        }
+
+        while self.pending.len() > MAX_PENDING_FRAMES {
+            let frame = self.pending.pop_front().unwrap();
+            self.push_ready(frame.breakdown);
+        }
+        self.collect_ready();
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = GpuFrameBreakdown> + '_ {
+        self.ready.drain(..)
+    }
+
+    fn collect_ready(&mut self) {
+        while let Some(frame) = self.pending.front() && frame.presented && frame.pending_submissions == 0 {
+            let frame = self.pending.pop_front().unwrap();
+            self.push_ready(frame.breakdown);
+        }
+    }
+
+    fn push_ready(&mut self, breakdown: GpuFrameBreakdown) {
+        if self.ready.len() >= MAX_READY_FRAMES {
+            self.ready.pop_front();
+        }
+        self.ready.push_back(breakdown);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_breakdown() {
+        let mut collector = GpuScopeCollector::new(2.0);
+
+        // frame 0: upload submission and main submission
+        collector.on_submit(0);
+        collector.on_submit(0);
+        collector.on_present(0);
+
+        // frame 1 submitted before frame 0 is resolved
+        collector.on_submit(1);
+        collector.on_resolved(0, 1, &[("Uploads", 0, 100, 600)]);
+        assert_eq!(collector.drain().count(), 0);
+
+        collector.on_resolved(0, 2, &[("Main pass", 0, 1_000, 3_000), ("UI", 1, 2_000, 2_500), ("UI", 1, 2_600, 2_700)]);
+        let frames: Vec<_> = collector.drain().collect();
+        assert_eq!(frames.len(), 1);
+        let frame = &frames[0];
+        assert_eq!(frame.frame_index, 0);
+        assert_eq!(frame.scopes.len(), 4);
+        assert_eq!(frame.scopes[0].duration, Duration::from_nanos(1_000));
+        assert_eq!(frame.total("UI"), Duration::from_nanos(1_200));
+        assert_eq!(frame.total_gpu_time(), Duration::from_nanos(5_000));
+
+        // frame 1 is not reported until presented
+        collector.on_resolved(1, 3, &[("Main pass", 0, 0, 10)]);
+        assert_eq!(collector.drain().count(), 0);
+        collector.on_present(1);
+        let frames: Vec<_> = collector.drain().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame_index, 1);
+    }
+
+    #[test]
+    fn test_frame_index_from_frame_timing() {
+        let mut collector = GpuScopeCollector::new(1.0);
+
+        // frames 0 and 1 have no timestamp scopes
+        collector.on_present(0);
+        collector.on_present(1);
+        collector.on_submit(2);
+        collector.on_present(2);
+        collector.on_resolved(2, 5, &[("Main pass", 0, 0, 10)]);
+
+        let frames: Vec<_> = collector.drain().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame_index, 2);
+    }
+}
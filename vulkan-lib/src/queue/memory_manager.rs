@@ -133,6 +133,14 @@ impl MemoryManager {
             .expect("Guaranteed to support at least 1 device_local memory type for buffer").0 as u32
     }
 
+    pub fn memory_types(&self) -> &[MemoryType] {
+        &self.memory_types
+    }
+
+    pub fn memory_heaps(&self) -> &[MemoryHeap] {
+        &self.memory_heaps
+    }
+
     pub fn select_memory_type(&self, memory_type_bits: u32, algorithm: MemoryTypeAlgorithm) -> u32 {
         match algorithm {
             MemoryTypeAlgorithm::Host => self.best_host_type(memory_type_bits),
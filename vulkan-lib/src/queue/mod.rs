@@ -4,23 +4,27 @@ pub mod memory_manager;
 pub mod recording;
 pub mod semaphores;
 pub mod shared;
+pub mod frame_timing;
+pub mod gpu_scopes;
 
 use std::collections::HashMap;
 use std::sync;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use anyhow::Context;
 use ash::vk;
-use ash::vk::{AccessFlags, BufferMemoryBarrier, CommandBufferBeginInfo, DependencyFlags, Extent2D, ImageAspectFlags, ImageCreateFlags, ImageLayout, ImageMemoryBarrier, ImageSubresourceRange, ImageUsageFlags, ImageView, MemoryHeap, MemoryType, PhysicalDevice, PipelineBindPoint, PipelineStageFlags, Queue, Rect2D, RenderPassBeginInfo, SubpassContents, Viewport, WHOLE_SIZE};
+use ash::vk::{AccessFlags, BufferMemoryBarrier, CommandBufferBeginInfo, DependencyFlags, DeviceSize, Extent2D, ImageAspectFlags, ImageCreateFlags, ImageLayout, ImageMemoryBarrier, ImageSubresourceRange, ImageUsageFlags, ImageView, MemoryHeap, MemoryType, PhysicalDevice, PipelineBindPoint, PipelineStageFlags, Queue, Rect2D, RenderPassBeginInfo, SubpassContents, Viewport, WHOLE_SIZE};
 use log::{info, warn};
 use smallvec::{smallvec, SmallVec};
 use sparkles::monotonic::get_perf_frequency;
 use sparkles::{range_event_start, static_name};
 use sparkles::external_events::ExternalEventsSource;
 use strum::IntoDiscriminant;
-use crate::extensions::calibrated_timestamps::CalibratedTimestamps;
-use crate::extensions::low_latency2::{LowLatency2, ReflexMode};
+use crate::capture::{capture_submission, CaptureEvent};
+use crate::extensions::calibrated_timestamps::{CalibratedTimestamps, HostClockMapping};
+use crate::extensions::hdr_metadata::{HdrMasteringMetadata, HdrMetadata};
+use crate::extensions::low_latency2::{LatencyMarker, LatencyTimings, LowLatency2, ReflexMode};
 use crate::extensions::present_timing::PresentTiming;
 use crate::resources::image::ImageResource;
 use crate::resources::render_pass::{AttachmentUsage, FrameBufferAttachment, RenderPassResource};
@@ -32,11 +36,15 @@ use crate::queue::queue_local::QueueLocalToken;
 use crate::queue::recording::{DeviceCommand, DrawCommand, RecordContext, SpecificResourceUsage};
 use crate::queue::semaphores::{SemaphoreManager, WaitSemaphoreRef, WaitSemaphoreStagesRef, SemaphoreWaitOperation};
 use crate::resources::{LastResourceUsage, RequiredSync, ResourceUsage};
-use crate::swapchain_wrapper::SwapchainWrapper;
+use crate::swapchain_wrapper::{OutputColorSpace, SwapchainConfig, SwapchainFormatChange, SwapchainInfo, SwapchainWrapper};
+use crate::instance_builder::EnabledCapabilities;
+use crate::resources::registry::ResourceRegistry;
 use crate::VulkanInstance;
 use crate::wrappers::device::VkDeviceRef;
 use crate::wrappers::surface::VkSurfaceRef;
-use crate::wrappers::timestamp_pool::TimestampPool;
+use crate::wrappers::timestamp_pool::{ScopeQueryPool, TimestampPool};
+use crate::queue::frame_timing::{FrameTimingCollector, FrameTimingReport};
+use crate::queue::gpu_scopes::{GpuFrameBreakdown, GpuScopeCollector};
 
 /// Data for a single framebuffer and its attachments
 pub(crate) struct FramebufferData {
@@ -92,15 +100,23 @@ pub struct GraphicsQueue {
 
     last_time_sync_tm: Option<Instant>,
     sparkles_gpu_channel: ExternalEventsSource,
+    frame_timing: FrameTimingCollector,
 
 
     // queries
     timestamp_pool: Option<TimestampPool>,
+    scope_pool: Option<ScopeQueryPool>,
+    gpu_scopes: GpuScopeCollector,
 
     // extensions
     calibrated_timestamps: Option<CalibratedTimestamps>,
+    host_clock: Option<HostClockMapping>,
     low_latency2: Option<LowLatency2>,
+    /// Present id of the frame being prepared, used for latency markers and present timings
+    present_id: u64,
     present_timing: Option<PresentTiming>,
+    hdr_metadata: Option<HdrMetadata>,
+    mastering_metadata: Option<HdrMasteringMetadata>,
 
 
     memory_manager: MemoryManager,
@@ -118,12 +134,15 @@ impl GraphicsQueue {
         timestamp_pool: Option<TimestampPool>,
         low_latency2: Option<LowLatency2>,
         mut present_timing: Option<PresentTiming>,
+        hdr_metadata: Option<HdrMetadata>,
         memory_types: Vec<MemoryType>,
         memory_heaps: Vec<MemoryHeap>,
     ) -> Self {
         let surface = swapchain_wrapper.surface();
 
+        let host_clock = calibrated_timestamps.as_ref().and_then(|c| c.host_clock_mapping());
         if let Some(pt) = present_timing.as_mut() {
+            pt.set_host_clock(host_clock);
             pt.on_swapchain_created(swapchain_wrapper.get_swapchain(), swapchain_wrapper.image_count());
         }
 
@@ -134,7 +153,11 @@ impl GraphicsQueue {
             }
         }
 
+        let frame_timing = FrameTimingCollector::new(timestamp_pool.as_ref().map(|p| p.period()));
+
         let device = instance.device.clone();
+        let scope_pool = timestamp_pool.as_ref().map(|_| ScopeQueryPool::new(device.clone()));
+        let gpu_scopes = GpuScopeCollector::new(timestamp_pool.as_ref().map(|p| p.period()).unwrap_or(0.0));
         GraphicsQueue {
             device: device.clone(),
             instance,
@@ -152,10 +175,17 @@ impl GraphicsQueue {
 
             last_time_sync_tm: None,
             sparkles_gpu_channel,
+            frame_timing,
             timestamp_pool,
+            scope_pool,
+            gpu_scopes,
             calibrated_timestamps,
+            host_clock,
             low_latency2,
+            present_id: 1,
             present_timing,
+            hdr_metadata,
+            mastering_metadata: None,
 
             memory_manager: MemoryManager::new(
                 device.clone(),
@@ -176,6 +206,8 @@ impl GraphicsQueue {
     }
 
     fn get_or_create_framebuffers(&mut self, render_pass: &Arc<RenderPassResource>) -> SmallVec<[vk::Framebuffer; 4]> {
+        // stale render passes are rejected by RecordContext::render_pass
+        debug_assert_eq!(render_pass.swapchain_format(), self.swapchain_wrapper.get_surface_format());
         let framebuffer_set = self.framebuffers
             .entry(render_pass.render_pass)
             .or_insert_with(|| {
@@ -310,10 +342,22 @@ impl GraphicsQueue {
     }
 
     // Swapchain methods
-    pub fn recreate_resize(&mut self, new_extent: (u32, u32)) {
+    /// Returns Some if swapchain format has changed, see `recreate`
+    #[must_use]
+    pub fn recreate_resize(&mut self, new_extent: (u32, u32)) -> Option<SwapchainFormatChange> {
+        let config = self.swapchain_wrapper.config().clone();
+        self.recreate(new_extent, config)
+    }
+
+    /// Recreate swapchain with new configuration. Chosen values are available in `swapchain_info`.
+    /// If surface format has changed, render passes and pipelines must be recreated for the new format,
+    /// `RecordContext::render_pass` returns an error for render passes created for the old format.
+    #[must_use]
+    pub fn recreate(&mut self, new_extent: (u32, u32), config: SwapchainConfig) -> Option<SwapchainFormatChange> {
         #[cfg(feature = "sync-swapchain-recreate")]
         self.wait_idle();
         let g = range_event_start!("[Vulkan] Recreate swapchain");
+        self.instance.capture(|| [CaptureEvent::Resize { width: new_extent.0, height: new_extent.1 }]);
         let new_extent = Extent2D {
             width: new_extent.0,
             height: new_extent.1,
@@ -360,15 +404,16 @@ impl GraphicsQueue {
         }
 
         // 2. Recreate swapchain and recycle old swapchain images
-        let old_format = self.swapchain_wrapper.get_surface_format();
+        let old_format = self.swapchain_wrapper.surface_format();
         let old_swapchain_images = unsafe {
             self.swapchain_wrapper
-                .recreate(self.physical_device, new_extent, self.surface.clone())
+                .recreate(self.physical_device, new_extent, self.surface.clone(), config)
                 .unwrap()
         };
-        let new_format = self.swapchain_wrapper.get_surface_format();
-        if new_format != old_format {
-            unimplemented!("Swapchain format has changed");
+        let new_format = self.swapchain_wrapper.surface_format();
+        let format_change = SwapchainFormatChange::detect(old_format, new_format);
+        if let Some(change) = &format_change {
+            warn!("Swapchain format has changed: {:?} -> {:?}", change.old, change.new);
         }
 
         // Add old swapchain images to recycling queue
@@ -383,6 +428,31 @@ impl GraphicsQueue {
                 self.swapchain_wrapper.image_count(),
             );
         }
+        self.apply_hdr_metadata();
+
+        format_change
+    }
+
+    /// Set mastering display metadata for HDR output. Kept across swapchain recreations,
+    /// ignored while output color space is not HDR or VK_EXT_hdr_metadata is not available.
+    pub fn set_hdr_metadata(&mut self, metadata: HdrMasteringMetadata) {
+        self.mastering_metadata = Some(metadata);
+        self.apply_hdr_metadata();
+    }
+
+    fn apply_hdr_metadata(&self) {
+        let Some(metadata) = &self.mastering_metadata else {
+            return;
+        };
+        if !self.swapchain_wrapper.info().output_color_space.is_hdr() {
+            return;
+        }
+        if let Some(hdr_metadata) = &self.hdr_metadata {
+            hdr_metadata.set_metadata(self.swapchain_wrapper.get_swapchain(), metadata);
+        }
+        else {
+            warn!("VK_EXT_hdr_metadata is not available, mastering metadata ignored");
+        }
     }
 
     /// Toggle NVIDIA Reflex / Reflex+Boost on the current swapchain.
@@ -394,6 +464,31 @@ impl GraphicsQueue {
         }
     }
 
+    /// Mark frame phase for the frame being prepared. Present markers are set by `queue_present`.
+    /// No-op if VK_NV_low_latency2 isn't available on this device.
+    pub fn set_latency_marker(&self, marker: LatencyMarker) {
+        if let Some(ll2) = &self.low_latency2 {
+            ll2.set_marker(self.swapchain_wrapper.get_swapchain(), self.present_id, marker);
+        }
+    }
+
+    /// Call before sampling input for the next frame, blocks until the driver lets the frame start.
+    /// Returns time spent sleeping, zero if VK_NV_low_latency2 isn't available on this device.
+    pub fn latency_sleep(&mut self) -> Duration {
+        match &mut self.low_latency2 {
+            Some(ll2) => ll2.sleep(self.swapchain_wrapper.get_swapchain()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Driver-measured timings of recent frames. Empty if VK_NV_low_latency2 isn't available on this device.
+    pub fn latency_timings(&self) -> Vec<LatencyTimings> {
+        match &self.low_latency2 {
+            Some(ll2) => ll2.get_timings(self.swapchain_wrapper.get_swapchain()),
+            None => Vec::new(),
+        }
+    }
+
     pub fn wait_idle(&mut self) {
         let g = range_event_start!("[Vulkan] Wait queue idle");
         unsafe {
@@ -432,6 +527,25 @@ impl GraphicsQueue {
         self.swapchain_wrapper.get_extent()
     }
 
+    /// Layers, extensions and features enabled during initialization
+    pub fn capabilities(&self) -> &EnabledCapabilities {
+        &self.instance.capabilities
+    }
+
+    /// Live buffers, images, samplers, pipelines, render passes and descriptor sets
+    pub fn resource_registry(&self) -> &ResourceRegistry {
+        &self.instance.registry
+    }
+
+    pub fn swapchain_info(&self) -> &SwapchainInfo {
+        self.swapchain_wrapper.info()
+    }
+
+    /// Shaders must encode output for this color space
+    pub fn swapchain_color_space(&self) -> OutputColorSpace {
+        self.swapchain_wrapper.info().output_color_space
+    }
+
     fn handle_add_sync_point(&mut self) {
         if let Some(calibrated_timestamps) = &self.calibrated_timestamps {
             let g = range_event_start!("Add gpu time sync point");
@@ -439,12 +553,28 @@ impl GraphicsQueue {
                 self.last_time_sync_tm = Some(Instant::now());
 
                 if let Some((gpu_tm, host_tm, provider)) = calibrated_timestamps.get_timestamps_pair() {
+                    if let Some(host_clock) = self.host_clock && host_clock.domain() == provider
+                        && let Some(instant) = host_clock.to_instant(host_tm) {
+                        self.frame_timing.on_gpu_sync_point(gpu_tm, instant);
+                    }
                     self.sparkles_gpu_channel.push_sync_point(host_tm * (1_000_000_000 / get_perf_frequency()), gpu_tm);
                 }
             }
         }
     }
 
+    /// Reports of presented frames, available once GPU and display timings arrive.
+    /// Values not supported by the device are left empty.
+    pub fn drain_frame_timings(&mut self) -> impl Iterator<Item = FrameTimingReport> + '_ {
+        self.frame_timing.drain()
+    }
+
+    /// Per-frame GPU time of `RecordContext::timestamp_scope` ranges, available once submissions are waited.
+    /// Empty if timestamp queries are not supported
+    pub fn drain_gpu_scopes(&mut self) -> impl Iterator<Item = GpuFrameBreakdown> + '_ {
+        self.gpu_scopes.drain()
+    }
+
     pub fn record_device_commands<F>(&mut self, wait_ref: Option<WaitSemaphoreStagesRef>, f: F) -> usize
     where
         F: FnOnce(&mut RecordContext) {
@@ -462,6 +592,18 @@ impl GraphicsQueue {
         (new_wait_ref, sub_num)
     }
 
+    /// Reset queries begun in render pass, `commands` start after its RenderPassBegin
+    fn reset_render_pass_queries(&self, cmd_buffer: vk::CommandBuffer, commands: &[DeviceCommand]) {
+        let render_pass_commands = commands.iter().take_while(|cmd| !matches!(cmd, DeviceCommand::RenderPassEnd { .. }));
+        for cmd in render_pass_commands {
+            if let DeviceCommand::BeginQuery { pool, query } = cmd {
+                unsafe {
+                    self.device.cmd_reset_query_pool(cmd_buffer, pool.query_pool, *query, 1);
+                }
+            }
+        }
+    }
+
     fn split_into_barrier_groups<'a>(commands: &'a [DeviceCommand]) -> Vec<&'a [DeviceCommand]> {
         if commands.is_empty() {
             return vec![];
@@ -528,7 +670,8 @@ impl GraphicsQueue {
         let g = range_event_start!("Record and submit");
         self.handle_add_sync_point();
 
-        let mut record_context = RecordContext::new(); // lives for 'c
+        let mut record_context = RecordContext::new(&self.instance.device_limits) // lives for 'c
+            .with_swapchain_format(self.swapchain_wrapper.get_surface_format());
         f(&mut record_context);
 
         self.recycle_old_resources();
@@ -568,6 +711,17 @@ impl GraphicsQueue {
 
         // record commands grouped by barriers
         let commands = record_context.take_commands();
+        self.instance.capture(|| capture_submission(&commands, submission_num));
+
+        // reserve queries for timestamp scopes
+        let scope_count = commands.iter().filter(|c| matches!(c, DeviceCommand::TimestampBegin { .. })).count() as u32;
+        let scope_queries = match &mut self.scope_pool {
+            Some(scope_pool) if scope_count > 0 => scope_pool.allocate(cmd_buffer, scope_count),
+            _ => None,
+        };
+        let mut scopes = Vec::new();
+        let mut open_scopes = Vec::new();
+
         let groups = Self::split_into_barrier_groups(&commands);
 
         for (group_num, group) in groups.iter().enumerate() {
@@ -591,6 +745,7 @@ impl GraphicsQueue {
                         SpecificResourceUsage::BufferUsage {
                             usage,
                             buffer,
+                            range,
                         } => {
                             // 1) update state if waited on host
                             let buffer_inner = buffer.buffer_inner().get(&mut self.token);
@@ -601,13 +756,16 @@ impl GraphicsQueue {
 
                             // 3) add memory barrier if required
                             if let Some(required_sync) = required_sync {
-                                if buffer_barriers.iter().any(|b| b.buffer == buffer.buffer()) {
+                                let (offset, size) = range.map(|r| (r.start, r.end - r.start))
+                                    .unwrap_or((0, WHOLE_SIZE));
+                                if buffer_barriers.iter().any(|b| b.buffer == buffer.buffer() && buffer_ranges_overlap(b.offset, b.size, offset, size)) {
                                     panic!("Missing required pipeline barrier between same buffer usages! Required sync: {:?}", required_sync);
                                 }
 
                                 let barrier = BufferMemoryBarrier::default()
                                     .buffer(buffer.buffer())
-                                    .size(WHOLE_SIZE)
+                                    .offset(offset)
+                                    .size(size)
                                     .src_access_mask(required_sync.src_access)
                                     .dst_access_mask(required_sync.dst_access);
 
@@ -766,7 +924,7 @@ impl GraphicsQueue {
             }
 
             // 3) Record all commands from group to the command buffer
-            for cmd in group.iter() {
+            for (cmd_i, cmd) in group.iter().enumerate() {
                 #[cfg(feature = "recording-logs")]
                 info!("  Recording command: {:?}", cmd.discriminant());
                 match cmd {
@@ -848,6 +1006,8 @@ impl GraphicsQueue {
                             .framebuffer(framebuffers[*framebuffer_index as usize])
                             .clear_values(clear_values)
                             .render_area(Rect2D::default().extent(self.swapchain_wrapper.swapchain_extent));
+                        // queries must be reset outside of render pass, right before they are begun
+                        self.reset_render_pass_queries(cmd_buffer, &group[cmd_i + 1..]);
                         unsafe {
                             self.device.cmd_begin_render_pass(cmd_buffer, &info, SubpassContents::INLINE);
 
@@ -866,20 +1026,23 @@ impl GraphicsQueue {
                                                    instance_count,
                                                    first_vertex,
                                                    first_instance,
-                                                   new_vertex_buffer,
+                                                   new_vertex_buffers,
                                                    pipeline,
                                                    pipeline_changed: pipeline_handle_changed,
                                                    new_descriptor_set_bindings,
                                                } ) => {
                         unsafe {
-                            if let Some(vert_binding) = new_vertex_buffer {
-                                let offset = vert_binding.custom_range.as_ref().map(|r| r.start).unwrap_or(0) as u64;
-                                self.device.cmd_bind_vertex_buffers(cmd_buffer, 0, &[vert_binding.buffer.buffer], &[offset]);
+                            for run in consecutive_binding_runs(new_vertex_buffers) {
+                                let buffers: SmallVec<[_; 2]> = run.iter().map(|(_, b)| b.buffer.buffer).collect();
+                                let offsets: SmallVec<[_; 2]> = run.iter()
+                                    .map(|(_, b)| b.custom_range.as_ref().map(|r| r.start).unwrap_or(0) as u64)
+                                    .collect();
+                                self.device.cmd_bind_vertex_buffers(cmd_buffer, run[0].0, &buffers, &offsets);
                             }
                             if *pipeline_handle_changed {
                                 self.device.cmd_bind_pipeline(cmd_buffer, PipelineBindPoint::GRAPHICS, pipeline.pipeline);
                             }
-                            for (binding, descriptor_set) in new_descriptor_set_bindings {
+                            for (binding, descriptor_set, dynamic_offsets) in new_descriptor_set_bindings {
                                 // update descriptor set if have new bindings
                                 descriptor_set.update_descriptor_set(&self.device);
 
@@ -890,7 +1053,7 @@ impl GraphicsQueue {
                                     pipeline.pipeline_layout,
                                     *binding,
                                     &[descriptor_set.descriptor_set],
-                                    &[],
+                                    dynamic_offsets,
                                 );
                             }
                             self.device.cmd_draw(cmd_buffer, *vertex_count, *instance_count, *first_vertex, *first_instance);
@@ -904,6 +1067,53 @@ impl GraphicsQueue {
                             self.device.cmd_end_render_pass(cmd_buffer);
                         }
                     }
+                    DeviceCommand::BeginLabel { name, color } => {
+                        if let Some(debug_utils) = self.instance.debug_utils() {
+                            debug_utils.cmd_begin_label(cmd_buffer, name, *color);
+                        }
+                    }
+                    DeviceCommand::EndLabel => {
+                        if let Some(debug_utils) = self.instance.debug_utils() {
+                            debug_utils.cmd_end_label(cmd_buffer);
+                        }
+                    }
+                    DeviceCommand::InsertLabel { name, color } => {
+                        if let Some(debug_utils) = self.instance.debug_utils() {
+                            debug_utils.cmd_insert_label(cmd_buffer, name, *color);
+                        }
+                    }
+                    DeviceCommand::TimestampBegin { name } => {
+                        if let Some(scope_queries) = &scope_queries {
+                            let scope = scopes.len() as u32;
+                            self.scope_pool.as_ref().unwrap().write_timestamp(cmd_buffer, scope_queries, scope, false);
+                            scopes.push((*name, open_scopes.len() as u32));
+                            open_scopes.push(scope);
+                        }
+                    }
+                    DeviceCommand::TimestampEnd => {
+                        if let Some(scope_queries) = &scope_queries && let Some(scope) = open_scopes.pop() {
+                            self.scope_pool.as_ref().unwrap().write_timestamp(cmd_buffer, scope_queries, scope, true);
+                        }
+                    }
+                    DeviceCommand::BeginQuery { pool, query } => {
+                        unsafe {
+                            self.device.cmd_begin_query(cmd_buffer, pool.query_pool, *query, pool.kind().control_flags());
+                        }
+                    }
+                    DeviceCommand::EndQuery { pool, query } => {
+                        unsafe {
+                            self.device.cmd_end_query(cmd_buffer, pool.query_pool, *query);
+                        }
+                    }
+                    DeviceCommand::CopyQueryResults { pool, first_query, query_count, dst, dst_offset, stride } => {
+                        unsafe {
+                            self.device.cmd_copy_query_pool_results(
+                                cmd_buffer, pool.query_pool, *first_query, *query_count,
+                                dst.buffer, *dst_offset, *stride,
+                                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                            );
+                        }
+                    }
                 }
             }
             #[cfg(feature = "recording-logs")]
@@ -956,6 +1166,7 @@ impl GraphicsQueue {
 
         // submit
         let g = range_event_start!("Submit command buffer");
+        self.frame_timing.on_submit(submission_num, Instant::now());
         unsafe {
             self.device.queue_submit(self.queue, &[submit_info], fence).unwrap();
         }
@@ -965,10 +1176,27 @@ impl GraphicsQueue {
         if let Some(timestamp_pool) = &mut self.timestamp_pool {
             let ev_name = self.sparkles_gpu_channel.map_event_name(static_name!("Command buffer execution"));
             for (submission_num, begin, end) in timestamp_pool.read_timestamps() {
+                self.frame_timing.on_gpu_timestamps(submission_num, begin, end);
                 self.sparkles_gpu_channel.push_events(&[begin, end], &[(ev_name, 1), (ev_name, 0x81)])
             }
         }
 
+        // handle timestamp scopes
+        if let Some(scope_pool) = &mut self.scope_pool {
+            if let Some(scope_queries) = scope_queries {
+                let frame_index = self.frame_timing.next_frame_index();
+                self.gpu_scopes.on_submit(frame_index);
+                scope_pool.on_submitted(submission_num, frame_index, scope_queries, scopes);
+            }
+            for resolved in scope_pool.resolve(self.instance.shared_state.last_host_waited_submission().num()) {
+                for &(name, _, begin, end) in &resolved.scopes {
+                    let ev_name = self.sparkles_gpu_channel.map_event_name(static_name!(name));
+                    self.sparkles_gpu_channel.push_events(&[begin, end], &[(ev_name, 1), (ev_name, 0x81)])
+                }
+                self.gpu_scopes.on_resolved(resolved.frame_index, resolved.submission_num, &resolved.scopes);
+            }
+        }
+
         // register fence
         self.instance.shared_state.submitted_fence(submission_num, fence);
 
@@ -1003,6 +1231,9 @@ impl GraphicsQueue {
 
     // Present with semaphore wait
     pub fn queue_present(&mut self, image_index: u32, wait_ref: WaitSemaphoreRef) -> anyhow::Result<bool> {
+        self.instance.capture(|| [CaptureEvent::Present { image_index }]);
+        self.instance.flush_capture();
+
         // Convert to WaitSemaphoreStagesRef (present doesn't use stages)
         let wait_stages_ref = wait_ref.with_stages(PipelineStageFlags::ALL_COMMANDS);
 
@@ -1057,20 +1288,52 @@ impl GraphicsQueue {
             .unwrap_or(std::ptr::null());
         present_info.p_next = pt_chain.cast();
 
+        // low latency markers and present timings are matched to presents by id
+        let present_ids = [self.present_id];
+        let mut present_id_info = vk::PresentIdKHR::default()
+            .present_ids(&present_ids);
+        let present_id_enabled = self.instance.capabilities.has_device_extension(ash::khr::present_id::NAME);
+        if present_id_enabled {
+            present_id_info.p_next = pt_chain.cast();
+            present_info.p_next = (&present_id_info as *const vk::PresentIdKHR).cast();
+        }
+
+        self.set_latency_marker(LatencyMarker::PresentStart);
         let result = unsafe {
             self.swapchain_wrapper
                 .swapchain_loader
                 .queue_present(self.queue, &present_info)
         }.context("queue_present");
+        self.set_latency_marker(LatencyMarker::PresentEnd);
+        self.present_id += 1;
 
+        let frame_index = self.frame_timing.next_frame_index();
+        if let SemaphoreWaitOperation::SubmissionWait(sub_num) = wait_operation {
+            self.frame_timing.on_present(sub_num, present_id_enabled.then_some(present_ids[0]), Instant::now(), !pt_chain.is_null());
+        }
+        self.gpu_scopes.on_present(frame_index);
         if let Some(pt) = self.present_timing.as_mut() {
-            pt.drain_and_log(swapchain);
+            for (present_id, displayed) in pt.drain_and_log(swapchain) {
+                self.frame_timing.on_displayed(present_id, displayed);
+            }
         }
 
         result
     }
 }
 
+/// Runs of consecutive bindings, each bound with one vkCmdBindVertexBuffers call
+fn consecutive_binding_runs<T>(bindings: &[(u32, T)]) -> impl Iterator<Item = &[(u32, T)]> {
+    bindings.chunk_by(|(a, _), (b, _)| a + 1 == *b)
+}
+
+/// Ranges in `BufferMemoryBarrier` form, size can be WHOLE_SIZE
+fn buffer_ranges_overlap(offset_a: DeviceSize, size_a: DeviceSize, offset_b: DeviceSize, size_b: DeviceSize) -> bool {
+    let end_a = if size_a == WHOLE_SIZE { DeviceSize::MAX } else { offset_a + size_a };
+    let end_b = if size_b == WHOLE_SIZE { DeviceSize::MAX } else { offset_b + size_b };
+    offset_a < end_b && offset_b < end_a
+}
+
 impl Drop for GraphicsQueue {
     fn drop(&mut self) {
         info!("Dropping graphics queue >.<");
@@ -1105,3 +1368,43 @@ impl OptionSeqNumShared {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::WHOLE_SIZE;
+    use super::{buffer_ranges_overlap, consecutive_binding_runs};
+
+    #[test]
+    fn ranges_overlap() {
+        assert!(buffer_ranges_overlap(0, 16, 8, 16));
+        assert!(buffer_ranges_overlap(8, 16, 0, 16));
+        assert!(buffer_ranges_overlap(0, 64, 16, 8));
+    }
+
+    #[test]
+    fn adjacent_ranges_do_not_overlap() {
+        assert!(!buffer_ranges_overlap(0, 16, 16, 16));
+        assert!(!buffer_ranges_overlap(16, 16, 0, 16));
+    }
+
+    #[test]
+    fn whole_size_ranges() {
+        assert!(buffer_ranges_overlap(0, WHOLE_SIZE, 1024, 16));
+        assert!(buffer_ranges_overlap(256, WHOLE_SIZE, 0, WHOLE_SIZE));
+        assert!(!buffer_ranges_overlap(256, WHOLE_SIZE, 0, 256));
+    }
+
+    #[test]
+    fn vertex_buffer_binding_runs() {
+        let bindings = [(0, "quad"), (1, "instances"), (3, "colors"), (4, "normals"), (6, "uvs")];
+        let runs: Vec<_> = consecutive_binding_runs(&bindings)
+            .map(|run| (run[0].0, run.iter().map(|(_, b)| *b).collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(runs, [
+            (0, vec!["quad", "instances"]),
+            (3, vec!["colors", "normals"]),
+            (6, vec!["uvs"]),
+        ]);
+        assert_eq!(consecutive_binding_runs::<()>(&[]).count(), 0);
+    }
+}
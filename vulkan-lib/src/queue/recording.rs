@@ -1,10 +1,11 @@
 use strum::EnumDiscriminants;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
 use std::{iter, mem};
 use std::ops::{Deref, DerefMut, Range};
 use std::sync::Arc;
 use smallvec::{smallvec, SmallVec};
-use ash::vk::{self, AccessFlags, BufferCopy, BufferImageCopy, ClearValue, DeviceSize, Format, ImageAspectFlags, ImageLayout, PipelineStageFlags};
+use ash::vk::{self, AccessFlags, BufferCopy, BufferImageCopy, ClearValue, DescriptorType, DeviceSize, Format, ImageAspectFlags, ImageLayout, PipelineStageFlags};
 use log::{error, warn};
 use crate::queue::{FramebufferSet, OptionSeqNumShared};
 use crate::queue::queue_local::{QueueLocal, QueueLocalToken};
@@ -12,10 +13,13 @@ use crate::resources::buffer::{BufferResource, BufferResourceInner};
 use crate::resources::descriptor_set::{BoundResource, DescriptorSetResource};
 use crate::resources::image::ImageResource;
 use crate::resources::pipeline::GraphicsPipelineResource;
+use crate::resources::query_pool::QueryPoolResource;
 use crate::resources::render_pass::RenderPassResource;
 use crate::resources::{RequiredSync, ResourceUsage};
 use crate::resources::staging_buffer::{StagingBuffer, StagingBufferRange};
+use crate::shaders::updates::BufferUpdate;
 use crate::swapchain_wrapper::SwapchainImages;
+use crate::wrappers::debug_utils::to_cstring;
 
 pub(crate) enum AnyBufferRange {
     Staging(StagingBufferRange),
@@ -126,22 +130,60 @@ fn prepare_buffer_copy(src: &AnyBufferRange, dst: &BufferRange) -> BufferCopy {
         .size(size)
 }
 
+/// One offset per dynamic descriptor of the set
+pub type DynamicOffsets = SmallVec<[u32; 2]>;
+/// Set index, descriptor set and its dynamic offsets
+pub type DescriptorSetBindings = SmallVec<[(u32, Arc<DescriptorSetResource>, DynamicOffsets); 4]>;
+
 pub struct RecordContext {
     commands: Vec<DeviceCommand>,
     bound_pipeline: Option<Arc<GraphicsPipelineResource>>,
     pipeline_changed: bool,
-    bound_descriptor_sets: HashMap<u32, Arc<DescriptorSetResource>>,
-    bound_vertex_buffer: Option<BufferRange>
+    bound_descriptor_sets: HashMap<u32, (Arc<DescriptorSetResource>, DynamicOffsets)>,
+    /// Binding index and buffer, sorted by binding
+    bound_vertex_buffers: SmallVec<[(u32, BufferRange); 2]>,
+    min_uniform_buffer_offset_alignment: DeviceSize,
+    min_storage_buffer_offset_alignment: DeviceSize,
+    /// Debug labels begun and not yet ended
+    open_labels: u32,
+    /// Queries begun in current recording, each can be begun once per submission
+    begun_queries: HashSet<(vk::QueryPool, u32)>,
+    /// Queries begun in current render pass and not yet ended
+    active_queries: SmallVec<[(Arc<QueryPoolResource>, u32); 2]>,
+    /// Current swapchain format, render passes for other formats are rejected
+    swapchain_format: Option<Format>,
 }
 
 impl RecordContext {
-    pub fn new() -> Self {
+    pub fn new(device_limits: &vk::PhysicalDeviceLimits) -> Self {
         Self {
             commands: Vec::new(),
             bound_pipeline: None,
             pipeline_changed: false,
-            bound_vertex_buffer: None,
+            bound_vertex_buffers: SmallVec::new(),
             bound_descriptor_sets: HashMap::new(),
+            min_uniform_buffer_offset_alignment: device_limits.min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment: device_limits.min_storage_buffer_offset_alignment,
+            open_labels: 0,
+            begun_queries: HashSet::new(),
+            active_queries: SmallVec::new(),
+            swapchain_format: None,
+        }
+    }
+
+    pub fn with_swapchain_format(mut self, swapchain_format: Format) -> Self {
+        self.swapchain_format = Some(swapchain_format);
+        self
+    }
+
+    /// Framebuffers are created for the current swapchain format, so render passes created before a format change can't be used
+    fn check_render_pass_format(&self, render_pass_format: Format) -> anyhow::Result<()> {
+        match self.swapchain_format {
+            Some(swapchain_format) if swapchain_format != render_pass_format => anyhow::bail!(
+                "Render pass was created for swapchain format {:?}, but current format is {:?}. Render passes must be recreated after swapchain format change",
+                render_pass_format, swapchain_format
+            ),
+            _ => Ok(()),
         }
     }
 
@@ -150,15 +192,73 @@ impl RecordContext {
         self.pipeline_changed = true;
     }
 
+    /// Dynamic bindings of the set, if any, are bound with zero offsets
     pub fn bind_descriptor_set(&mut self, set: u32, descriptor_set: Arc<DescriptorSetResource>) {
+        let dynamic_offsets = smallvec![0; descriptor_set.dynamic_buffers().len()];
+        self.bind_descriptor_set_impl(set, descriptor_set, dynamic_offsets);
+    }
+
+    /// Bind descriptor set with one offset per dynamic descriptor, ordered by binding number, then by array element.
+    /// Offsets must be aligned to minUniformBufferOffsetAlignment (minStorageBufferOffsetAlignment for storage buffers)
+    /// and keep the bound range inside the buffer.
+    #[must_use]
+    pub fn try_bind_descriptor_set_dynamic(&mut self, set: u32, descriptor_set: Arc<DescriptorSetResource>, dynamic_offsets: &[u32]) -> Option<()> {
+        let dynamic_buffers: SmallVec<[_; 2]> = descriptor_set.dynamic_buffers().into_iter()
+            .map(|(ty, buffer)| (ty, buffer.map(|(buffer, range)| (buffer.size() as DeviceSize, range))))
+            .collect();
+        self.validate_dynamic_offsets(&dynamic_buffers, dynamic_offsets)?;
+
+        self.bind_descriptor_set_impl(set, descriptor_set, SmallVec::from_slice(dynamic_offsets));
+        Some(())
+    }
+
+    /// `dynamic_buffers` are descriptor types with bound buffer size and range
+    fn validate_dynamic_offsets(&self, dynamic_buffers: &[(DescriptorType, Option<(DeviceSize, DeviceSize)>)], dynamic_offsets: &[u32]) -> Option<()> {
+        if dynamic_buffers.len() != dynamic_offsets.len() {
+            warn!("Descriptor set has {} dynamic descriptors, but {} dynamic offsets provided!", dynamic_buffers.len(), dynamic_offsets.len());
+            return None;
+        }
+
+        for (i, ((descriptor_type, buffer), &offset)) in dynamic_buffers.iter().zip(dynamic_offsets).enumerate() {
+            let alignment = if *descriptor_type == DescriptorType::UNIFORM_BUFFER_DYNAMIC {
+                self.min_uniform_buffer_offset_alignment
+            }
+            else {
+                self.min_storage_buffer_offset_alignment
+            };
+            if !(offset as DeviceSize).is_multiple_of(alignment) {
+                warn!("Dynamic offset {} of descriptor {} is not aligned to {}!", offset, i, alignment);
+                return None;
+            }
+
+            if let Some((buffer_size, range)) = buffer && offset as DeviceSize + range > *buffer_size {
+                warn!("Dynamic offset {} with range {} of descriptor {} exceeds buffer size {}!", offset, range, i, buffer_size);
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn bind_descriptor_set_impl(&mut self, set: u32, descriptor_set: Arc<DescriptorSetResource>, dynamic_offsets: DynamicOffsets) {
         descriptor_set.lock_updates();
-        if let Some(prev) = self.bound_descriptor_sets.insert(set, descriptor_set) {
+        if let Some((prev, _)) = self.bound_descriptor_sets.insert(set, (descriptor_set.clone(), dynamic_offsets))
+            && !Arc::ptr_eq(&prev, &descriptor_set) {
             prev.unlock_updates();
         }
     }
 
     pub fn bind_vertex_buffer(&mut self, buf: BufferRange) {
-        self.bound_vertex_buffer = Some(buf);
+        self.bind_vertex_buffers(0, &[buf]);
+    }
+
+    /// Bind `bufs` to consecutive bindings starting at `first`, each from the start of its range
+    pub fn bind_vertex_buffers(&mut self, first: u32, bufs: &[BufferRange]) {
+        for (binding, buf) in (first..).zip(bufs) {
+            match self.bound_vertex_buffers.binary_search_by_key(&binding, |(b, _)| *b) {
+                Ok(i) => self.bound_vertex_buffers[i].1 = buf.clone(),
+                Err(i) => self.bound_vertex_buffers.insert(i, (binding, buf.clone())),
+            }
+        }
     }
 
     #[track_caller]
@@ -180,6 +280,27 @@ impl RecordContext {
         })
     }
 
+    /// Copy dirty ranges packed by `DirtyUpdates::write` to their offsets in `dst`, one region per contiguous range
+    pub fn copy_buffer_update(&mut self, update: BufferUpdate, dst: Arc<BufferResource>) {
+        let BufferUpdate { staging, mut regions } = update;
+        let dst_size = dst.size() as DeviceSize;
+        regions.retain(|r| {
+            let fits = r.dst_offset + r.size <= dst_size;
+            if !fits {
+                warn!("copy_buffer_update: region {}..{} is out of destination buffer size {}, skipped", r.dst_offset, r.dst_offset + r.size, dst_size);
+            }
+            fits
+        });
+        if regions.is_empty() {
+            return;
+        }
+        self.commands.push(DeviceCommand::CopyBuffer {
+            src: staging.into(),
+            dst,
+            regions: regions.into_iter().collect(),
+        })
+    }
+
     /// Copy data from buffer range to full contents of the image.
     /// Safety:
     /// - bytes per texel must correctly represent texel block size in bytes.
@@ -219,6 +340,24 @@ impl RecordContext {
         })
     }
 
+    /// Copy with explicit regions, offsets are absolute in source and destination buffers
+    pub(crate) fn copy_buffer_regions(&mut self, src: AnyBufferRange, dst: Arc<BufferResource>, regions: SmallVec<[BufferCopy; 1]>) {
+        self.commands.push(DeviceCommand::CopyBuffer {
+            src,
+            dst,
+            regions
+        })
+    }
+
+    /// Copy with explicit regions, buffer offsets are absolute in source buffer
+    pub(crate) fn copy_buffer_to_image_regions(&mut self, src: AnyBufferRange, dst: Arc<ImageResource>, regions: SmallVec<[BufferImageCopy; 1]>) {
+        self.commands.push(DeviceCommand::CopyBufferToImage {
+            src,
+            dst,
+            regions
+        })
+    }
+
     pub fn fill_buffer(&mut self, buffer: BufferRange, data: u32) {
         let (offset, size) = if let Some(range) = buffer.custom_range {
             (range.start, range.end - range.start)
@@ -262,10 +401,73 @@ impl RecordContext {
         self.commands.push(DeviceCommand::Barrier)
     }
 
-    pub fn render_pass<F>(&mut self, render_pass: Arc<RenderPassResource>, framebuffer_index: u32, clear_values: SmallVec<[ClearValue; 3]>, f: F)
+    /// Open labeled region of commands, shown in graphics debuggers. Must be closed with `end_label`
+    pub fn begin_label(&mut self, name: &str, color: [f32; 4]) {
+        self.open_labels += 1;
+        self.commands.push(DeviceCommand::BeginLabel {
+            name: to_cstring(name),
+            color,
+        })
+    }
+
+    pub fn end_label(&mut self) {
+        if self.open_labels == 0 {
+            warn!("end_label called without matching begin_label, ignored");
+            return;
+        }
+        self.open_labels -= 1;
+        self.commands.push(DeviceCommand::EndLabel)
+    }
+
+    /// Single label between commands
+    pub fn insert_label(&mut self, name: &str, color: [f32; 4]) {
+        self.commands.push(DeviceCommand::InsertLabel {
+            name: to_cstring(name),
+            color,
+        })
+    }
+
+    /// Measure GPU time of commands recorded in `f`. Results are available in
+    /// `GraphicsQueue::drain_gpu_scopes` and in the "Vulkan GPU" sparkles source once submission is waited
+    pub fn timestamp_scope<F>(&mut self, name: &'static str, f: F)
+    where
+        F: FnOnce(&mut RecordContext)
+    {
+        self.commands.push(DeviceCommand::TimestampBegin { name });
+        f(self);
+        self.commands.push(DeviceCommand::TimestampEnd);
+    }
+
+    /// Copy results of `queries` into `dst` as u64 values, `values_per_query` values per query.
+    /// Waits for queries to complete on device
+    pub fn copy_query_results(&mut self, pool: Arc<QueryPoolResource>, queries: Range<u32>, dst: BufferRange) {
+        if queries.is_empty() || queries.end > pool.count() {
+            warn!("copy_query_results skipped: query range {:?} is out of pool size {}", queries, pool.count());
+            return;
+        }
+        let stride = pool.kind().values_per_query() as u64 * size_of::<u64>() as u64;
+        let size = stride * queries.len() as u64;
+        if (dst.len() as u64) < size {
+            warn!("copy_query_results skipped: destination range of {} bytes is smaller than {} bytes of results", dst.len(), size);
+            return;
+        }
+        let dst_offset = dst.custom_range.as_ref().map(|r| r.start).unwrap_or(0) as u64;
+        self.commands.push(DeviceCommand::CopyQueryResults {
+            pool,
+            first_query: queries.start,
+            query_count: queries.len() as u32,
+            dst: dst.buffer,
+            dst_offset,
+            stride,
+        })
+    }
+
+    /// Records render pass commands. Fails without recording anything if the render pass was created for another swapchain format
+    pub fn render_pass<F>(&mut self, render_pass: Arc<RenderPassResource>, framebuffer_index: u32, clear_values: SmallVec<[ClearValue; 3]>, f: F) -> anyhow::Result<()>
     where
         F: FnOnce(&mut RenderPassContext<'_>)
     {
+        self.check_render_pass_format(render_pass.swapchain_format())?;
         self.commands.push(DeviceCommand::RenderPassBegin {
             render_pass: render_pass.clone(),
             framebuffer_index,
@@ -275,17 +477,31 @@ impl RecordContext {
             base: &mut *self,
         };
         f(&mut render_pass_ctx);
+        if !self.active_queries.is_empty() {
+            warn!("{} queries were not ended, ending at the end of render pass", self.active_queries.len());
+            for (pool, query) in mem::take(&mut self.active_queries) {
+                self.commands.push(DeviceCommand::EndQuery { pool, query });
+            }
+        }
         self.commands.push(DeviceCommand::RenderPassEnd {
             render_pass,
             framebuffer_index,
         });
+        Ok(())
     }
 
     pub(crate) fn take_commands(&mut self) -> Vec<DeviceCommand> {
+        if self.open_labels > 0 {
+            warn!("{} debug labels were not ended, closing at the end of recording", self.open_labels);
+            for _ in 0..mem::take(&mut self.open_labels) {
+                self.commands.push(DeviceCommand::EndLabel);
+            }
+        }
+        self.begun_queries.clear();
         mem::take(&mut self.commands)
     }
     pub(crate) fn unlock_descriptor_sets(&self) {
-        for ds in self.bound_descriptor_sets.values() {
+        for (ds, _) in self.bound_descriptor_sets.values() {
             ds.unlock_updates();
         }
     }
@@ -314,13 +530,54 @@ impl<'a> RenderPassContext<'a> {
         panic!("Pipeline barriers are not allowed inside render passes! Barriers must be placed before RenderPassBegin.");
     }
 
+    /// Same as `RecordContext::timestamp_scope`, for commands inside render pass
+    pub fn timestamp_scope<F>(&mut self, name: &'static str, f: F)
+    where
+        F: FnOnce(&mut RenderPassContext<'_>)
+    {
+        self.commands.push(DeviceCommand::TimestampBegin { name });
+        f(self);
+        self.commands.push(DeviceCommand::TimestampEnd);
+    }
+
+    /// Begin query, ended with `end_query` in the same render pass. Query is reset right before the render pass,
+    /// so it can be begun once per submission. Only one query of each type can be active at a time
+    pub fn begin_query(&mut self, pool: &Arc<QueryPoolResource>, query: u32) {
+        if query >= pool.count() {
+            warn!("begin_query skipped: query {} is out of pool size {}", query, pool.count());
+            return;
+        }
+        if self.active_queries.iter().any(|(p, _)| p.kind().query_type() == pool.kind().query_type()) {
+            warn!("begin_query skipped: query of type {:?} is already active", pool.kind().query_type());
+            return;
+        }
+        if !self.begun_queries.insert((pool.query_pool, query)) {
+            warn!("begin_query skipped: query {} was already used in this submission", query);
+            return;
+        }
+        self.active_queries.push((pool.clone(), query));
+        self.commands.push(DeviceCommand::BeginQuery {
+            pool: pool.clone(),
+            query,
+        })
+    }
+
+    pub fn end_query(&mut self, pool: &Arc<QueryPoolResource>, query: u32) {
+        let Some(i) = self.active_queries.iter().position(|(p, q)| Arc::ptr_eq(p, pool) && *q == query) else {
+            warn!("end_query called without matching begin_query, ignored");
+            return;
+        };
+        let (pool, query) = self.active_queries.remove(i);
+        self.commands.push(DeviceCommand::EndQuery { pool, query })
+    }
+
     pub fn draw(&mut self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
         let mut new_descriptor_set_bindings = SmallVec::new();
-        for (i, descriptor_set) in &self.bound_descriptor_sets {
-            new_descriptor_set_bindings.push((*i, descriptor_set.clone()));
+        for (i, (descriptor_set, dynamic_offsets)) in &self.bound_descriptor_sets {
+            new_descriptor_set_bindings.push((*i, descriptor_set.clone(), dynamic_offsets.clone()));
         }
         self.bound_descriptor_sets.clear();
-        let new_vertex_buffer = self.bound_vertex_buffer.take();
+        let new_vertex_buffers = mem::take(&mut self.bound_vertex_buffers);
         let pipeline = self.bound_pipeline.clone().expect("You must bind pipeline before draw command");
         let pipeline_changed = self.pipeline_changed;
         self.pipeline_changed = false;
@@ -330,7 +587,7 @@ impl<'a> RenderPassContext<'a> {
             instance_count,
             first_vertex,
             first_instance,
-            new_vertex_buffer,
+            new_vertex_buffers,
             new_descriptor_set_bindings,
             pipeline,
             pipeline_changed,
@@ -344,10 +601,12 @@ pub enum DrawCommand {
         instance_count: u32,
         first_vertex: u32,
         first_instance: u32,
-        new_vertex_buffer: Option<BufferRange>,
+        /// Binding index and buffer, sorted by binding
+        new_vertex_buffers: SmallVec<[(u32, BufferRange); 2]>,
         pipeline: Arc<GraphicsPipelineResource>,
         pipeline_changed: bool,
-        new_descriptor_set_bindings: SmallVec<[(u32, Arc<DescriptorSetResource>); 4]>,
+        /// Set index, descriptor set and its dynamic offsets
+        new_descriptor_set_bindings: DescriptorSetBindings,
     },
 }
 
@@ -382,7 +641,9 @@ impl AnyBuffer {
 pub(crate) enum SpecificResourceUsage {
     BufferUsage {
         usage: ResourceUsage,
-        buffer: AnyBuffer
+        buffer: AnyBuffer,
+        /// Accessed byte range, None for the whole buffer
+        range: Option<Range<DeviceSize>>,
     },
     ImageUsage {
         usage: ResourceUsage,
@@ -446,6 +707,35 @@ pub enum DeviceCommand {
         render_pass: Arc<RenderPassResource>,
         framebuffer_index: u32,
     },
+    BeginLabel {
+        name: CString,
+        color: [f32; 4],
+    },
+    EndLabel,
+    InsertLabel {
+        name: CString,
+        color: [f32; 4],
+    },
+    TimestampBegin {
+        name: &'static str,
+    },
+    TimestampEnd,
+    BeginQuery {
+        pool: Arc<QueryPoolResource>,
+        query: u32,
+    },
+    EndQuery {
+        pool: Arc<QueryPoolResource>,
+        query: u32,
+    },
+    CopyQueryResults {
+        pool: Arc<QueryPoolResource>,
+        first_query: u32,
+        query_count: u32,
+        dst: Arc<BufferResource>,
+        dst_offset: u64,
+        stride: u64,
+    },
 }
 
 impl DeviceCommand {
@@ -474,6 +764,7 @@ impl DeviceCommand {
                                 AnyBufferRange::Staging(s) => AnyBuffer::Staging(s.buffer.clone()),
                                 AnyBufferRange::Device(d) => AnyBuffer::Device(d.buffer.clone()),
                             },
+                            range: None,
                         },
                         SpecificResourceUsage::BufferUsage {
                             usage: ResourceUsage::new(
@@ -482,6 +773,7 @@ impl DeviceCommand {
                                 AccessFlags::TRANSFER_WRITE,
                             ),
                             buffer: AnyBuffer::Device(dst.clone()),
+                            range: None,
                         },
                     ].into_iter()
                 )
@@ -508,6 +800,7 @@ impl DeviceCommand {
                                 AnyBufferRange::Staging(s) => AnyBuffer::Staging(s.buffer.clone()),
                                 AnyBufferRange::Device(d) => AnyBuffer::Device(d.buffer.clone()),
                             },
+                            range: None,
                         },
                         SpecificResourceUsage::ImageUsage {
                             usage: ResourceUsage::new(
@@ -532,10 +825,32 @@ impl DeviceCommand {
                             AccessFlags::TRANSFER_WRITE,
                         ),
                         buffer: AnyBuffer::Device(buffer.clone()),
+                        range: None,
                     },
                 ))
             }
             DeviceCommand::Barrier => Box::new(iter::empty()),
+            DeviceCommand::BeginLabel { .. } | DeviceCommand::EndLabel | DeviceCommand::InsertLabel { .. } => Box::new(iter::empty()),
+            DeviceCommand::TimestampBegin { .. } | DeviceCommand::TimestampEnd => Box::new(iter::empty()),
+            DeviceCommand::BeginQuery { pool, .. } | DeviceCommand::EndQuery { pool, .. } => {
+                pool.submission_usage.store(Some(submission_num));
+                Box::new(iter::empty())
+            }
+            DeviceCommand::CopyQueryResults { pool, query_count, dst, dst_offset, stride, .. } => {
+                pool.submission_usage.store(Some(submission_num));
+                dst.submission_usage.store(Some(submission_num));
+                Box::new(iter::once(
+                    SpecificResourceUsage::BufferUsage {
+                        usage: ResourceUsage::new(
+                            submission_num,
+                            PipelineStageFlags::TRANSFER,
+                            AccessFlags::TRANSFER_WRITE,
+                        ),
+                        buffer: AnyBuffer::Device(dst.clone()),
+                        range: Some(*dst_offset..*dst_offset + *stride * *query_count as u64),
+                    },
+                ))
+            }
             DeviceCommand::ImageLayoutTransition {image, new_layout, image_aspect} => {
                 image.submission_usage.store(Some(submission_num));
                 Box::new(iter::once(
@@ -665,7 +980,7 @@ impl DeviceCommand {
             }
             DeviceCommand::DrawCommand(
                 DrawCommand::Draw {
-                    new_vertex_buffer,
+                    new_vertex_buffers,
                     new_descriptor_set_bindings,
                     pipeline,
                     pipeline_changed,
@@ -673,7 +988,7 @@ impl DeviceCommand {
                 }
             ) => {
                 let mut usages: SmallVec<[_; 10]> = smallvec![];
-                if let Some(v_buf) = new_vertex_buffer {
+                for (_, v_buf) in new_vertex_buffers {
                     usages.push(SpecificResourceUsage::BufferUsage {
                         buffer: AnyBuffer::Device(v_buf.buffer.clone()),
                         usage: ResourceUsage::new(
@@ -681,56 +996,93 @@ impl DeviceCommand {
                             PipelineStageFlags::VERTEX_INPUT,
                             AccessFlags::VERTEX_ATTRIBUTE_READ,
                         ),
+                        range: None,
                     });
                     v_buf.buffer.submission_usage.store(Some(submission_num));
                 }
-                for (set_index, descriptor_set) in new_descriptor_set_bindings {
+                for (set_index, descriptor_set, dynamic_offsets) in new_descriptor_set_bindings {
                     // collect usage for bound resources
                     for binding in descriptor_set.bindings().lock().unwrap().iter() {
-                        match binding.resource.as_ref().expect("all descriptor set resources must be bound") {
-                            BoundResource::Buffer(buf) => {
-                                buf.submission_usage.store(Some(submission_num));
-                                usages.push(SpecificResourceUsage::BufferUsage {
-                                    buffer: AnyBuffer::Device(buf.clone()),
-                                    usage: ResourceUsage::new(
-                                        submission_num,
-                                        PipelineStageFlags::VERTEX_SHADER | PipelineStageFlags::FRAGMENT_SHADER,
-                                        AccessFlags::UNIFORM_READ,
-                                    ),
-                                })
-                            }
-                            BoundResource::CombinedImageSampler {image, sampler} => {
-                                image.submission_usage.store(Some(submission_num));
-                                sampler.submission_usage.store(Some(submission_num));
-                                usages.push(SpecificResourceUsage::ImageUsage {
-                                    image: image.clone(),
-                                    usage: ResourceUsage::new(
-                                        submission_num,
-                                        PipelineStageFlags::FRAGMENT_SHADER,
-                                        AccessFlags::SHADER_READ,
-                                    ),
-                                    required_layout: Some(ImageLayout::SHADER_READ_ONLY_OPTIMAL),
-                                    image_aspect: ImageAspectFlags::COLOR,
-                                });
+                        for resource in binding.resources.iter() {
+                            let Some(resource) = resource else {
+                                if binding.is_partially_bound() {
+                                    continue;
+                                }
+                                panic!("all descriptor set resources must be bound");
+                            };
+                            match resource {
+                                BoundResource::Buffer(buf) => {
+                                    buf.submission_usage.store(Some(submission_num));
+                                    usages.push(SpecificResourceUsage::BufferUsage {
+                                        buffer: AnyBuffer::Device(buf.clone()),
+                                        usage: ResourceUsage::new(
+                                            submission_num,
+                                            PipelineStageFlags::VERTEX_SHADER | PipelineStageFlags::FRAGMENT_SHADER,
+                                            AccessFlags::UNIFORM_READ,
+                                        ),
+                                        range: None,
+                                    })
+                                }
+                                BoundResource::DynamicBuffer { .. } => {
+                                    // exact range depends on dynamic offset, handled below
+                                }
+                                BoundResource::CombinedImageSampler {image, sampler} => {
+                                    image.submission_usage.store(Some(submission_num));
+                                    sampler.submission_usage.store(Some(submission_num));
+                                    usages.push(SpecificResourceUsage::ImageUsage {
+                                        image: image.clone(),
+                                        usage: ResourceUsage::new(
+                                            submission_num,
+                                            PipelineStageFlags::FRAGMENT_SHADER,
+                                            AccessFlags::SHADER_READ,
+                                        ),
+                                        required_layout: Some(ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                                        image_aspect: ImageAspectFlags::COLOR,
+                                    });
                                 
-                            }
-                            BoundResource::Image(image) => {
-                                image.submission_usage.store(Some(submission_num));
-                                usages.push(SpecificResourceUsage::ImageUsage {
-                                    image: image.clone(),
-                                    usage: ResourceUsage::new(
-                                        submission_num,
-                                        PipelineStageFlags::FRAGMENT_SHADER,
-                                        AccessFlags::SHADER_READ,
-                                    ),
-                                    required_layout: Some(ImageLayout::SHADER_READ_ONLY_OPTIMAL),
-                                    image_aspect: ImageAspectFlags::COLOR,
-                                })
-                            }
+                                }
+                                BoundResource::Image(image) => {
+                                    image.submission_usage.store(Some(submission_num));
+                                    usages.push(SpecificResourceUsage::ImageUsage {
+                                        image: image.clone(),
+                                        usage: ResourceUsage::new(
+                                            submission_num,
+                                            PipelineStageFlags::FRAGMENT_SHADER,
+                                            AccessFlags::SHADER_READ,
+                                        ),
+                                        required_layout: Some(ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                                        image_aspect: ImageAspectFlags::COLOR,
+                                    })
+                                }
                             
+                            }
                         }
                     }
 
+                    for ((descriptor_type, buffer), offset) in descriptor_set.dynamic_buffers().into_iter().zip(dynamic_offsets.iter()) {
+                        let Some((buf, range)) = buffer else {
+                            continue;
+                        };
+                        let access_flags = if descriptor_type == DescriptorType::UNIFORM_BUFFER_DYNAMIC {
+                            AccessFlags::UNIFORM_READ
+                        }
+                        else {
+                            AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE
+                        };
+                        let start = *offset as DeviceSize;
+
+                        buf.submission_usage.store(Some(submission_num));
+                        usages.push(SpecificResourceUsage::BufferUsage {
+                            buffer: AnyBuffer::Device(buf),
+                            usage: ResourceUsage::new(
+                                submission_num,
+                                PipelineStageFlags::VERTEX_SHADER | PipelineStageFlags::FRAGMENT_SHADER,
+                                access_flags,
+                            ),
+                            range: Some(start..start + range),
+                        })
+                    }
+
                     // mark descriptor sets used
                     descriptor_set.submission_usage.store(Some(submission_num));
                 }
@@ -743,4 +1095,71 @@ impl DeviceCommand {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::{self, ColorSpaceKHR, DescriptorType, Format, SurfaceFormatKHR};
+    use crate::swapchain_wrapper::{choose_surface_format, SwapchainColorEncoding, SwapchainFormatChange};
+    use super::RecordContext;
+
+    fn record_context() -> RecordContext {
+        let limits = vk::PhysicalDeviceLimits {
+            min_uniform_buffer_offset_alignment: 256,
+            min_storage_buffer_offset_alignment: 64,
+            ..Default::default()
+        };
+        RecordContext::new(&limits)
+    }
+
+    #[test]
+    fn dynamic_offsets_alignment() {
+        let ctx = record_context();
+        let uniform = [(DescriptorType::UNIFORM_BUFFER_DYNAMIC, Some((1024, 128)))];
+        let storage = [(DescriptorType::STORAGE_BUFFER_DYNAMIC, Some((1024, 128)))];
+        assert!(ctx.validate_dynamic_offsets(&uniform, &[512]).is_some());
+        assert!(ctx.validate_dynamic_offsets(&uniform, &[64]).is_none());
+        assert!(ctx.validate_dynamic_offsets(&storage, &[64]).is_some());
+        assert!(ctx.validate_dynamic_offsets(&storage, &[32]).is_none());
+    }
+
+    #[test]
+    fn dynamic_offsets_bounds() {
+        let ctx = record_context();
+        let uniform = [(DescriptorType::UNIFORM_BUFFER_DYNAMIC, Some((1024, 256)))];
+        // range ends exactly at buffer end
+        assert!(ctx.validate_dynamic_offsets(&uniform, &[768]).is_some());
+        assert!(ctx.validate_dynamic_offsets(&uniform, &[1024]).is_none());
+        // unbound element of partially bound binding
+        assert!(ctx.validate_dynamic_offsets(&[(DescriptorType::UNIFORM_BUFFER_DYNAMIC, None)], &[4096]).is_some());
+    }
+
+    #[test]
+    fn dynamic_offsets_count() {
+        let ctx = record_context();
+        let buffers = [
+            (DescriptorType::UNIFORM_BUFFER_DYNAMIC, Some((1024, 256))),
+            (DescriptorType::STORAGE_BUFFER_DYNAMIC, Some((1024, 256))),
+        ];
+        assert!(ctx.validate_dynamic_offsets(&buffers, &[0]).is_none());
+        assert!(ctx.validate_dynamic_offsets(&buffers, &[0, 64, 128]).is_none());
+        assert!(ctx.validate_dynamic_offsets(&buffers, &[256, 64]).is_some());
+    }
+
+    #[test]
+    fn render_pass_rejected_after_format_change() {
+        let sdr_formats = [SurfaceFormatKHR { format: Format::B8G8R8A8_UNORM, color_space: ColorSpaceKHR::SRGB_NONLINEAR }];
+        let hdr_formats = [SurfaceFormatKHR { format: Format::A2B10G10R10_UNORM_PACK32, color_space: ColorSpaceKHR::HDR10_ST2084_EXT }];
+        // swapchain recreated on a surface with different formats
+        let old = choose_surface_format(&sdr_formats, &[], SwapchainColorEncoding::Unorm);
+        let new = choose_surface_format(&hdr_formats, &[], SwapchainColorEncoding::Unorm);
+        let change = SwapchainFormatChange::detect(old, new).unwrap();
+
+        let ctx = record_context().with_swapchain_format(change.old.format);
+        assert!(ctx.check_render_pass_format(change.old.format).is_ok());
+
+        let ctx = record_context().with_swapchain_format(change.new.format);
+        assert!(ctx.check_render_pass_format(change.old.format).is_err());
+        assert!(ctx.check_render_pass_format(change.new.format).is_ok());
+    }
+}
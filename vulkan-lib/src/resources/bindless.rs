@@ -0,0 +1,291 @@
+use std::sync::Arc;
+use ash::vk::DescriptorType;
+use log::warn;
+use crate::queue::shared::SharedState;
+use crate::resources::descriptor_set::DescriptorSetResource;
+use crate::resources::image::ImageResource;
+use crate::resources::sampler::SamplerResource;
+use crate::resources::VulkanAllocator;
+use crate::shaders::DescriptorSetLayoutBindingDesc;
+
+enum TableSlot<T> {
+    Free,
+    Used(T),
+    /// Removed, but may still be accessed by submissions up to this number
+    Released(usize),
+}
+
+/// Stable slot indices with deferred reuse of removed slots.
+/// Changed slots are collected as dirty until taken.
+struct TableSlots<T> {
+    slots: Vec<TableSlot<T>>,
+    free_indices: Vec<u32>,
+    released: Vec<u32>,
+    dirty: Vec<u32>,
+}
+
+impl<T> TableSlots<T> {
+    fn new(capacity: u32) -> Self {
+        Self {
+            slots: (0..capacity).map(|_| TableSlot::Free).collect(),
+            free_indices: (0..capacity).rev().collect(),
+            released: Vec::new(),
+            dirty: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, value: T) -> Option<u32> {
+        let index = self.free_indices.pop()?;
+        self.slots[index as usize] = TableSlot::Used(value);
+        self.dirty.push(index);
+        Some(index)
+    }
+
+    /// Returns false if slot is not in use
+    fn remove(&mut self, index: u32, last_submission: usize) -> bool {
+        let Some(slot) = self.slots.get_mut(index as usize) else {
+            return false;
+        };
+        if !matches!(slot, TableSlot::Used(_)) {
+            return false;
+        }
+
+        *slot = TableSlot::Released(last_submission);
+        self.released.push(index);
+        true
+    }
+
+    fn get(&self, index: u32) -> Option<&T> {
+        match self.slots.get(index as usize)? {
+            TableSlot::Used(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Released slots which are no longer used by submissions become free
+    fn recycle(&mut self, last_waited: usize) {
+        let slots = &mut self.slots;
+        let free_indices = &mut self.free_indices;
+        let dirty = &mut self.dirty;
+        self.released.retain(|&index| {
+            let TableSlot::Released(submission) = slots[index as usize] else {
+                return false;
+            };
+            if submission > last_waited {
+                return true;
+            }
+
+            slots[index as usize] = TableSlot::Free;
+            free_indices.push(index);
+            dirty.push(index);
+            false
+        });
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty.extend(0..self.slots.len() as u32);
+    }
+
+    fn take_dirty(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn len(&self) -> usize {
+        self.slots.iter().filter(|s| matches!(s, TableSlot::Used(_))).count()
+    }
+}
+
+/// Array of combined image samplers in a single descriptor binding, addressed by stable indices.
+///
+/// With update-after-bind and update-unused-while-pending support, all changes are written into the same descriptor set:
+/// only free slots are written, which are not accessed by pending submissions.
+/// Otherwise, set is reallocated on change while the previous one is still in use.
+pub struct BindlessTextureTable {
+    bindings: &'static [DescriptorSetLayoutBindingDesc],
+    binding_index: u32,
+    descriptor_set: Arc<DescriptorSetResource>,
+    partially_bound: bool,
+    update_in_place: bool,
+    slots: TableSlots<(Arc<ImageResource>, Arc<SamplerResource>)>,
+    placeholder: (Arc<ImageResource>, Arc<SamplerResource>),
+    shared_state: SharedState,
+}
+
+impl BindlessTextureTable {
+    /// `binding_index` must refer to CombinedImageSampler array binding in `bindings`.
+    /// Placeholder is bound to free slots when partial binding is not supported by device.
+    pub fn new(allocator: &mut VulkanAllocator, bindings: &'static [DescriptorSetLayoutBindingDesc], binding_index: u32,
+               placeholder_image: Arc<ImageResource>, placeholder_sampler: Arc<SamplerResource>) -> Option<Self> {
+        let Some(desc) = bindings.iter().find(|b| b.binding == binding_index) else {
+            warn!("Binding {} not found for bindless texture table!", binding_index);
+            return None;
+        };
+        if desc.descriptor_type != DescriptorType::COMBINED_IMAGE_SAMPLER {
+            warn!("Bindless texture table requires CombinedImageSampler binding, got {:?}", desc.descriptor_type);
+            return None;
+        }
+
+        let descriptor_set = allocator.allocate_descriptor_set(bindings, Some("bindless textures"));
+        let (partially_bound, update_in_place) = {
+            let set_bindings = descriptor_set.bindings().lock().unwrap();
+            let binding = set_bindings.iter().find(|b| b.binding_index == binding_index).unwrap();
+            (binding.is_partially_bound(), binding.is_update_after_bind() && binding.is_update_unused_while_pending())
+        };
+
+        let mut slots = TableSlots::new(desc.descriptor_count);
+        if !partially_bound {
+            slots.mark_all_dirty();
+        }
+        Some(Self {
+            bindings,
+            binding_index,
+            descriptor_set,
+            partially_bound,
+            update_in_place,
+            slots,
+            placeholder: (placeholder_image, placeholder_sampler),
+            shared_state: allocator.shared(),
+        })
+    }
+
+    /// Returns index of the texture in the table, or None if the table is full.
+    /// Descriptor is written on next `prepare`.
+    pub fn insert(&mut self, image: Arc<ImageResource>, sampler: Arc<SamplerResource>) -> Option<u32> {
+        self.recycle_released();
+        let index = self.slots.insert((image, sampler));
+        if index.is_none() {
+            warn!("Bindless texture table is full ({} slots)", self.capacity());
+        }
+        index
+    }
+
+    /// Index becomes available again once all submissions recorded before this call are waited.
+    pub fn remove(&mut self, index: u32) {
+        if !self.slots.remove(index, self.shared_state.last_submission_num()) {
+            warn!("Bindless texture table index {} is not in use", index);
+        }
+    }
+
+    pub fn get(&self, index: u32) -> Option<&Arc<ImageResource>> {
+        self.slots.get(index).map(|(image, _)| image)
+    }
+
+    /// Write pending changes to descriptor set. Must be called before binding the set in a new recording.
+    pub fn prepare(&mut self, allocator: &mut VulkanAllocator) -> Arc<DescriptorSetResource> {
+        self.recycle_released();
+        let dirty = self.slots.take_dirty();
+        if dirty.is_empty() {
+            return self.descriptor_set.clone();
+        }
+
+        let last_waited = self.shared_state.last_host_waited_submission().num();
+        let in_flight = self.descriptor_set.submission_usage.load().is_some_and(|n| n > last_waited);
+        if in_flight && !self.update_in_place {
+            // previous set holds its resources until it is no longer used
+            let new_set = allocator.allocate_descriptor_set(self.bindings, Some("bindless textures"));
+            new_set.copy_bindings_from(&self.descriptor_set);
+            self.descriptor_set = new_set;
+        }
+
+        for index in dirty {
+            self.write_slot(index);
+        }
+        self.descriptor_set.clone()
+    }
+
+    pub fn descriptor_set(&self) -> &Arc<DescriptorSetResource> {
+        &self.descriptor_set
+    }
+
+    pub fn binding_index(&self) -> u32 {
+        self.binding_index
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.slots.len()
+    }
+
+    /// Changes are written into the same descriptor set, even while it is in use
+    pub fn is_update_in_place(&self) -> bool {
+        self.update_in_place
+    }
+
+    fn recycle_released(&mut self) {
+        self.slots.recycle(self.shared_state.last_host_waited_submission().num());
+    }
+
+    fn write_slot(&mut self, index: u32) {
+        let res = match &self.slots.slots[index as usize] {
+            TableSlot::Used((image, sampler)) => {
+                self.descriptor_set.try_bind_image_sampler_at(self.binding_index, index, image.clone(), sampler.clone())
+            }
+            TableSlot::Free if self.partially_bound => {
+                self.descriptor_set.try_unbind_at(self.binding_index, index)
+            }
+            TableSlot::Free => {
+                let (image, sampler) = self.placeholder.clone();
+                self.descriptor_set.try_bind_image_sampler_at(self.binding_index, index, image, sampler)
+            }
+            TableSlot::Released(_) => Some(()),
+        };
+        if res.is_none() {
+            warn!("Failed to update bindless texture table slot {}", index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableSlots;
+
+    #[test]
+    fn insert_until_full() {
+        let mut slots = TableSlots::new(3);
+        assert_eq!(slots.insert('a'), Some(0));
+        assert_eq!(slots.insert('b'), Some(1));
+        assert_eq!(slots.insert('c'), Some(2));
+        assert_eq!(slots.insert('d'), None);
+        assert_eq!(slots.len(), 3);
+        assert_eq!(slots.get(1), Some(&'b'));
+        assert_eq!(slots.take_dirty(), vec![0, 1, 2]);
+        assert!(slots.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn remove_only_used() {
+        let mut slots = TableSlots::new(2);
+        let i = slots.insert('a').unwrap();
+        assert!(!slots.remove(1, 0));
+        assert!(!slots.remove(5, 0));
+        assert!(slots.remove(i, 0));
+        assert!(!slots.remove(i, 0));
+        assert_eq!(slots.get(i), None);
+        assert_eq!(slots.len(), 0);
+    }
+
+    #[test]
+    fn released_slot_recycled_after_wait() {
+        let mut slots = TableSlots::new(1);
+        let i = slots.insert('a').unwrap();
+        slots.take_dirty();
+        slots.remove(i, 5);
+
+        // still used by submission 5
+        slots.recycle(4);
+        assert_eq!(slots.insert('b'), None);
+        assert!(slots.take_dirty().is_empty());
+
+        slots.recycle(5);
+        assert_eq!(slots.take_dirty(), vec![i]);
+        assert_eq!(slots.insert('b'), Some(i));
+        assert_eq!(slots.get(i), Some(&'b'));
+    }
+}
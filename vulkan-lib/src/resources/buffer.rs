@@ -5,6 +5,7 @@ use ash::vk;
 use ash::vk::{BufferCreateFlags, BufferCreateInfo, BufferUsageFlags, DeviceSize, MemoryAllocateInfo};
 use log::{error, warn};
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::queue_local::QueueLocal;
 use crate::resources::LastResourceUsage;
 use crate::queue::memory_manager::{MemoryManager, MemoryTypeAlgorithm};
@@ -15,6 +16,8 @@ use crate::wrappers::device::VkDeviceRef;
 pub struct BufferResource {
     pub(crate) buffer: vk::Buffer,
     pub(crate) memory: vk::DeviceMemory,
+    memory_type: u32,
+    memory_size: DeviceSize,
     size: usize,
     pub(crate) submission_usage: OptionSeqNumShared,
     pub(crate) inner: QueueLocal<BufferResourceInner>,
@@ -55,6 +58,8 @@ impl BufferResource {
         BufferResource {
             buffer,
             memory,
+            memory_type,
+            memory_size: allocation_size,
             size: size as usize,
             submission_usage: OptionSeqNumShared::default(),
             inner: QueueLocal::new(BufferResourceInner {
@@ -68,6 +73,11 @@ impl BufferResource {
     pub fn size(&self) -> usize {
         self.size
     }
+
+    /// Memory type index and allocation size
+    pub(crate) fn memory(&self) -> (u32, DeviceSize) {
+        (self.memory_type, self.memory_size)
+    }
     
     pub fn full(self: &Arc<Self>) -> BufferRange {
         BufferRange {
@@ -113,6 +123,7 @@ pub(crate) fn destroy_buffer_resource(buffer_resource: &BufferResource, no_usage
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::Buffer, buffer_resource.buffer);
             let device = instance.device.clone();
             unsafe {
                 device.destroy_buffer(buffer_resource.buffer, None);
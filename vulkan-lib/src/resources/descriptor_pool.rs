@@ -1,13 +1,16 @@
-use ash::vk::{DescriptorPool, DescriptorPoolCreateFlags, DescriptorPoolCreateInfo, DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo, DescriptorSetLayout, DescriptorType};
+use ash::vk::{DescriptorBindingFlags, DescriptorPool, DescriptorPoolCreateFlags, DescriptorPoolCreateInfo, DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo, DescriptorSetLayout, DescriptorType};
 use smallvec::SmallVec;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicBool;
-use ash::vk;
+use ash::vk::{self, Handle};
 use log::warn;
+use crate::capture::CaptureEvent;
 use crate::queue::OptionSeqNumShared;
 use crate::queue::shared::SharedState;
+use crate::resources::registry::{ResourceKind, ResourceRegistry};
 use crate::resources::descriptor_set::{DescriptorSetBinding, DescriptorSetResource};
+use crate::resources::usage_report::{DescriptorPoolUsage, DescriptorTypeUsage};
 use crate::shaders::DescriptorSetLayoutBindingDesc;
 use crate::wrappers::device::VkDeviceRef;
 
@@ -20,10 +23,11 @@ struct DescriptorPoolInfo {
     descriptor_counts: HashMap<DescriptorType, u32>,
     allocated_sets: u32,
     allocated_descriptor_counts: HashMap<DescriptorType, u32>,
+    update_after_bind: bool,
 }
 
 impl DescriptorPoolInfo {
-    fn new(device: &VkDeviceRef, max_sets: u32, descriptor_type_counts: &HashMap<DescriptorType, u32>) -> Self {
+    fn new(device: &VkDeviceRef, max_sets: u32, descriptor_type_counts: &HashMap<DescriptorType, u32>, update_after_bind: bool) -> Self {
         let pool_sizes: SmallVec<[DescriptorPoolSize; 8]> = descriptor_type_counts
             .iter()
             .map(|(&ty, &count)| {
@@ -33,8 +37,12 @@ impl DescriptorPoolInfo {
             })
             .collect();
 
+        let mut flags = DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET;
+        if update_after_bind {
+            flags |= DescriptorPoolCreateFlags::UPDATE_AFTER_BIND;
+        }
         let pool_create_info = DescriptorPoolCreateInfo::default()
-            .flags(DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .flags(flags)
             .max_sets(max_sets)
             .pool_sizes(&pool_sizes);
 
@@ -48,11 +56,12 @@ impl DescriptorPoolInfo {
             allocated_sets: 0,
             descriptor_counts: descriptor_type_counts.clone(),
             allocated_descriptor_counts: HashMap::new(),
+            update_after_bind,
         }
     }
 
-    fn can_allocate(&self, required_descriptors: &HashMap<DescriptorType, u32>) -> bool {
-        if self.allocated_sets >= self.max_sets {
+    fn can_allocate(&self, required_descriptors: &HashMap<DescriptorType, u32>, update_after_bind: bool) -> bool {
+        if self.allocated_sets >= self.max_sets || self.update_after_bind != update_after_bind {
             return false;
         }
 
@@ -106,15 +115,17 @@ pub(crate) struct DescriptorSetAllocator {
     pools: Vec<DescriptorPoolInfo>,
     sets: Vec<Arc<DescriptorSetResource>>,
     shared_state: SharedState,
+    registry: Arc<ResourceRegistry>,
 }
 
 impl DescriptorSetAllocator {
-    pub fn new(device: VkDeviceRef, shared_state: SharedState) -> Self {
+    pub fn new(device: VkDeviceRef, shared_state: SharedState, registry: Arc<ResourceRegistry>) -> Self {
         Self {
             device,
             pools: Vec::new(),
             sets: Vec::new(),
             shared_state,
+            registry,
         }
     }
 
@@ -126,50 +137,45 @@ impl DescriptorSetAllocator {
         counts
     }
 
-    fn find_or_create_pool(&mut self, required_descriptors: &HashMap<DescriptorType, u32>) -> usize {
+    fn find_or_create_pool(&mut self, required_descriptors: &HashMap<DescriptorType, u32>, update_after_bind: bool) -> usize {
         // Try to find an existing pool with capacity
         for (index, pool) in self.pools.iter().enumerate() {
-            if pool.can_allocate(required_descriptors) {
+            if pool.can_allocate(required_descriptors, update_after_bind) {
                 return index;
             }
         }
 
         // No suitable pool found, create a new one with exponential growth
-        let new_max_sets = if self.pools.is_empty() {
-            INITIAL_POOL_SIZE
+        let last_pool = self.pools.iter().rev().find(|p| p.update_after_bind == update_after_bind);
+        let new_max_sets = if let Some(last_pool) = last_pool {
+            last_pool.max_sets * 2
         } else {
-            self.pools.last().unwrap().max_sets * 2
+            INITIAL_POOL_SIZE
         };
 
         let mut new_descriptor_counts = HashMap::new();
         for (&ty, &required_count) in required_descriptors {
-            let base_count = if self.pools.is_empty() {
-                INITIAL_DESCRIPTORS_PER_TYPE
+            let base_count = if let Some(last_pool) = last_pool {
+                last_pool.descriptor_counts.get(&ty).copied().unwrap_or(INITIAL_DESCRIPTORS_PER_TYPE) * 2
             } else {
-                self.pools.last().unwrap().descriptor_counts.get(&ty).copied().unwrap_or(INITIAL_DESCRIPTORS_PER_TYPE) * 2
+                INITIAL_DESCRIPTORS_PER_TYPE
             };
             new_descriptor_counts.insert(ty, base_count.max(required_count));
         }
 
-        let new_pool = DescriptorPoolInfo::new(&self.device, new_max_sets, &new_descriptor_counts);
+        let new_pool = DescriptorPoolInfo::new(&self.device, new_max_sets, &new_descriptor_counts, update_after_bind);
         self.pools.push(new_pool);
         self.pools.len() - 1
     }
 
-    pub fn allocate_descriptor_set(&mut self, layout: DescriptorSetLayout, bindings_desc: &[DescriptorSetLayoutBindingDesc]) -> Arc<DescriptorSetResource> {
+    pub fn allocate_descriptor_set(&mut self, layout: DescriptorSetLayout, bindings_desc: &[DescriptorSetLayoutBindingDesc], name: Option<&str>) -> Arc<DescriptorSetResource> {
         let required_descriptors = Self::calculate_required_descriptors(bindings_desc);
-        let pool_index = self.find_or_create_pool(&required_descriptors);
+        let update_after_bind = bindings_desc.iter().any(|b| b.binding_flags.contains(DescriptorBindingFlags::UPDATE_AFTER_BIND));
+        let pool_index = self.find_or_create_pool(&required_descriptors, update_after_bind);
         let descriptor_set = self.pools[pool_index].allocate(&self.device, layout, &required_descriptors);
+        self.registry.register(ResourceKind::DescriptorSet, descriptor_set, name, 0);
 
-        let bindings = bindings_desc.iter().map(|b| {
-            DescriptorSetBinding {
-                binding_index: b.binding,
-                descriptor_count: b.descriptor_count,
-                descriptor_type: b.descriptor_type,
-                resource: None,
-                resource_updated: false,
-            }
-        }).collect();
+        let bindings = bindings_desc.iter().map(DescriptorSetBinding::new).collect();
 
         let ds = Arc::new(DescriptorSetResource {
             descriptor_set,
@@ -185,8 +191,36 @@ impl DescriptorSetAllocator {
         ds
     }
 
+    pub fn pool_usage(&self) -> Vec<DescriptorPoolUsage> {
+        self.pools.iter().map(|pool| {
+            let mut descriptors: Vec<_> = pool.descriptor_counts.iter().map(|(&ty, &capacity)| DescriptorTypeUsage {
+                descriptor_type: format!("{:?}", ty),
+                capacity,
+                allocated: pool.allocated_descriptor_counts.get(&ty).copied().unwrap_or(0),
+            }).collect();
+            descriptors.sort_by(|a, b| a.descriptor_type.cmp(&b.descriptor_type));
+
+            DescriptorPoolUsage {
+                max_sets: pool.max_sets,
+                allocated_sets: pool.allocated_sets,
+                update_after_bind: pool.update_after_bind,
+                descriptors,
+            }
+        }).collect()
+    }
+
+    pub fn set_count(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Sets no longer referenced outside of allocator
+    pub fn pending_free_count(&self) -> usize {
+        self.sets.iter().filter(|s| Arc::strong_count(s) == 1).count()
+    }
+
     /// Call this periodically to recycle descriptor sets that are no longer in use by the GPU.
     pub fn on_submission_waited(&mut self, last_waited_submission: usize) {
+        let mut freed: SmallVec<[vk::DescriptorSet; 4]> = SmallVec::new();
         let mut i = 0;
         while i < self.sets.len() {
             if self.sets[i].submission_usage.load().is_none_or(|u| u <= last_waited_submission) && Arc::strong_count(&self.sets[i]) == 1 {
@@ -195,19 +229,23 @@ impl DescriptorSetAllocator {
                 let descriptor_set = ds.descriptor_set;
                 let pool_idx = ds.pool_index;
                 let bindings = ds.bindings.lock().unwrap();
-                let req_desc = Self::calculate_required_descriptors(&bindings.iter().map(|b| DescriptorSetLayoutBindingDesc {
-                    binding: b.binding_index,
-                    descriptor_type: b.descriptor_type,
-                    descriptor_count: b.descriptor_count,
-                    stage_flags: vk::ShaderStageFlags::empty(),
-                }).collect::<Vec<_>>());
+                let req_desc = Self::calculate_required_descriptors(&bindings.iter().map(|b| b.desc()).collect::<Vec<_>>());
 
                 self.pools[pool_idx].free(&self.device, descriptor_set, &req_desc);
+                self.registry.unregister(ResourceKind::DescriptorSet, descriptor_set);
+                freed.push(descriptor_set);
             }
             else {
                 i += 1;
             }
         }
+
+        if !freed.is_empty() && let Some(instance) = crate::try_get_instance() {
+            instance.capture(|| freed.iter().map(|ds| CaptureEvent::Destroy {
+                kind: ResourceKind::DescriptorSet,
+                handle: ds.as_raw(),
+            }));
+        }
     }
 }
 
@@ -247,6 +285,17 @@ impl Drop for DescriptorSetAllocator {
                 sets_in_use, sets_leaked
             );
         }
+        let leaked = self.sets.iter()
+            .filter(|set| Arc::strong_count(set) > 1)
+            .map(|set| set.descriptor_set);
+        if let Some(report) = self.registry.leak_report_of(ResourceKind::DescriptorSet, leaked) {
+            warn!("{}", report);
+        }
+
+        // sets are freed together with their pools
+        for set in &self.sets {
+            self.registry.unregister(ResourceKind::DescriptorSet, set.descriptor_set);
+        }
 
         unsafe {
             for pool in &self.pools {
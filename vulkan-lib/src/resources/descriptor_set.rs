@@ -2,7 +2,7 @@ use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use ash::vk;
-use ash::vk::{DescriptorBufferInfo, DescriptorImageInfo, DescriptorSetLayout, DescriptorType, ImageLayout, WriteDescriptorSet, WHOLE_SIZE};
+use ash::vk::{DescriptorBindingFlags, DescriptorBufferInfo, DescriptorImageInfo, DescriptorSetLayout, DescriptorType, DeviceSize, ImageLayout, WriteDescriptorSet, WHOLE_SIZE};
 use log::{error, warn};
 use slotmap::DefaultKey;
 use smallvec::{smallvec, SmallVec};
@@ -17,6 +17,11 @@ use crate::wrappers::device::VkDeviceRef;
 #[derive(Clone)]
 pub enum BoundResource {
     Buffer(Arc<BufferResource>),
+    /// Buffer for dynamic binding, `range` bytes are visible starting from dynamic offset
+    DynamicBuffer {
+        buffer: Arc<BufferResource>,
+        range: DeviceSize,
+    },
     Image(Arc<ImageResource>),
     CombinedImageSampler {
         image: Arc<ImageResource>,
@@ -28,10 +33,63 @@ pub struct DescriptorSetBinding {
     pub binding_index: u32,
     pub descriptor_type: DescriptorType,
     pub descriptor_count: u32,
-    pub resource: Option<BoundResource>,
-    pub resource_updated: bool,
+    pub binding_flags: DescriptorBindingFlags,
+    /// One slot per array element
+    pub resources: SmallVec<[Option<BoundResource>; 1]>,
+    pub resource_updated: SmallVec<[bool; 1]>,
+}
+
+impl DescriptorSetBinding {
+    pub(crate) fn new(desc: &DescriptorSetLayoutBindingDesc) -> Self {
+        Self {
+            binding_index: desc.binding,
+            descriptor_type: desc.descriptor_type,
+            descriptor_count: desc.descriptor_count,
+            binding_flags: desc.binding_flags,
+            resources: smallvec![None; desc.descriptor_count as usize],
+            resource_updated: smallvec![false; desc.descriptor_count as usize],
+        }
+    }
+
+    pub fn is_partially_bound(&self) -> bool {
+        self.binding_flags.contains(DescriptorBindingFlags::PARTIALLY_BOUND)
+    }
+
+    pub fn is_update_after_bind(&self) -> bool {
+        self.binding_flags.contains(DescriptorBindingFlags::UPDATE_AFTER_BIND)
+    }
+
+    pub fn is_update_unused_while_pending(&self) -> bool {
+        self.binding_flags.contains(DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING)
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self.descriptor_type, DescriptorType::UNIFORM_BUFFER_DYNAMIC | DescriptorType::STORAGE_BUFFER_DYNAMIC)
+    }
+
+    fn accepts(&self, resource: &BoundResource) -> bool {
+        match resource {
+            BoundResource::Buffer(_) => matches!(self.descriptor_type, DescriptorType::UNIFORM_BUFFER | DescriptorType::STORAGE_BUFFER),
+            BoundResource::DynamicBuffer { .. } => self.is_dynamic(),
+            BoundResource::Image(_) => matches!(self.descriptor_type, DescriptorType::SAMPLED_IMAGE | DescriptorType::STORAGE_IMAGE),
+            BoundResource::CombinedImageSampler { .. } => self.descriptor_type == DescriptorType::COMBINED_IMAGE_SAMPLER,
+        }
+    }
+
+    pub(crate) fn desc(&self) -> DescriptorSetLayoutBindingDesc {
+        DescriptorSetLayoutBindingDesc {
+            binding: self.binding_index,
+            descriptor_type: self.descriptor_type,
+            descriptor_count: self.descriptor_count,
+            stage_flags: vk::ShaderStageFlags::empty(),
+            binding_flags: self.binding_flags,
+        }
+    }
 }
 
+/// Descriptor type and bound buffer with its range for each dynamic descriptor
+pub(crate) type DynamicBuffers = SmallVec<[(DescriptorType, Option<(Arc<BufferResource>, DeviceSize)>); 2]>;
+
 pub struct DescriptorSetResource {
     pub(crate) descriptor_set: vk::DescriptorSet,
     pub(crate) pool_index: usize,
@@ -46,60 +104,123 @@ impl DescriptorSetResource {
         &self.bindings
     }
 
-    #[must_use]
-    pub fn try_bind_buffer(&self, binding_index: u32, buffer: Arc<BufferResource>) -> Option<()> {
+    fn try_bind_at(&self, binding_index: u32, array_element: u32, resource: Option<BoundResource>, op: &str) -> Option<()> {
         let mut bindings = self.bindings.lock().unwrap();
-        if self.updates_locked.load(Ordering::Relaxed) {
-            warn!("Attempted to bind buffer to descriptor set while updates are locked!");
+        let Some(binding) = bindings.iter_mut().find(|b| b.binding_index == binding_index) else {
+            warn!("Incorrect binding index specified in {op}!");
+            return None;
+        };
+
+        if self.updates_locked.load(Ordering::Relaxed) && !binding.is_update_after_bind() {
+            warn!("Attempted to {op} on descriptor set while updates are locked!");
             return None;
         }
 
-        if let Some(binding) = bindings.iter_mut().find(|b| b.binding_index == binding_index) {
-            binding.resource = Some(BoundResource::Buffer(buffer));
-            binding.resource_updated = true;
-            Some(())
+        if array_element >= binding.descriptor_count {
+            warn!("Array element {} is out of bounds for binding {} with {} descriptors in {op}!",
+                array_element, binding_index, binding.descriptor_count);
+            return None;
+        }
+
+        if let Some(resource) = &resource && !binding.accepts(resource) {
+            warn!("Resource type does not match descriptor type {:?} of binding {} in {op}!", binding.descriptor_type, binding_index);
+            return None;
         }
-        else {
-            warn!("Incorrect binding index specified in bind_buffer!");
-            None
+
+        if resource.is_none() && !binding.is_partially_bound() {
+            warn!("Attempted to {op} on binding {} which is not partially bound!", binding_index);
+            return None;
         }
+
+        binding.resources[array_element as usize] = resource;
+        binding.resource_updated[array_element as usize] = true;
+        Some(())
     }
 
     #[must_use]
-    pub fn try_bind_image(&self, binding_index: u32, image: Arc<ImageResource>) -> Option<()> {
-        let mut bindings = self.bindings.lock().unwrap();
-        if self.updates_locked.load(Ordering::Relaxed) {
-            warn!("Attempted to bind buffer to descriptor set while updates are locked!");
+    pub fn try_bind_buffer(&self, binding_index: u32, buffer: Arc<BufferResource>) -> Option<()> {
+        self.try_bind_buffer_at(binding_index, 0, buffer)
+    }
+
+    #[must_use]
+    pub fn try_bind_buffer_at(&self, binding_index: u32, array_element: u32, buffer: Arc<BufferResource>) -> Option<()> {
+        self.try_bind_at(binding_index, array_element, Some(BoundResource::Buffer(buffer)), "bind_buffer")
+    }
+
+    /// Bind buffer to UniformBufferDynamic/StorageBufferDynamic binding.
+    /// Each draw sees `range` bytes starting from the dynamic offset passed at bind time.
+    #[must_use]
+    pub fn try_bind_dynamic_buffer(&self, binding_index: u32, buffer: Arc<BufferResource>, range: DeviceSize) -> Option<()> {
+        self.try_bind_dynamic_buffer_at(binding_index, 0, buffer, range)
+    }
+
+    #[must_use]
+    pub fn try_bind_dynamic_buffer_at(&self, binding_index: u32, array_element: u32, buffer: Arc<BufferResource>, range: DeviceSize) -> Option<()> {
+        if range == 0 || range > buffer.size() as DeviceSize {
+            warn!("Invalid range {} for dynamic buffer of size {}", range, buffer.size());
             return None;
         }
+        self.try_bind_at(binding_index, array_element, Some(BoundResource::DynamicBuffer { buffer, range }), "bind_dynamic_buffer")
+    }
 
-        if let Some(binding) = bindings.iter_mut().find(|b| b.binding_index == binding_index) {
-            binding.resource = Some(BoundResource::Image(image));
-            binding.resource_updated = true;
-            Some(())
-        }
-        else {
-            warn!("Incorrect binding index specified in bind_image!");
-            None
+    /// Dynamic descriptors in the order of dynamic offsets: by binding number, then by array element.
+    /// Unbound elements of partially bound bindings are reported as None.
+    pub(crate) fn dynamic_buffers(&self) -> DynamicBuffers {
+        let bindings = self.bindings.lock().unwrap();
+        let mut dynamic_bindings: SmallVec<[&DescriptorSetBinding; 2]> = bindings.iter()
+            .filter(|b| b.is_dynamic())
+            .collect();
+        dynamic_bindings.sort_by_key(|b| b.binding_index);
+
+        let mut res = SmallVec::new();
+        for binding in dynamic_bindings {
+            for resource in binding.resources.iter() {
+                let buffer = match resource {
+                    Some(BoundResource::DynamicBuffer { buffer, range }) => Some((buffer.clone(), *range)),
+                    _ => None,
+                };
+                res.push((binding.descriptor_type, buffer));
+            }
         }
+        res
+    }
+
+    #[must_use]
+    pub fn try_bind_image(&self, binding_index: u32, image: Arc<ImageResource>) -> Option<()> {
+        self.try_bind_image_at(binding_index, 0, image)
+    }
+
+    #[must_use]
+    pub fn try_bind_image_at(&self, binding_index: u32, array_element: u32, image: Arc<ImageResource>) -> Option<()> {
+        self.try_bind_at(binding_index, array_element, Some(BoundResource::Image(image)), "bind_image")
     }
 
     #[must_use]
     pub fn try_bind_image_sampler(&self, binding_index: u32, image: Arc<ImageResource>, sampler: Arc<SamplerResource>) -> Option<()> {
-        let mut bindings = self.bindings.lock().unwrap();
-        if self.updates_locked.load(Ordering::Relaxed) {
-            warn!("Attempted to bind buffer to descriptor set while updates are locked!");
-            return None;
-        }
+        self.try_bind_image_sampler_at(binding_index, 0, image, sampler)
+    }
 
-        if let Some(binding) = bindings.iter_mut().find(|b| b.binding_index == binding_index) {
-            binding.resource = Some(BoundResource::CombinedImageSampler { image, sampler });
-            binding.resource_updated = true;
-            Some(())
-        }
-        else {
-            warn!("Incorrect binding index specified in bind_image_and_sampler!");
-            None
+    #[must_use]
+    pub fn try_bind_image_sampler_at(&self, binding_index: u32, array_element: u32, image: Arc<ImageResource>, sampler: Arc<SamplerResource>) -> Option<()> {
+        self.try_bind_at(binding_index, array_element, Some(BoundResource::CombinedImageSampler { image, sampler }), "bind_image_and_sampler")
+    }
+
+    /// Release resource from array element of partially bound binding.
+    /// The descriptor itself is left as is, shaders must not access this element anymore.
+    #[must_use]
+    pub fn try_unbind_at(&self, binding_index: u32, array_element: u32) -> Option<()> {
+        self.try_bind_at(binding_index, array_element, None, "unbind")
+    }
+
+    /// Copy all bound resources from another set with the same layout
+    pub(crate) fn copy_bindings_from(&self, other: &DescriptorSetResource) {
+        let src = other.bindings.lock().unwrap();
+        let mut dst = self.bindings.lock().unwrap();
+        for (dst, src) in dst.iter_mut().zip(src.iter()) {
+            for (i, resource) in src.resources.iter().enumerate() {
+                dst.resources[i] = resource.clone();
+                dst.resource_updated[i] = resource.is_some();
+            }
         }
     }
 
@@ -111,47 +232,55 @@ impl DescriptorSetResource {
         self.updates_locked.store(false, Ordering::Relaxed);
     }
 
-    /// SAFETY: Must ensure descriptor set is not currently used in any command buffers.
+    /// SAFETY: Must ensure descriptor set is not currently used in any command buffers,
+    /// except for update-after-bind bindings, where only updated elements must be unused.
     pub(crate) fn update_descriptor_set(&self, device: &VkDeviceRef) {
         let mut buffer_bindings: SmallVec<[_; 4]> = smallvec![];
         let mut image_bindings: SmallVec<[_; 4]> = smallvec![];
         let mut bindings = self.bindings.lock().unwrap();
         for binding in bindings.iter_mut() {
-            if binding.resource.is_none() {
-                error!("Descriptor set binding {}:{:?} is not set during draw command!", binding.binding_index, binding.descriptor_type);
-            }
+            let partially_bound = binding.is_partially_bound();
+            for (array_element, (resource, updated)) in binding.resources.iter().zip(binding.resource_updated.iter_mut()).enumerate() {
+                if resource.is_none() && !partially_bound {
+                    error!("Descriptor set binding {}[{}]:{:?} is not set during draw command!", binding.binding_index, array_element, binding.descriptor_type);
+                }
 
-            if !binding.resource_updated {
-                continue;
-            }
-            binding.resource_updated = false;
-            if let Some(resource) = &binding.resource {
-                match resource {
-                    BoundResource::Buffer(buffer) => {
-                        buffer_bindings.push((binding.binding_index, buffer.buffer));
-                    }
-                    BoundResource::Image(image) => {
-                        image_bindings.push((binding.binding_index, image.image_view, None))
-                    }
-                    BoundResource::CombinedImageSampler {
-                        image, sampler
-                    } => {
-                        image_bindings.push((binding.binding_index, image.image_view, Some(sampler.clone())))
+                if !*updated {
+                    continue;
+                }
+                *updated = false;
+                let dst = (binding.binding_index, array_element as u32, binding.descriptor_type);
+                if let Some(resource) = resource {
+                    match resource {
+                        BoundResource::Buffer(buffer) => {
+                            buffer_bindings.push((dst, buffer.buffer, WHOLE_SIZE));
+                        }
+                        BoundResource::DynamicBuffer { buffer, range } => {
+                            buffer_bindings.push((dst, buffer.buffer, *range));
+                        }
+                        BoundResource::Image(image) => {
+                            image_bindings.push((dst, image.image_view, None))
+                        }
+                        BoundResource::CombinedImageSampler {
+                            image, sampler
+                        } => {
+                            image_bindings.push((dst, image.image_view, Some(sampler.clone())))
+                        }
                     }
                 }
             }
         }
 
         let buffer_infos: SmallVec<[_; 4]> = buffer_bindings.into_iter()
-            .map(|(i, buf)| {
-                (i, DescriptorBufferInfo::default()
+            .map(|(dst, buf, range)| {
+                (dst, DescriptorBufferInfo::default()
                     .buffer(buf)
                     .offset(0)
-                    .range(WHOLE_SIZE))
+                    .range(range))
             }).collect();
 
         let image_infos: SmallVec<[_; 4]> = image_bindings.into_iter()
-            .map(|(i, iv, sampler)| {
+            .map(|(dst, iv, sampler)| {
                 let mut info = DescriptorImageInfo::default()
                     .image_view(iv)
                     .image_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL);
@@ -159,29 +288,26 @@ impl DescriptorSetResource {
                 if let Some(sampler) = sampler {
                     info.sampler = sampler.sampler;
                 }
-                (i, info)
+                (dst, info)
             }).collect();
 
         let mut descriptor_writes: SmallVec<[_; 4]> = smallvec![];
-        for (binding, buffer_info) in buffer_infos.iter() {
+        for ((binding, array_element, descriptor_type), buffer_info) in buffer_infos.iter() {
             descriptor_writes.push(WriteDescriptorSet::default()
                 .dst_set(self.descriptor_set)
                 .dst_binding(*binding)
-                .descriptor_type(DescriptorType::UNIFORM_BUFFER)
+                .dst_array_element(*array_element)
+                .descriptor_type(*descriptor_type)
                 .buffer_info(std::slice::from_ref(&buffer_info))
             );
         }
 
-        for (binding, image_info) in image_infos.iter() {
-            let t = if image_info.sampler != vk::Sampler::null() {
-                DescriptorType::COMBINED_IMAGE_SAMPLER
-            } else {
-                DescriptorType::SAMPLED_IMAGE
-            };
+        for ((binding, array_element, descriptor_type), image_info) in image_infos.iter() {
             descriptor_writes.push(WriteDescriptorSet::default()
                 .dst_set(self.descriptor_set)
                 .dst_binding(*binding)
-                .descriptor_type(t)
+                .dst_array_element(*array_element)
+                .descriptor_type(*descriptor_type)
                 .image_info(std::slice::from_ref(&image_info))
             );
         }
@@ -192,4 +318,4 @@ impl DescriptorSetResource {
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -5,6 +5,7 @@ use ash::vk::{Extent3D, Format, ImageCreateFlags, ImageCreateInfo, ImageLayout,
 use log::{error, warn};
 use slotmap::DefaultKey;
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::queue_local::QueueLocal;
 use crate::queue::memory_manager::{MemoryManager, MemoryTypeAlgorithm};
 use crate::queue::OptionSeqNumShared;
@@ -14,6 +15,9 @@ use crate::wrappers::device::VkDeviceRef;
 pub struct ImageResource {
     pub(crate) image: vk::Image,
     memory: Option<vk::DeviceMemory>,
+    memory_type: u32,
+    /// Bytes of device memory bound to the image, 0 for swapchain images
+    memory_size: vk::DeviceSize,
     pub(crate) image_view: vk::ImageView,
     format: vk::Format,
     extent: vk::Extent2D,
@@ -86,6 +90,8 @@ impl ImageResource {
         Self {
             image,
             memory: Some(memory),
+            memory_type,
+            memory_size: allocation_size,
             image_view,
             format,
             extent: vk::Extent2D { width, height },
@@ -119,6 +125,8 @@ impl ImageResource {
         Self {
             image,
             memory: None,
+            memory_type: 0,
+            memory_size: 0,
             image_view,
             format,
             extent: vk::Extent2D { width, height },
@@ -136,6 +144,15 @@ impl ImageResource {
         self.extent
     }
 
+    pub(crate) fn memory_size(&self) -> vk::DeviceSize {
+        self.memory_size
+    }
+
+    /// Memory type index and allocation size, None for swapchain images
+    pub(crate) fn memory(&self) -> Option<(u32, vk::DeviceSize)> {
+        self.memory.map(|_| (self.memory_type, self.memory_size))
+    }
+
     pub fn get_aspect_flags(&self) -> vk::ImageAspectFlags {
         format_aspect_flags(self.format)
     }
@@ -170,6 +187,7 @@ pub(crate) fn destroy_image_resource(image_resource: &ImageResource, no_usages:
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::Image, image_resource.image);
             let device = instance.device.clone();
             unsafe {
                 device.destroy_image_view(image_resource.image_view, None);
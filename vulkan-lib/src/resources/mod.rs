@@ -1,8 +1,9 @@
 use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
 use ash::vk;
-use ash::vk::{AccessFlags, BufferCreateFlags, BufferUsageFlags, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DeviceSize, Format, ImageCreateFlags, ImageUsageFlags, PipelineStageFlags, SampleCountFlags, SamplerCreateInfo};
-use log::{error, info};
+use ash::vk::{Handle, AccessFlags, BufferCreateFlags, BufferUsageFlags, DescriptorBindingFlags, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutBindingFlagsCreateInfo, DescriptorSetLayoutCreateFlags, DescriptorSetLayoutCreateInfo, DeviceSize, Format, ImageCreateFlags, ImageUsageFlags, PipelineStageFlags, SampleCountFlags, SamplerCreateInfo};
+use log::{error, info, warn};
 use slotmap::DefaultKey;
 use smallvec::SmallVec;
 use descriptor_pool::DescriptorSetAllocator;
@@ -11,12 +12,18 @@ use crate::resources::descriptor_set::DescriptorSetResource;
 use crate::resources::image::{destroy_image_resource, ImageResource};
 use crate::resources::pipeline::{destroy_pipeline, GraphicsPipelineDesc, GraphicsPipelineResource};
 use crate::resources::render_pass::{destroy_render_pass, AttachmentsDescription, FrameBufferAttachment, RenderPassResource};
+use crate::resources::registry::{ResourceKind, ResourceRegistry};
 use crate::resources::sampler::SamplerResource;
+use crate::resources::query_pool::{destroy_query_pool, QueryKind, QueryPoolResource};
+use crate::resources::usage_report::{ResourceUsageReport, StagingBufferUsage};
 use crate::queue::memory_manager::MemoryManager;
 use crate::queue::shared::{HostWaitedNum, SharedState};
 use crate::resources::staging_buffer::{destroy_staging_buffer_resource, StagingBuffer, StagingBufferResource};
 use crate::shaders::DescriptorSetLayoutBindingDesc;
-use crate::VulkanInstance;
+use crate::shaders::reflection::ShaderInterfaceError;
+use crate::shaders::hot_reload::{HotPipeline, ShaderSources, ShaderWatcher};
+use crate::{DeviceFeature, VulkanInstance};
+use crate::capture::{capture_bindings, capture_pipeline, capture_render_pass, CaptureEvent, CapturedQueryKind, CapturedSampler};
 use crate::wrappers::device::VkDeviceRef;
 
 pub mod buffer;
@@ -27,6 +34,10 @@ pub mod descriptor_set;
 pub mod sampler;
 pub mod descriptor_pool;
 pub mod staging_buffer;
+pub mod bindless;
+pub mod registry;
+pub mod usage_report;
+pub mod query_pool;
 
 /// Object responsible for creating new vulkan resources (images, buffers, pipelines...).
 /// Needs to be manually periodically polled to destroy unused resources (garbage collection style).
@@ -41,6 +52,8 @@ pub struct VulkanAllocator {
     render_passes: Vec<Arc<RenderPassResource>>,
     pipelines: Vec<Arc<GraphicsPipelineResource>>,
     samplers: Vec<Arc<SamplerResource>>,
+    query_pools: Vec<Arc<QueryPoolResource>>,
+    shader_watcher: ShaderWatcher,
     instance: Arc<VulkanInstance>,
 }
 
@@ -51,7 +64,7 @@ impl VulkanAllocator {
     ) -> Self {
         let device = instance.device.clone();
         let shared_state = instance.shared_state.clone();
-        let descriptor_set_allocator = DescriptorSetAllocator::new(device.clone(), shared_state.clone());
+        let descriptor_set_allocator = DescriptorSetAllocator::new(device.clone(), shared_state.clone(), instance.registry.clone());
 
         Self {
             instance,
@@ -64,6 +77,8 @@ impl VulkanAllocator {
             render_passes: Vec::new(),
             pipelines: Vec::new(),
             samplers: Vec::new(),
+            query_pools: Vec::new(),
+            shader_watcher: ShaderWatcher::default(),
         }
     }
 
@@ -71,34 +86,86 @@ impl VulkanAllocator {
         self.instance.shared_state.clone()
     }
 
-    pub fn allocate_descriptor_set(&mut self, bindings: &'static [DescriptorSetLayoutBindingDesc]) -> Arc<DescriptorSetResource> {
+    /// Live resources of the instance, including ones created by other allocators
+    pub fn registry(&self) -> &ResourceRegistry {
+        &self.instance.registry
+    }
+
+    pub fn allocate_descriptor_set(&mut self, bindings: &'static [DescriptorSetLayoutBindingDesc], name: Option<&str>) -> Arc<DescriptorSetResource> {
         let layout = self.get_or_create_descriptor_set_layout(bindings);
-        let resource = self.descriptor_set_allocator.allocate_descriptor_set(layout, bindings);
+        let bindings = self.supported_bindings(bindings);
+        let resource = self.descriptor_set_allocator.allocate_descriptor_set(layout, &bindings, name);
+        self.set_debug_name(resource.descriptor_set, name);
+        self.instance.capture(|| [CaptureEvent::AllocateDescriptorSet {
+            handle: resource.descriptor_set.as_raw(),
+            bindings: capture_bindings(&bindings),
+            name: name.map(str::to_string),
+        }]);
         resource
     }
 
-    pub fn new_buffer(&mut self, usage: BufferUsageFlags, flags: BufferCreateFlags, size: DeviceSize) -> Arc<BufferResource> {
+    /// Bindings with binding flags not supported by device removed
+    fn supported_bindings(&self, bindings_desc: &[DescriptorSetLayoutBindingDesc]) -> SmallVec<[DescriptorSetLayoutBindingDesc; 5]> {
+        bindings_desc.iter().map(|desc| {
+            let supported_flags = self.instance.descriptor_indexing.supported_binding_flags(desc.descriptor_type);
+            DescriptorSetLayoutBindingDesc {
+                binding_flags: desc.binding_flags & supported_flags,
+                ..*desc
+            }
+        }).collect()
+    }
+
+    pub fn new_buffer(&mut self, usage: BufferUsageFlags, flags: BufferCreateFlags, size: DeviceSize, name: Option<&str>) -> Arc<BufferResource> {
         let res = Arc::new(BufferResource::new(&self.instance.device, &mut self.memory_manager, usage, flags, size));
+        self.set_debug_name(res.buffer, name);
+        self.instance.registry.register(ResourceKind::Buffer, res.buffer, name, res.size() as u64);
+        self.instance.capture(|| [CaptureEvent::CreateBuffer {
+            handle: res.buffer.as_raw(),
+            usage: usage.as_raw(),
+            flags: flags.as_raw(),
+            size,
+            name: name.map(str::to_string),
+        }]);
         self.buffers.push(res.clone());
         res
     }
     
-    pub fn new_staging_buffer(&mut self, size: DeviceSize) -> StagingBufferResource {
+    pub fn new_staging_buffer(&mut self, size: DeviceSize, name: Option<&str>) -> StagingBufferResource {
         let usage = BufferUsageFlags::empty();
         let flags = BufferCreateFlags::empty();
         let res = Arc::new(StagingBuffer::new(&self.instance.device, &mut self.memory_manager, usage, flags, size));
+        self.set_debug_name(res.buffer, name);
+        self.instance.registry.register(ResourceKind::StagingBuffer, res.buffer, name, res.size() as u64);
+        self.instance.capture(|| [CaptureEvent::CreateStagingBuffer {
+            handle: res.buffer.as_raw(),
+            size,
+            name: name.map(str::to_string),
+        }]);
         self.staging_buffers.push(res.clone());
         StagingBufferResource(res)
     }
 
     pub fn new_image(&mut self, usage: ImageUsageFlags, flags: ImageCreateFlags,
-                     width: u32, height: u32, format: Format, samples: SampleCountFlags) -> Arc<ImageResource> {
+                     width: u32, height: u32, format: Format, samples: SampleCountFlags, name: Option<&str>) -> Arc<ImageResource> {
         let res = Arc::new(ImageResource::new(&self.instance.device, &mut self.memory_manager, usage, flags, width, height, format, samples));
+        self.set_debug_name(res.image, name);
+        self.set_debug_name(res.image_view, name);
+        self.instance.registry.register(ResourceKind::Image, res.image, name, res.memory_size());
+        self.instance.capture(|| [CaptureEvent::CreateImage {
+            handle: res.image.as_raw(),
+            usage: usage.as_raw(),
+            flags: flags.as_raw(),
+            width,
+            height,
+            format: format.as_raw(),
+            samples: samples.as_raw(),
+            name: name.map(str::to_string),
+        }]);
         self.images.push(res.clone());
         res
     }
 
-    pub fn new_sampler(&mut self, f: impl FnOnce(SamplerCreateInfo) -> SamplerCreateInfo) -> Arc<SamplerResource> {
+    pub fn new_sampler(&mut self, f: impl FnOnce(SamplerCreateInfo) -> SamplerCreateInfo, name: Option<&str>) -> Arc<SamplerResource> {
         let default_info =
             SamplerCreateInfo::default()
                 .mag_filter(vk::Filter::LINEAR)
@@ -118,6 +185,13 @@ impl VulkanAllocator {
                 .mip_lod_bias(0.0);
         let sampler_info = f(default_info);
         let sampler = SamplerResource::new(&self.instance.device, &sampler_info);
+        self.set_debug_name(sampler.sampler, name);
+        self.instance.registry.register(ResourceKind::Sampler, sampler.sampler, name, 0);
+        self.instance.capture(|| [CaptureEvent::CreateSampler {
+            handle: sampler.sampler.as_raw(),
+            info: CapturedSampler::new(&sampler_info),
+            name: name.map(str::to_string),
+        }]);
         let res = Arc::new(sampler);
         self.samplers.push(res.clone());
 
@@ -128,25 +202,144 @@ impl VulkanAllocator {
         &mut self,
         attachments_description: AttachmentsDescription,
         swapchain_format: vk::Format,
+        name: Option<&str>,
     ) -> Arc<RenderPassResource> {
         let res = Arc::new(RenderPassResource::new(
             &self.instance.device,
             attachments_description,
             swapchain_format,
         ));
+        self.set_debug_name(res.render_pass, name);
+        self.instance.registry.register(ResourceKind::RenderPass, res.render_pass, name, 0);
+        self.instance.capture(|| [capture_render_pass(&res, swapchain_format, name)]);
         self.render_passes.push(res.clone());
         res
     }
-    pub fn new_pipeline(&mut self, render_pass: Arc<RenderPassResource>, pipeline_desc: GraphicsPipelineDesc, with_depth_test: bool) -> Arc<GraphicsPipelineResource> {
+    /// Shader interface mismatches are logged, use `try_new_pipeline` to handle them
+    pub fn new_pipeline(&mut self, render_pass: Arc<RenderPassResource>, pipeline_desc: GraphicsPipelineDesc, with_depth_test: bool, name: Option<&str>) -> Arc<GraphicsPipelineResource> {
+        if let Err(e) = pipeline_desc.validate() {
+            error!("Pipeline {:?}: {}", name, e);
+        }
+        self.new_pipeline_impl(render_pass, pipeline_desc, with_depth_test, name)
+    }
+
+    /// Fails if shaders do not match vertex attributes or descriptor set layouts of `pipeline_desc`
+    pub fn try_new_pipeline(&mut self, render_pass: Arc<RenderPassResource>, pipeline_desc: GraphicsPipelineDesc, with_depth_test: bool, name: Option<&str>) -> Result<Arc<GraphicsPipelineResource>, ShaderInterfaceError> {
+        pipeline_desc.validate()?;
+        Ok(self.new_pipeline_impl(render_pass, pipeline_desc, with_depth_test, name))
+    }
+
+    /// Pipeline rebuilt from `sources` when they change, see `reload_shaders`. Sources are not watched in release builds
+    pub fn new_hot_pipeline(&mut self, render_pass: Arc<RenderPassResource>, pipeline_desc: GraphicsPipelineDesc, with_depth_test: bool, sources: ShaderSources, name: Option<&str>) -> HotPipeline {
+        let pipeline = self.new_pipeline(render_pass.clone(), pipeline_desc.clone(), with_depth_test, name);
+        self.shader_watcher.watch(pipeline, render_pass, pipeline_desc, with_depth_test, sources, name)
+    }
+
+    /// Replace pipelines of hot pipelines with sources recompiled by the watcher thread. Previous pipeline is kept
+    /// if compilation fails or shaders do not match pipeline description. Returns number of replaced pipelines
+    pub fn reload_shaders(&mut self) -> usize {
+        let mut watcher = mem::take(&mut self.shader_watcher);
+        let mut reloaded = 0;
+        for (entry, code) in watcher.changed() {
+            let (vert_shader, frag_shader) = match code {
+                Ok(code) => code,
+                Err(e) => {
+                    error!("Failed to compile shaders of pipeline {:?}, keeping previous pipeline: {:#}", entry.name, e);
+                    continue;
+                }
+            };
+            let desc = GraphicsPipelineDesc {
+                vert_shader,
+                frag_shader,
+                ..entry.desc.clone()
+            };
+            match self.try_new_pipeline(entry.render_pass.clone(), desc.clone(), entry.with_depth_test, entry.name.as_deref()) {
+                Ok(pipeline) => {
+                    info!("Reloaded shaders of pipeline {:?}", entry.name);
+                    entry.replace(desc, pipeline);
+                    reloaded += 1;
+                }
+                Err(e) => error!("Reloaded shaders of pipeline {:?} are rejected, keeping previous pipeline: {}", entry.name, e),
+            }
+        }
+        self.shader_watcher = watcher;
+        reloaded
+    }
+
+    /// Recreate hot pipeline for new render pass, e.g. after swapchain format change. Keeps reloaded shaders
+    pub fn rebuild_hot_pipeline(&mut self, pipeline: &HotPipeline, render_pass: Arc<RenderPassResource>) {
+        let mut watcher = mem::take(&mut self.shader_watcher);
+        match watcher.find(pipeline) {
+            Some(entry) => {
+                let new_pipeline = self.new_pipeline(render_pass.clone(), entry.desc.clone(), entry.with_depth_test, entry.name.as_deref());
+                entry.render_pass = render_pass;
+                entry.replace(entry.desc.clone(), new_pipeline);
+            }
+            None => warn!("rebuild_hot_pipeline: pipeline was not created by this allocator, skipped"),
+        }
+        self.shader_watcher = watcher;
+    }
+
+    fn new_pipeline_impl(&mut self, render_pass: Arc<RenderPassResource>, pipeline_desc: GraphicsPipelineDesc, with_depth_test: bool, name: Option<&str>) -> Arc<GraphicsPipelineResource> {
         let descriptor_set_layouts = pipeline_desc.bindings.iter()
             .map(|bindings_desc| self.get_or_create_descriptor_set_layout(bindings_desc))
             .collect();
+        let captured_desc = self.instance.capture_enabled().then(|| (render_pass.render_pass, pipeline_desc.clone()));
 
         let res = Arc::new(GraphicsPipelineResource::new(&self.instance.device, render_pass, pipeline_desc, descriptor_set_layouts, with_depth_test));
+        self.set_debug_name(res.pipeline, name);
+        self.set_debug_name(res.pipeline_layout, name);
+        self.instance.registry.register(ResourceKind::Pipeline, res.pipeline, name, 0);
+        if let Some((render_pass, desc)) = captured_desc {
+            self.instance.capture(|| [capture_pipeline(&res, render_pass, &desc, with_depth_test, name)]);
+        }
         self.pipelines.push(res.clone());
 
         res
     }
+
+    /// `precise` requires `DeviceFeature::OcclusionQueryPrecise`, falls back to non-precise queries without it
+    pub fn new_occlusion_query_pool(&mut self, count: u32, precise: bool, name: Option<&str>) -> anyhow::Result<Arc<QueryPoolResource>> {
+        let precise = if precise && !self.instance.capabilities.has_feature(DeviceFeature::OcclusionQueryPrecise) {
+            warn!("OcclusionQueryPrecise feature is not enabled, creating non-precise occlusion queries");
+            false
+        }
+        else {
+            precise
+        };
+        self.new_query_pool(QueryKind::Occlusion { precise }, count, name)
+    }
+
+    /// Fails if `DeviceFeature::PipelineStatisticsQuery` is not enabled
+    pub fn new_pipeline_statistics_query_pool(&mut self, count: u32, statistics: vk::QueryPipelineStatisticFlags, name: Option<&str>) -> anyhow::Result<Arc<QueryPoolResource>> {
+        if !self.instance.capabilities.has_feature(DeviceFeature::PipelineStatisticsQuery) {
+            anyhow::bail!("PipelineStatisticsQuery feature is not enabled, cannot create pipeline statistics query pool");
+        }
+        if statistics.is_empty() {
+            anyhow::bail!("Pipeline statistics query pool requires at least one statistic");
+        }
+        self.new_query_pool(QueryKind::PipelineStatistics(statistics), count, name)
+    }
+
+    fn new_query_pool(&mut self, kind: QueryKind, count: u32, name: Option<&str>) -> anyhow::Result<Arc<QueryPoolResource>> {
+        let res = Arc::new(QueryPoolResource::new(&self.instance.device, kind, count)?);
+        self.set_debug_name(res.query_pool, name);
+        self.instance.registry.register(ResourceKind::QueryPool, res.query_pool, name, 0);
+        self.instance.capture(|| [CaptureEvent::CreateQueryPool {
+            handle: res.query_pool.as_raw(),
+            kind: CapturedQueryKind::from(kind),
+            count,
+            name: name.map(str::to_string),
+        }]);
+        self.query_pools.push(res.clone());
+        Ok(res)
+    }
+    fn set_debug_name<H: vk::Handle>(&self, handle: H, name: Option<&str>) {
+        if let Some(name) = name {
+            self.instance.set_debug_name(handle, name);
+        }
+    }
+
     fn get_or_create_descriptor_set_layout(&mut self, bindings_desc: &[DescriptorSetLayoutBindingDesc]) -> DescriptorSetLayout {
         let key: Vec<DescriptorSetLayoutBindingDesc> = bindings_desc.to_vec();
 
@@ -154,16 +347,33 @@ impl VulkanAllocator {
             return layout;
         }
 
-        let bindings: Vec<DescriptorSetLayoutBinding> = bindings_desc.iter().map(|desc| {
+        let supported_bindings = self.supported_bindings(bindings_desc);
+        for (requested, supported) in bindings_desc.iter().zip(supported_bindings.iter()) {
+            if requested.binding_flags != supported.binding_flags {
+                warn!("Binding flags {:?} for binding {} are not supported by device, falling back to {:?}",
+                    requested.binding_flags, requested.binding, supported.binding_flags);
+            }
+        }
+
+        let bindings: Vec<DescriptorSetLayoutBinding> = supported_bindings.iter().map(|desc| {
             DescriptorSetLayoutBinding::default()
                 .binding(desc.binding)
                 .descriptor_type(desc.descriptor_type)
                 .descriptor_count(desc.descriptor_count)
                 .stage_flags(desc.stage_flags)
         }).collect();
+        let binding_flags: Vec<DescriptorBindingFlags> = supported_bindings.iter().map(|desc| desc.binding_flags).collect();
 
-        let layout_create_info = DescriptorSetLayoutCreateInfo::default()
+        let mut binding_flags_info = DescriptorSetLayoutBindingFlagsCreateInfo::default()
+            .binding_flags(&binding_flags);
+        let mut layout_create_info = DescriptorSetLayoutCreateInfo::default()
             .bindings(&bindings);
+        if binding_flags.iter().any(|f| !f.is_empty()) {
+            if binding_flags.iter().any(|f| f.contains(DescriptorBindingFlags::UPDATE_AFTER_BIND)) {
+                layout_create_info = layout_create_info.flags(DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL);
+            }
+            layout_create_info = layout_create_info.push_next(&mut binding_flags_info);
+        }
 
         let layout = unsafe {
             self.instance.device.create_descriptor_set_layout(&layout_create_info, None).unwrap()
@@ -174,20 +384,57 @@ impl VulkanAllocator {
     }
 
 
-    pub fn dump_resource_usage(&self) {
-        let buffer_count = self.buffers.len();
-        let staging_buffer_count = self.staging_buffers.len();
-        let image_count = self.images.len();
-        let render_pass_count = self.render_passes.len();
-        let pipeline_count = self.pipelines.len();
-        let sampler_count = self.samplers.len();
-        println!("Resource usage dump:");
-        println!("Buffers: {}", buffer_count);
-        println!("Staging buffers: {}", staging_buffer_count);
-        println!("Images: {}", image_count);
-        println!("Render passes: {}", render_pass_count);
-        println!("Pipelines: {}", pipeline_count);
-        println!("Samplers: {}", sampler_count);
+    /// Serializable snapshot of resources owned by this allocator and their memory
+    pub fn resource_usage(&self) -> ResourceUsageReport {
+        let mut report = ResourceUsageReport::new(self.memory_manager.memory_types(), self.memory_manager.memory_heaps());
+
+        for buffer in &self.buffers {
+            let (memory_type, allocated) = buffer.memory();
+            report.add_resource(ResourceKind::Buffer, allocated);
+            report.add_memory(memory_type, allocated, buffer.size() as u64);
+        }
+        for staging_buffer in &self.staging_buffers {
+            let (memory_type, allocated) = staging_buffer.memory();
+            report.add_resource(ResourceKind::StagingBuffer, allocated);
+            report.add_memory(memory_type, allocated, staging_buffer.size() as u64);
+            report.staging_buffers.push(StagingBufferUsage {
+                name: self.instance.registry.name(ResourceKind::StagingBuffer, staging_buffer.buffer),
+                size: staging_buffer.size() as u64,
+                frozen: staging_buffer.frozen_len() as u64,
+            });
+        }
+        for image in &self.images {
+            let (memory_type, allocated) = image.memory().unwrap_or_default();
+            report.add_resource(ResourceKind::Image, allocated);
+            report.add_memory(memory_type, allocated, allocated);
+        }
+        for _ in &self.samplers {
+            report.add_resource(ResourceKind::Sampler, 0);
+        }
+        for _ in &self.pipelines {
+            report.add_resource(ResourceKind::Pipeline, 0);
+        }
+        for _ in &self.render_passes {
+            report.add_resource(ResourceKind::RenderPass, 0);
+        }
+        for _ in &self.query_pools {
+            report.add_resource(ResourceKind::QueryPool, 0);
+        }
+        for _ in 0..self.descriptor_set_allocator.set_count() {
+            report.add_resource(ResourceKind::DescriptorSet, 0);
+        }
+        report.descriptor_pools = self.descriptor_set_allocator.pool_usage();
+
+        report.pending_destruction = self.buffers.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.staging_buffers.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.images.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.samplers.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.pipelines.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.render_passes.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.query_pools.iter().filter(|r| Arc::strong_count(r) == 1).count()
+            + self.descriptor_set_allocator.pending_free_count();
+
+        report
     }
 
     pub fn destroy_old_resources(&mut self) {
@@ -250,6 +497,17 @@ impl VulkanAllocator {
                 i += 1;
             }
         }
+
+        let mut i = 0;
+        while i < self.query_pools.len() {
+            if self.query_pools[i].submission_usage.load().is_none_or(|n| n <= last_waited) && Arc::strong_count(&self.query_pools[i]) == 1 {
+                destroy_query_pool(&self.query_pools[i], true);
+                self.query_pools.swap_remove(i);
+            }
+            else {
+                i += 1;
+            }
+        }
     }
 }
 
@@ -3,14 +3,17 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use ash::vk;
 use ash::vk::{ColorComponentFlags, CompareOp, CullModeFlags, DescriptorSetLayout, DynamicState, GraphicsPipelineCreateInfo, Pipeline, PipelineCache, PipelineCacheCreateInfo, PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo, PipelineDepthStencilStateCreateInfo, PipelineDynamicStateCreateInfo, PipelineInputAssemblyStateCreateInfo, PipelineLayout, PipelineLayoutCreateInfo, PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo, PipelineShaderStageCreateInfo, PipelineVertexInputStateCreateInfo, PipelineViewportStateCreateInfo, PrimitiveTopology, SampleCountFlags, ShaderModuleCreateInfo, ShaderStageFlags, VertexInputAttributeDescription, VertexInputBindingDescription, FALSE};
+use anyhow::Context;
 use log::{error, warn};
 use smallvec::SmallVec;
 use sparkles::range_event_start;
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::OptionSeqNumShared;
 use crate::resources::render_pass::RenderPassResource;
 use crate::shaders::DescriptorSetLayoutBindingDesc;
-use crate::shaders::layout::MemberMeta;
+use crate::shaders::layout::{LayoutInfo, MemberMeta};
+use crate::shaders::reflection::{check_pipeline_interface, derive_bindings, derive_vertex_input, ShaderInterfaceError, ShaderReflection};
 use crate::wrappers::device::VkDeviceRef;
 
 pub struct GraphicsPipelineResource {
@@ -155,6 +158,7 @@ fn get_assembly_create_info(assembly: &VertexAssembly) -> PipelineInputAssemblyS
         },
     }
 }
+#[derive(Clone)]
 pub struct GraphicsPipelineDesc {
     pub vertex_assembly: VertexAssembly,
     pub attributes: VertexInputDesc,
@@ -173,33 +177,93 @@ impl GraphicsPipelineDesc {
             frag_shader: shaders.1.to_vec(),
         }
     }
+
+    /// Layouts derived from SPIR-V reflection: vertex inputs tightly packed into binding 0 in location order,
+    /// descriptor bindings of both stages merged. Derived binding slices are leaked, create description once per shader pair
+    pub fn from_shaders(shaders: (&'static [u8], &'static [u8]), input_rate: vk::VertexInputRate) -> anyhow::Result<Self> {
+        let vert = ShaderReflection::parse(shaders.0).context("Failed to reflect vertex shader")?;
+        let frag = ShaderReflection::parse(shaders.1).context("Failed to reflect fragment shader")?;
+        let attributes = derive_vertex_input(&vert, input_rate)?;
+        let bindings = derive_bindings(&[&vert, &frag])?.into_iter()
+            .map(|set| &*Box::leak(set.into_boxed_slice()))
+            .collect();
+        let desc = Self::new(shaders, attributes, bindings);
+        desc.validate()?;
+        Ok(desc)
+    }
+
+    /// Check vertex attributes and descriptor set layouts against SPIR-V of both shaders
+    pub fn validate(&self) -> Result<(), ShaderInterfaceError> {
+        check_pipeline_interface(&self.vert_shader, &self.frag_shader, &self.attributes, &self.bindings)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct VertexInputDesc {
     attrib_desc: Vec<VertexInputAttributeDescription>,
     binding_desc: Vec<VertexInputBindingDescription>,
 }
 impl VertexInputDesc {
+    /// Single binding 0 with INSTANCE input rate
     pub fn new(members_meta: &'static [MemberMeta], size: usize) -> Self {
-        let binding_desc = vec![VertexInputBindingDescription::default()
-            .binding(0)
-            .input_rate(vk::VertexInputRate::INSTANCE)
-            .stride(size as u32)];
-
-        let attrib_desc = members_meta.iter().enumerate().map(|(i, member)| {
-            VertexInputAttributeDescription::default()
-                .binding(0)
-                .format(member.ty.format())
-                .offset(member.range.start as u32)
-                .location(i as u32)
-        }).collect::<Vec<_>>();
+        Self::default().with_members(members_meta, size, vk::VertexInputRate::INSTANCE)
+    }
+
+    /// Append binding with attributes of layout `T`, locations continue after previous bindings
+    pub fn with_binding<T: LayoutInfo>(self, input_rate: vk::VertexInputRate) -> Self {
+        self.with_members(T::MEMBERS_META, T::SIZE, input_rate)
+    }
+
+    /// Append binding with `size` stride and attributes of `members_meta`, locations continue after previous bindings
+    pub fn with_members(mut self, members_meta: &'static [MemberMeta], size: usize, input_rate: vk::VertexInputRate) -> Self {
+        let binding = self.binding_desc.len() as u32;
+        self.binding_desc.push(VertexInputBindingDescription::default()
+            .binding(binding)
+            .input_rate(input_rate)
+            .stride(size as u32));
+
+        // matrices and arrays take location per column/element, 64-bit 3 and 4 component vectors take two
+        let mut location = self.attrib_desc.iter()
+            .map(|a| a.location + Self::location_count(a.format))
+            .max()
+            .unwrap_or(0);
+        let attributes = members_meta.iter()
+            .flat_map(|member| member.ty.vertex_attributes().into_iter().map(|(offset, format)| (member.range.start + offset, format)))
+            .map(|(offset, format)| {
+                let desc = VertexInputAttributeDescription::default()
+                    .binding(binding)
+                    .format(format)
+                    .offset(offset as u32)
+                    .location(location);
+                location += Self::location_count(format);
+                desc
+            });
+        self.attrib_desc.extend(attributes);
+        self
+    }
+
+    fn location_count(format: vk::Format) -> u32 {
+        match format {
+            vk::Format::R64G64B64_SFLOAT | vk::Format::R64G64B64A64_SFLOAT => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn from_descriptions(attrib_desc: Vec<VertexInputAttributeDescription>, binding_desc: Vec<VertexInputBindingDescription>) -> Self {
         Self {
             attrib_desc,
             binding_desc,
         }
     }
 
+    pub fn attribute_descriptions(&self) -> &[VertexInputAttributeDescription] {
+        &self.attrib_desc
+    }
+
+    pub fn binding_descriptions(&self) -> &[VertexInputBindingDescription] {
+        &self.binding_desc
+    }
+
     pub fn get_input_state_create_info<'a>(&'a self) -> PipelineVertexInputStateCreateInfo<'a> {
         PipelineVertexInputStateCreateInfo::default()
             .vertex_attribute_descriptions(&self.attrib_desc)
@@ -233,6 +297,7 @@ pub(crate) fn destroy_pipeline(pipeline: &GraphicsPipelineResource, no_usages: b
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::Pipeline, pipeline.pipeline);
             let device = instance.device.clone();
             unsafe {
                 device.destroy_pipeline_cache(pipeline.pipeline_cache, None);
@@ -0,0 +1,164 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use ash::vk;
+use ash::vk::{QueryControlFlags, QueryPipelineStatisticFlags, QueryResultFlags};
+use log::{error, warn};
+use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
+use crate::queue::OptionSeqNumShared;
+use crate::queue::shared::HostWaitedNum;
+use crate::wrappers::device::VkDeviceRef;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Number of samples passed depth and stencil tests. Without `precise` only zero/non-zero is guaranteed
+    Occlusion { precise: bool },
+    /// One counter per enabled statistic, in bit order
+    PipelineStatistics(QueryPipelineStatisticFlags),
+}
+
+impl QueryKind {
+    pub(crate) fn query_type(&self) -> vk::QueryType {
+        match self {
+            QueryKind::Occlusion { .. } => vk::QueryType::OCCLUSION,
+            QueryKind::PipelineStatistics(_) => vk::QueryType::PIPELINE_STATISTICS,
+        }
+    }
+
+    pub(crate) fn control_flags(&self) -> QueryControlFlags {
+        match self {
+            QueryKind::Occlusion { precise: true } => QueryControlFlags::PRECISE,
+            _ => QueryControlFlags::empty(),
+        }
+    }
+
+    /// Number of u64 values written per query
+    pub fn values_per_query(&self) -> u32 {
+        match self {
+            QueryKind::Occlusion { .. } => 1,
+            QueryKind::PipelineStatistics(statistics) => statistics.as_raw().count_ones(),
+        }
+    }
+}
+
+pub struct QueryPoolResource {
+    pub(crate) query_pool: vk::QueryPool,
+    kind: QueryKind,
+    count: u32,
+    pub(crate) submission_usage: OptionSeqNumShared,
+
+    dropped: AtomicBool,
+}
+
+impl QueryPoolResource {
+    pub(crate) fn new(device: &VkDeviceRef, kind: QueryKind, count: u32) -> anyhow::Result<Self> {
+        let mut info = vk::QueryPoolCreateInfo::default()
+            .query_type(kind.query_type())
+            .query_count(count);
+        if let QueryKind::PipelineStatistics(statistics) = kind {
+            info = info.pipeline_statistics(statistics);
+        }
+        let query_pool = unsafe { device.create_query_pool(&info, None) }
+            .map_err(|e| anyhow::anyhow!("Failed to create query pool: {:?}", e))?;
+
+        Ok(Self {
+            query_pool,
+            kind,
+            count,
+            submission_usage: OptionSeqNumShared::default(),
+
+            dropped: AtomicBool::new(false),
+        })
+    }
+
+    pub fn kind(&self) -> QueryKind {
+        self.kind
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Results of `queries`, `values_per_query` values each. Queries are reset when they are begun,
+    /// so only queries used in the last submission have results.
+    /// None if that submission is not yet waited on host or some query was not used
+    #[must_use]
+    pub fn try_get_results(&self, queries: Range<u32>, host_waited_num: HostWaitedNum) -> Option<Vec<u64>> {
+        if queries.is_empty() || queries.end > self.count {
+            warn!("Query range {:?} is out of pool size {}", queries, self.count);
+            return None;
+        }
+        let last_usage = self.submission_usage.load()?;
+        if host_waited_num.num() < last_usage {
+            return None;
+        }
+
+        let instance = try_get_instance()?;
+        let values_per_query = self.kind.values_per_query() as usize;
+        let mut results = vec![0u64; queries.len() * values_per_query];
+        let stride = (values_per_query * size_of::<u64>()) as vk::DeviceSize;
+        let res = unsafe {
+            // ash derives stride from element type, statistics need one block of values per query
+            (instance.device.fp_v1_0().get_query_pool_results)(
+                instance.device.handle(), self.query_pool, queries.start, queries.len() as u32,
+                size_of_val(results.as_slice()), results.as_mut_ptr().cast(), stride, QueryResultFlags::TYPE_64,
+            ).result()
+        };
+        match res {
+            Ok(()) => Some(results),
+            Err(vk::Result::NOT_READY) => None,
+            Err(e) => {
+                warn!("Failed to get query pool results: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for QueryPoolResource {
+    fn drop(&mut self) {
+        if !self.dropped.load(Ordering::Relaxed) {
+            destroy_query_pool(self, false);
+        }
+    }
+}
+
+pub(crate) fn destroy_query_pool(query_pool: &QueryPoolResource, no_usages: bool) {
+    if !query_pool.dropped.swap(true, Ordering::Relaxed) {
+        if let Some(instance) = try_get_instance() {
+            if !no_usages {
+                let last_host_waited = instance.shared_state.last_host_waited_cached().num();
+                if query_pool.submission_usage.load().is_some_and(|u| u > last_host_waited) {
+                    warn!("Trying to destroy query pool resource, but VulkanAllocator was destroyed earlier! Calling device_wait_idle...");
+                    unsafe {
+                        instance.device.device_wait_idle().unwrap();
+                    }
+                }
+            }
+            instance.unregister_resource(ResourceKind::QueryPool, query_pool.query_pool);
+            let device = instance.device.clone();
+            unsafe {
+                device.destroy_query_pool(query_pool.query_pool, None);
+            }
+        }
+        else {
+            error!("VulkanInstance was destroyed! Cannot destroy query pool resource");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_per_query() {
+        assert_eq!(QueryKind::Occlusion { precise: true }.values_per_query(), 1);
+        let statistics = QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS
+            | QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES
+            | QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS;
+        assert_eq!(QueryKind::PipelineStatistics(statistics).values_per_query(), 3);
+        assert_eq!(QueryKind::Occlusion { precise: true }.control_flags(), QueryControlFlags::PRECISE);
+        assert_eq!(QueryKind::Occlusion { precise: false }.control_flags(), QueryControlFlags::empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use ash::vk::Handle;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ResourceKind {
+    Buffer,
+    StagingBuffer,
+    Image,
+    Sampler,
+    Pipeline,
+    RenderPass,
+    DescriptorSet,
+    QueryPool,
+}
+
+impl ResourceKind {
+    /// Staging buffers and descriptor sets are allocated per frame
+    fn is_long_lived(self) -> bool {
+        !matches!(self, ResourceKind::StagingBuffer | ResourceKind::DescriptorSet)
+    }
+}
+
+/// Live resource, created by `VulkanAllocator`
+#[derive(Debug, Clone)]
+pub struct ResourceRecord {
+    pub kind: ResourceKind,
+    /// Raw vulkan handle
+    pub handle: u64,
+    pub name: Option<String>,
+    /// Bytes of device memory owned by the resource
+    pub size: u64,
+    pub created_at: Instant,
+    /// Captured in debug builds, see `ResourceRegistry::set_per_frame_backtraces`
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+/// Tracks every live resource created through allocators of the instance
+pub struct ResourceRegistry {
+    records: Mutex<HashMap<(ResourceKind, u64), ResourceRecord>>,
+    per_frame_backtraces: AtomicBool,
+}
+
+impl Default for ResourceRegistry {
+    fn default() -> Self {
+        Self {
+            records: Mutex::new(HashMap::new()),
+            per_frame_backtraces: AtomicBool::new(true),
+        }
+    }
+}
+
+impl ResourceRegistry {
+    /// Capture backtraces of staging buffers and descriptor sets, which are allocated per frame. Enabled by default,
+    /// disable if capture cost shows in debug builds. Other kinds are always captured in debug builds
+    pub fn set_per_frame_backtraces(&self, enabled: bool) {
+        self.per_frame_backtraces.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn register<H: Handle>(&self, kind: ResourceKind, handle: H, name: Option<&str>, size: u64) {
+        let handle = handle.as_raw();
+        let capture = kind.is_long_lived() || self.per_frame_backtraces.load(Ordering::Relaxed);
+        let backtrace = (cfg!(debug_assertions) && capture).then(|| Arc::new(Backtrace::force_capture()));
+        self.records.lock().insert((kind, handle), ResourceRecord {
+            kind,
+            handle,
+            name: name.map(str::to_string),
+            size,
+            created_at: Instant::now(),
+            backtrace,
+        });
+    }
+
+    /// No-op for resources not created by allocator (e.g. swapchain images)
+    pub(crate) fn unregister<H: Handle>(&self, kind: ResourceKind, handle: H) {
+        self.records.lock().remove(&(kind, handle.as_raw()));
+    }
+
+    pub(crate) fn name<H: Handle>(&self, kind: ResourceKind, handle: H) -> Option<String> {
+        self.records.lock().get(&(kind, handle.as_raw())).and_then(|r| r.name.clone())
+    }
+
+    /// All live resources in creation order
+    pub fn records(&self) -> Vec<ResourceRecord> {
+        let mut records: Vec<_> = self.records.lock().values().cloned().collect();
+        records.sort_by_key(|r| r.created_at);
+        records
+    }
+
+    pub fn records_of(&self, kind: ResourceKind) -> Vec<ResourceRecord> {
+        let mut records: Vec<_> = self.records.lock().values().filter(|r| r.kind == kind).cloned().collect();
+        records.sort_by_key(|r| r.created_at);
+        records
+    }
+
+    pub fn count(&self, kind: ResourceKind) -> usize {
+        self.records.lock().values().filter(|r| r.kind == kind).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.lock().is_empty()
+    }
+
+    /// Human-readable list of live resources, None if nothing is alive
+    pub fn leak_report(&self) -> Option<String> {
+        Self::format_report(&self.records())
+    }
+
+    /// Same as `leak_report`, limited to given resources
+    pub(crate) fn leak_report_of<H: Handle>(&self, kind: ResourceKind, handles: impl IntoIterator<Item = H>) -> Option<String> {
+        let mut records: Vec<_> = {
+            let all = self.records.lock();
+            handles.into_iter().filter_map(|h| all.get(&(kind, h.as_raw())).cloned()).collect()
+        };
+        records.sort_by_key(|r| r.created_at);
+        Self::format_report(&records)
+    }
+
+    fn format_report(records: &[ResourceRecord]) -> Option<String> {
+        if records.is_empty() {
+            return None;
+        }
+
+        let mut report = format!("{} resources were not freed:", records.len());
+        for record in records {
+            let _ = write!(report, "\n  {:?} 0x{:x} \"{}\" ({} bytes)",
+                record.kind, record.handle, record.name.as_deref().unwrap_or("<unnamed>"), record.size);
+            if let Some(backtrace) = &record.backtrace {
+                let _ = write!(report, "\n    created at:\n{}", backtrace);
+            }
+        }
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk;
+
+    #[test]
+    fn test_register_and_report() {
+        let registry = ResourceRegistry::default();
+        assert!(registry.leak_report().is_none());
+
+        registry.register(ResourceKind::Buffer, vk::Buffer::from_raw(1), Some("vertices"), 256);
+        registry.register(ResourceKind::Image, vk::Image::from_raw(1), None, 1024);
+        registry.register(ResourceKind::Buffer, vk::Buffer::from_raw(2), Some("indices"), 64);
+        assert_eq!(registry.count(ResourceKind::Buffer), 2);
+        assert_eq!(registry.len(), 3);
+
+        registry.unregister(ResourceKind::Buffer, vk::Buffer::from_raw(1));
+        // unknown handle is ignored
+        registry.unregister(ResourceKind::Sampler, vk::Sampler::from_raw(7));
+
+        let records = registry.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, ResourceKind::Image);
+        assert_eq!(records[1].name.as_deref(), Some("indices"));
+        assert_eq!(records[1].backtrace.is_some(), cfg!(debug_assertions));
+
+        let report = registry.leak_report().unwrap();
+        assert!(report.starts_with("2 resources were not freed"));
+        assert!(report.contains("\"<unnamed>\" (1024 bytes)"));
+        assert!(report.contains("\"indices\" (64 bytes)"));
+
+        let report = registry.leak_report_of(ResourceKind::Buffer, [vk::Buffer::from_raw(2), vk::Buffer::from_raw(3)]).unwrap();
+        assert!(report.starts_with("1 resources were not freed"));
+        assert!(!report.contains("<unnamed>"));
+    }
+
+    #[test]
+    fn test_per_frame_backtraces_opt_out() {
+        let registry = ResourceRegistry::default();
+        registry.register(ResourceKind::DescriptorSet, vk::DescriptorSet::from_raw(1), None, 0);
+        registry.set_per_frame_backtraces(false);
+        registry.register(ResourceKind::Pipeline, vk::Pipeline::from_raw(1), None, 0);
+        registry.register(ResourceKind::DescriptorSet, vk::DescriptorSet::from_raw(2), None, 0);
+
+        let sets = registry.records_of(ResourceKind::DescriptorSet);
+        assert_eq!(sets[0].backtrace.is_some(), cfg!(debug_assertions));
+        assert!(sets[1].backtrace.is_none());
+        assert_eq!(registry.records_of(ResourceKind::Pipeline)[0].backtrace.is_some(), cfg!(debug_assertions));
+    }
+}
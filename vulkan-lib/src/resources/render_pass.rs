@@ -6,6 +6,7 @@ use log::{error, warn};
 use smallvec::{smallvec, SmallVec};
 use sparkles::range_event_start;
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::OptionSeqNumShared;
 use crate::queue::memory_manager::MemoryManager;
 use crate::resources::image::ImageResource;
@@ -129,6 +130,11 @@ impl RenderPassResource {
     pub fn attachments_desc(&self) -> AttachmentsDescription {
         self.attachments_description.clone()
     }
+
+    /// Swapchain format this render pass was created for
+    pub fn swapchain_format(&self) -> Format {
+        self.attachments_description.swapchain_format()
+    }
 }
 #[derive(Clone)]
 pub struct AttachmentsDescription {
@@ -171,6 +177,14 @@ impl AttachmentsDescription {
         self.color_attachement_desc
     }
 
+    pub fn swapchain_layout(&self) -> ImageLayout {
+        self.swapchain_layout
+    }
+
+    pub fn swapchain_format(&self) -> Format {
+        self.swapchain_attachment_desc.format
+    }
+
     pub fn fill_defaults(&mut self, swapchain_format: Format) {
         self.swapchain_attachment_desc.format = swapchain_format;
         // self.color_attachment_desc.load_op = AttachmentLoadOp::CLEAR;
@@ -244,6 +258,7 @@ pub(crate) fn destroy_render_pass(render_pass: &RenderPassResource, no_usages: b
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::RenderPass, render_pass.render_pass);
             let device = instance.device.clone();
             unsafe {
                 device.destroy_render_pass(render_pass.render_pass, None)
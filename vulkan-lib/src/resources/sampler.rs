@@ -2,6 +2,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use ash::vk;
 use log::{error, warn};
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::OptionSeqNumShared;
 use crate::wrappers::device::VkDeviceRef;
 
@@ -52,6 +53,7 @@ pub(crate) fn destroy_sampler(sampler: &SamplerResource, no_usages: bool) {
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::Sampler, sampler.sampler);
             let device = instance.device.clone();
             unsafe {
                 device.destroy_sampler(sampler.sampler, None);
@@ -1,11 +1,12 @@
 use std::ops::Range;
-use std::slice::from_raw_parts_mut;
+use std::slice::{from_raw_parts, from_raw_parts_mut};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use ash::vk;
 use ash::vk::{BufferCreateFlags, BufferCreateInfo, BufferUsageFlags, DeviceSize, MemoryAllocateInfo, MemoryMapFlags};
 use log::{error, warn};
 use crate::try_get_instance;
+use crate::resources::registry::ResourceKind;
 use crate::queue::queue_local::QueueLocal;
 use crate::resources::LastResourceUsage;
 use crate::queue::memory_manager::{MemoryManager, MemoryTypeAlgorithm};
@@ -53,6 +54,8 @@ impl StagingBufferResource {
 pub(crate) struct StagingBuffer {
     pub(crate) buffer: vk::Buffer,
     pub(crate) memory: vk::DeviceMemory,
+    memory_type: u32,
+    memory_size: DeviceSize,
     size: usize,
     pub(crate) submission_usage: OptionSeqNumShared,
     pub(crate) inner: QueueLocal<BufferResourceInner>,
@@ -97,6 +100,8 @@ impl StagingBuffer {
         StagingBuffer {
             buffer,
             memory,
+            memory_type,
+            memory_size: allocation_size,
             size: size as usize,
             submission_usage: OptionSeqNumShared::default(),
             inner: QueueLocal::new(BufferResourceInner {
@@ -113,6 +118,24 @@ impl StagingBuffer {
         self.size
     }
 
+    /// Memory type index and allocation size
+    pub(crate) fn memory(&self) -> (u32, DeviceSize) {
+        (self.memory_type, self.memory_size)
+    }
+
+    /// Bytes currently frozen for pending uploads
+    pub(crate) fn frozen_len(&self) -> usize {
+        *self.frozen_len.lock().unwrap() as usize
+    }
+
+    /// Copy of mapped contents, used by capture
+    pub(crate) fn read(&self, range: Range<u64>) -> Vec<u8> {
+        assert!(range.end as usize <= self.size);
+        unsafe {
+            from_raw_parts(self.mapped.add(range.start as usize), (range.end - range.start) as usize).to_vec()
+        }
+    }
+
     pub fn try_freeze(self: &Arc<Self>, size: usize) -> Option<StagingBufferRange> {
         let mut current_frozen = self.frozen_len.lock().unwrap();
         if *current_frozen as usize + size <= self.size {
@@ -166,6 +189,7 @@ pub(crate) fn destroy_staging_buffer_resource(buffer_resource: &StagingBuffer, n
                     }
                 }
             }
+            instance.unregister_resource(ResourceKind::StagingBuffer, buffer_resource.buffer);
             let device = instance.device.clone();
             unsafe {
                 device.unmap_memory(buffer_resource.memory);
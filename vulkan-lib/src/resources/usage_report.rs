@@ -0,0 +1,180 @@
+use ash::vk::{MemoryHeap, MemoryHeapFlags, MemoryType};
+use serde::Serialize;
+use crate::resources::registry::ResourceKind;
+
+/// Snapshot of resources owned by `VulkanAllocator`, see [`ResourceUsageReport::to_json`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResourceUsageReport {
+    pub resources: Vec<ResourceTypeUsage>,
+    pub memory_types: Vec<MemoryTypeUsage>,
+    pub memory_heaps: Vec<MemoryHeapUsage>,
+    pub descriptor_pools: Vec<DescriptorPoolUsage>,
+    /// Resources referenced only by allocator, waiting for GPU or the next `destroy_old_resources`
+    pub pending_destruction: usize,
+    pub staging_buffers: Vec<StagingBufferUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceTypeUsage {
+    pub kind: ResourceKind,
+    pub count: usize,
+    /// Bytes of device memory allocated for resources of this type
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryTypeUsage {
+    pub index: u32,
+    pub heap_index: u32,
+    pub property_flags: String,
+    /// Sum of allocation sizes, including alignment padding
+    pub allocated_bytes: u64,
+    /// Sum of requested resource sizes
+    pub used_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryHeapUsage {
+    pub index: u32,
+    pub size: u64,
+    pub device_local: bool,
+    pub allocated_bytes: u64,
+    pub used_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DescriptorPoolUsage {
+    pub max_sets: u32,
+    pub allocated_sets: u32,
+    pub update_after_bind: bool,
+    pub descriptors: Vec<DescriptorTypeUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DescriptorTypeUsage {
+    pub descriptor_type: String,
+    pub capacity: u32,
+    pub allocated: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StagingBufferUsage {
+    pub name: Option<String>,
+    pub size: u64,
+    /// Bytes frozen for uploads which are not yet unfrozen
+    pub frozen: u64,
+}
+
+impl ResourceUsageReport {
+    pub(crate) fn new(memory_types: &[MemoryType], memory_heaps: &[MemoryHeap]) -> Self {
+        Self {
+            memory_types: memory_types.iter().enumerate().map(|(i, t)| MemoryTypeUsage {
+                index: i as u32,
+                heap_index: t.heap_index,
+                property_flags: format!("{:?}", t.property_flags),
+                allocated_bytes: 0,
+                used_bytes: 0,
+            }).collect(),
+            memory_heaps: memory_heaps.iter().enumerate().map(|(i, h)| MemoryHeapUsage {
+                index: i as u32,
+                size: h.size,
+                device_local: h.flags.contains(MemoryHeapFlags::DEVICE_LOCAL),
+                allocated_bytes: 0,
+                used_bytes: 0,
+            }).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn add_resource(&mut self, kind: ResourceKind, bytes: u64) {
+        match self.resources.iter_mut().find(|r| r.kind == kind) {
+            Some(usage) => {
+                usage.count += 1;
+                usage.bytes += bytes;
+            }
+            None => self.resources.push(ResourceTypeUsage { kind, count: 1, bytes }),
+        }
+    }
+
+    pub(crate) fn add_memory(&mut self, memory_type: u32, allocated_bytes: u64, used_bytes: u64) {
+        let Some(type_usage) = self.memory_types.get_mut(memory_type as usize) else {
+            return;
+        };
+        type_usage.allocated_bytes += allocated_bytes;
+        type_usage.used_bytes += used_bytes;
+        if let Some(heap_usage) = self.memory_heaps.get_mut(type_usage.heap_index as usize) {
+            heap_usage.allocated_bytes += allocated_bytes;
+            heap_usage.used_bytes += used_bytes;
+        }
+    }
+
+    pub fn resource(&self, kind: ResourceKind) -> Option<&ResourceTypeUsage> {
+        self.resources.iter().find(|r| r.kind == kind)
+    }
+
+    pub fn total_allocated_bytes(&self) -> u64 {
+        self.memory_heaps.iter().map(|h| h.allocated_bytes).sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Report contains only plain data")
+    }
+
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Report contains only plain data")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::MemoryPropertyFlags;
+
+    #[test]
+    fn test_memory_accounting() {
+        let memory_types = [
+            MemoryType { property_flags: MemoryPropertyFlags::DEVICE_LOCAL, heap_index: 0 },
+            MemoryType { property_flags: MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT, heap_index: 1 },
+            MemoryType { property_flags: MemoryPropertyFlags::DEVICE_LOCAL | MemoryPropertyFlags::HOST_VISIBLE, heap_index: 0 },
+        ];
+        let memory_heaps = [
+            MemoryHeap { size: 1 << 30, flags: MemoryHeapFlags::DEVICE_LOCAL },
+            MemoryHeap { size: 1 << 32, flags: MemoryHeapFlags::empty() },
+        ];
+        let mut report = ResourceUsageReport::new(&memory_types, &memory_heaps);
+        report.add_resource(ResourceKind::Buffer, 256);
+        report.add_memory(0, 256, 200);
+        report.add_resource(ResourceKind::Buffer, 512);
+        report.add_memory(2, 512, 512);
+        report.add_resource(ResourceKind::StagingBuffer, 1024);
+        report.add_memory(1, 1024, 1000);
+        // unknown memory type is ignored
+        report.add_memory(7, 1, 1);
+
+        let buffers = report.resource(ResourceKind::Buffer).unwrap();
+        assert_eq!((buffers.count, buffers.bytes), (2, 768));
+        assert!(report.resource(ResourceKind::Image).is_none());
+
+        assert_eq!(report.memory_types[0].used_bytes, 200);
+        assert_eq!(report.memory_heaps[0].allocated_bytes, 768);
+        assert_eq!(report.memory_heaps[0].used_bytes, 712);
+        assert!(report.memory_heaps[0].device_local);
+        assert_eq!(report.memory_heaps[1].allocated_bytes, 1024);
+        assert_eq!(report.total_allocated_bytes(), 1792);
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut report = ResourceUsageReport::default();
+        report.add_resource(ResourceKind::Sampler, 0);
+        report.pending_destruction = 3;
+        report.staging_buffers.push(StagingBufferUsage { name: Some("upload".to_string()), size: 64, frozen: 16 });
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["resources"][0]["kind"], "Sampler");
+        assert_eq!(json["resources"][0]["count"], 1);
+        assert_eq!(json["pending_destruction"], 3);
+        assert_eq!(json["staging_buffers"][0]["name"], "upload");
+        assert_eq!(json["staging_buffers"][0]["frozen"], 16);
+    }
+}
@@ -0,0 +1,339 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use anyhow::{bail, Context};
+use log::warn;
+use parking_lot::Mutex;
+use crate::resources::pipeline::{GraphicsPipelineDesc, GraphicsPipelineResource};
+use crate::resources::render_pass::RenderPassResource;
+
+/// Sources are checked for changes by the watcher thread this often
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// GLSL sources of pipeline shaders, recompiled with `glslc` on change in debug builds
+#[derive(Debug, Clone)]
+pub struct ShaderSources {
+    pub vert: PathBuf,
+    pub frag: PathBuf,
+}
+
+impl ShaderSources {
+    pub fn new(vert: impl Into<PathBuf>, frag: impl Into<PathBuf>) -> Self {
+        Self {
+            vert: vert.into(),
+            frag: frag.into(),
+        }
+    }
+
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        [modified(&self.vert), modified(&self.frag)]
+    }
+
+    fn compile(&self) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        Ok((compile_glsl(&self.vert)?, compile_glsl(&self.frag)?))
+    }
+}
+
+/// Sources of shader compiled by `use_shader!` with the same name: `shaders/{name}.vert` and `shaders/{name}.frag`
+/// of the calling crate
+#[macro_export]
+macro_rules! shader_sources {
+    ($name:expr) => {
+        $crate::shaders::hot_reload::ShaderSources::new(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/", $name, ".vert"),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/", $name, ".frag"),
+        )
+    };
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn compile_glsl(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let output = Command::new("glslc")
+        .arg(path)
+        .arg("-o")
+        .arg("-")
+        .output()
+        .context("Failed to run glslc")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// Pipeline replaced on shader change. Bind `get()` on each recording to pick up reloaded pipeline,
+/// previous one is destroyed by `VulkanAllocator` once submissions using it are waited
+#[derive(Clone)]
+pub struct HotPipeline {
+    current: Arc<Mutex<Arc<GraphicsPipelineResource>>>,
+}
+
+impl HotPipeline {
+    pub fn get(&self) -> Arc<GraphicsPipelineResource> {
+        self.current.lock().clone()
+    }
+}
+
+pub(crate) struct WatchedPipeline {
+    id: u64,
+    pub render_pass: Arc<RenderPassResource>,
+    /// Description with the latest successfully compiled shaders
+    pub desc: GraphicsPipelineDesc,
+    pub with_depth_test: bool,
+    pub name: Option<String>,
+    pipeline: HotPipeline,
+}
+
+impl WatchedPipeline {
+    pub fn replace(&mut self, desc: GraphicsPipelineDesc, pipeline: Arc<GraphicsPipelineResource>) {
+        self.desc = desc;
+        *self.pipeline.current.lock() = pipeline;
+    }
+
+    pub fn is(&self, pipeline: &HotPipeline) -> bool {
+        Arc::ptr_eq(&self.pipeline.current, &pipeline.current)
+    }
+}
+
+type CompileFn = fn(&ShaderSources) -> anyhow::Result<(Vec<u8>, Vec<u8>)>;
+/// SPIR-V of vertex and fragment shaders, or compile error
+type Compiled = anyhow::Result<(Vec<u8>, Vec<u8>)>;
+
+enum WatchRequest {
+    Add(WatchedSources),
+    Remove(u64),
+}
+
+/// Modification time of sources as of the last compilation, initially of the pipeline creation
+struct WatchedSources {
+    id: u64,
+    sources: ShaderSources,
+    modified: [Option<SystemTime>; 2],
+}
+
+impl WatchedSources {
+    fn new(id: u64, sources: ShaderSources) -> Self {
+        Self {
+            id,
+            modified: sources.modified(),
+            sources,
+        }
+    }
+
+    /// True if sources are modified since previous poll
+    fn poll(&mut self) -> bool {
+        let modified = self.sources.modified();
+        // missing files are not a change, keep previous pipeline until they are back
+        if modified.iter().any(Option::is_none) || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+/// Polls modification time of watched sources and compiles changed ones, so `glslc` never blocks rendering.
+/// Exits when `ShaderWatcher` is dropped
+fn watch_sources(requests: Receiver<WatchRequest>, compiled: Sender<(u64, Compiled)>, compile: CompileFn) {
+    let mut watched: Vec<WatchedSources> = Vec::new();
+    loop {
+        match requests.recv_timeout(POLL_INTERVAL) {
+            Ok(WatchRequest::Add(sources)) => watched.push(sources),
+            Ok(WatchRequest::Remove(id)) => watched.retain(|w| w.id != id),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        for w in watched.iter_mut() {
+            if w.poll() && compiled.send((w.id, compile(&w.sources))).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct WatcherThread {
+    requests: Sender<WatchRequest>,
+    compiled: Receiver<(u64, Compiled)>,
+}
+
+impl WatcherThread {
+    fn spawn(compile: CompileFn) -> Option<Self> {
+        let (requests_tx, requests_rx) = mpsc::channel();
+        let (compiled_tx, compiled_rx) = mpsc::channel();
+        let res = thread::Builder::new()
+            .name("shader watcher".to_string())
+            .spawn(move || watch_sources(requests_rx, compiled_tx, compile));
+        if let Err(e) = res {
+            warn!("Failed to spawn shader watcher thread, shaders are not reloaded: {}", e);
+            return None;
+        }
+        Some(Self {
+            requests: requests_tx,
+            compiled: compiled_rx,
+        })
+    }
+}
+
+/// Hot pipelines with their current shaders. Sources are watched and compiled on a separate thread
+#[derive(Default)]
+pub(crate) struct ShaderWatcher {
+    entries: Vec<WatchedPipeline>,
+    next_id: u64,
+    thread: Option<WatcherThread>,
+}
+
+impl ShaderWatcher {
+    /// Sources are watched only in debug builds
+    pub fn watch(
+        &mut self,
+        pipeline: Arc<GraphicsPipelineResource>,
+        render_pass: Arc<RenderPassResource>,
+        desc: GraphicsPipelineDesc,
+        with_depth_test: bool,
+        sources: ShaderSources,
+        name: Option<&str>,
+    ) -> HotPipeline {
+        let pipeline = HotPipeline {
+            current: Arc::new(Mutex::new(pipeline)),
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        if cfg!(debug_assertions) {
+            if self.thread.is_none() {
+                self.thread = WatcherThread::spawn(compile_sources);
+            }
+            if let Some(thread) = &self.thread {
+                let _ = thread.requests.send(WatchRequest::Add(WatchedSources::new(id, sources)));
+            }
+        }
+        self.entries.push(WatchedPipeline {
+            id,
+            render_pass,
+            desc,
+            with_depth_test,
+            name: name.map(str::to_string),
+            pipeline: pipeline.clone(),
+        });
+        pipeline
+    }
+
+    pub fn find(&mut self, pipeline: &HotPipeline) -> Option<&mut WatchedPipeline> {
+        self.entries.iter_mut().find(|e| e.is(pipeline))
+    }
+
+    /// Entries recompiled by the watcher thread since previous call. Entries of dropped pipelines are removed
+    pub fn changed(&mut self) -> Vec<(&mut WatchedPipeline, Compiled)> {
+        let Some(thread) = &self.thread else {
+            return Vec::new();
+        };
+        self.entries.retain(|e| {
+            let alive = Arc::strong_count(&e.pipeline.current) > 1;
+            if !alive {
+                let _ = thread.requests.send(WatchRequest::Remove(e.id));
+            }
+            alive
+        });
+        let mut compiled: Vec<(u64, Compiled)> = thread.compiled.try_iter().collect();
+        self.entries.iter_mut().filter_map(|e| {
+            // only the latest compilation of each entry matters
+            let i = compiled.iter().rposition(|(id, _)| *id == e.id)?;
+            let (_, code) = compiled.swap_remove(i);
+            Some((e, code))
+        }).collect()
+    }
+}
+
+fn compile_sources(sources: &ShaderSources) -> Compiled {
+    sources.compile()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vulkan-lib-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn set_modified(path: &Path, secs: u64) {
+        File::options().write(true).open(path).unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
+
+    /// Vertex shader source is returned as SPIR-V, sources starting with "error" fail
+    fn fake_compile(sources: &ShaderSources) -> Compiled {
+        let vert = fs::read(&sources.vert)?;
+        if vert.starts_with(b"error") {
+            bail!("{}: syntax error", sources.vert.display());
+        }
+        Ok((vert, fs::read(&sources.frag)?))
+    }
+
+    #[test]
+    fn test_change_detection() {
+        let dir = test_dir("change-detection");
+        let sources = ShaderSources::new(dir.join("a.vert"), dir.join("a.frag"));
+        fs::write(&sources.vert, "v1").unwrap();
+        fs::write(&sources.frag, "f1").unwrap();
+        set_modified(&sources.vert, 1_000);
+        set_modified(&sources.frag, 1_000);
+
+        let mut watched = WatchedSources::new(0, sources.clone());
+        assert!(!watched.poll());
+
+        set_modified(&sources.frag, 2_000);
+        assert!(watched.poll());
+        assert!(!watched.poll());
+
+        // missing file is not a change, its return is
+        fs::remove_file(&sources.vert).unwrap();
+        assert!(!watched.poll());
+        fs::write(&sources.vert, "v2").unwrap();
+        set_modified(&sources.vert, 3_000);
+        assert!(watched.poll());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_error_reported_and_watching_continues() {
+        let dir = test_dir("compile-error");
+        let sources = ShaderSources::new(dir.join("a.vert"), dir.join("a.frag"));
+        fs::write(&sources.vert, "v1").unwrap();
+        fs::write(&sources.frag, "f1").unwrap();
+        set_modified(&sources.vert, 1_000);
+
+        let (requests_tx, requests_rx) = mpsc::channel();
+        let (compiled_tx, compiled_rx) = mpsc::channel();
+        requests_tx.send(WatchRequest::Add(WatchedSources::new(7, sources.clone()))).unwrap();
+        let thread = thread::spawn(move || watch_sources(requests_rx, compiled_tx, fake_compile));
+
+        fs::write(&sources.vert, "error").unwrap();
+        set_modified(&sources.vert, 2_000);
+        let (id, code) = compiled_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(id, 7);
+        assert!(code.unwrap_err().to_string().contains("syntax error"));
+
+        // fixed source is compiled again
+        fs::write(&sources.vert, "v2").unwrap();
+        set_modified(&sources.vert, 3_000);
+        let (id, code) = compiled_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(code.unwrap(), (b"v2".to_vec(), b"f1".to_vec()));
+
+        // thread exits with watcher
+        drop(requests_tx);
+        thread.join().unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,138 +5,146 @@ use crate::resources::pipeline::VertexInputDesc;
 pub mod types {
     use std::mem::MaybeUninit;
     use ash::vk::Format;
+    use super::MemberMeta;
 
-    pub trait GlslType {
+    pub trait GlslType: Copy {
         const T: GlslTypeVariant;
         type Inner;
-    }
 
-    #[derive(Copy, Clone)]
-    #[repr(C, align(8))]
-    pub struct vec2<const P: usize>([f32; 2], MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for vec2<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Vec2;
-        type Inner = [f32; 2];
-    }
-    impl<const P: usize> From<[f32; 2]> for vec2<P> {
-        fn from(data: [f32; 2]) -> Self {
-            vec2(data, MaybeUninit::uninit())
-        }
-    }
-    impl<const P: usize> From<vec2<P>> for [f32; 2] {
-        fn from(data: vec2<P>) -> [f32; 2] {
-            data.0
-        }
+        fn from_inner(inner: Self::Inner) -> Self;
+        fn into_inner(self) -> Self::Inner;
     }
 
-    #[derive(Copy, Clone)]
-    #[repr(C, align(16))]
-    pub struct vec3<const P: usize>([f32; 3], MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for vec3<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Vec3;
-        type Inner = [f32; 3];
-    }
-    impl<const P: usize> From<[f32; 3]> for vec3<P> {
-        fn from(data: [f32; 3]) -> Self {
-            vec3(data, MaybeUninit::uninit())
-        }
-    }
-    impl<const P: usize> From<vec3<P>> for [f32; 3] {
-        fn from(data: vec3<P>) -> [f32; 3] {
-            data.0
-        }
-    }
+    /// Scalar or vector type with `P` u32 words of padding after it
+    macro_rules! glsl_type {
+        ($name:ident, $align:literal, $inner:ty, $variant:ident) => {
+            #[derive(Copy, Clone)]
+            #[repr(C, align($align))]
+            pub struct $name<const P: usize>($inner, MaybeUninit<[u32; P]>);
+            impl<const P: usize> GlslType for $name<P> {
+                const T: GlslTypeVariant = GlslTypeVariant::$variant;
+                type Inner = $inner;
 
-    #[derive(Copy, Clone)]
-    #[repr(C, align(16))]
-    pub struct vec4<const P: usize>([f32; 4], MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for vec4<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Vec4;
-        type Inner = [f32; 4];
-    }
-    impl<const P: usize> From<[f32; 4]> for vec4<P> {
-        fn from(data: [f32; 4]) -> Self {
-            vec4(data, MaybeUninit::uninit())
-        }
-    }
-    impl<const P: usize> From<vec4<P>> for [f32; 4] {
-        fn from(data: vec4<P>) -> [f32; 4] {
-            data.0
-        }
+                fn from_inner(inner: $inner) -> Self {
+                    inner.into()
+                }
+                fn into_inner(self) -> $inner {
+                    self.into()
+                }
+            }
+            impl<const P: usize> From<$inner> for $name<P> {
+                fn from(data: $inner) -> Self {
+                    $name(data, MaybeUninit::uninit())
+                }
+            }
+            impl<const P: usize> From<$name<P>> for $inner {
+                fn from(data: $name<P>) -> $inner {
+                    data.0
+                }
+            }
+        };
     }
 
+    glsl_type!(float, 4, f32, Float);
+    glsl_type!(vec2, 8, [f32; 2], Vec2);
+    glsl_type!(vec3, 16, [f32; 3], Vec3);
+    glsl_type!(vec4, 16, [f32; 4], Vec4);
 
-    #[derive(Copy, Clone)]
-    #[repr(C)]
-    pub struct float<const P: usize>(f32, MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for float<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Float;
-        type Inner = f32;
-    }
-    impl<const P: usize> From<f32> for float<P> {
-        fn from(data: f32) -> Self {
-            float(data, MaybeUninit::uninit())
-        }
-    }
-    impl<const P: usize> From<float<P>> for f32 {
-        fn from(data: float<P>) -> f32 {
-            data.0
-        }
-    }
+    glsl_type!(int, 4, i32, Int);
+    glsl_type!(ivec2, 8, [i32; 2], Ivec2);
+    glsl_type!(ivec3, 16, [i32; 3], Ivec3);
+    glsl_type!(ivec4, 16, [i32; 4], Ivec4);
+
+    glsl_type!(uint, 4, u32, Uint);
+    glsl_type!(uvec2, 8, [u32; 2], Uvec2);
+    glsl_type!(uvec3, 16, [u32; 3], Uvec3);
+    glsl_type!(uvec4, 16, [u32; 4], Uvec4);
 
+    glsl_type!(dvec2, 16, [f64; 2], Dvec2);
+    glsl_type!(dvec3, 32, [f64; 3], Dvec3);
+    glsl_type!(dvec4, 32, [f64; 4], Dvec4);
 
+    // vertex attributes only, read as vec4 or vec2 in shader
+    glsl_type!(unorm8x4, 4, [u8; 4], Unorm8x4);
+    glsl_type!(snorm8x4, 4, [i8; 4], Snorm8x4);
+    glsl_type!(unorm16x2, 4, [u16; 2], Unorm16x2);
+    glsl_type!(snorm16x2, 4, [i16; 2], Snorm16x2);
+    glsl_type!(unorm10_10_10_2, 4, u32, Unorm10_10_10_2);
+
+    /// GLSL bool, stored as 32-bit uint
     #[derive(Copy, Clone)]
     #[repr(C)]
-    pub struct uint<const P: usize>(u32, MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for uint<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Uint;
-        type Inner = u32;
-    }
-    impl<const P: usize> From<u32> for uint<P> {
-        fn from(data: u32) -> Self {
-            uint(data, MaybeUninit::uninit())
+    pub struct bool<const P: usize>(u32, MaybeUninit<[u32; P]>);
+    impl<const P: usize> GlslType for bool<P> {
+        const T: GlslTypeVariant = GlslTypeVariant::Bool;
+        type Inner = core::primitive::bool;
+
+        fn from_inner(inner: core::primitive::bool) -> Self {
+            inner.into()
         }
-    }
-    impl<const P: usize> From<uint<P>> for u32 {
-        fn from(data: uint<P>) -> u32 {
-            data.0
+        fn into_inner(self) -> core::primitive::bool {
+            self.into()
         }
     }
-    
-    #[derive(Copy, Clone)]
-    #[repr(C)]
-    pub struct int<const P: usize>(i32, MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for int<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Int;
-        type Inner = i32;
-    }
-    impl<const P: usize> From<i32> for int<P> {
-        fn from(data: i32) -> Self {
-            int(data, MaybeUninit::uninit())
+    impl<const P: usize> From<core::primitive::bool> for bool<P> {
+        fn from(data: core::primitive::bool) -> Self {
+            bool(data as u32, MaybeUninit::uninit())
         }
     }
-    impl<const P: usize> From<int<P>> for i32 {
-        fn from(data: int<P>) -> i32 {
-            data.0
+    impl<const P: usize> From<bool<P>> for core::primitive::bool {
+        fn from(data: bool<P>) -> core::primitive::bool {
+            data.0 != 0
         }
     }
 
+    /// Matrix column of `R` floats followed by `C` u32 words of padding
     #[derive(Copy, Clone)]
-    #[repr(C, align(8))]
-    pub struct ivec2<const P: usize>([i32; 2], MaybeUninit<[u32; P]>);
-    impl<const P: usize> GlslType for ivec2<P> {
-        const T: GlslTypeVariant = GlslTypeVariant::Ivec2;
-        type Inner = [i32; 2];
+    #[repr(C)]
+    pub struct Column<const R: usize, const C: usize>([f32; R], MaybeUninit<[u32; C]>);
+
+    /// Column-major square matrix with `C` words of padding after each column and `P` after the matrix
+    macro_rules! glsl_matrix {
+        ($name:ident, $align:literal, $n:literal, $variant:ident) => {
+            #[derive(Copy, Clone)]
+            #[repr(C, align($align))]
+            pub struct $name<const P: usize, const C: usize = 0>([Column<$n, C>; $n], MaybeUninit<[u32; P]>);
+            impl<const P: usize, const C: usize> GlslType for $name<P, C> {
+                const T: GlslTypeVariant = GlslTypeVariant::$variant { column_stride: size_of::<Column<$n, C>>() };
+                type Inner = [[f32; $n]; $n];
+
+                fn from_inner(inner: [[f32; $n]; $n]) -> Self {
+                    inner.into()
+                }
+                fn into_inner(self) -> [[f32; $n]; $n] {
+                    self.into()
+                }
+            }
+            impl<const P: usize, const C: usize> From<[[f32; $n]; $n]> for $name<P, C> {
+                fn from(data: [[f32; $n]; $n]) -> Self {
+                    $name(data.map(|c| Column(c, MaybeUninit::uninit())), MaybeUninit::uninit())
+                }
+            }
+            impl<const P: usize, const C: usize> From<$name<P, C>> for [[f32; $n]; $n] {
+                fn from(data: $name<P, C>) -> [[f32; $n]; $n] {
+                    data.0.map(|c| c.0)
+                }
+            }
+        };
     }
 
-    impl<const P: usize> From<[i32; 2]> for ivec2<P> {
-        fn from(value: [i32; 2]) -> Self {
-            ivec2(value, MaybeUninit::uninit())
+    glsl_matrix!(mat2, 8, 2, Mat2);
+    glsl_matrix!(mat3, 16, 3, Mat3);
+    glsl_matrix!(mat4, 16, 4, Mat4);
+
+    impl<E: GlslType, const N: usize> GlslType for [E; N] {
+        const T: GlslTypeVariant = GlslTypeVariant::Array { elem: &E::T, len: N, stride: size_of::<E>() };
+        type Inner = [E::Inner; N];
+
+        fn from_inner(inner: [E::Inner; N]) -> Self {
+            inner.map(E::from_inner)
         }
-    }
-    impl<const P: usize> From<ivec2<P>> for [i32; 2] {
-        fn from(value: ivec2<P>) -> [i32; 2] {
-            value.0
+        fn into_inner(self) -> [E::Inner; N] {
+            self.map(E::into_inner)
         }
     }
 
@@ -150,10 +158,32 @@ pub mod types {
         Uint,
         Int,
         Ivec2,
+        Ivec3,
+        Ivec4,
+        Uvec2,
+        Uvec3,
+        Uvec4,
+        Dvec2,
+        Dvec3,
+        Dvec4,
+        Bool,
+        Unorm8x4,
+        Snorm8x4,
+        Unorm16x2,
+        Snorm16x2,
+        Unorm10_10_10_2,
+        Mat2 { column_stride: usize },
+        Mat3 { column_stride: usize },
+        Mat4 { column_stride: usize },
+        Array { elem: &'static GlslTypeVariant, len: usize, stride: usize },
+        /// Nested `define_layout!` struct
+        Struct { members: &'static [MemberMeta] },
     }
     impl GlslTypeVariant {
-        pub fn format(&self) -> Format {
-            match self {
+        /// Format of single vertex attribute location: column format for matrices, element format for arrays.
+        /// `None` for types which can't be vertex inputs
+        pub fn format(&self) -> Option<Format> {
+            Some(match self {
                 GlslTypeVariant::Vec2 => Format::R32G32_SFLOAT,
                 GlslTypeVariant::Vec3 => Format::R32G32B32_SFLOAT,
                 GlslTypeVariant::Vec4 => Format::R32G32B32A32_SFLOAT,
@@ -161,10 +191,74 @@ pub mod types {
                 GlslTypeVariant::Uint => Format::R32_UINT,
                 GlslTypeVariant::Int => Format::R32_SINT,
                 GlslTypeVariant::Ivec2 => Format::R32G32_SINT,
+                GlslTypeVariant::Ivec3 => Format::R32G32B32_SINT,
+                GlslTypeVariant::Ivec4 => Format::R32G32B32A32_SINT,
+                GlslTypeVariant::Uvec2 => Format::R32G32_UINT,
+                GlslTypeVariant::Uvec3 => Format::R32G32B32_UINT,
+                GlslTypeVariant::Uvec4 => Format::R32G32B32A32_UINT,
+                GlslTypeVariant::Dvec2 => Format::R64G64_SFLOAT,
+                GlslTypeVariant::Dvec3 => Format::R64G64B64_SFLOAT,
+                GlslTypeVariant::Dvec4 => Format::R64G64B64A64_SFLOAT,
+                GlslTypeVariant::Unorm8x4 => Format::R8G8B8A8_UNORM,
+                GlslTypeVariant::Snorm8x4 => Format::R8G8B8A8_SNORM,
+                GlslTypeVariant::Unorm16x2 => Format::R16G16_UNORM,
+                GlslTypeVariant::Snorm16x2 => Format::R16G16_SNORM,
+                GlslTypeVariant::Unorm10_10_10_2 => Format::A2B10G10R10_UNORM_PACK32,
+                GlslTypeVariant::Mat2 { .. } => Format::R32G32_SFLOAT,
+                GlslTypeVariant::Mat3 { .. } => Format::R32G32B32_SFLOAT,
+                GlslTypeVariant::Mat4 { .. } => Format::R32G32B32A32_SFLOAT,
+                GlslTypeVariant::Array { elem, .. } => return elem.format(),
+                GlslTypeVariant::Bool | GlslTypeVariant::Struct { .. } => return None,
+            })
+        }
+
+        /// Vertex attributes of member in location order: offset from member start and format.
+        /// Empty for types which can't be vertex inputs
+        pub fn vertex_attributes(&self) -> Vec<(usize, Format)> {
+            match *self {
+                GlslTypeVariant::Mat2 { column_stride } => columns(2, column_stride, Format::R32G32_SFLOAT),
+                GlslTypeVariant::Mat3 { column_stride } => columns(3, column_stride, Format::R32G32B32_SFLOAT),
+                GlslTypeVariant::Mat4 { column_stride } => columns(4, column_stride, Format::R32G32B32A32_SFLOAT),
+                GlslTypeVariant::Array { elem, len, stride } => (0..len)
+                    .flat_map(|i| elem.vertex_attributes().into_iter().map(move |(offset, format)| (i * stride + offset, format)))
+                    .collect(),
+                _ => self.format().into_iter().map(|format| (0, format)).collect(),
+            }
+        }
+
+        /// Base alignment in std430 layout, std140 rounds it up to 16 for arrays and structs
+        pub const fn std430_align(&self) -> usize {
+            match self {
+                GlslTypeVariant::Float | GlslTypeVariant::Uint | GlslTypeVariant::Int | GlslTypeVariant::Bool
+                | GlslTypeVariant::Unorm8x4 | GlslTypeVariant::Snorm8x4 | GlslTypeVariant::Unorm16x2
+                | GlslTypeVariant::Snorm16x2 | GlslTypeVariant::Unorm10_10_10_2 => 4,
+                GlslTypeVariant::Vec2 | GlslTypeVariant::Ivec2 | GlslTypeVariant::Uvec2 => 8,
+                GlslTypeVariant::Vec3 | GlslTypeVariant::Vec4 | GlslTypeVariant::Ivec3 | GlslTypeVariant::Ivec4
+                | GlslTypeVariant::Uvec3 | GlslTypeVariant::Uvec4 | GlslTypeVariant::Dvec2 => 16,
+                GlslTypeVariant::Dvec3 | GlslTypeVariant::Dvec4 => 32,
+                GlslTypeVariant::Mat2 { .. } => 8,
+                GlslTypeVariant::Mat3 { .. } | GlslTypeVariant::Mat4 { .. } => 16,
+                GlslTypeVariant::Array { elem, .. } => elem.std430_align(),
+                GlslTypeVariant::Struct { members } => {
+                    let mut align = 4;
+                    let mut i = 0;
+                    while i < members.len() {
+                        let member_align = members[i].ty.std430_align();
+                        if member_align > align {
+                            align = member_align;
+                        }
+                        i += 1;
+                    }
+                    align
+                }
             }
         }
     }
 
+    fn columns(count: usize, stride: usize, format: Format) -> Vec<(usize, Format)> {
+        (0..count).map(|i| (i * stride, format)).collect()
+    }
+
 }
 
 pub trait LayoutInfo : Sized {
@@ -185,9 +279,96 @@ pub trait LayoutInfo : Sized {
     }
 }
 
+#[derive(Debug)]
 pub struct MemberMeta {
     pub name: &'static str,
     pub range: Range<usize>,
     pub ty: GlslTypeVariant,
     // r#type: TypeId,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::types::*;
+    use super::{LayoutInfo, MemberMeta};
+    use crate::resources::pipeline::VertexInputDesc;
+    use ash::vk::{Format, VertexInputRate};
+
+    #[test]
+    fn matrix_columns() {
+        assert_eq!(size_of::<mat2<0>>(), 16);
+        assert_eq!(size_of::<mat2<0, 2>>(), 32);
+        assert_eq!(size_of::<mat3<0, 1>>(), 48);
+        let m: mat3<0, 1> = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]].into();
+        assert_eq!(<[[f32; 3]; 3]>::from(m)[2], [7.0, 8.0, 9.0]);
+
+        let attributes = <mat3<0, 1> as GlslType>::T.vertex_attributes();
+        assert_eq!(attributes, [(0, Format::R32G32B32_SFLOAT), (16, Format::R32G32B32_SFLOAT), (32, Format::R32G32B32_SFLOAT)]);
+    }
+
+    #[test]
+    fn arrays() {
+        let ty = <[float<3>; 4] as GlslType>::T;
+        assert!(matches!(ty, GlslTypeVariant::Array { len: 4, stride: 16, .. }));
+        assert_eq!(ty.vertex_attributes().len(), 4);
+        assert_eq!(ty.std430_align(), 4);
+        assert_eq!(<[bool<0>; 2] as GlslType>::T.format(), None);
+        let flags: bool<0> = true.into();
+        assert!(core::primitive::bool::from(flags));
+    }
+
+    #[test]
+    fn vertex_and_instance_bindings() {
+        static VERTEX: [MemberMeta; 2] = [
+            MemberMeta { name: "pos", range: 0..8, ty: <vec2<0> as GlslType>::T },
+            MemberMeta { name: "uv", range: 8..16, ty: <vec2<0> as GlslType>::T },
+        ];
+        static INSTANCE: [MemberMeta; 2] = [
+            MemberMeta { name: "transform", range: 0..48, ty: <mat3<0, 1> as GlslType>::T },
+            MemberMeta { name: "color", range: 48..64, ty: <vec4<0> as GlslType>::T },
+        ];
+        let desc = VertexInputDesc::default()
+            .with_members(&VERTEX, 16, VertexInputRate::VERTEX)
+            .with_members(&INSTANCE, 64, VertexInputRate::INSTANCE);
+
+        let bindings: Vec<_> = desc.binding_descriptions().iter().map(|b| (b.binding, b.stride, b.input_rate)).collect();
+        assert_eq!(bindings, [(0, 16, VertexInputRate::VERTEX), (1, 64, VertexInputRate::INSTANCE)]);
+        let attributes: Vec<_> = desc.attribute_descriptions().iter().map(|a| (a.location, a.binding, a.offset)).collect();
+        assert_eq!(attributes, [(0, 0, 0), (1, 0, 8), (2, 1, 0), (3, 1, 16), (4, 1, 32), (5, 1, 48)]);
+    }
+
+    struct WideVertex;
+
+    impl LayoutInfo for WideVertex {
+        const MEMBERS_META: &'static [MemberMeta] = &[
+            MemberMeta { name: "pos", range: 0..24, ty: <dvec3<0> as GlslType>::T },
+            MemberMeta { name: "weights", range: 32..48, ty: <mat2<0> as GlslType>::T },
+        ];
+        const SIZE: usize = 48;
+    }
+
+    #[test]
+    fn locations_continue_across_bindings() {
+        static COLOR: [MemberMeta; 1] = [
+            MemberMeta { name: "color", range: 0..16, ty: <vec4<0> as GlslType>::T },
+        ];
+        let desc = VertexInputDesc::default()
+            .with_binding::<WideVertex>(VertexInputRate::VERTEX)
+            .with_members(&COLOR, 16, VertexInputRate::INSTANCE)
+            .with_binding::<WideVertex>(VertexInputRate::INSTANCE);
+
+        let bindings: Vec<_> = desc.binding_descriptions().iter().map(|b| (b.binding, b.stride)).collect();
+        assert_eq!(bindings, [(0, 48), (1, 16), (2, 48)]);
+        // dvec3 takes two locations, mat2 one per column
+        let attributes: Vec<_> = desc.attribute_descriptions().iter().map(|a| (a.location, a.binding, a.offset, a.format)).collect();
+        assert_eq!(attributes, [
+            (0, 0, 0, Format::R64G64B64_SFLOAT),
+            (2, 0, 32, Format::R32G32_SFLOAT),
+            (3, 0, 40, Format::R32G32_SFLOAT),
+            (4, 1, 0, Format::R32G32B32A32_SFLOAT),
+            (5, 2, 0, Format::R64G64B64_SFLOAT),
+            (7, 2, 32, Format::R32G32_SFLOAT),
+            (8, 2, 40, Format::R32G32_SFLOAT),
+        ]);
+    }
+}
@@ -1,7 +1,10 @@
-use ash::vk::{DescriptorType, ShaderStageFlags};
+use ash::vk::{DescriptorBindingFlags, DescriptorType, ShaderStageFlags};
 use smallvec::SmallVec;
 
 pub mod layout;
+pub mod reflection;
+pub mod hot_reload;
+pub mod updates;
 
 #[derive(Debug, Clone)]
 pub enum UniformBindingType {
@@ -26,6 +29,8 @@ pub struct DescriptorSetLayoutBindingDesc {
     pub descriptor_type: DescriptorType,
     pub descriptor_count: u32,
     pub stage_flags: ShaderStageFlags,
+    /// Requested `VK_EXT_descriptor_indexing` flags. Unsupported flags are dropped at layout creation.
+    pub binding_flags: DescriptorBindingFlags,
 }
 
 #[macro_export]
@@ -34,7 +39,7 @@ macro_rules! descriptor_set {
         pub struct $name:ident {
             $(
                 $(#[$stage:ident])?
-                $binding:literal -> $desc_type:ident $([$count:literal])?
+                $binding:literal -> $desc_type:ident $([$count:literal])? $(($($flag:ident),* $(,)?))?
             ),* $(,)?
         }
     ) => {
@@ -42,16 +47,20 @@ macro_rules! descriptor_set {
 
         impl $name {
             pub fn bindings() -> &'static [$crate::shaders::DescriptorSetLayoutBindingDesc] {
-                &[
+                const BINDINGS: &[$crate::shaders::DescriptorSetLayoutBindingDesc] = &[
                     $(
                         $crate::shaders::DescriptorSetLayoutBindingDesc {
                             binding: $binding,
                             descriptor_type: descriptor_set!(@desc_type $desc_type),
                             descriptor_count: descriptor_set!(@count $($count)?),
                             stage_flags: descriptor_set!(@stage $($stage)?),
+                            binding_flags: $crate::vk::DescriptorBindingFlags::from_raw(
+                                0 $($(| descriptor_set!(@flag $flag).as_raw())*)?
+                            ),
                         },
                     )*
-                ]
+                ];
+                BINDINGS
             }
         }
     };
@@ -64,9 +73,15 @@ macro_rules! descriptor_set {
     (@stage frag) => { $crate::ShaderStageFlags::FRAGMENT };
     (@stage comp) => { $crate::ShaderStageFlags::COMPUTE };
 
+    (@flag partial) => { $crate::vk::DescriptorBindingFlags::PARTIALLY_BOUND };
+    (@flag update_after_bind) => { $crate::vk::DescriptorBindingFlags::UPDATE_AFTER_BIND };
+    (@flag update_unused) => { $crate::vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING };
+
     (@desc_type UniformBuffer) => { $crate::DescriptorType::UNIFORM_BUFFER };
     (@desc_type CombinedImageSampler) => { $crate::DescriptorType::COMBINED_IMAGE_SAMPLER };
     (@desc_type StorageBuffer) => { $crate::DescriptorType::STORAGE_BUFFER };
+    (@desc_type UniformBufferDynamic) => { $crate::DescriptorType::UNIFORM_BUFFER_DYNAMIC };
+    (@desc_type StorageBufferDynamic) => { $crate::DescriptorType::STORAGE_BUFFER_DYNAMIC };
     (@desc_type StorageImage) => { $crate::DescriptorType::STORAGE_IMAGE };
     (@desc_type SampledImage) => { $crate::DescriptorType::SAMPLED_IMAGE };
     (@desc_type Sampler) => { $crate::DescriptorType::SAMPLER };
@@ -0,0 +1,915 @@
+use std::collections::HashMap;
+use std::fmt;
+use anyhow::{bail, Context};
+use ash::vk::{DescriptorType, Format, ShaderStageFlags, VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate};
+use log::warn;
+use crate::resources::pipeline::VertexInputDesc;
+use crate::shaders::DescriptorSetLayoutBindingDesc;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+mod op {
+    pub const NAME: u32 = 5;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const EXECUTION_MODE: u32 = 16;
+    pub const TYPE_VOID: u32 = 19;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const FUNCTION: u32 = 54;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+}
+
+mod decoration {
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const OUTPUT: u32 = 3;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericType {
+    Float,
+    Sint,
+    Uint,
+    Bool,
+}
+
+/// Scalar, vector or matrix type of shader input or output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceType {
+    pub numeric: NumericType,
+    /// Bits per component
+    pub width: u32,
+    pub components: u32,
+    /// 1 for scalars and vectors, each matrix column takes its own location
+    pub columns: u32,
+}
+
+impl InterfaceType {
+    /// Attribute format of a single column, None for bool and 8/16-bit types
+    pub fn vertex_format(&self) -> Option<Format> {
+        let formats = match (self.numeric, self.width) {
+            (NumericType::Float, 32) => [Format::R32_SFLOAT, Format::R32G32_SFLOAT, Format::R32G32B32_SFLOAT, Format::R32G32B32A32_SFLOAT],
+            (NumericType::Sint, 32) => [Format::R32_SINT, Format::R32G32_SINT, Format::R32G32B32_SINT, Format::R32G32B32A32_SINT],
+            (NumericType::Uint, 32) => [Format::R32_UINT, Format::R32G32_UINT, Format::R32G32B32_UINT, Format::R32G32B32A32_UINT],
+            (NumericType::Float, 64) => [Format::R64_SFLOAT, Format::R64G64_SFLOAT, Format::R64G64B64_SFLOAT, Format::R64G64B64A64_SFLOAT],
+            _ => return None,
+        };
+        formats.get(self.components.checked_sub(1)? as usize).copied()
+    }
+
+    /// Bytes of a single column
+    pub fn column_size(&self) -> u32 {
+        self.width / 8 * self.components
+    }
+}
+
+impl fmt::Display for InterfaceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}{}", self.numeric, self.width)?;
+        if self.columns > 1 {
+            write!(f, "mat{}x{}", self.columns, self.components)
+        }
+        else if self.components > 1 {
+            write!(f, "vec{}", self.components)
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub name: Option<String>,
+    pub ty: InterfaceType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: DescriptorType,
+    /// 0 for runtime-sized arrays
+    pub count: u32,
+    pub name: Option<String>,
+    /// Size of uniform or storage block in bytes, runtime-sized array excluded
+    pub block_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushConstantBlock {
+    pub name: Option<String>,
+    pub size: u32,
+}
+
+/// Interface of the first entry point of SPIR-V module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderReflection {
+    pub stage: ShaderStageFlags,
+    pub entry_point: String,
+    /// Sorted by location, built-ins excluded
+    pub inputs: Vec<InterfaceVariable>,
+    /// Sorted by location, built-ins excluded
+    pub outputs: Vec<InterfaceVariable>,
+    /// Sorted by set and binding
+    pub bindings: Vec<ReflectedBinding>,
+    pub push_constants: Option<PushConstantBlock>,
+    /// Compute shaders only
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+impl ShaderReflection {
+    pub fn parse(code: &[u8]) -> anyhow::Result<Self> {
+        if !code.len().is_multiple_of(4) || code.len() < 20 {
+            bail!("SPIR-V size {} is not a multiple of 4 or is smaller than header", code.len());
+        }
+        let words: Vec<u32> = code.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect();
+        if words[0] != SPIRV_MAGIC {
+            bail!("Invalid SPIR-V magic {:#x}", words[0]);
+        }
+
+        let mut module = Module::default();
+        let mut i = 5;
+        while i < words.len() {
+            let word_count = (words[i] >> 16) as usize;
+            let opcode = words[i] & 0xffff;
+            if word_count == 0 || i + word_count > words.len() {
+                bail!("Malformed SPIR-V instruction at word {}", i);
+            }
+            // declarations always precede function definitions
+            if opcode == op::FUNCTION {
+                break;
+            }
+            module.instruction(opcode, &words[i + 1..i + word_count])
+                .with_context(|| format!("Invalid SPIR-V instruction {} at word {}", opcode, i))?;
+            i += word_count;
+        }
+        module.reflect()
+    }
+
+    pub fn input(&self, location: u32) -> Option<&InterfaceVariable> {
+        self.inputs.iter().find(|v| (v.location..v.location + v.ty.columns).contains(&location))
+    }
+
+    pub fn output(&self, location: u32) -> Option<&InterfaceVariable> {
+        self.outputs.iter().find(|v| (v.location..v.location + v.ty.columns).contains(&location))
+    }
+
+    pub fn binding(&self, set: u32, binding: u32) -> Option<&ReflectedBinding> {
+        self.bindings.iter().find(|b| b.set == set && b.binding == binding)
+    }
+}
+
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    Other,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Decorations {
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    offset: Option<u32>,
+    array_stride: Option<u32>,
+    matrix_stride: Option<u32>,
+    built_in: bool,
+    buffer_block: bool,
+}
+
+impl Decorations {
+    fn apply(&mut self, decoration: u32, value: Option<u32>) {
+        match decoration {
+            decoration::BUFFER_BLOCK => self.buffer_block = true,
+            decoration::BUILT_IN => self.built_in = true,
+            decoration::ARRAY_STRIDE => self.array_stride = value,
+            decoration::MATRIX_STRIDE => self.matrix_stride = value,
+            decoration::LOCATION => self.location = value,
+            decoration::BINDING => self.binding = value,
+            decoration::DESCRIPTOR_SET => self.set = value,
+            decoration::OFFSET => self.offset = value,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, String)>,
+    local_size: Option<[u32; 3]>,
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), Decorations>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    /// Id, pointer type and storage class
+    variables: Vec<(u32, u32, u32)>,
+}
+
+fn operand(ops: &[u32], i: usize) -> anyhow::Result<u32> {
+    ops.get(i).copied().context("Truncated instruction")
+}
+
+/// Nul-terminated UTF-8 string packed into words
+fn literal_string(ops: &[u32]) -> String {
+    let bytes: Vec<u8> = ops.iter().flat_map(|w| w.to_le_bytes()).take_while(|b| *b != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    fn instruction(&mut self, opcode: u32, ops: &[u32]) -> anyhow::Result<()> {
+        match opcode {
+            op::NAME => {
+                self.names.insert(operand(ops, 0)?, literal_string(&ops[1..]));
+            }
+            op::ENTRY_POINT if self.entry_point.is_none() => {
+                self.entry_point = Some((operand(ops, 0)?, literal_string(ops.get(2..).unwrap_or_default())));
+            }
+            op::EXECUTION_MODE if ops.get(1) == Some(&EXECUTION_MODE_LOCAL_SIZE) => {
+                self.local_size = Some([operand(ops, 2)?, operand(ops, 3)?, operand(ops, 4)?]);
+            }
+            op::DECORATE => {
+                self.decorations.entry(operand(ops, 0)?).or_default().apply(operand(ops, 1)?, ops.get(2).copied());
+            }
+            op::MEMBER_DECORATE => {
+                self.member_decorations.entry((operand(ops, 0)?, operand(ops, 1)?)).or_default()
+                    .apply(operand(ops, 2)?, ops.get(3).copied());
+            }
+            op::TYPE_VOID => {
+                self.types.insert(operand(ops, 0)?, Type::Other);
+            }
+            op::TYPE_BOOL => {
+                self.types.insert(operand(ops, 0)?, Type::Bool);
+            }
+            op::TYPE_INT => {
+                self.types.insert(operand(ops, 0)?, Type::Int { width: operand(ops, 1)?, signed: operand(ops, 2)? != 0 });
+            }
+            op::TYPE_FLOAT => {
+                self.types.insert(operand(ops, 0)?, Type::Float { width: operand(ops, 1)? });
+            }
+            op::TYPE_VECTOR => {
+                self.types.insert(operand(ops, 0)?, Type::Vector { component: operand(ops, 1)?, count: operand(ops, 2)? });
+            }
+            op::TYPE_MATRIX => {
+                self.types.insert(operand(ops, 0)?, Type::Matrix { column: operand(ops, 1)?, count: operand(ops, 2)? });
+            }
+            op::TYPE_IMAGE => {
+                self.types.insert(operand(ops, 0)?, Type::Image { dim: operand(ops, 2)?, sampled: operand(ops, 6)? });
+            }
+            op::TYPE_SAMPLER => {
+                self.types.insert(operand(ops, 0)?, Type::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(ops, 0)?, Type::SampledImage);
+            }
+            op::TYPE_ARRAY => {
+                let length_id = operand(ops, 2)?;
+                let length = *self.constants.get(&length_id).context("Array length is not a constant")?;
+                self.types.insert(operand(ops, 0)?, Type::Array { element: operand(ops, 1)?, length });
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(ops, 0)?, Type::RuntimeArray { element: operand(ops, 1)? });
+            }
+            op::TYPE_STRUCT => {
+                self.types.insert(operand(ops, 0)?, Type::Struct { members: ops[1..].to_vec() });
+            }
+            op::TYPE_POINTER => {
+                self.types.insert(operand(ops, 0)?, Type::Pointer { pointee: operand(ops, 2)? });
+            }
+            op::CONSTANT => {
+                // only 32-bit integer constants are used as array lengths
+                self.constants.insert(operand(ops, 1)?, operand(ops, 2)?);
+            }
+            op::VARIABLE => {
+                self.variables.push((operand(ops, 1)?, operand(ops, 0)?, operand(ops, 2)?));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).copied().unwrap_or_default()
+    }
+
+    fn ty(&self, id: u32) -> anyhow::Result<&Type> {
+        self.types.get(&id).with_context(|| format!("Unknown type %{}", id))
+    }
+
+    fn interface_type(&self, id: u32) -> anyhow::Result<Option<InterfaceType>> {
+        Ok(match self.ty(id)? {
+            Type::Bool => Some(InterfaceType { numeric: NumericType::Bool, width: 32, components: 1, columns: 1 }),
+            Type::Int { width, signed } => Some(InterfaceType {
+                numeric: if *signed { NumericType::Sint } else { NumericType::Uint },
+                width: *width,
+                components: 1,
+                columns: 1,
+            }),
+            Type::Float { width } => Some(InterfaceType { numeric: NumericType::Float, width: *width, components: 1, columns: 1 }),
+            Type::Vector { component, count } => self.interface_type(*component)?
+                .map(|t| InterfaceType { components: *count, ..t }),
+            Type::Matrix { column, count } => self.interface_type(*column)?
+                .map(|t| InterfaceType { columns: *count, ..t }),
+            _ => None,
+        })
+    }
+
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> anyhow::Result<u32> {
+        Ok(match self.ty(id)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => self.size(*component, None)? * count,
+            Type::Matrix { column, count } => matrix_stride.map_or(self.size(*column, None), Ok)? * count,
+            Type::Array { element, length } => {
+                let stride = self.decorations(id).array_stride.map_or(self.size(*element, matrix_stride), Ok)?;
+                stride * length
+            }
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (i, member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(id, i as u32)).copied().unwrap_or_default();
+                    let offset = decorations.offset.unwrap_or(size);
+                    size = size.max(offset + self.size(*member, decorations.matrix_stride)?);
+                }
+                size
+            }
+            _ => 0,
+        })
+    }
+
+    fn is_built_in(&self, variable: u32, pointee: u32) -> anyhow::Result<bool> {
+        if self.decorations(variable).built_in {
+            return Ok(true);
+        }
+        // gl_PerVertex block
+        Ok(match self.ty(pointee)? {
+            Type::Struct { members } => (0..members.len() as u32)
+                .any(|i| self.member_decorations.get(&(pointee, i)).is_some_and(|d| d.built_in)),
+            _ => false,
+        })
+    }
+
+    fn descriptor_type(&self, storage_class: u32, ty: u32) -> anyhow::Result<Option<DescriptorType>> {
+        Ok(match (storage_class, self.ty(ty)?) {
+            (storage_class::STORAGE_BUFFER, _) => Some(DescriptorType::STORAGE_BUFFER),
+            (storage_class::UNIFORM, _) if self.decorations(ty).buffer_block => Some(DescriptorType::STORAGE_BUFFER),
+            (storage_class::UNIFORM, _) => Some(DescriptorType::UNIFORM_BUFFER),
+            (_, Type::SampledImage) => Some(DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (_, Type::Sampler) => Some(DescriptorType::SAMPLER),
+            (_, Type::Image { dim: DIM_SUBPASS_DATA, .. }) => Some(DescriptorType::INPUT_ATTACHMENT),
+            (_, Type::Image { dim: DIM_BUFFER, sampled: 2 }) => Some(DescriptorType::STORAGE_TEXEL_BUFFER),
+            (_, Type::Image { dim: DIM_BUFFER, .. }) => Some(DescriptorType::UNIFORM_TEXEL_BUFFER),
+            (_, Type::Image { sampled: 2, .. }) => Some(DescriptorType::STORAGE_IMAGE),
+            (_, Type::Image { .. }) => Some(DescriptorType::SAMPLED_IMAGE),
+            _ => None,
+        })
+    }
+
+    fn reflect(self) -> anyhow::Result<ShaderReflection> {
+        let (execution_model, entry_point) = self.entry_point.clone().context("SPIR-V module has no entry point")?;
+        let stage = match execution_model {
+            0 => ShaderStageFlags::VERTEX,
+            1 => ShaderStageFlags::TESSELLATION_CONTROL,
+            2 => ShaderStageFlags::TESSELLATION_EVALUATION,
+            3 => ShaderStageFlags::GEOMETRY,
+            4 => ShaderStageFlags::FRAGMENT,
+            5 => ShaderStageFlags::COMPUTE,
+            model => bail!("Unsupported execution model {}", model),
+        };
+
+        let mut reflection = ShaderReflection {
+            stage,
+            entry_point,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            bindings: Vec::new(),
+            push_constants: None,
+            workgroup_size: self.local_size.filter(|_| stage == ShaderStageFlags::COMPUTE),
+        };
+
+        for &(id, pointer, class) in &self.variables {
+            let Type::Pointer { pointee } = *self.ty(pointer)? else {
+                bail!("Variable %{} is not a pointer", id);
+            };
+            let name = self.names.get(&id).filter(|n| !n.is_empty()).cloned()
+                .or_else(|| self.names.get(&pointee).filter(|n| !n.is_empty()).cloned());
+            let decorations = self.decorations(id);
+            match class {
+                storage_class::INPUT | storage_class::OUTPUT => {
+                    if self.is_built_in(id, pointee)? {
+                        continue;
+                    }
+                    let location = decorations.location.with_context(|| format!("Interface variable {:?} has no location", name))?;
+                    let Some(ty) = self.interface_type(pointee)? else {
+                        warn!("Interface variable {:?} at location {} is not a scalar, vector or matrix, skipped", name, location);
+                        continue;
+                    };
+                    let variable = InterfaceVariable { location, name, ty };
+                    if class == storage_class::INPUT {
+                        reflection.inputs.push(variable);
+                    }
+                    else {
+                        reflection.outputs.push(variable);
+                    }
+                }
+                storage_class::UNIFORM | storage_class::UNIFORM_CONSTANT | storage_class::STORAGE_BUFFER => {
+                    let (element, count) = match *self.ty(pointee)? {
+                        Type::Array { element, length } => (element, length),
+                        Type::RuntimeArray { element } => (element, 0),
+                        _ => (pointee, 1),
+                    };
+                    let Some(descriptor_type) = self.descriptor_type(class, element)? else {
+                        continue;
+                    };
+                    let binding = decorations.binding.with_context(|| format!("Resource {:?} has no binding", name))?;
+                    let block_size = matches!(descriptor_type, DescriptorType::UNIFORM_BUFFER | DescriptorType::STORAGE_BUFFER)
+                        .then(|| self.size(element, None))
+                        .transpose()?;
+                    reflection.bindings.push(ReflectedBinding {
+                        set: decorations.set.unwrap_or(0),
+                        binding,
+                        descriptor_type,
+                        count,
+                        name,
+                        block_size,
+                    });
+                }
+                storage_class::PUSH_CONSTANT => {
+                    reflection.push_constants = Some(PushConstantBlock {
+                        name,
+                        size: self.size(pointee, None)?,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        reflection.inputs.sort_by_key(|v| v.location);
+        reflection.outputs.sort_by_key(|v| v.location);
+        reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+        Ok(reflection)
+    }
+}
+
+/// Numeric type and component count of vertex attribute format
+fn format_numeric_type(format: Format) -> Option<(NumericType, u32)> {
+    Some(match format {
+        Format::R32_SFLOAT | Format::R16_SFLOAT | Format::R8_UNORM | Format::R8_SNORM | Format::R16_UNORM | Format::R16_SNORM | Format::R64_SFLOAT => (NumericType::Float, 1),
+        Format::R32G32_SFLOAT | Format::R16G16_SFLOAT | Format::R8G8_UNORM | Format::R8G8_SNORM | Format::R16G16_UNORM | Format::R16G16_SNORM | Format::R64G64_SFLOAT => (NumericType::Float, 2),
+        Format::R32G32B32_SFLOAT | Format::R16G16B16_SFLOAT | Format::R64G64B64_SFLOAT => (NumericType::Float, 3),
+        Format::R32G32B32A32_SFLOAT | Format::R16G16B16A16_SFLOAT | Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SNORM | Format::B8G8R8A8_UNORM
+        | Format::R16G16B16A16_UNORM | Format::R16G16B16A16_SNORM | Format::A2B10G10R10_UNORM_PACK32 | Format::R64G64B64A64_SFLOAT => (NumericType::Float, 4),
+        Format::R32_SINT | Format::R16_SINT | Format::R8_SINT => (NumericType::Sint, 1),
+        Format::R32G32_SINT | Format::R16G16_SINT | Format::R8G8_SINT => (NumericType::Sint, 2),
+        Format::R32G32B32_SINT => (NumericType::Sint, 3),
+        Format::R32G32B32A32_SINT | Format::R16G16B16A16_SINT | Format::R8G8B8A8_SINT => (NumericType::Sint, 4),
+        Format::R32_UINT | Format::R16_UINT | Format::R8_UINT => (NumericType::Uint, 1),
+        Format::R32G32_UINT | Format::R16G16_UINT | Format::R8G8_UINT => (NumericType::Uint, 2),
+        Format::R32G32B32_UINT => (NumericType::Uint, 3),
+        Format::R32G32B32A32_UINT | Format::R16G16B16A16_UINT | Format::R8G8B8A8_UINT => (NumericType::Uint, 4),
+        _ => return None,
+    })
+}
+
+/// Single difference between shader interface and Rust-side pipeline description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceMismatch {
+    InvalidShader {
+        stage: ShaderStageFlags,
+        error: String,
+    },
+    UnexpectedStage {
+        expected: ShaderStageFlags,
+        found: ShaderStageFlags,
+    },
+    MissingAttribute {
+        location: u32,
+        name: Option<String>,
+    },
+    AttributeType {
+        location: u32,
+        name: Option<String>,
+        format: Format,
+        shader: InterfaceType,
+    },
+    MissingVertexOutput {
+        location: u32,
+        name: Option<String>,
+    },
+    VaryingType {
+        location: u32,
+        vertex: InterfaceType,
+        fragment: InterfaceType,
+    },
+    MissingBinding {
+        set: u32,
+        binding: u32,
+        name: Option<String>,
+    },
+    DescriptorType {
+        set: u32,
+        binding: u32,
+        shader: DescriptorType,
+        layout: DescriptorType,
+    },
+    DescriptorCount {
+        set: u32,
+        binding: u32,
+        shader: u32,
+        layout: u32,
+    },
+    StageNotVisible {
+        set: u32,
+        binding: u32,
+        stage: ShaderStageFlags,
+    },
+    PushConstants {
+        stage: ShaderStageFlags,
+        size: u32,
+    },
+}
+
+impl fmt::Display for InterfaceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |name: &Option<String>| name.as_ref().map(|n| format!(" '{}'", n)).unwrap_or_default();
+        match self {
+            InterfaceMismatch::InvalidShader { stage, error } => write!(f, "{:?} shader cannot be reflected: {}", stage, error),
+            InterfaceMismatch::UnexpectedStage { expected, found } => write!(f, "expected {:?} shader, found {:?}", expected, found),
+            InterfaceMismatch::MissingAttribute { location, name: n } =>
+                write!(f, "vertex input{} at location {} has no vertex attribute", name(n), location),
+            InterfaceMismatch::AttributeType { location, name: n, format, shader } =>
+                write!(f, "vertex input{} at location {} is {}, attribute format is {:?}", name(n), location, shader, format),
+            InterfaceMismatch::MissingVertexOutput { location, name: n } =>
+                write!(f, "fragment input{} at location {} is not written by vertex shader", name(n), location),
+            InterfaceMismatch::VaryingType { location, vertex, fragment } =>
+                write!(f, "vertex output at location {} is {}, fragment input is {}", location, vertex, fragment),
+            InterfaceMismatch::MissingBinding { set, binding, name: n } =>
+                write!(f, "resource{} (set {}, binding {}) is missing from descriptor set layout", name(n), set, binding),
+            InterfaceMismatch::DescriptorType { set, binding, shader, layout } =>
+                write!(f, "set {} binding {} is {:?} in shader, {:?} in layout", set, binding, shader, layout),
+            InterfaceMismatch::DescriptorCount { set, binding, shader, layout } =>
+                write!(f, "set {} binding {} has {} descriptors in shader, {} in layout", set, binding, shader, layout),
+            InterfaceMismatch::StageNotVisible { set, binding, stage } =>
+                write!(f, "set {} binding {} is not visible to {:?} stage", set, binding, stage),
+            InterfaceMismatch::PushConstants { stage, size } =>
+                write!(f, "{:?} shader uses {} bytes of push constants, pipeline layouts have no push constant ranges", stage, size),
+        }
+    }
+}
+
+/// Returned when shaders do not match vertex input or descriptor set layouts of pipeline description
+#[derive(Debug, Clone)]
+pub struct ShaderInterfaceError {
+    pub mismatches: Vec<InterfaceMismatch>,
+}
+
+impl fmt::Display for ShaderInterfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Shader interface does not match pipeline description:")?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  - {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderInterfaceError {}
+
+/// Buffer descriptors can be bound as dynamic ones
+fn descriptor_types_compatible(shader: DescriptorType, layout: DescriptorType) -> bool {
+    match shader {
+        DescriptorType::UNIFORM_BUFFER => matches!(layout, DescriptorType::UNIFORM_BUFFER | DescriptorType::UNIFORM_BUFFER_DYNAMIC),
+        DescriptorType::STORAGE_BUFFER => matches!(layout, DescriptorType::STORAGE_BUFFER | DescriptorType::STORAGE_BUFFER_DYNAMIC),
+        _ => shader == layout,
+    }
+}
+
+fn reflect_stage(code: &[u8], expected: ShaderStageFlags, mismatches: &mut Vec<InterfaceMismatch>) -> Option<ShaderReflection> {
+    match ShaderReflection::parse(code) {
+        Ok(reflection) if reflection.stage == expected => Some(reflection),
+        Ok(reflection) => {
+            mismatches.push(InterfaceMismatch::UnexpectedStage { expected, found: reflection.stage });
+            None
+        }
+        Err(e) => {
+            mismatches.push(InterfaceMismatch::InvalidShader { stage: expected, error: format!("{:#}", e) });
+            None
+        }
+    }
+}
+
+fn check_bindings(reflection: &ShaderReflection, bindings: &[&[DescriptorSetLayoutBindingDesc]], mismatches: &mut Vec<InterfaceMismatch>) {
+    for reflected in &reflection.bindings {
+        let (set, binding) = (reflected.set, reflected.binding);
+        let Some(desc) = bindings.get(set as usize).and_then(|s| s.iter().find(|b| b.binding == binding)) else {
+            mismatches.push(InterfaceMismatch::MissingBinding { set, binding, name: reflected.name.clone() });
+            continue;
+        };
+        if !descriptor_types_compatible(reflected.descriptor_type, desc.descriptor_type) {
+            mismatches.push(InterfaceMismatch::DescriptorType { set, binding, shader: reflected.descriptor_type, layout: desc.descriptor_type });
+        }
+        if desc.descriptor_count < reflected.count.max(1) {
+            mismatches.push(InterfaceMismatch::DescriptorCount { set, binding, shader: reflected.count, layout: desc.descriptor_count });
+        }
+        if !desc.stage_flags.contains(reflection.stage) {
+            mismatches.push(InterfaceMismatch::StageNotVisible { set, binding, stage: reflection.stage });
+        }
+    }
+    if let Some(push_constants) = &reflection.push_constants {
+        mismatches.push(InterfaceMismatch::PushConstants { stage: reflection.stage, size: push_constants.size });
+    }
+}
+
+/// Check vertex attributes against vertex shader inputs, vertex outputs against fragment inputs
+/// and descriptor set layouts against resources of both stages
+pub(crate) fn check_pipeline_interface(
+    vert_shader: &[u8],
+    frag_shader: &[u8],
+    attributes: &VertexInputDesc,
+    bindings: &[&[DescriptorSetLayoutBindingDesc]],
+) -> Result<(), ShaderInterfaceError> {
+    let mut mismatches = Vec::new();
+    let vert = reflect_stage(vert_shader, ShaderStageFlags::VERTEX, &mut mismatches);
+    let frag = reflect_stage(frag_shader, ShaderStageFlags::FRAGMENT, &mut mismatches);
+
+    if let Some(vert) = &vert {
+        for input in &vert.inputs {
+            for location in input.location..input.location + input.ty.columns {
+                let Some(attribute) = attributes.attribute_descriptions().iter().find(|a| a.location == location) else {
+                    mismatches.push(InterfaceMismatch::MissingAttribute { location, name: input.name.clone() });
+                    continue;
+                };
+                // component count may differ, missing components are filled with defaults
+                if let Some((numeric, _)) = format_numeric_type(attribute.format) && numeric != input.ty.numeric {
+                    mismatches.push(InterfaceMismatch::AttributeType { location, name: input.name.clone(), format: attribute.format, shader: input.ty });
+                }
+            }
+        }
+        check_bindings(vert, bindings, &mut mismatches);
+    }
+    if let Some(frag) = &frag {
+        if let Some(vert) = &vert {
+            for input in &frag.inputs {
+                match vert.output(input.location) {
+                    None => mismatches.push(InterfaceMismatch::MissingVertexOutput { location: input.location, name: input.name.clone() }),
+                    Some(output) if output.ty.numeric != input.ty.numeric || output.ty.components < input.ty.components => {
+                        mismatches.push(InterfaceMismatch::VaryingType { location: input.location, vertex: output.ty, fragment: input.ty });
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        check_bindings(frag, bindings, &mut mismatches);
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(ShaderInterfaceError { mismatches })
+    }
+}
+
+/// Attributes of vertex shader inputs in location order, tightly packed into binding 0
+pub(crate) fn derive_vertex_input(vert: &ShaderReflection, input_rate: VertexInputRate) -> anyhow::Result<VertexInputDesc> {
+    let mut attrib_desc = Vec::new();
+    let mut offset = 0;
+    for input in &vert.inputs {
+        let format = input.ty.vertex_format()
+            .with_context(|| format!("Vertex input {:?} of type {} has no attribute format", input.name, input.ty))?;
+        for column in 0..input.ty.columns {
+            attrib_desc.push(VertexInputAttributeDescription::default()
+                .binding(0)
+                .format(format)
+                .offset(offset)
+                .location(input.location + column));
+            offset += input.ty.column_size();
+        }
+    }
+    let binding_desc = if attrib_desc.is_empty() {
+        vec![]
+    }
+    else {
+        vec![VertexInputBindingDescription::default()
+            .binding(0)
+            .input_rate(input_rate)
+            .stride(offset)]
+    };
+    Ok(VertexInputDesc::from_descriptions(attrib_desc, binding_desc))
+}
+
+/// Descriptor set layouts with bindings of all stages merged, one entry per set up to the highest used
+pub(crate) fn derive_bindings(stages: &[&ShaderReflection]) -> anyhow::Result<Vec<Vec<DescriptorSetLayoutBindingDesc>>> {
+    let mut sets: Vec<Vec<DescriptorSetLayoutBindingDesc>> = Vec::new();
+    for stage in stages {
+        if let Some(push_constants) = &stage.push_constants {
+            bail!("{:?} shader uses {} bytes of push constants, which are not supported by pipeline layouts", stage.stage, push_constants.size);
+        }
+        for reflected in &stage.bindings {
+            if reflected.count == 0 {
+                bail!("Runtime-sized descriptor array {:?} (set {}, binding {}) requires explicit layout", reflected.name, reflected.set, reflected.binding);
+            }
+            let set = reflected.set as usize;
+            if sets.len() <= set {
+                sets.resize_with(set + 1, Vec::new);
+            }
+            match sets[set].iter_mut().find(|b| b.binding == reflected.binding) {
+                Some(existing) if existing.descriptor_type != reflected.descriptor_type => bail!(
+                    "Set {} binding {} is {:?} in one stage and {:?} in another",
+                    reflected.set, reflected.binding, existing.descriptor_type, reflected.descriptor_type
+                ),
+                Some(existing) => {
+                    existing.stage_flags |= stage.stage;
+                    existing.descriptor_count = existing.descriptor_count.max(reflected.count);
+                }
+                None => sets[set].push(DescriptorSetLayoutBindingDesc {
+                    binding: reflected.binding,
+                    descriptor_type: reflected.descriptor_type,
+                    descriptor_count: reflected.count,
+                    stage_flags: stage.stage,
+                    binding_flags: Default::default(),
+                }),
+            }
+        }
+    }
+    for set in &mut sets {
+        set.sort_by_key(|b| b.binding);
+    }
+    Ok(sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::VertexInputRate;
+    use crate::descriptor_set;
+    use super::*;
+
+    // solid_vert/solid_frag: interface of app/shaders/solid.{vert,frag}
+    // textured_frag: solid_frag with `sampler2D fontTexture` (binding 1) and `push_constant Tint { vec4 color; float alpha; }`
+    // particles_comp: local_size 64, `buffer Particles { vec4 data[]; }` (0, 0), `uniform Params { mat4 transform; uint count; }` (0, 1),
+    // `sampler2D textures[4]` (1, 0), `writeonly image2D outImage` (1, 1)
+    const SOLID_VERT: &[u8] = include_bytes!("fixtures/solid_vert.spv");
+    const SOLID_FRAG: &[u8] = include_bytes!("fixtures/solid_frag.spv");
+    const TEXTURED_FRAG: &[u8] = include_bytes!("fixtures/textured_frag.spv");
+    const PARTICLES_COMP: &[u8] = include_bytes!("fixtures/particles_comp.spv");
+
+    descriptor_set! {
+        pub struct SolidSet {
+            #[vert]
+            0 -> UniformBuffer,
+            #[frag]
+            1 -> CombinedImageSampler,
+        }
+    }
+
+    fn ty(numeric: NumericType, components: u32) -> InterfaceType {
+        InterfaceType { numeric, width: 32, components, columns: 1 }
+    }
+
+    fn attributes(formats: &[Format]) -> VertexInputDesc {
+        let attributes = formats.iter().enumerate().map(|(i, format)| VertexInputAttributeDescription::default()
+            .location(i as u32)
+            .format(*format)).collect();
+        VertexInputDesc::from_descriptions(attributes, vec![])
+    }
+
+    #[test]
+    fn test_reflect_vertex_shader() {
+        let vert = ShaderReflection::parse(SOLID_VERT).unwrap();
+        assert_eq!(vert.stage, ShaderStageFlags::VERTEX);
+        assert_eq!(vert.entry_point, "main");
+        let inputs: Vec<_> = vert.inputs.iter().map(|v| (v.location, v.name.as_deref().unwrap(), v.ty)).collect();
+        assert_eq!(inputs, [
+            (0, "pos", ty(NumericType::Sint, 2)),
+            (1, "size", ty(NumericType::Sint, 2)),
+            (2, "d", ty(NumericType::Float, 1)),
+            (3, "color", ty(NumericType::Float, 4)),
+        ]);
+        assert_eq!(vert.outputs.len(), 2);
+        assert_eq!(vert.output(1).unwrap().ty, ty(NumericType::Float, 4));
+
+        let uniform = vert.binding(0, 0).unwrap();
+        assert_eq!(uniform.descriptor_type, DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(uniform.block_size, Some(8));
+        assert_eq!(uniform.name.as_deref(), Some("uniformData"));
+        assert_eq!(vert.push_constants, None);
+        assert_eq!(vert.workgroup_size, None);
+    }
+
+    #[test]
+    fn test_reflect_resources() {
+        let frag = ShaderReflection::parse(TEXTURED_FRAG).unwrap();
+        assert_eq!(frag.stage, ShaderStageFlags::FRAGMENT);
+        assert_eq!(frag.binding(0, 1).unwrap().descriptor_type, DescriptorType::COMBINED_IMAGE_SAMPLER);
+        assert_eq!(frag.push_constants.as_ref().map(|p| p.size), Some(20));
+
+        let comp = ShaderReflection::parse(PARTICLES_COMP).unwrap();
+        assert_eq!(comp.stage, ShaderStageFlags::COMPUTE);
+        assert_eq!(comp.workgroup_size, Some([64, 1, 1]));
+        let bindings: Vec<_> = comp.bindings.iter().map(|b| (b.set, b.binding, b.descriptor_type, b.count, b.block_size)).collect();
+        assert_eq!(bindings, [
+            (0, 0, DescriptorType::STORAGE_BUFFER, 1, Some(0)),
+            (0, 1, DescriptorType::UNIFORM_BUFFER, 1, Some(68)),
+            (1, 0, DescriptorType::COMBINED_IMAGE_SAMPLER, 4, None),
+            (1, 1, DescriptorType::STORAGE_IMAGE, 1, None),
+        ]);
+
+        assert!(ShaderReflection::parse(&[0; 20]).is_err());
+        assert!(ShaderReflection::parse(&SOLID_VERT[..SOLID_VERT.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_check_pipeline_interface() {
+        let solid_attributes = attributes(&[Format::R32G32_SINT, Format::R32G32_SINT, Format::R32_SFLOAT, Format::R32G32B32A32_SFLOAT]);
+        check_pipeline_interface(SOLID_VERT, SOLID_FRAG, &solid_attributes, &[SolidSet::bindings()]).unwrap();
+
+        // float position, missing color attribute, no descriptor sets
+        let wrong_attributes = attributes(&[Format::R32G32_SFLOAT, Format::R32G32_SINT, Format::R32_SFLOAT]);
+        let err = check_pipeline_interface(SOLID_VERT, SOLID_FRAG, &wrong_attributes, &[]).unwrap_err();
+        assert_eq!(err.mismatches, [
+            InterfaceMismatch::AttributeType { location: 0, name: Some("pos".into()), format: Format::R32G32_SFLOAT, shader: ty(NumericType::Sint, 2) },
+            InterfaceMismatch::MissingAttribute { location: 3, name: Some("color".into()) },
+            InterfaceMismatch::MissingBinding { set: 0, binding: 0, name: Some("uniformData".into()) },
+        ]);
+        assert!(err.to_string().contains("vertex input 'pos' at location 0 is Sint32vec2, attribute format is R32G32_SFLOAT"));
+
+        // sampler is visible to fragment stage only, push constants are not supported
+        let err = check_pipeline_interface(SOLID_VERT, TEXTURED_FRAG, &solid_attributes, &[SolidSet::bindings()]).unwrap_err();
+        assert_eq!(err.mismatches, [InterfaceMismatch::PushConstants { stage: ShaderStageFlags::FRAGMENT, size: 20 }]);
+
+        let err = check_pipeline_interface(SOLID_FRAG, SOLID_VERT, &solid_attributes, &[SolidSet::bindings()]).unwrap_err();
+        assert_eq!(err.mismatches, [
+            InterfaceMismatch::UnexpectedStage { expected: ShaderStageFlags::VERTEX, found: ShaderStageFlags::FRAGMENT },
+            InterfaceMismatch::UnexpectedStage { expected: ShaderStageFlags::FRAGMENT, found: ShaderStageFlags::VERTEX },
+        ]);
+    }
+
+    #[test]
+    fn test_derive_layouts() {
+        let vert = ShaderReflection::parse(SOLID_VERT).unwrap();
+        let frag = ShaderReflection::parse(TEXTURED_FRAG).unwrap();
+        let input = derive_vertex_input(&vert, VertexInputRate::INSTANCE).unwrap();
+        let attributes: Vec<_> = input.attribute_descriptions().iter().map(|a| (a.location, a.format, a.offset)).collect();
+        assert_eq!(attributes, [
+            (0, Format::R32G32_SINT, 0),
+            (1, Format::R32G32_SINT, 8),
+            (2, Format::R32_SFLOAT, 16),
+            (3, Format::R32G32B32A32_SFLOAT, 20),
+        ]);
+        assert_eq!(input.binding_descriptions()[0].stride, 36);
+
+        let solid_frag = ShaderReflection::parse(SOLID_FRAG).unwrap();
+        let sets = derive_bindings(&[&vert, &solid_frag]).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0][0].stage_flags, ShaderStageFlags::VERTEX);
+        assert!(derive_bindings(&[&vert, &frag]).is_err());
+
+        let comp = ShaderReflection::parse(PARTICLES_COMP).unwrap();
+        let sets = derive_bindings(&[&comp]).unwrap();
+        assert_eq!(sets.len(), 2);
+        assert_eq!((sets[1][0].descriptor_type, sets[1][0].descriptor_count), (DescriptorType::COMBINED_IMAGE_SAMPLER, 4));
+    }
+}
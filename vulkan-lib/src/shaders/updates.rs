@@ -0,0 +1,379 @@
+use std::ops::Range;
+use std::slice::from_raw_parts;
+use ash::vk::BufferCopy;
+use smallvec::SmallVec;
+use crate::resources::staging_buffer::StagingBufferRange;
+use crate::shaders::layout::LayoutInfo;
+
+/// Bytes to upload at `offset` of destination buffer
+#[derive(Debug, Clone, Copy)]
+pub struct GraphicsUpdateCmd<'a> {
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// Source of dirty byte ranges, derived for structs of update sources with `#[derive(CollectDrawStateUpdates)]`
+pub trait CollectDrawStateUpdates {
+    fn collect_updates(&self) -> impl Iterator<Item = GraphicsUpdateCmd<'_>> + '_;
+    /// Called after collected updates are uploaded
+    fn clear_updates(&mut self);
+}
+
+/// Layout value modified through generated `{Name}Ext` setters
+pub trait ModifyLayout<T> {
+    /// # Safety
+    /// Returned range must cover all bytes of `T` modified by `f`
+    unsafe fn modify_field(&mut self, f: impl FnOnce(&mut T) -> Range<usize>);
+}
+
+/// Dirty byte ranges. Each range is kept until collected once per copy of multi-buffered destination
+#[derive(Debug, Clone)]
+pub struct DirtyRanges {
+    ranges: SmallVec<[(Range<usize>, u8); 4]>,
+    copies: u8,
+}
+
+impl DirtyRanges {
+    pub fn new(copies: u8) -> Self {
+        Self {
+            ranges: SmallVec::new(),
+            copies: copies.max(1),
+        }
+    }
+
+    pub fn mark(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        if let Some((last, left)) = self.ranges.last_mut()
+            && *left == self.copies && range.start <= last.end && last.start <= range.end {
+            *last = last.start.min(range.start)..last.end.max(range.end);
+            return;
+        }
+        self.ranges.push((range, self.copies));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.ranges.iter().map(|(r, _)| r.clone())
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.retain(|(_, left)| {
+            *left -= 1;
+            *left > 0
+        });
+    }
+
+    /// Drop ranges past `len` bytes
+    fn truncate(&mut self, len: usize) {
+        self.ranges.retain(|(r, _)| {
+            r.end = r.end.min(len);
+            r.start < r.end
+        });
+    }
+}
+
+/// Single layout value with dirty ranges of modified fields
+pub struct StateUpdatesBytes<T> {
+    value: T,
+    offset: usize,
+    dirty: DirtyRanges,
+}
+
+impl<T: LayoutInfo> StateUpdatesBytes<T> {
+    /// Whole value is dirty initially
+    pub fn new(value: T) -> Self {
+        let mut dirty = DirtyRanges::new(1);
+        dirty.mark(0..T::SIZE);
+        Self {
+            value,
+            offset: 0,
+            dirty,
+        }
+    }
+
+    /// Offset of value in destination buffer
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Keep ranges dirty until uploaded to each of `copies` destination buffers
+    pub fn with_copies(mut self, copies: u8) -> Self {
+        let ranges: Vec<_> = self.dirty.iter().collect();
+        self.dirty = DirtyRanges::new(copies);
+        ranges.into_iter().for_each(|r| self.dirty.mark(r));
+        self
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.dirty.mark(0..T::SIZE);
+    }
+}
+
+impl<T: LayoutInfo> ModifyLayout<T> for StateUpdatesBytes<T> {
+    unsafe fn modify_field(&mut self, f: impl FnOnce(&mut T) -> Range<usize>) {
+        let range = f(&mut self.value);
+        self.dirty.mark(range);
+    }
+}
+
+impl<T: LayoutInfo> CollectDrawStateUpdates for StateUpdatesBytes<T> {
+    fn collect_updates(&self) -> impl Iterator<Item = GraphicsUpdateCmd<'_>> + '_ {
+        let bytes = self.value.as_bytes();
+        self.dirty.iter().map(move |r| GraphicsUpdateCmd {
+            offset: self.offset + r.start,
+            data: &bytes[r],
+        })
+    }
+
+    fn clear_updates(&mut self) {
+        self.dirty.clear();
+    }
+}
+
+/// Array of layout values, e.g. instance attributes, with dirty ranges of modified elements and fields
+pub struct StateUpdatesArray<T> {
+    items: Vec<T>,
+    offset: usize,
+    dirty: DirtyRanges,
+}
+
+impl<T: LayoutInfo> StateUpdatesArray<T> {
+    pub fn new() -> Self {
+        Self::from_vec(Vec::new())
+    }
+
+    /// All elements are dirty initially
+    pub fn from_vec(items: Vec<T>) -> Self {
+        let mut dirty = DirtyRanges::new(1);
+        dirty.mark(0..items.len() * T::SIZE);
+        Self {
+            items,
+            offset: 0,
+            dirty,
+        }
+    }
+
+    /// Offset of first element in destination buffer
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Keep ranges dirty until uploaded to each of `copies` destination buffers
+    pub fn with_copies(mut self, copies: u8) -> Self {
+        let ranges: Vec<_> = self.dirty.iter().collect();
+        self.dirty = DirtyRanges::new(copies);
+        ranges.into_iter().for_each(|r| self.dirty.mark(r));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Size of all elements in bytes
+    pub fn bytes_len(&self) -> usize {
+        self.items.len() * T::SIZE
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn has_updates(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    pub fn push(&mut self, value: T) {
+        let start = self.bytes_len();
+        self.items.push(value);
+        self.dirty.mark(start..start + T::SIZE);
+    }
+
+    pub fn set(&mut self, i: usize, value: T) {
+        self.items[i] = value;
+        self.dirty.mark(i * T::SIZE..(i + 1) * T::SIZE);
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.items.truncate(len);
+        self.dirty.truncate(self.bytes_len());
+    }
+
+    /// Element for field setters of generated `{Name}Ext` trait
+    pub fn get_mut(&mut self, i: usize) -> StateUpdatesElement<'_, T> {
+        StateUpdatesElement {
+            value: &mut self.items[i],
+            offset: i * T::SIZE,
+            dirty: &mut self.dirty,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            from_raw_parts(self.items.as_ptr() as *const u8, self.bytes_len())
+        }
+    }
+}
+
+impl<T: LayoutInfo> Default for StateUpdatesArray<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: LayoutInfo> ModifyLayout<T> for StateUpdatesElement<'_, T> {
+    unsafe fn modify_field(&mut self, f: impl FnOnce(&mut T) -> Range<usize>) {
+        let range = f(self.value);
+        self.dirty.mark(self.offset + range.start..self.offset + range.end);
+    }
+}
+
+impl<T: LayoutInfo> CollectDrawStateUpdates for StateUpdatesArray<T> {
+    fn collect_updates(&self) -> impl Iterator<Item = GraphicsUpdateCmd<'_>> + '_ {
+        let bytes = self.as_bytes();
+        self.dirty.iter().map(move |r| GraphicsUpdateCmd {
+            offset: self.offset + r.start,
+            data: &bytes[r],
+        })
+    }
+
+    fn clear_updates(&mut self) {
+        self.dirty.clear();
+    }
+}
+
+pub struct StateUpdatesElement<'a, T> {
+    value: &'a mut T,
+    offset: usize,
+    dirty: &'a mut DirtyRanges,
+}
+
+/// Collected updates sorted by offset with overlaps removed, adjacent ones form single copy region
+pub struct DirtyUpdates<'a> {
+    cmds: Vec<GraphicsUpdateCmd<'a>>,
+}
+
+impl<'a> DirtyUpdates<'a> {
+    pub fn new(updates: impl Iterator<Item = GraphicsUpdateCmd<'a>>) -> Self {
+        let mut cmds: Vec<_> = updates.filter(|c| !c.data.is_empty()).collect();
+        cmds.sort_by_key(|c| c.offset);
+        let mut end: usize = 0;
+        cmds.retain_mut(|c| {
+            let overlap = end.saturating_sub(c.offset).min(c.data.len());
+            c.offset += overlap;
+            c.data = &c.data[overlap..];
+            end = end.max(c.offset + c.data.len());
+            !c.data.is_empty()
+        });
+        Self { cmds }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    /// Staging bytes required by `write`
+    pub fn size(&self) -> usize {
+        self.cmds.iter().map(|c| c.data.len()).sum()
+    }
+
+    /// Pack updates into staging range of at least `size()` bytes
+    pub fn write(self, mut staging: StagingBufferRange) -> BufferUpdate {
+        let mut regions: SmallVec<[BufferCopy; 4]> = SmallVec::new();
+        let base = staging.range.start;
+        staging.update(|data| {
+            let mut pos = 0;
+            for cmd in &self.cmds {
+                data[pos..pos + cmd.data.len()].copy_from_slice(cmd.data);
+                match regions.last_mut() {
+                    Some(region) if region.dst_offset + region.size == cmd.offset as u64 => region.size += cmd.data.len() as u64,
+                    _ => regions.push(BufferCopy::default()
+                        .src_offset(base + pos as u64)
+                        .dst_offset(cmd.offset as u64)
+                        .size(cmd.data.len() as u64)),
+                }
+                pos += cmd.data.len();
+            }
+        });
+        BufferUpdate { staging, regions }
+    }
+}
+
+/// Dirty ranges packed in staging buffer, recorded with `RecordContext::copy_buffer_update`
+pub struct BufferUpdate {
+    pub(crate) staging: StagingBufferRange,
+    pub(crate) regions: SmallVec<[BufferCopy; 4]>,
+}
+
+impl BufferUpdate {
+    /// Bytes uploaded
+    pub fn len(&self) -> usize {
+        self.regions.iter().map(|r| r.size as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// End of last written byte in destination buffer
+    pub fn dst_end(&self) -> usize {
+        self.regions.iter().map(|r| (r.dst_offset + r.size) as usize).max().unwrap_or(0)
+    }
+
+    pub fn regions(&self) -> &[BufferCopy] {
+        &self.regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_ranges_kept_per_copy() {
+        let mut dirty = DirtyRanges::new(2);
+        dirty.mark(0..8);
+        dirty.mark(8..12);
+        dirty.mark(32..36);
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), [0..12, 32..36]);
+        dirty.clear();
+        dirty.mark(4..6);
+        // still pending range for second copy is not merged with new one
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), [0..12, 32..36, 4..6]);
+        dirty.clear();
+        assert_eq!(dirty.iter().next(), Some(4..6));
+        assert_eq!(dirty.iter().count(), 1);
+        dirty.clear();
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn updates_merged() {
+        let bytes: Vec<u8> = (0..64).collect();
+        let cmds = [
+            GraphicsUpdateCmd { offset: 40, data: &bytes[40..48] },
+            GraphicsUpdateCmd { offset: 0, data: &bytes[0..8] },
+            GraphicsUpdateCmd { offset: 4, data: &bytes[4..16] },
+            GraphicsUpdateCmd { offset: 16, data: &bytes[16..20] },
+        ];
+        let updates = DirtyUpdates::new(cmds.into_iter());
+        assert_eq!(updates.size(), 28);
+        assert_eq!(updates.cmds.iter().map(|c| (c.offset, c.data.len())).collect::<Vec<_>>(), [(0, 8), (8, 8), (16, 4), (40, 8)]);
+    }
+}
@@ -2,9 +2,9 @@ use std::mem;
 use std::sync::Arc;
 use ash::vk;
 use ash::khr::swapchain;
-use ash::vk::{Extent2D, Format, Image, ImageAspectFlags, ImageTiling, ImageUsageFlags, ImageView, PhysicalDevice, PresentModeKHR, SampleCountFlags, SwapchainKHR};
-use log::info;
-use smallvec::SmallVec;
+use ash::vk::{ColorSpaceKHR, CompositeAlphaFlagsKHR, Extent2D, Format, Image, ImageAspectFlags, ImageTiling, ImageUsageFlags, ImageView, PhysicalDevice, PresentModeKHR, SampleCountFlags, SurfaceFormatKHR, SwapchainKHR};
+use log::{info, warn};
+use smallvec::{smallvec, SmallVec};
 use sparkles::range_event_start;
 #[cfg(feature = "present-timing")]
 use crate::extensions::present_timing::SWAPCHAIN_CREATE_PRESENT_TIMING_BIT_EXT;
@@ -13,8 +13,185 @@ use crate::wrappers::device::VkDeviceRef;
 use crate::wrappers::image::{image_2d_info, imageview_info_for_image, swapchain_info};
 use crate::wrappers::surface::VkSurfaceRef;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapchainColorEncoding {
+    /// Shader output is written as is
+    Unorm,
+    /// Shader output is converted from linear to sRGB on write
+    Srgb,
+}
+
+/// Color space the presentation engine interprets swapchain images in.
+/// Shaders must encode output accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    /// sRGB primaries and transfer function
+    Sdr,
+    /// BT.2020 primaries, PQ (ST 2084) encoded absolute luminance
+    Hdr10,
+    /// sRGB primaries, linear extended range values, 1.0 is 80 nits
+    ScRgb,
+    /// Display-P3 primaries, sRGB transfer function
+    DisplayP3,
+}
+
+impl OutputColorSpace {
+    /// Supported formats in order of preference
+    fn candidates(&self) -> &'static [(Format, ColorSpaceKHR)] {
+        match self {
+            OutputColorSpace::Sdr => &[],
+            OutputColorSpace::Hdr10 => &[
+                (Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+                (Format::A2R10G10B10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+            ],
+            OutputColorSpace::ScRgb => &[
+                (Format::R16G16B16A16_SFLOAT, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            ],
+            OutputColorSpace::DisplayP3 => &[
+                (Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::B8G8R8A8_UNORM, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::R8G8B8A8_UNORM, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+            ],
+        }
+    }
+
+    pub fn from_color_space(color_space: ColorSpaceKHR) -> Self {
+        match color_space {
+            ColorSpaceKHR::HDR10_ST2084_EXT => OutputColorSpace::Hdr10,
+            ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputColorSpace::ScRgb,
+            ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT => OutputColorSpace::DisplayP3,
+            _ => OutputColorSpace::Sdr,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, OutputColorSpace::Hdr10 | OutputColorSpace::ScRgb)
+    }
+}
+
+/// Desired swapchain parameters. Unsupported values are replaced with supported ones,
+/// actually chosen values are reported in `SwapchainInfo`.
+#[derive(Debug, Clone)]
+pub struct SwapchainConfig {
+    /// First supported mode is used. FIFO is always supported and used when nothing else matches.
+    pub present_modes: SmallVec<[PresentModeKHR; 4]>,
+    pub color_encoding: SwapchainColorEncoding,
+    /// Preferred output color spaces, first supported is used. Falls back to SDR with `color_encoding`.
+    pub color_spaces: SmallVec<[OutputColorSpace; 3]>,
+    /// Clamped to surface limits. None: one more than the minimum, so 2 images can be acquired at a time.
+    pub image_count: Option<u32>,
+    /// Use PRE_MULTIPLIED or POST_MULTIPLIED for transparent windows. Falls back to any supported mode.
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+    /// Added to COLOR_ATTACHMENT | TRANSFER_DST. Unsupported flags are dropped.
+    pub extra_usage: ImageUsageFlags,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            // IMMEDIATE preferred over MAILBOX because of better presentation latency with present_timing (not confirmed)
+            present_modes: smallvec![PresentModeKHR::IMMEDIATE, PresentModeKHR::MAILBOX],
+            color_encoding: SwapchainColorEncoding::Unorm,
+            color_spaces: SmallVec::new(),
+            image_count: None,
+            composite_alpha: CompositeAlphaFlagsKHR::OPAQUE,
+            extra_usage: ImageUsageFlags::empty(),
+        }
+    }
+}
+
+impl SwapchainConfig {
+    /// vsync-limited presentation, lowest power usage
+    pub fn fifo() -> Self {
+        Self {
+            present_modes: smallvec![PresentModeKHR::FIFO],
+            ..Self::default()
+        }
+    }
+
+    /// Prefer HDR10, then scRGB, fall back to SDR
+    pub fn hdr() -> Self {
+        Self {
+            color_spaces: smallvec![OutputColorSpace::Hdr10, OutputColorSpace::ScRgb],
+            ..Self::default()
+        }
+    }
+}
+
+/// Parameters of created swapchain
+#[derive(Debug, Clone, Copy)]
+pub struct SwapchainInfo {
+    pub present_mode: PresentModeKHR,
+    pub format: Format,
+    pub color_space: ColorSpaceKHR,
+    pub output_color_space: OutputColorSpace,
+    pub image_count: u32,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+    pub image_usage: ImageUsageFlags,
+    pub extent: Extent2D,
+}
+
+/// Surface format changed on swapchain recreation, e.g. window moved between SDR and HDR monitors.
+/// Render passes and pipelines created for the old format must be recreated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainFormatChange {
+    pub old: SurfaceFormatKHR,
+    pub new: SurfaceFormatKHR,
+}
+
+impl SwapchainFormatChange {
+    pub(crate) fn detect(old: SurfaceFormatKHR, new: SurfaceFormatKHR) -> Option<Self> {
+        (old != new).then_some(Self { old, new })
+    }
+}
+
+pub(crate) fn choose_surface_format(surface_formats: &[SurfaceFormatKHR], color_spaces: &[OutputColorSpace], color_encoding: SwapchainColorEncoding) -> SurfaceFormatKHR {
+    let hdr_format = color_spaces.iter()
+        .flat_map(|c| c.candidates())
+        .find_map(|(format, color_space)| {
+            surface_formats.iter().find(|f| f.format == *format && f.color_space == *color_space)
+        });
+    if let Some(format) = hdr_format {
+        return *format;
+    }
+    if !color_spaces.is_empty() && color_spaces != [OutputColorSpace::Sdr] {
+        info!("None of {:?} output color spaces supported, falling back to SDR", color_spaces);
+    }
+
+    let preferred_formats: &[Format] = match color_encoding {
+        SwapchainColorEncoding::Unorm => &[Format::B8G8R8A8_UNORM, Format::R8G8B8A8_UNORM],
+        SwapchainColorEncoding::Srgb => &[Format::B8G8R8A8_SRGB, Format::R8G8B8A8_SRGB],
+    };
+
+    preferred_formats.iter().find_map(|format| {
+        surface_formats.iter().find(|f| {
+            f.format == *format && f.color_space == ColorSpaceKHR::SRGB_NONLINEAR
+        })
+    }).copied().unwrap_or_else(|| {
+        let res = surface_formats[0];
+        warn!("No {:?} swapchain format supported, using {:?}", color_encoding, res.format);
+        res
+    })
+}
+
+fn choose_composite_alpha(supported: CompositeAlphaFlagsKHR, desired: CompositeAlphaFlagsKHR) -> CompositeAlphaFlagsKHR {
+    if supported.contains(desired) {
+        return desired;
+    }
+
+    let res = [CompositeAlphaFlagsKHR::OPAQUE, CompositeAlphaFlagsKHR::INHERIT,
+        CompositeAlphaFlagsKHR::PRE_MULTIPLIED, CompositeAlphaFlagsKHR::POST_MULTIPLIED]
+        .into_iter()
+        .find(|f| supported.contains(*f))
+        .unwrap_or(CompositeAlphaFlagsKHR::OPAQUE);
+    warn!("Composite alpha {:?} is not supported, using {:?}", desired, res);
+    res
+}
+
 pub struct SwapchainWrapper {
     device: VkDeviceRef,
+    config: SwapchainConfig,
+    info: SwapchainInfo,
 
     swapchain: SwapchainKHR,
     pub swapchain_loader: swapchain::Device,
@@ -36,7 +213,7 @@ impl SwapchainWrapper {
     /// Extent is used from surface capabilities. If does not exist, use `extent`
     pub fn new(device: VkDeviceRef, physical_device: PhysicalDevice,
                extent: Extent2D, surface_ref: VkSurfaceRef, old_swapchain: Option<SwapchainKHR>,
-               latency_tracking: bool, present_timing: bool) -> anyhow::Result<SwapchainWrapper> {
+               latency_tracking: bool, present_timing: bool, config: SwapchainConfig) -> anyhow::Result<SwapchainWrapper> {
         let g = range_event_start!("[Vulkan] Init swapchain");
 
         let surface_loader = surface_ref.loader();
@@ -47,26 +224,27 @@ impl SwapchainWrapper {
         let surface_formats = unsafe { surface_loader.get_physical_device_surface_formats(physical_device, *surface)? };
         let surface_present_modes = unsafe { surface_loader.get_physical_device_surface_present_modes(physical_device, *surface)? };
 
-        //prefer B8G8R8A8_UNORM and SRGB_NONLINEAR
-        let surface_format = surface_formats.iter().find(|f| {
-            f.format == vk::Format::B8G8R8A8_UNORM && f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-        }).unwrap_or_else(|| {
-            surface_formats.first().unwrap()
-        });
+        let surface_format = choose_surface_format(&surface_formats, &config.color_spaces, config.color_encoding);
 
-        // IMMEDIATE preferred over MAILBOX because of better presentation latency with present_timing (not confirmed)
-        let present_mode = surface_present_modes.iter().find(|m| {
-            **m == vk::PresentModeKHR::IMMEDIATE
-        }).unwrap_or_else(|| {
-            surface_present_modes.iter().find(|m| {
-                **m == vk::PresentModeKHR::MAILBOX
-            }).unwrap_or_else(|| {
-                surface_present_modes.first().unwrap()
-            })
-        });
+        let present_mode = config.present_modes.iter()
+            .find(|m| surface_present_modes.contains(m))
+            .copied()
+            .unwrap_or(PresentModeKHR::FIFO);
 
-        // 1 additional image, so we can acquire 2 images at a time.
-        let image_count = surface_capabilities.min_image_count + 1;
+        // 1 additional image by default, so we can acquire 2 images at a time.
+        let mut image_count = config.image_count.unwrap_or(surface_capabilities.min_image_count + 1)
+            .max(surface_capabilities.min_image_count);
+        if surface_capabilities.max_image_count != 0 {
+            image_count = image_count.min(surface_capabilities.max_image_count);
+        }
+
+        let composite_alpha = choose_composite_alpha(surface_capabilities.supported_composite_alpha, config.composite_alpha);
+
+        let unsupported_usage = config.extra_usage & !surface_capabilities.supported_usage_flags;
+        if !unsupported_usage.is_empty() {
+            warn!("Swapchain image usage {:?} is not supported and will be ignored", unsupported_usage);
+        }
+        let image_usage = ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST | (config.extra_usage & surface_capabilities.supported_usage_flags);
 
         let mut swapchain_extent = if surface_capabilities.current_extent.width != u32::MAX {
             surface_capabilities.current_extent
@@ -88,7 +266,7 @@ impl SwapchainWrapper {
 
 
         let swapchain_loader = swapchain::Device::new(device.instance(), &device);
-        let swapchain_image_info = image_2d_info(surface_format.format, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST,
+        let swapchain_image_info = image_2d_info(surface_format.format, image_usage,
                                              swapchain_extent, SampleCountFlags::TYPE_1, ImageTiling::OPTIMAL);
 
         let latency_create_info = vk::SwapchainLatencyCreateInfoNV::default()
@@ -98,8 +276,8 @@ impl SwapchainWrapper {
             .surface(*surface)
             .min_image_count(image_count)
             .pre_transform(surface_capabilities.current_transform)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(*present_mode)
+            .composite_alpha(composite_alpha)
+            .present_mode(present_mode)
             .clipped(true);
 
         #[cfg(feature = "present-timing")]
@@ -123,6 +301,17 @@ impl SwapchainWrapper {
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
         let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
 
+        let info = SwapchainInfo {
+            present_mode,
+            format: surface_format.format,
+            color_space: surface_format.color_space,
+            output_color_space: OutputColorSpace::from_color_space(surface_format.color_space),
+            image_count: swapchain_images.len() as u32,
+            composite_alpha,
+            image_usage,
+            extent: swapchain_extent,
+        };
+
         let swapchain_images = swapchain_images.into_iter()
             .map(|i| {
                 Arc::new(ImageResource::from_image(&device, i, surface_format.format, swapchain_extent.width, swapchain_extent.height))
@@ -137,6 +326,8 @@ impl SwapchainWrapper {
             swapchain_extent,
 
             device,
+            config,
+            info,
             surface: surface_ref,
             latency_tracking,
             present_timing,
@@ -154,6 +345,13 @@ impl SwapchainWrapper {
         self.swapchain_format
     }
 
+    pub fn surface_format(&self) -> SurfaceFormatKHR {
+        SurfaceFormatKHR {
+            format: self.info.format,
+            color_space: self.info.color_space,
+        }
+    }
+
     pub fn get_extent(&self) -> Extent2D {
         self.swapchain_extent
     }
@@ -161,17 +359,25 @@ impl SwapchainWrapper {
         &self.swapchain_images
     }
 
+    pub fn info(&self) -> &SwapchainInfo {
+        &self.info
+    }
+
+    pub fn config(&self) -> &SwapchainConfig {
+        &self.config
+    }
+
     /// # Safety
     /// Image views should not be used. Swapchain should not be used.
     /// Returns the old swapchain images that need to be destroyed later
     pub unsafe fn recreate(&mut self, physical_device: PhysicalDevice,
-                           extent: Extent2D, surface: VkSurfaceRef) -> anyhow::Result<SwapchainImages> {
+                           extent: Extent2D, surface: VkSurfaceRef, config: SwapchainConfig) -> anyhow::Result<SwapchainImages> {
 
         let swapchain = self.swapchain;
         let latency_tracking = self.latency_tracking;
         let present_timing = self.present_timing;
         let old_images = mem::take(&mut self.swapchain_images);
-        *self = Self::new(self.device.clone(), physical_device, extent, surface, Some(swapchain), latency_tracking, present_timing)?;
+        *self = Self::new(self.device.clone(), physical_device, extent, surface, Some(swapchain), latency_tracking, present_timing, config)?;
         Ok(old_images)
     }
 }
@@ -181,4 +387,88 @@ impl Drop for SwapchainWrapper {
         let g = range_event_start!("[Vulkan] Destroy swapchain");
         unsafe { self.swapchain_loader.destroy_swapchain(self.swapchain, None); }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_format(format: Format, color_space: ColorSpaceKHR) -> SurfaceFormatKHR {
+        SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    /// Simulates surface format query results before and after swapchain recreation
+    fn recreate_with_formats(old_formats: &[SurfaceFormatKHR], new_formats: &[SurfaceFormatKHR], encoding: SwapchainColorEncoding) -> Option<SwapchainFormatChange> {
+        let old = choose_surface_format(old_formats, &[], encoding);
+        let new = choose_surface_format(new_formats, &[], encoding);
+        SwapchainFormatChange::detect(old, new)
+    }
+
+    #[test]
+    fn test_format_unchanged_on_same_surface() {
+        let formats = [
+            surface_format(Format::B8G8R8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+            surface_format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+        ];
+
+        assert_eq!(recreate_with_formats(&formats, &formats, SwapchainColorEncoding::Unorm), None);
+        assert_eq!(recreate_with_formats(&formats, &formats, SwapchainColorEncoding::Srgb), None);
+    }
+
+    #[test]
+    fn test_format_change_detected() {
+        let sdr_formats = [
+            surface_format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+        ];
+        // surface without 8-bit sRGB formats, as reported by some HDR outputs and android surfaces
+        let hdr_formats = [
+            surface_format(Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+            surface_format(Format::R16G16B16A16_SFLOAT, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+        ];
+
+        let change = recreate_with_formats(&sdr_formats, &hdr_formats, SwapchainColorEncoding::Unorm).unwrap();
+        assert_eq!(change.old, sdr_formats[0]);
+        assert_eq!(change.new, hdr_formats[0]);
+
+        let change = recreate_with_formats(&hdr_formats, &sdr_formats, SwapchainColorEncoding::Unorm).unwrap();
+        assert_eq!(change.old, hdr_formats[0]);
+        assert_eq!(change.new, sdr_formats[0]);
+    }
+
+    #[test]
+    fn test_color_space_change_detected() {
+        let old_formats = [
+            surface_format(Format::R8G8B8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+        ];
+        let new_formats = [
+            surface_format(Format::R8G8B8A8_UNORM, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+        ];
+
+        let change = recreate_with_formats(&old_formats, &new_formats, SwapchainColorEncoding::Unorm).unwrap();
+        assert_eq!(change.old.format, change.new.format);
+        assert_ne!(change.old.color_space, change.new.color_space);
+    }
+
+    #[test]
+    fn test_hdr_color_space_selection() {
+        let formats = [
+            surface_format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+            surface_format(Format::R16G16B16A16_SFLOAT, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            surface_format(Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+        ];
+
+        let hdr10 = choose_surface_format(&formats, &[OutputColorSpace::Hdr10, OutputColorSpace::ScRgb], SwapchainColorEncoding::Unorm);
+        assert_eq!(hdr10, formats[2]);
+        let scrgb = choose_surface_format(&formats, &[OutputColorSpace::ScRgb, OutputColorSpace::Hdr10], SwapchainColorEncoding::Unorm);
+        assert_eq!(scrgb, formats[1]);
+        assert_eq!(OutputColorSpace::from_color_space(scrgb.color_space), OutputColorSpace::ScRgb);
+
+        // SDR fallback when requested color space is not supported
+        let sdr = choose_surface_format(&formats, &[OutputColorSpace::DisplayP3], SwapchainColorEncoding::Unorm);
+        assert_eq!(sdr, formats[0]);
+        assert_eq!(OutputColorSpace::from_color_space(sdr.color_space), OutputColorSpace::Sdr);
+    }
+}
@@ -3,7 +3,7 @@ use std::ffi::{c_char, CStr};
 use std::slice;
 use std::sync::Arc;
 use ash::{vk, Entry};
-use ash::vk::{ApplicationInfo, DebugReportCallbackCreateInfoEXT, InstanceCreateInfo, EXT_CALIBRATED_TIMESTAMPS_NAME};
+use ash::vk::{ApplicationInfo, DebugUtilsMessengerCreateInfoEXT, InstanceCreateInfo, EXT_CALIBRATED_TIMESTAMPS_NAME};
 use log::{info, warn};
 use sparkles::range_event_start;
 use crate::wrappers::device::{VkDevice, VkDeviceRef};
@@ -38,7 +38,7 @@ impl CapabilitiesChecker {
 
     pub fn create_instance(&mut self, entry: &Entry, app_info: &ApplicationInfo,
            required_layers: &mut Vec<*const c_char>, required_extensions: &mut Vec<*const c_char>,
-            debug_report_info: &mut DebugReportCallbackCreateInfoEXT) -> anyhow::Result<Arc<VkInstance>> {
+            debug_messenger_info: &mut DebugUtilsMessengerCreateInfoEXT) -> anyhow::Result<Arc<VkInstance>> {
 
         let g = range_event_start!("[VulkanHelpers] Create instance");
 
@@ -91,7 +91,7 @@ impl CapabilitiesChecker {
 
         let mut create_info = InstanceCreateInfo::default()
             .application_info(app_info)
-            .push_next(debug_report_info);
+            .push_next(debug_messenger_info);
 
         // check if KHR_portability_enumeration supported
         if cfg!(feature="portability_subset") {
@@ -176,6 +176,22 @@ impl CapabilitiesChecker {
         Ok(VkDevice::new(device, instance).into())
     }
 
+    pub fn activated_layers(&self) -> Vec<String> {
+        self.activated_layers.iter().cloned().collect()
+    }
+
+    pub fn activated_instance_extensions(&self) -> Vec<String> {
+        self.activated_instance_extensions.iter().cloned().collect()
+    }
+
+    pub fn activated_device_extensions(&self) -> Vec<String> {
+        self.activated_device_extensions.iter().cloned().collect()
+    }
+
+    pub fn is_instance_extension_enabled(&self, extension_name: &CStr) -> bool {
+        self.activated_instance_extensions.contains(extension_name.to_str().unwrap())
+    }
+
     pub fn is_device_extension_enabled(&self, extension_name: &CStr) -> bool {
         self.activated_device_extensions.contains(extension_name.to_str().unwrap())
     }
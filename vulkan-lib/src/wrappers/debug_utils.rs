@@ -0,0 +1,150 @@
+use std::borrow::Cow;
+use std::ffi::{c_void, CStr, CString};
+use ash::{vk, Entry};
+use ash::vk::{DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessageTypeFlagsEXT, DebugUtilsMessengerCreateInfoEXT};
+use log::Level;
+use crate::wrappers::instance::VkInstanceRef;
+
+/// Target of validation messages in `log`
+const LOG_TARGET: &str = "vulkan";
+
+pub struct VkDebugUtils {
+    debug_utils_h: ash::ext::debug_utils::Instance,
+    messenger_h: vk::DebugUtilsMessengerEXT,
+    /// Object naming and command buffer labels, available after device creation
+    device_h: Option<ash::ext::debug_utils::Device>,
+    instance: VkInstanceRef
+}
+
+fn cstr_or_empty<'a>(p: *const std::ffi::c_char) -> Cow<'a, str> {
+    if p.is_null() {
+        Cow::Borrowed("")
+    }
+    else {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy()
+    }
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    severity: DebugUtilsMessageSeverityFlagsEXT,
+    message_type: DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    _p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    let level = if severity.contains(DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        Level::Error
+    } else if severity.contains(DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        Level::Warn
+    } else if severity.contains(DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        Level::Info
+    } else {
+        Level::Debug
+    };
+    if !log::log_enabled!(target: LOG_TARGET, level) || p_callback_data.is_null() {
+        return vk::FALSE;
+    }
+
+    let data = unsafe { &*p_callback_data };
+    let msg = cstr_or_empty(data.p_message);
+    let id_name = cstr_or_empty(data.p_message_id_name);
+
+    // named objects make messages readable without looking up raw handles
+    let mut objects = String::new();
+    if !data.p_objects.is_null() {
+        let object_infos = unsafe { std::slice::from_raw_parts(data.p_objects, data.object_count as usize) };
+        for object in object_infos {
+            let name = cstr_or_empty(object.p_object_name);
+            if !name.is_empty() {
+                objects.push_str(&format!("\n    {:?} 0x{:x} \"{}\"", object.object_type, object.object_handle, name));
+            }
+        }
+    }
+
+    log::log!(target: LOG_TARGET, level, "[{:?}] {}: {}{}", message_type, id_name, msg, objects);
+    vk::FALSE
+}
+
+impl VkDebugUtils {
+    /// Can be used AFTER instance is created
+    pub fn new(entry: &Entry, instance: VkInstanceRef, verbose: bool) -> anyhow::Result<VkDebugUtils> {
+        let debug_utils_h = ash::ext::debug_utils::Instance::new(entry, &instance);
+
+        let messenger_h = unsafe {
+            debug_utils_h.create_debug_utils_messenger(
+                &Self::get_messenger_create_info(verbose), None) }?;
+
+        Ok(VkDebugUtils {
+            debug_utils_h,
+            messenger_h,
+            device_h: None,
+            instance
+        })
+    }
+
+    /// Can be used during instance creation. `verbose`: also report info and verbose messages
+    pub fn get_messenger_create_info(verbose: bool) -> DebugUtilsMessengerCreateInfoEXT<'static> {
+        let mut severity = DebugUtilsMessageSeverityFlagsEXT::ERROR
+            | DebugUtilsMessageSeverityFlagsEXT::WARNING;
+        if verbose {
+            severity |= DebugUtilsMessageSeverityFlagsEXT::INFO | DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+        }
+        vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(severity)
+            .message_type(DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | DebugUtilsMessageTypeFlagsEXT::PERFORMANCE)
+            .pfn_user_callback(Some(vulkan_debug_callback))
+    }
+
+    pub fn attach_device(&mut self, device: &ash::Device) {
+        self.device_h = Some(ash::ext::debug_utils::Device::new(&self.instance, device));
+    }
+
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(device_h) = &self.device_h else {
+            return;
+        };
+        let name = to_cstring(name);
+        let info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        unsafe {
+            let _ = device_h.set_debug_utils_object_name(&info);
+        }
+    }
+
+    pub fn cmd_begin_label(&self, cmd_buffer: vk::CommandBuffer, name: &CStr, color: [f32; 4]) {
+        if let Some(device_h) = &self.device_h {
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(name)
+                .color(color);
+            unsafe { device_h.cmd_begin_debug_utils_label(cmd_buffer, &label) };
+        }
+    }
+
+    pub fn cmd_end_label(&self, cmd_buffer: vk::CommandBuffer) {
+        if let Some(device_h) = &self.device_h {
+            unsafe { device_h.cmd_end_debug_utils_label(cmd_buffer) };
+        }
+    }
+
+    pub fn cmd_insert_label(&self, cmd_buffer: vk::CommandBuffer, name: &CStr, color: [f32; 4]) {
+        if let Some(device_h) = &self.device_h {
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(name)
+                .color(color);
+            unsafe { device_h.cmd_insert_debug_utils_label(cmd_buffer, &label) };
+        }
+    }
+}
+
+/// Interior nul bytes are dropped instead of failing
+pub(crate) fn to_cstring(name: &str) -> CString {
+    CString::new(name).unwrap_or_else(|_| CString::new(name.replace('\0', "")).unwrap())
+}
+
+impl Drop for VkDebugUtils {
+    fn drop(&mut self) {
+        unsafe { self.debug_utils_h.destroy_debug_utils_messenger(self.messenger_h, None) };
+    }
+}
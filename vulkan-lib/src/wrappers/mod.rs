@@ -1,6 +1,6 @@
 pub mod instance;
 pub mod capabilities_checker;
-pub mod debug_report;
+pub mod debug_utils;
 pub mod device;
 pub mod surface;
 pub mod image;
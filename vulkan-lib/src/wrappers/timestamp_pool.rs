@@ -1,8 +1,13 @@
+use std::collections::VecDeque;
 use ash::vk;
 use ash::vk::{CommandBuffer, PipelineStageFlags, QueryPool, QueryPoolCreateInfo, QueryResultFlags};
 use log::{info, warn};
+use crate::queue::gpu_scopes::ResolvedScope;
 use crate::wrappers::device::VkDeviceRef;
 
+/// Queries in each query pool of `ScopeQueryPool`, unless single submission requires more
+const SCOPE_QUERY_CHUNK: u32 = 64;
+
 #[derive(Copy, Clone)]
 pub enum QuerySlot {
     Submitted(usize),
@@ -44,6 +49,11 @@ impl TimestampPool {
             need_reset: true,
         })
     }
+    /// Nanoseconds per timestamp tick
+    pub fn period(&self) -> f32 {
+        self.tm_period
+    }
+
     pub fn write_start_timestamp(&mut self, cb: CommandBuffer, submission_num: usize) -> u32 {
         let mut slot = 0;
         for i in self.cur_i..self.cur_i + self.slots.len() {
@@ -142,3 +152,174 @@ impl Drop for TimestampPool {
         unsafe { self.device.destroy_query_pool(self.query_pool, None); }
     }
 }
+
+struct ScopeQueryChunk {
+    query_pool: QueryPool,
+    used: Vec<bool>,
+}
+
+/// Queries reserved for timestamp scopes of a single submission, two per scope
+pub struct ScopeQueries {
+    chunk: usize,
+    first: u32,
+    count: u32,
+}
+
+struct PendingScopes {
+    submission_num: usize,
+    frame_index: u64,
+    queries: ScopeQueries,
+    /// Name and depth of each scope
+    scopes: Vec<(&'static str, u32)>,
+}
+
+pub struct ResolvedScopes {
+    pub submission_num: usize,
+    pub frame_index: u64,
+    pub scopes: Vec<ResolvedScope>,
+}
+
+/// Timestamp queries for `RecordContext::timestamp_scope`. Grows by adding query pools when all queries are in flight
+pub struct ScopeQueryPool {
+    device: VkDeviceRef,
+    chunks: Vec<ScopeQueryChunk>,
+    pending: VecDeque<PendingScopes>,
+}
+
+impl ScopeQueryPool {
+    pub fn new(device: VkDeviceRef) -> Self {
+        Self {
+            device,
+            chunks: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Reserve and reset queries for `scope_count` scopes. Must be called outside of render pass
+    pub fn allocate(&mut self, cb: CommandBuffer, scope_count: u32) -> Option<ScopeQueries> {
+        let count = scope_count * 2;
+        let found = self.chunks.iter().enumerate().find_map(|(i, chunk)| {
+            Self::find_free_range(&chunk.used, count).map(|first| (i, first))
+        });
+        let (chunk, first) = match found {
+            Some(found) => found,
+            None => {
+                let query_count = count.max(SCOPE_QUERY_CHUNK);
+                let info = QueryPoolCreateInfo::default()
+                    .query_type(vk::QueryType::TIMESTAMP)
+                    .query_count(query_count);
+                let query_pool = match unsafe { self.device.create_query_pool(&info, None) } {
+                    Ok(query_pool) => query_pool,
+                    Err(e) => {
+                        warn!("Failed to grow timestamp scope query pool: {:?}", e);
+                        return None;
+                    }
+                };
+                info!("Timestamp scope query pool grown to {} pools", self.chunks.len() + 1);
+                self.chunks.push(ScopeQueryChunk {
+                    query_pool,
+                    used: vec![false; query_count as usize],
+                });
+                (self.chunks.len() - 1, 0)
+            }
+        };
+
+        let chunk_ref = &mut self.chunks[chunk];
+        chunk_ref.used[first as usize..(first + count) as usize].fill(true);
+        unsafe { self.device.cmd_reset_query_pool(cb, chunk_ref.query_pool, first, count) };
+        Some(ScopeQueries {
+            chunk,
+            first,
+            count,
+        })
+    }
+
+    fn find_free_range(used: &[bool], count: u32) -> Option<u32> {
+        let mut run = 0;
+        for (i, used) in used.iter().enumerate() {
+            run = if *used { 0 } else { run + 1 };
+            if run == count {
+                return Some(i as u32 + 1 - count);
+            }
+        }
+        None
+    }
+
+    pub fn write_timestamp(&self, cb: CommandBuffer, queries: &ScopeQueries, scope: u32, is_end: bool) {
+        let (query, stage) = if is_end {
+            (scope * 2 + 1, PipelineStageFlags::BOTTOM_OF_PIPE)
+        }
+        else {
+            (scope * 2, PipelineStageFlags::TOP_OF_PIPE)
+        };
+        debug_assert!(query < queries.count);
+        let query_pool = self.chunks[queries.chunk].query_pool;
+        unsafe { self.device.cmd_write_timestamp(cb, stage, query_pool, queries.first + query) };
+    }
+
+    pub fn on_submitted(&mut self, submission_num: usize, frame_index: u64, queries: ScopeQueries, scopes: Vec<(&'static str, u32)>) {
+        self.pending.push_back(PendingScopes {
+            submission_num,
+            frame_index,
+            queries,
+            scopes,
+        });
+    }
+
+    /// Read results of submissions up to `last_waited_submission` and release their queries
+    pub fn resolve(&mut self, last_waited_submission: usize) -> Vec<ResolvedScopes> {
+        let mut res = vec![];
+        while let Some(pending) = self.pending.front() && pending.submission_num <= last_waited_submission {
+            let pending = self.pending.pop_front().unwrap();
+            let chunk = &mut self.chunks[pending.queries.chunk];
+
+            let mut buffer = vec![(0u64, 0u64); pending.queries.count as usize];
+            let query_res = unsafe { self.device.get_query_pool_results(chunk.query_pool, pending.queries.first, &mut buffer, QueryResultFlags::TYPE_64 | QueryResultFlags::WITH_AVAILABILITY) };
+            if let Err(e) = query_res && e != vk::Result::NOT_READY {
+                warn!("Failed to read timestamp scopes from query pool: {:?}", e);
+            }
+            let first = pending.queries.first as usize;
+            chunk.used[first..first + pending.queries.count as usize].fill(false);
+
+            let scopes = pending.scopes.iter().enumerate().filter_map(|(i, &(name, depth))| {
+                let (start, start_available) = buffer[i * 2];
+                let (end, end_available) = buffer[i * 2 + 1];
+                (start_available != 0 && end_available != 0).then_some((name, depth, start, end))
+            }).collect();
+            res.push(ResolvedScopes {
+                submission_num: pending.submission_num,
+                frame_index: pending.frame_index,
+                scopes,
+            });
+        }
+        res
+    }
+}
+
+impl Drop for ScopeQueryPool {
+    fn drop(&mut self) {
+        for chunk in &self.chunks {
+            unsafe { self.device.destroy_query_pool(chunk.query_pool, None); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_free_range() {
+        let used = [false, false, true, true, false, false];
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 2), Some(0));
+        // free queries at the end and at the start are not contiguous
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 4), None);
+
+        // fragmented: first run that fits is taken
+        let used = [false, true, false, false, true, false, false, false];
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 1), Some(0));
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 2), Some(2));
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 3), Some(5));
+        assert_eq!(ScopeQueryPool::find_free_range(&used, 4), None);
+    }
+}